@@ -16,6 +16,8 @@ cfg_if::cfg_if! {
         pub mod rustls_impl;
         pub use rustls_impl::ecdsa;
         pub use rustls_impl::hash;
+        pub use rustls_impl::rand;
+        pub use rustls_impl::signature;
         pub use rustls_impl::tls;
     }
 }
@@ -23,6 +25,8 @@ cfg_if::cfg_if! {
 pub mod crl;
 pub mod x509;
 
+use signature::SignatureAlgorithm;
+
 pub type Result<T> = core::result::Result<T, Error>;
 
 pub const SHA384_DIGEST_SIZE: usize = 48;
@@ -50,6 +54,9 @@ pub enum Error {
     /// Failed to verify the ECDSA digital signature of the given data
     EcdsaVerify,
 
+    /// Failed to calculate the digital signature of the given data
+    Sign,
+
     /// Couldn't configure the TLS contex, e.g., cipher suite, TLS protocol version.
     SetupTlsContext(tls::TlsLibError),
 
@@ -59,11 +66,14 @@ pub enum Error {
     /// Error occurs during reading/writing the tls connection
     TlsStream,
 
+    /// Failed to read/write the transport under the tls connection
+    TlsTransport,
+
     /// Unable to get the TLS peer's certificates
     TlsGetPeerCert,
 
     /// Unable to verify the TLS peer's certificates
-    TlsVerifyPeerCert(String),
+    TlsVerifyPeerCert(PeerCertError),
 
     /// Error occurs during processing the tls connection
     TlsConnection,
@@ -87,6 +97,65 @@ pub enum Error {
     Unexpected,
 }
 
+/// Reason for rejecting the TLS peer's certificates, returned by the
+/// `tls::PeerVerifier` and carried through the TLS alert to the user of the
+/// TLS session.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PeerCertError {
+    /// The local or the peer attestation can't be verified
+    MutualAttestation,
+
+    /// The certificate key isn't the one bound in the peer attestation
+    MismatchPublicKey,
+
+    /// The certificate isn't valid at the current time
+    OutsideValidity,
+
+    /// The policy used to verify the peer is invalid, with the policy error
+    InvalidPolicy(PolicyErrorCode),
+
+    /// The peer doesn't satisfy the policy, with the policy error
+    PolicyUnsatisfied(PolicyErrorCode),
+
+    /// The certificate doesn't fit in the resources of the session
+    OutOfResource,
+}
+
+/// Variant of the `PolicyError` of the policy crate, without its details.
+///
+/// The policy crate depends on this crate, so a `PeerVerifier` rejecting the
+/// peer because of the policy reports the variant of its error.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PolicyErrorCode {
+    FailGetReport,
+    InvalidParameter,
+    InvalidPolicy,
+    InvalidEventLog,
+    PlatformNotFound,
+    PlatformNotMatch,
+    UnqualifiedPlatformInfo,
+    UnqualifiedQeInfo,
+    UnqualifiedTdxModuleInfo,
+    UnqualifiedMigTdInfo,
+    Crypto,
+    SignatureVerificationFailed,
+    InvalidCollateral,
+    InvalidOperation,
+    InvalidReference,
+    InvalidServtdIdentity,
+    InvalidServtdTcbMapping,
+    InvalidPolicyIssuers,
+    PolicyHashMismatch,
+    InvalidQuote,
+    SvnMismatch,
+    TcbEvaluation,
+    CrlEvaluation,
+    HashCalculation,
+    QuoteVerification,
+    QuoteGeneration,
+    GetTdxReport,
+}
+
 impl From<x509::DerError> for Error {
     fn from(e: x509::DerError) -> Error {
         Error::GenerateCertificate(e)
@@ -97,24 +166,76 @@ pub fn pem_cert_to_der(cert: &[u8]) -> Result<CertificateDer<'static>> {
     CertificateDer::from_pem_slice(cert).map_err(|_| Error::DecodePemCert)
 }
 
+/// Decodes all the certificates of a PEM chain, in their order in the chain
+pub fn pem_cert_chain_to_der(cert_chain: &[u8]) -> Result<Vec<CertificateDer<'static>>> {
+    extract_cert_chain_from_pem(cert_chain)
+}
+
+/// Extracts the public key of the leaf certificate of a PEM chain
+pub fn leaf_public_key(cert_chain_pem: &[u8]) -> Result<Vec<u8>> {
+    let leaf = pem_cert_to_der(cert_chain_pem)?;
+    let leaf = x509::Certificate::from_der(leaf.as_ref()).map_err(|_| Error::ParseCertificate)?;
+    extract_public_key_from_cert(&leaf)
+}
+
 /// Verifies a certificate chain and then verifies a message signature
 pub fn verify_cert_chain_and_signature(
     cert_chain_pem: &[u8],
     message: &[u8],
     signature: &[u8],
+) -> Result<()> {
+    verify_cert_chain_and_signature_with_algorithms(
+        cert_chain_pem,
+        message,
+        signature,
+        SignatureAlgorithm::ALL,
+    )
+}
+
+/// Verifies a certificate chain and then verifies a message signature, only
+/// accepting the signature algorithms in `allowed`
+pub fn verify_cert_chain_and_signature_with_algorithms(
+    cert_chain_pem: &[u8],
+    message: &[u8],
+    signature: &[u8],
+    allowed: &[SignatureAlgorithm],
 ) -> Result<()> {
     let cert_chain = extract_cert_chain_from_pem(cert_chain_pem)?;
 
-    verify_certificate_chain(&cert_chain)?;
+    verify_certificate_chain(&cert_chain, allowed)?;
 
     // Extract public key from the leaf certificate and verify signature
     let leaf_cert = &cert_chain[0];
-    verify_signature_with_cert(leaf_cert, message, signature)?;
+    verify_signature_with_cert(leaf_cert, message, signature, allowed)?;
 
     Ok(())
 }
 
-fn extract_cert_chain_from_pem(cert_chain_pem: &[u8]) -> Result<Vec<CertificateDer>> {
+/// Verifies a certificate chain and then a JWS signature (RFC 7515) made with
+/// `algorithm` by the key of the leaf certificate, only accepting the
+/// signature algorithms in `allowed`
+pub fn verify_cert_chain_and_jws_signature(
+    cert_chain_pem: &[u8],
+    signing_input: &[u8],
+    signature: &[u8],
+    algorithm: SignatureAlgorithm,
+    allowed: &[SignatureAlgorithm],
+) -> Result<()> {
+    if !allowed.contains(&algorithm) {
+        return Err(Error::UnsupportedAlgorithm);
+    }
+
+    let cert_chain = extract_cert_chain_from_pem(cert_chain_pem)?;
+
+    verify_certificate_chain(&cert_chain, allowed)?;
+
+    let leaf_cert =
+        x509::Certificate::from_der(cert_chain[0].as_ref()).map_err(|_| Error::ParseCertificate)?;
+    let public_key = extract_public_key_from_cert(&leaf_cert)?;
+    algorithm.verify_jws(&public_key, signing_input, signature)
+}
+
+fn extract_cert_chain_from_pem(cert_chain_pem: &[u8]) -> Result<Vec<CertificateDer<'static>>> {
     let mut cert_chain = Vec::new();
 
     // Extract all certificates from the PEM chain
@@ -133,7 +254,10 @@ fn extract_cert_chain_from_pem(cert_chain_pem: &[u8]) -> Result<Vec<CertificateD
 }
 
 /// Verifies a certificate chain (leaf to root)
-fn verify_certificate_chain(cert_chain: &[CertificateDer<'_>]) -> Result<()> {
+fn verify_certificate_chain(
+    cert_chain: &[CertificateDer<'_>],
+    allowed: &[SignatureAlgorithm],
+) -> Result<()> {
     if cert_chain.is_empty() {
         return Err(Error::CertChainVerification(
             "Empty certificate chain".into(),
@@ -151,7 +275,7 @@ fn verify_certificate_chain(cert_chain: &[CertificateDer<'_>]) -> Result<()> {
         let issuer_cert = x509::Certificate::from_der(cert_chain[i + 1].as_ref())
             .map_err(|_| Error::ParseCertificate)?;
 
-        verify_cert_signature(&subject_cert, &issuer_cert)?;
+        verify_cert_signature(&subject_cert, &issuer_cert, allowed)?;
     }
 
     Ok(())
@@ -161,9 +285,11 @@ fn verify_certificate_chain(cert_chain: &[CertificateDer<'_>]) -> Result<()> {
 fn verify_cert_signature(
     subject_cert: &x509::Certificate,
     issuer_cert: &x509::Certificate,
+    allowed: &[SignatureAlgorithm],
 ) -> Result<()> {
     let issuer_public_key = extract_public_key_from_cert(issuer_cert)?;
-    let signature_algorithm = &subject_cert.signature_algorithm;
+    let signature_algorithm =
+        SignatureAlgorithm::from_algorithm_identifier(&subject_cert.signature_algorithm)?;
 
     // Get the signature from subject certificate
     let signature = subject_cert
@@ -182,21 +308,31 @@ fn verify_cert_signature(
         &tbs_cert_der,
         signature,
         signature_algorithm,
+        allowed,
     )
 }
 
-/// Verifies a message signature using the public key from a certificate
+/// Verifies a message signature using the public key from a certificate, the
+/// signature algorithm is given by the type of the key
 fn verify_signature_with_cert(
     cert_der: &CertificateDer<'_>,
     message: &[u8],
     signature: &[u8],
+    allowed: &[SignatureAlgorithm],
 ) -> Result<()> {
     let cert =
         x509::Certificate::from_der(cert_der.as_ref()).map_err(|_| Error::ParseCertificate)?;
     let public_key = extract_public_key_from_cert(&cert)?;
-    let signature_algorithm = &cert.signature_algorithm;
+    let signature_algorithm =
+        SignatureAlgorithm::from_public_key_info(&cert.tbs_certificate.subject_public_key_info)?;
 
-    verify_signature_with_algorithm(&public_key, message, signature, signature_algorithm)
+    verify_signature_with_algorithm(
+        &public_key,
+        message,
+        signature,
+        signature_algorithm,
+        allowed,
+    )
 }
 
 /// Extracts the public key from a certificate
@@ -211,34 +347,19 @@ fn extract_public_key_from_cert(cert: &x509::Certificate) -> Result<Vec<u8>> {
     Ok(public_key_bytes.to_vec())
 }
 
-/// Verifies a signature using the specified algorithm
+/// Verifies a signature using the specified algorithm if it is allowed
 fn verify_signature_with_algorithm(
     public_key: &[u8],
     message: &[u8],
     signature: &[u8],
-    signature_algorithm: &x509::AlgorithmIdentifier,
+    signature_algorithm: SignatureAlgorithm,
+    allowed: &[SignatureAlgorithm],
 ) -> Result<()> {
-    // ECDSA with SHA-384: 1.2.840.10045.4.3.3
-    const ECDSA_WITH_SHA384: &[u32] = &[1, 2, 840, 10045, 4, 3, 3];
-
-    // Match against known signature algorithm OIDs
-    let algorithm_oid = &signature_algorithm.algorithm;
-    let oid_arcs: Vec<u32> = algorithm_oid.arcs().collect();
-
-    // Only ECDSA-P384 with SHA384 signature is supported
-    match oid_arcs.as_slice() {
-        ECDSA_WITH_SHA384 => ecdsa::ecdsa_verify_with_algorithm(
-            public_key,
-            message,
-            signature,
-            &ecdsa::ECDSA_P384_SHA384_ASN1,
-        )
-        .map_err(|_| Error::SignatureVerification),
-        _ => {
-            // Unsupported algorithm
-            Err(Error::UnsupportedAlgorithm)
-        }
+    if !allowed.contains(&signature_algorithm) {
+        return Err(Error::UnsupportedAlgorithm);
     }
+
+    signature_algorithm.verify(public_key, message, signature)
 }
 
 #[cfg(test)]
@@ -295,6 +416,10 @@ rPSb+wS9KsT0dcF2DU5F12BycQ==
 ";
 
         let cert_chain = extract_cert_chain_from_pem(test_pem).unwrap();
-        assert!(verify_certificate_chain(&cert_chain).is_ok());
+        assert!(verify_certificate_chain(&cert_chain, SignatureAlgorithm::ALL).is_ok());
+        assert!(matches!(
+            verify_certificate_chain(&cert_chain, &[SignatureAlgorithm::EcdsaP256Sha256]),
+            Err(Error::UnsupportedAlgorithm)
+        ));
     }
 }
@@ -22,9 +22,10 @@ pub struct EcdsaPk {
     pk: Document,
 }
 
+// The private key must never be printed
 impl core::fmt::Debug for EcdsaPk {
-    fn fmt(&self, _f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
-        Ok(())
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str("EcdsaPk(<redacted>)")
     }
 }
 
@@ -156,3 +157,21 @@ fn test_ecdsa() {
     let pub_key = ecdsa.public_key().unwrap();
     assert!(ecdsa_verify(&pub_key, data, &sig).is_ok());
 }
+
+#[test]
+fn test_ecdsa_zeroize_on_drop() {
+    use core::mem::MaybeUninit;
+
+    let mut slot: alloc::boxed::Box<MaybeUninit<EcdsaPk>> =
+        alloc::boxed::Box::new(MaybeUninit::uninit());
+    let ecdsa = slot.write(EcdsaPk::new().unwrap());
+    let offset = ecdsa.private_key().as_ptr() as usize - ecdsa as *const EcdsaPk as usize;
+    let len = ecdsa.private_key().len();
+    assert!(ecdsa.private_key().iter().any(|b| *b != 0));
+
+    // Drop the key but keep the heap storage alive to scan it
+    unsafe { slot.assume_init_drop() };
+    let private_key =
+        unsafe { core::slice::from_raw_parts((slot.as_ptr() as *const u8).add(offset), len) };
+    assert!(private_key.iter().all(|b| *b == 0));
+}
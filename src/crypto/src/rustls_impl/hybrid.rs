@@ -0,0 +1,218 @@
+// Copyright (c) 2025 Intel Corporation
+//
+// SPDX-License-Identifier: BSD-2-Clause-Patent
+
+//! X25519MLKEM768 hybrid key exchange, as specified in
+//! draft-kwiatkowski-tls-ecdhe-mlkem.
+//!
+//! The key share of the client is the ML-KEM-768 encapsulation key followed by
+//! its X25519 public key, the key share of the server is the ML-KEM-768
+//! ciphertext followed by its X25519 public key. The shared secret is the
+//! ML-KEM shared secret followed by the X25519 shared secret.
+
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use core::fmt;
+use ml_kem::kem::{Decapsulate, Encapsulate};
+use ml_kem::{Ciphertext, Encoded, EncodedSizeUser, KemCore, MlKem768};
+use rand_core::{CryptoRng, RngCore};
+use rustls::crypto::ring::kx_group::X25519;
+use rustls::crypto::{ActiveKeyExchange, CompletedKeyExchange, SharedSecret, SupportedKxGroup};
+use rustls::{Error, NamedGroup, PeerMisbehaved};
+use zeroize::Zeroize;
+
+use super::rand::get_random;
+
+type EncapsulationKey = <MlKem768 as KemCore>::EncapsulationKey;
+type DecapsulationKey = <MlKem768 as KemCore>::DecapsulationKey;
+
+const MLKEM768_ENCAPSULATION_KEY_SIZE: usize = 1184;
+const MLKEM768_CIPHERTEXT_SIZE: usize = 1088;
+const X25519_KEY_SHARE_SIZE: usize = 32;
+
+pub static X25519MLKEM768: &dyn SupportedKxGroup = &X25519MlKem768;
+
+struct X25519MlKem768;
+
+impl fmt::Debug for X25519MlKem768 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("X25519MLKEM768")
+    }
+}
+
+impl SupportedKxGroup for X25519MlKem768 {
+    fn start(&self) -> Result<Box<dyn ActiveKeyExchange>, Error> {
+        let x25519 = X25519.start()?;
+
+        let mut rng = Rng::default();
+        let (decapsulation_key, encapsulation_key) = MlKem768::generate(&mut rng);
+        rng.check()?;
+
+        let mut pub_key =
+            Vec::with_capacity(MLKEM768_ENCAPSULATION_KEY_SIZE + X25519_KEY_SHARE_SIZE);
+        pub_key.extend_from_slice(&encapsulation_key.as_bytes());
+        pub_key.extend_from_slice(x25519.pub_key());
+
+        Ok(Box::new(Active {
+            decapsulation_key,
+            x25519,
+            pub_key,
+        }))
+    }
+
+    fn start_and_complete(&self, client_share: &[u8]) -> Result<CompletedKeyExchange, Error> {
+        if client_share.len() != MLKEM768_ENCAPSULATION_KEY_SIZE + X25519_KEY_SHARE_SIZE {
+            return Err(PeerMisbehaved::InvalidKeyShare.into());
+        }
+        let (mlkem_share, x25519_share) = client_share.split_at(MLKEM768_ENCAPSULATION_KEY_SIZE);
+
+        let encoded = Encoded::<EncapsulationKey>::try_from(mlkem_share)
+            .map_err(|_| Error::from(PeerMisbehaved::InvalidKeyShare))?;
+        let encapsulation_key = EncapsulationKey::from_bytes(&encoded);
+        let x25519 = X25519.start_and_complete(x25519_share)?;
+
+        let mut rng = Rng::default();
+        let (ciphertext, mut mlkem_secret) = encapsulation_key
+            .encapsulate(&mut rng)
+            .map_err(|_| Error::from(PeerMisbehaved::InvalidKeyShare))?;
+        let secret = combine_secrets(&mlkem_secret, &x25519.secret);
+        mlkem_secret.as_mut_slice().zeroize();
+        rng.check()?;
+
+        let mut pub_key = Vec::with_capacity(MLKEM768_CIPHERTEXT_SIZE + X25519_KEY_SHARE_SIZE);
+        pub_key.extend_from_slice(&ciphertext);
+        pub_key.extend_from_slice(&x25519.pub_key);
+
+        Ok(CompletedKeyExchange {
+            group: self.name(),
+            pub_key,
+            secret,
+        })
+    }
+
+    fn name(&self) -> NamedGroup {
+        NamedGroup::X25519MLKEM768
+    }
+}
+
+struct Active {
+    decapsulation_key: DecapsulationKey,
+    x25519: Box<dyn ActiveKeyExchange>,
+    pub_key: Vec<u8>,
+}
+
+impl ActiveKeyExchange for Active {
+    fn complete(self: Box<Self>, server_share: &[u8]) -> Result<SharedSecret, Error> {
+        if server_share.len() != MLKEM768_CIPHERTEXT_SIZE + X25519_KEY_SHARE_SIZE {
+            return Err(PeerMisbehaved::InvalidKeyShare.into());
+        }
+        let (mlkem_share, x25519_share) = server_share.split_at(MLKEM768_CIPHERTEXT_SIZE);
+
+        let ciphertext = Ciphertext::<MlKem768>::try_from(mlkem_share)
+            .map_err(|_| Error::from(PeerMisbehaved::InvalidKeyShare))?;
+        let x25519_secret = self.x25519.complete(x25519_share)?;
+
+        let mut mlkem_secret = self
+            .decapsulation_key
+            .decapsulate(&ciphertext)
+            .map_err(|_| Error::from(PeerMisbehaved::InvalidKeyShare))?;
+        let secret = combine_secrets(&mlkem_secret, &x25519_secret);
+        mlkem_secret.as_mut_slice().zeroize();
+
+        Ok(secret)
+    }
+
+    fn pub_key(&self) -> &[u8] {
+        &self.pub_key
+    }
+
+    fn group(&self) -> NamedGroup {
+        NamedGroup::X25519MLKEM768
+    }
+}
+
+// Random number generator of ML-KEM over `get_random`. The interface of
+// ML-KEM cannot fail, a failure is reported by `check()` once the random
+// numbers are drawn.
+#[derive(Default)]
+struct Rng {
+    failed: bool,
+}
+
+impl Rng {
+    fn check(&self) -> Result<(), Error> {
+        if self.failed {
+            return Err(Error::General("GetRandom".into()));
+        }
+        Ok(())
+    }
+}
+
+impl RngCore for Rng {
+    fn next_u32(&mut self) -> u32 {
+        let mut bytes = [0u8; 4];
+        self.fill_bytes(&mut bytes);
+        u32::from_le_bytes(bytes)
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        let mut bytes = [0u8; 8];
+        self.fill_bytes(&mut bytes);
+        u64::from_le_bytes(bytes)
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        if get_random(dest).is_err() {
+            self.failed = true;
+        }
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand_core::Error> {
+        self.fill_bytes(dest);
+        Ok(())
+    }
+}
+
+impl CryptoRng for Rng {}
+
+// The ML-KEM shared secret is copied into the combined secret, which is zeroized
+// on drop by rustls. The caller zeroizes its own copy right after.
+fn combine_secrets(mlkem_secret: &[u8], x25519_secret: &SharedSecret) -> SharedSecret {
+    let mut secret = Vec::with_capacity(mlkem_secret.len() + x25519_secret.secret_bytes().len());
+    secret.extend_from_slice(mlkem_secret);
+    secret.extend_from_slice(x25519_secret.secret_bytes());
+    SharedSecret::from(secret)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_x25519mlkem768_agreement() {
+        let client = X25519MLKEM768.start().unwrap();
+        assert_eq!(
+            client.pub_key().len(),
+            MLKEM768_ENCAPSULATION_KEY_SIZE + X25519_KEY_SHARE_SIZE
+        );
+
+        let server = X25519MLKEM768.start_and_complete(client.pub_key()).unwrap();
+        assert_eq!(
+            server.pub_key.len(),
+            MLKEM768_CIPHERTEXT_SIZE + X25519_KEY_SHARE_SIZE
+        );
+
+        let secret = client.complete(&server.pub_key).unwrap();
+        assert_eq!(secret.secret_bytes(), server.secret.secret_bytes());
+        assert_eq!(secret.secret_bytes().len(), 64);
+    }
+
+    #[test]
+    fn test_x25519mlkem768_invalid_share() {
+        let client = X25519MLKEM768.start().unwrap();
+        assert!(X25519MLKEM768
+            .start_and_complete(&client.pub_key()[..X25519_KEY_SHARE_SIZE])
+            .is_err());
+        assert!(client.complete(&[0u8; X25519_KEY_SHARE_SIZE]).is_err());
+    }
+}
@@ -4,4 +4,8 @@
 
 pub mod ecdsa;
 pub mod hash;
+#[cfg(feature = "pq_hybrid")]
+pub mod hybrid;
+pub mod rand;
+pub mod signature;
 pub mod tls;
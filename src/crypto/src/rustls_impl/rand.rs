@@ -0,0 +1,12 @@
+// Copyright (c) 2025 Intel Corporation
+//
+// SPDX-License-Identifier: BSD-2-Clause-Patent
+
+use ring::rand::{SecureRandom, SystemRandom};
+
+use crate::{Error, Result};
+
+/// Fills `dest` with random bytes from the system random generator
+pub fn get_random(dest: &mut [u8]) -> Result<()> {
+    SystemRandom::new().fill(dest).map_err(|_| Error::GetRandom)
+}
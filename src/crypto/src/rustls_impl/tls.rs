@@ -16,32 +16,79 @@ use rustls::crypto::ring::cipher_suite::TLS13_AES_256_GCM_SHA384;
 use rustls::crypto::ring::default_provider;
 use rustls::crypto::ring::kx_group::SECP384R1;
 use rustls::crypto::ring::sign::any_ecdsa_type;
-use rustls::crypto::{verify_tls12_signature, verify_tls13_signature, CryptoProvider};
+use rustls::crypto::{
+    verify_tls12_signature, verify_tls13_signature, CryptoProvider, SupportedKxGroup,
+};
 use rustls::server::danger::{ClientCertVerified, ClientCertVerifier};
 use rustls::server::{ClientHello, ResolvesServerCert};
 use rustls::sign::{CertifiedKey, SigningKey};
 use rustls::time_provider::TimeProvider;
 use rustls::version::TLS13;
 use rustls::{ClientConfig, ServerConfig};
+use spin::Mutex;
 extern crate alloc;
 
-use crate::{Error, Result};
+use crate::{Error, PeerCertError, Result};
+use core::fmt::Debug;
+use zeroize::Zeroize;
 
 use super::ecdsa::EcdsaPk;
 
 pub type TlsLibError = rustls::Error;
+
+/// Heap taken by the record buffers of one `SecureChannel`: the inbound and
+/// outbound TLS buffers and the limit of the received application data.
+pub const TLS_RECORD_BUFFERS_SIZE: usize =
+    2 * connection::TLS_BUFFER_SIZE + connection::APP_DATA_BUFFER_LIMIT;
 const TLS_CUSTOM_CALLBACK_ERROR: &str = "TlsCustomCallbackError";
 
-pub struct SecureChannel<T: AsyncRead + AsyncWrite + Unpin> {
+// rustls only carries a string for the failure of a custom verifier, the
+// typed outcome of the verification is handed over to the connection through
+// this slot.
+type PeerCertOutcome<C> = Arc<Mutex<Option<core::result::Result<C, PeerCertError>>>>;
+
+/// Verifies the certificates of the TLS peer during the handshake.
+///
+/// The state of the verifier is zeroized when the TLS session drops it, if
+/// the session holds the last reference to it.
+pub trait PeerVerifier: Debug + Zeroize {
+    /// Claims about the peer established by the verification
+    type Claims: Clone + Debug + Send + Sync + 'static;
+
+    /// Verifies the DER encoded end-entity certificate of the peer and
+    /// returns the claims established from it, which are kept by the
+    /// `SecureChannel`. A rejected peer is reported with
+    /// `Error::TlsVerifyPeerCert`.
+    fn verify(&self, cert: &[u8]) -> Result<Self::Claims>;
+}
+
+/// TLS session over the transport `T`.
+///
+/// The record buffers, which hold plaintext, are scrubbed once consumed and when
+/// the channel is dropped. The handshake and traffic secrets are owned by rustls
+/// and the ring provider and are out of scope: wiping them on drop is left to
+/// those crates.
+pub struct SecureChannel<T: AsyncRead + AsyncWrite + Unpin, C> {
     conn: TlsConnection<T>,
+    outcome: PeerCertOutcome<C>,
 }
 
-impl<T> SecureChannel<T>
+impl<T, C> SecureChannel<T, C>
 where
     T: AsyncRead + AsyncWrite + Unpin,
+    C: Clone,
 {
-    fn new(conn: TlsConnection<T>) -> Self {
-        SecureChannel { conn }
+    fn new(conn: TlsConnection<T>, outcome: PeerCertOutcome<C>) -> Self {
+        SecureChannel { conn, outcome }
+    }
+
+    /// Claims about the peer returned by the `PeerVerifier`, available once
+    /// the handshake verified the peer's certificates.
+    pub fn peer_claims(&self) -> Option<C> {
+        match self.outcome.lock().as_ref() {
+            Some(Ok(claims)) => Some(claims.clone()),
+            _ => None,
+        }
     }
 
     pub fn transport_mut(&mut self) -> &mut T {
@@ -49,11 +96,28 @@ where
     }
 
     pub async fn write(&mut self, data: &[u8]) -> Result<usize> {
-        self.conn.write(data).await
+        let result = self.conn.write(data).await;
+        result.map_err(|e| self.handle_stream_error(e))
     }
 
     pub async fn read(&mut self, data: &mut [u8]) -> Result<usize> {
-        self.conn.read(data).await
+        let result = self.conn.read(data).await;
+        result.map_err(|e| self.handle_stream_error(e))
+    }
+
+    fn handle_stream_error(&self, e: TlsConnectionError) -> Error {
+        match e {
+            TlsConnectionError::TlsLib(rustls::Error::General(desc))
+                if desc.starts_with(TLS_CUSTOM_CALLBACK_ERROR) =>
+            {
+                match self.outcome.lock().as_ref() {
+                    Some(Err(reason)) => Error::TlsVerifyPeerCert(reason.clone()),
+                    _ => Error::TlsStream,
+                }
+            }
+            TlsConnectionError::Transport => Error::TlsTransport,
+            _ => Error::TlsStream,
+        }
     }
 }
 
@@ -63,34 +127,17 @@ enum TlsConnection<T: AsyncRead + AsyncWrite + Unpin> {
 }
 
 impl<T: AsyncRead + AsyncWrite + Unpin> TlsConnection<T> {
-    async fn read(&mut self, data: &mut [u8]) -> Result<usize> {
+    async fn read(&mut self, data: &mut [u8]) -> core::result::Result<usize, TlsConnectionError> {
         match self {
-            Self::Server(conn) => conn.read(data).await.map_err(Self::handle_stream_error),
-            Self::Client(conn) => conn.read(data).await.map_err(Self::handle_stream_error),
+            Self::Server(conn) => conn.read(data).await,
+            Self::Client(conn) => conn.read(data).await,
         }
     }
 
-    async fn write(&mut self, data: &[u8]) -> Result<usize> {
+    async fn write(&mut self, data: &[u8]) -> core::result::Result<usize, TlsConnectionError> {
         match self {
-            Self::Server(conn) => conn.write(data).await.map_err(Self::handle_stream_error),
-            Self::Client(conn) => conn.write(data).await.map_err(Self::handle_stream_error),
-        }
-    }
-
-    fn handle_stream_error(e: TlsConnectionError) -> Error {
-        match e {
-            TlsConnectionError::TlsLib(rustls::Error::General(desc)) => {
-                if let Some(index) = desc.find(TLS_CUSTOM_CALLBACK_ERROR) {
-                    let start = index + TLS_CUSTOM_CALLBACK_ERROR.len() + 1;
-                    let end = match desc[start..].find(')') {
-                        Some(index) => start + index,
-                        None => return Error::TlsStream,
-                    };
-                    return Error::TlsVerifyPeerCert(desc[start..end].to_string());
-                }
-                Error::TlsStream
-            }
-            _ => Error::TlsStream,
+            Self::Server(conn) => conn.write(data).await,
+            Self::Client(conn) => conn.write(data).await,
         }
     }
 
@@ -102,17 +149,56 @@ impl<T: AsyncRead + AsyncWrite + Unpin> TlsConnection<T> {
     }
 }
 
-pub struct TlsConfig {
+/// Key exchange group of the TLS handshakes. Both ends only offer the
+/// configured group, so the handshake fails if they don't agree on it.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum KxGroup {
+    #[default]
+    Secp384r1,
+    /// Hybrid of X25519 and ML-KEM-768, only supported with the `pq_hybrid`
+    /// feature
+    X25519MlKem768,
+}
+
+impl KxGroup {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "secp384r1" => Some(Self::Secp384r1),
+            "x25519mlkem768" => Some(Self::X25519MlKem768),
+            _ => None,
+        }
+    }
+
+    /// Returns whether this build can negotiate the group
+    pub fn is_supported(&self) -> bool {
+        match self {
+            Self::Secp384r1 => true,
+            Self::X25519MlKem768 => cfg!(feature = "pq_hybrid"),
+        }
+    }
+
+    fn supported_kx_group(&self) -> Result<&'static dyn SupportedKxGroup> {
+        match self {
+            Self::Secp384r1 => Ok(SECP384R1),
+            #[cfg(feature = "pq_hybrid")]
+            Self::X25519MlKem768 => Ok(super::hybrid::X25519MLKEM768),
+            #[cfg(not(feature = "pq_hybrid"))]
+            Self::X25519MlKem768 => Err(Error::UnsupportedAlgorithm),
+        }
+    }
+}
+
+pub struct TlsConfig<C> {
     pub(crate) resolver: Resolver,
-    pub(crate) verifier: Verifier,
+    pub(crate) verifier: Verifier<C>,
+    pub(crate) kx_group: KxGroup,
 }
 
-impl TlsConfig {
+impl<C: Clone + Debug + Send + Sync + 'static> TlsConfig<C> {
     pub fn new(
         certs_der: Vec<Vec<u8>>,
         signing_key: EcdsaPk,
-        verify_callback: fn(&[u8], &[u8]) -> core::result::Result<(), Error>,
-        verify_callback_data: Vec<u8>,
+        peer_verifier: Arc<dyn PeerVerifier<Claims = C> + Send + Sync>,
     ) -> Result<Self> {
         let mut certs = Vec::new();
         for cert in certs_der {
@@ -121,9 +207,13 @@ impl TlsConfig {
         }
 
         let resolver = Resolver::new(certs, signing_key);
-        let verifier = Verifier::new(verify_callback, verify_callback_data);
+        let verifier = Verifier::new(peer_verifier);
 
-        Ok(Self { resolver, verifier })
+        Ok(Self {
+            resolver,
+            verifier,
+            kx_group: KxGroup::default(),
+        })
     }
 
     pub fn set_certs(&mut self, certs_der: Vec<Vec<u8>>, signing_key: EcdsaPk) -> Result<()> {
@@ -138,21 +228,31 @@ impl TlsConfig {
         Ok(())
     }
 
-    pub fn set_verify_callback(
+    pub fn set_peer_verifier(
         &mut self,
-        cb: fn(&[u8], &[u8]) -> core::result::Result<(), Error>,
-        data: Vec<u8>,
+        peer_verifier: Arc<dyn PeerVerifier<Claims = C> + Send + Sync>,
     ) -> Result<()> {
-        self.verifier = Verifier::new(cb, data);
+        self.verifier = Verifier::new(peer_verifier);
 
         Ok(())
     }
+
+    pub fn set_kx_group(&mut self, kx_group: KxGroup) -> Result<()> {
+        if !kx_group.is_supported() {
+            return Err(Error::UnsupportedAlgorithm);
+        }
+        self.kx_group = kx_group;
+
+        Ok(())
+    }
+
     pub fn tls_client<T: AsyncRead + AsyncWrite + Unpin>(
         self,
         stream: T,
-    ) -> Result<SecureChannel<T>> {
+    ) -> Result<SecureChannel<T, C>> {
+        let outcome = self.verifier.outcome.clone();
         let client_config = ClientConfig::builder_with_details(
-            Arc::new(crypto_provider()),
+            Arc::new(crypto_provider(self.kx_group)?),
             Arc::new(TlsTimeProvider {}),
         )
         .with_protocol_versions(&[&TLS13])
@@ -166,15 +266,19 @@ impl TlsConfig {
         let connection = TlsClientConnection::new(Arc::new(client_config), stream)
             .map_err(|_| Error::TlsConnection)?;
 
-        Ok(SecureChannel::new(TlsConnection::Client(connection)))
+        Ok(SecureChannel::new(
+            TlsConnection::Client(connection),
+            outcome,
+        ))
     }
 
     pub fn tls_server<T: AsyncRead + AsyncWrite + Unpin>(
         self,
         stream: T,
-    ) -> Result<SecureChannel<T>> {
+    ) -> Result<SecureChannel<T, C>> {
+        let outcome = self.verifier.outcome.clone();
         let server_config = ServerConfig::builder_with_details(
-            Arc::new(crypto_provider()),
+            Arc::new(crypto_provider(self.kx_group)?),
             Arc::new(TlsTimeProvider {}),
         )
         .with_protocol_versions(&[&TLS13])
@@ -185,15 +289,18 @@ impl TlsConfig {
         let connection = TlsServerConnection::new(Arc::new(server_config), stream)
             .map_err(|_| Error::TlsConnection)?;
 
-        Ok(SecureChannel::new(TlsConnection::Server(connection)))
+        Ok(SecureChannel::new(
+            TlsConnection::Server(connection),
+            outcome,
+        ))
     }
 }
 
-pub(crate) fn crypto_provider() -> CryptoProvider {
+pub(crate) fn crypto_provider(kx_group: KxGroup) -> Result<CryptoProvider> {
     let mut provider = default_provider();
     provider.cipher_suites = vec![TLS13_AES_256_GCM_SHA384];
-    provider.kx_groups = vec![SECP384R1];
-    provider
+    provider.kx_groups = vec![kx_group.supported_kx_group()?];
+    Ok(provider)
 }
 
 #[derive(Debug)]
@@ -241,21 +348,44 @@ impl ResolvesClientCert for Resolver {
 }
 
 #[derive(Debug)]
-pub(crate) struct Verifier {
-    // Function `cb` takes peer's certificates as first parameter and
-    // additional data required by `cb` to verify the certs as second
-    // parameter.
-    cb: fn(&[u8], &[u8]) -> Result<()>,
-    data: Vec<u8>,
+pub(crate) struct Verifier<C> {
+    peer_verifier: Arc<dyn PeerVerifier<Claims = C> + Send + Sync>,
+    outcome: PeerCertOutcome<C>,
+}
+
+impl<C: Clone + Debug + Send + Sync + 'static> Verifier<C> {
+    pub fn new(peer_verifier: Arc<dyn PeerVerifier<Claims = C> + Send + Sync>) -> Self {
+        Self {
+            peer_verifier,
+            outcome: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    fn verify(&self, end_entity: &CertificateDer<'_>) -> core::result::Result<(), rustls::Error> {
+        match self.peer_verifier.verify(end_entity.as_ref()) {
+            Ok(claims) => {
+                *self.outcome.lock() = Some(Ok(claims));
+                Ok(())
+            }
+            Err(Error::TlsVerifyPeerCert(reason)) => {
+                let desc = format!("{TLS_CUSTOM_CALLBACK_ERROR}({reason:?})");
+                *self.outcome.lock() = Some(Err(reason));
+                Err(rustls::Error::General(desc))
+            }
+            Err(_) => Err(rustls::Error::General("Unexpected".to_string())),
+        }
+    }
 }
 
-impl Verifier {
-    pub fn new(cb: fn(&[u8], &[u8]) -> Result<()>, data: Vec<u8>) -> Self {
-        Self { cb, data }
+impl<C> Drop for Verifier<C> {
+    fn drop(&mut self) {
+        if let Some(peer_verifier) = Arc::get_mut(&mut self.peer_verifier) {
+            peer_verifier.zeroize();
+        }
     }
 }
 
-impl ServerCertVerifier for Verifier {
+impl<C: Clone + Debug + Send + Sync + 'static> ServerCertVerifier for Verifier<C> {
     fn verify_server_cert(
         &self,
         end_entity: &CertificateDer<'_>,
@@ -264,16 +394,8 @@ impl ServerCertVerifier for Verifier {
         _ocsp_response: &[u8],
         _now: UnixTime,
     ) -> core::result::Result<ServerCertVerified, rustls::Error> {
-        if let Err(e) = (self.cb)(end_entity.as_ref(), &self.data) {
-            match e {
-                Error::TlsVerifyPeerCert(e) => Err(rustls::Error::General(format!(
-                    "{TLS_CUSTOM_CALLBACK_ERROR}({e})"
-                ))),
-                _ => Err(rustls::Error::General("Unexpected".to_string())),
-            }
-        } else {
-            Ok(ServerCertVerified::assertion())
-        }
+        self.verify(end_entity)?;
+        Ok(ServerCertVerified::assertion())
     }
 
     fn verify_tls13_signature(
@@ -311,23 +433,15 @@ impl ServerCertVerifier for Verifier {
     }
 }
 
-impl ClientCertVerifier for Verifier {
+impl<C: Clone + Debug + Send + Sync + 'static> ClientCertVerifier for Verifier<C> {
     fn verify_client_cert(
         &self,
         end_entity: &CertificateDer<'_>,
         _intermediates: &[CertificateDer<'_>],
         _now: UnixTime,
     ) -> core::result::Result<ClientCertVerified, rustls::Error> {
-        if let Err(e) = (self.cb)(end_entity.as_ref(), &self.data) {
-            match e {
-                Error::TlsVerifyPeerCert(e) => Err(rustls::Error::General(format!(
-                    "{TLS_CUSTOM_CALLBACK_ERROR}({e})"
-                ))),
-                _ => Err(rustls::Error::General("Unexpected".to_string())),
-            }
-        } else {
-            Ok(ClientCertVerified::assertion())
-        }
+        self.verify(end_entity)?;
+        Ok(ClientCertVerified::assertion())
     }
 
     fn verify_tls13_signature(
@@ -382,6 +496,7 @@ pub(crate) mod connection {
         },
         ClientConfig, ServerConfig,
     };
+    use zeroize::Zeroize;
 
     pub const PAGE_SIZE: usize = 0x1000;
     pub const TLS_BUFFER_SIZE: usize = 16 * PAGE_SIZE;
@@ -454,7 +569,7 @@ pub(crate) mod connection {
                 Err(e) => {
                     let InsufficientSizeError { required_size } = map_err(e)?;
                     let new_len = self.used + required_size;
-                    self.inner.resize(new_len, 0);
+                    self.grow(new_len);
 
                     f(self.unused_mut()).map_err(|_| TlsConnectionError::Encode)?
                 }
@@ -480,8 +595,9 @@ pub(crate) mod connection {
             &mut self.inner[self.used..]
         }
 
-        // Reset the used
+        // Reset the used, the content may be plaintext so scrub it
         pub fn reset(&mut self) {
+            self.inner[..self.used].zeroize();
             self.used = 0;
         }
 
@@ -492,8 +608,28 @@ pub(crate) mod connection {
 
         // Discard the first `size` bytes
         pub fn discard(&mut self, size: usize) {
-            self.inner.copy_within(size..self.used, 0);
+            let used = self.used;
+            self.inner.copy_within(size..used, 0);
             self.used -= size;
+            // Records are decrypted in place, do not leave stale copies behind
+            self.inner[self.used..used].zeroize();
+        }
+
+        // Grow the buffer without leaving the old allocation behind unscrubbed
+        fn grow(&mut self, new_len: usize) {
+            if new_len <= self.inner.len() {
+                return;
+            }
+            let mut inner = vec![0u8; new_len];
+            inner[..self.used].copy_from_slice(&self.inner[..self.used]);
+            self.inner.zeroize();
+            self.inner = inner;
+        }
+    }
+
+    impl Drop for TlsBuffer {
+        fn drop(&mut self) {
+            self.inner.zeroize();
         }
     }
 
@@ -745,16 +881,26 @@ pub(crate) mod connection {
         fn consume(&mut self, mut used: usize) {
             while let Some(mut buf) = self.chunks.pop_front() {
                 if used < buf.len() {
-                    buf.drain(..used);
-                    self.chunks.push_front(buf);
+                    let rest = buf[used..].to_vec();
+                    buf.zeroize();
+                    self.chunks.push_front(rest);
                     break;
                 } else {
                     used -= buf.len();
+                    buf.zeroize();
                 }
             }
         }
     }
 
+    impl Drop for ChunkVecBuffer {
+        fn drop(&mut self) {
+            for chunk in self.chunks.iter_mut() {
+                chunk.zeroize();
+            }
+        }
+    }
+
     pub struct TlsClientConnection<T: AsyncRead + AsyncWrite + Unpin> {
         conn: UnbufferedClientConnection,
         input: TlsBuffer,
@@ -938,6 +1084,45 @@ pub(crate) mod connection {
             }
         }
     }
+
+    #[cfg(test)]
+    mod test {
+        use super::*;
+
+        #[test]
+        fn test_tls_buffer_scrub() {
+            let mut buffer = TlsBuffer::new();
+            buffer.unused_mut()[..8].copy_from_slice(&[0xaa; 8]);
+            buffer.consume(8);
+
+            buffer.discard(4);
+            assert_eq!(buffer.used(), &[0xaa; 4]);
+            assert!(buffer.inner[4..8].iter().all(|b| *b == 0));
+
+            buffer.grow(TLS_BUFFER_SIZE * 2);
+            assert_eq!(buffer.used(), &[0xaa; 4]);
+
+            buffer.reset();
+            assert!(buffer.inner.iter().all(|b| *b == 0));
+        }
+
+        #[test]
+        fn test_chunk_vec_buffer_read() {
+            let mut chunks = ChunkVecBuffer::new(None);
+            chunks.append(vec![1, 2, 3]);
+            chunks.append(vec![4, 5]);
+
+            let mut out = [0u8; 2];
+            assert_eq!(chunks.read(&mut out), 2);
+            assert_eq!(out, [1, 2]);
+            assert_eq!(chunks.len(), 3);
+
+            let mut out = [0u8; 4];
+            assert_eq!(chunks.read(&mut out), 3);
+            assert_eq!(&out[..3], &[3, 4, 5]);
+            assert!(chunks.is_empty());
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -945,17 +1130,288 @@ pub(crate) struct TlsTimeProvider;
 
 impl TimeProvider for TlsTimeProvider {
     fn current_time(&self) -> Option<UnixTime> {
-        // Avoid RTC access in AzCVMEmu; use a fixed timestamp.
-        #[cfg(feature = "AzCVMEmu")]
-        {
-            Some(UnixTime::since_unix_epoch(Duration::new(1704067200u64, 0)))
-        }
-        #[cfg(not(feature = "AzCVMEmu"))]
-        {
-            Some(UnixTime::since_unix_epoch(Duration::new(
-                sys_time::get_sys_time()? as u64,
-                0,
+        current_time().map(UnixTime::since_unix_epoch)
+    }
+}
+
+/// Time since the Unix epoch used by the TLS sessions, read from the RTC.
+pub fn current_time() -> Option<Duration> {
+    // Avoid RTC access in AzCVMEmu and in unit tests; use a fixed timestamp.
+    #[cfg(any(feature = "AzCVMEmu", test))]
+    {
+        Some(Duration::new(1704067200u64, 0))
+    }
+    #[cfg(not(any(feature = "AzCVMEmu", test)))]
+    {
+        Some(Duration::new(sys_time::get_sys_time()? as u64, 0))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::x509::{
+        AlgorithmIdentifier, AnyRef, CertificateBuilder, Encode, ObjectIdentifier, Tag,
+    };
+    use crate::PolicyErrorCode;
+    use alloc::collections::VecDeque;
+    use core::future::Future;
+    use core::pin::pin;
+    use core::task::{Context, Poll, Waker};
+
+    const ID_EC_PUBKEY_OID: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.2.840.10045.2.1");
+    const SECP384R1_OID: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.3.132.0.34");
+    const ID_EC_SIG_OID: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.2.840.10045.4.3.3");
+
+    // One direction of an in-memory transport.
+    type Queue = Arc<Mutex<VecDeque<u8>>>;
+
+    struct Pipe {
+        rx: Queue,
+        tx: Queue,
+    }
+
+    fn pipe_pair() -> (Pipe, Pipe) {
+        let a: Queue = Arc::new(Mutex::new(VecDeque::new()));
+        let b: Queue = Arc::new(Mutex::new(VecDeque::new()));
+        (
+            Pipe {
+                rx: a.clone(),
+                tx: b.clone(),
+            },
+            Pipe { rx: b, tx: a },
+        )
+    }
+
+    impl AsyncRead for Pipe {
+        async fn read(&mut self, buf: &mut [u8]) -> async_io::Result<usize> {
+            core::future::poll_fn(|_| {
+                let mut rx = self.rx.lock();
+                if rx.is_empty() {
+                    return Poll::Pending;
+                }
+                let size = buf.len().min(rx.len());
+                for (dst, src) in buf.iter_mut().zip(rx.drain(..size)) {
+                    *dst = src;
+                }
+                Poll::Ready(Ok(size))
+            })
+            .await
+        }
+    }
+
+    impl AsyncWrite for Pipe {
+        async fn write(&mut self, buf: &[u8]) -> async_io::Result<usize> {
+            self.tx.lock().extend(buf);
+            Ok(buf.len())
+        }
+    }
+
+    #[derive(Debug)]
+    struct AcceptAll;
+
+    impl Zeroize for AcceptAll {
+        fn zeroize(&mut self) {}
+    }
+
+    impl PeerVerifier for AcceptAll {
+        type Claims = usize;
+
+        fn verify(&self, cert: &[u8]) -> Result<usize> {
+            Ok(cert.len())
+        }
+    }
+
+    #[derive(Debug)]
+    struct RejectAll;
+
+    impl Zeroize for RejectAll {
+        fn zeroize(&mut self) {}
+    }
+
+    impl PeerVerifier for RejectAll {
+        type Claims = usize;
+
+        fn verify(&self, _cert: &[u8]) -> Result<usize> {
+            Err(Error::TlsVerifyPeerCert(PeerCertError::PolicyUnsatisfied(
+                PolicyErrorCode::SvnMismatch,
             )))
         }
     }
+
+    // Verifier state standing for the local quote, reports its zeroization
+    #[derive(Debug)]
+    struct SecretState([u8; 16], Arc<Mutex<Option<[u8; 16]>>>);
+
+    impl Zeroize for SecretState {
+        fn zeroize(&mut self) {
+            self.0.zeroize();
+            *self.1.lock() = Some(self.0);
+        }
+    }
+
+    impl PeerVerifier for SecretState {
+        type Claims = usize;
+
+        fn verify(&self, cert: &[u8]) -> Result<usize> {
+            Ok(cert.len())
+        }
+    }
+
+    fn self_signed_config(kx_group: KxGroup) -> TlsConfig<usize> {
+        let signing_key = EcdsaPk::new().unwrap();
+        let pub_key = signing_key.public_key().unwrap();
+        let algorithm = AlgorithmIdentifier {
+            algorithm: ID_EC_PUBKEY_OID,
+            parameters: Some(AnyRef::new(Tag::ObjectIdentifier, SECP384R1_OID.as_bytes()).unwrap()),
+        };
+        let sig_alg = AlgorithmIdentifier {
+            algorithm: ID_EC_SIG_OID,
+            parameters: None,
+        };
+        let mut signature = Vec::new();
+        let cert = CertificateBuilder::new(sig_alg, algorithm, &pub_key)
+            .unwrap()
+            .add_common_name("MigTD")
+            .unwrap()
+            .sign(&mut signature, |tbs, signature| {
+                signature.extend(signing_key.sign(tbs).unwrap())
+            })
+            .unwrap()
+            .build()
+            .to_der()
+            .unwrap();
+
+        let mut config = TlsConfig::new(vec![cert], signing_key, Arc::new(AcceptAll)).unwrap();
+        config.set_kx_group(kx_group).unwrap();
+        config
+    }
+
+    // Drives the handshake of both ends and a round trip of application data,
+    // returns the results of the client and the server.
+    fn handshake(client: KxGroup, server: KxGroup) -> (Result<usize>, Result<usize>) {
+        handshake_with(self_signed_config(client), self_signed_config(server))
+    }
+
+    fn handshake_with(
+        client: TlsConfig<usize>,
+        server: TlsConfig<usize>,
+    ) -> (Result<usize>, Result<usize>) {
+        let (client_pipe, server_pipe) = pipe_pair();
+        let mut client = client.tls_client(client_pipe).unwrap();
+        let mut server = server.tls_server(server_pipe).unwrap();
+
+        let mut client_task = pin!(async {
+            client.write(b"ping").await?;
+            let mut buf = [0u8; 4];
+            let size = client.read(&mut buf).await?;
+            assert_eq!(&buf[..size], b"pong");
+            assert!(client.peer_claims().is_some_and(|len| len > 0));
+            Ok(size)
+        });
+        let mut server_task = pin!(async {
+            let mut buf = [0u8; 4];
+            let size = server.read(&mut buf).await?;
+            assert_eq!(&buf[..size], b"ping");
+            assert!(server.peer_claims().is_some_and(|len| len > 0));
+            server.write(b"pong").await
+        });
+
+        let mut cx = Context::from_waker(Waker::noop());
+        let mut client_result = None;
+        let mut server_result = None;
+        for _ in 0..64 {
+            if client_result.is_none() {
+                if let Poll::Ready(result) = client_task.as_mut().poll(&mut cx) {
+                    client_result = Some(result);
+                }
+            }
+            if server_result.is_none() {
+                if let Poll::Ready(result) = server_task.as_mut().poll(&mut cx) {
+                    server_result = Some(result);
+                }
+            }
+            if client_result.is_some() && server_result.is_some() {
+                break;
+            }
+            // A failed end stops sending, its peer would wait forever.
+            if matches!(client_result, Some(Err(_))) || matches!(server_result, Some(Err(_))) {
+                break;
+            }
+        }
+        (
+            client_result.unwrap_or(Err(Error::TlsStream)),
+            server_result.unwrap_or(Err(Error::TlsStream)),
+        )
+    }
+
+    #[test]
+    fn test_handshake_secp384r1() {
+        let (client, server) = handshake(KxGroup::Secp384r1, KxGroup::Secp384r1);
+        assert_eq!(client.unwrap(), 4);
+        assert_eq!(server.unwrap(), 4);
+    }
+
+    #[test]
+    fn test_verifier_zeroized_on_drop() {
+        let zeroized = Arc::new(Mutex::new(None));
+        let state = SecretState([0xa5; 16], zeroized.clone());
+        let mut config = self_signed_config(KxGroup::Secp384r1);
+        config.set_peer_verifier(Arc::new(state)).unwrap();
+
+        let (client_pipe, _server_pipe) = pipe_pair();
+        let client = config.tls_client(client_pipe).unwrap();
+        assert!(zeroized.lock().is_none());
+        drop(client);
+        assert_eq!(*zeroized.lock(), Some([0u8; 16]));
+    }
+
+    #[test]
+    fn test_handshake_peer_rejected() {
+        let client = self_signed_config(KxGroup::Secp384r1);
+        let mut server = self_signed_config(KxGroup::Secp384r1);
+        server.set_peer_verifier(Arc::new(RejectAll)).unwrap();
+
+        let (client, server) = handshake_with(client, server);
+        assert!(client.is_err());
+        match server {
+            Err(Error::TlsVerifyPeerCert(reason)) => assert_eq!(
+                reason,
+                PeerCertError::PolicyUnsatisfied(PolicyErrorCode::SvnMismatch)
+            ),
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+
+    #[cfg(feature = "pq_hybrid")]
+    #[test]
+    fn test_handshake_x25519mlkem768() {
+        let (client, server) = handshake(KxGroup::X25519MlKem768, KxGroup::X25519MlKem768);
+        assert_eq!(client.unwrap(), 4);
+        assert_eq!(server.unwrap(), 4);
+    }
+
+    // A MigTD built without `pq_hybrid` can only offer secp384r1. Each end
+    // offers the group of its policy only, so a MigTD offering the hybrid
+    // group never falls back to a classical one: both directions fail the
+    // handshake and no application data is exchanged.
+    #[cfg(feature = "pq_hybrid")]
+    #[test]
+    fn test_handshake_pq_hybrid_with_classical_peer() {
+        for (client, server) in [
+            (KxGroup::X25519MlKem768, KxGroup::Secp384r1),
+            (KxGroup::Secp384r1, KxGroup::X25519MlKem768),
+        ] {
+            let (client, server) = handshake(client, server);
+            assert!(client.is_err());
+            assert!(server.is_err());
+        }
+    }
+
+    #[cfg(not(feature = "pq_hybrid"))]
+    #[test]
+    fn test_kx_group_unsupported() {
+        let mut config = self_signed_config(KxGroup::Secp384r1);
+        assert!(config.set_kx_group(KxGroup::X25519MlKem768).is_err());
+    }
 }
@@ -37,6 +37,24 @@ impl<'a> CertificateBuilder<'a> {
         Ok(self)
     }
 
+    pub fn set_serial_number(mut self, serial_number: &'a [u8]) -> Result<Self, DerError> {
+        self.0.tbs_certificate.serial_number = UintRef::new(serial_number)?;
+        Ok(self)
+    }
+
+    /// Adds a common name to the subject, and to the issuer as the certificate is
+    /// self-signed.
+    pub fn add_common_name(mut self, common_name: &'a str) -> Result<Self, DerError> {
+        let mut name = SetOfVec::new();
+        name.insert(DistinguishedName {
+            attribute_type: ObjectIdentifier::new("2.5.4.3")?,
+            value: Utf8StringRef::new(common_name)?.into(),
+        })?;
+        self.0.tbs_certificate.issuer.push(name.clone());
+        self.0.tbs_certificate.subject.push(name);
+        Ok(self)
+    }
+
     pub fn set_public_key(
         mut self,
         algorithm: AlgorithmIdentifier<'a>,
@@ -291,12 +309,31 @@ impl From<GeneralizedTime> for Time {
     }
 }
 
+impl Time {
+    pub fn to_unix_duration(&self) -> core::time::Duration {
+        match self {
+            Time::Utc(time) => time.to_unix_duration(),
+            Time::Generalized(time) => time.to_unix_duration(),
+        }
+    }
+}
+
 #[derive(Clone, Debug, Eq, PartialEq, Sequence)]
 pub struct Validity {
     not_before: Time,
     not_after: Time,
 }
 
+impl Validity {
+    pub fn not_before(&self) -> &Time {
+        &self.not_before
+    }
+
+    pub fn not_after(&self) -> &Time {
+        &self.not_after
+    }
+}
+
 #[derive(Clone, Copy, Debug, Eq, PartialEq, Sequence)]
 pub struct SubjectPublicKeyInfo<'a> {
     pub algorithm: AlgorithmIdentifier<'a>,
@@ -9,13 +9,22 @@
 
 use std::env;
 use std::process;
+use std::sync::OnceLock;
 
 use alloc::vec::Vec;
+use futures_util::future::join;
 use migtd;
+use migtd::driver::crash;
 use migtd::migration::event;
-use migtd::migration::logging::{create_logarea, enable_logarea};
-use migtd::migration::session::{exchange_msk, report_status};
-use migtd::migration::{MigrationResult, MigtdMigrationInformation};
+use migtd::migration::log_control;
+use migtd::migration::logging::{create_logarea, enable_logarea, snapshot_logarea};
+use migtd::migration::metrics;
+use migtd::migration::session::{
+    batch_status_data, cancelable, complete_request, drain_requests, exchange_msk,
+    exchange_msk_batch, report_status,
+};
+use migtd::migration::transcript;
+use migtd::migration::{MigrationResult, MigtdMigrationInformation, ShutdownInfo};
 
 use tdx_tdcall_emu::tdreport_emu::tdcall_report_emulated;
 use tdx_tdcall_emu::tdx_emu::set_emulated_start_migration;
@@ -24,6 +33,19 @@ use tdx_tdcall_emu::{init_tcp_emulation_with_mode, start_tcp_server_sync, TcpEmu
 // Import shared functions from main.rs
 use crate::{basic_info, do_measurements};
 
+// Drain timeout of the shutdown requested while the migration is in flight, if any
+static SHUTDOWN_DRAIN_TIMEOUT: OnceLock<u32> = OnceLock::new();
+
+// File the emulated LogArea is mirrored to for host tools, if any
+static LOGAREA_DUMP_FILE: OnceLock<String> = OnceLock::new();
+
+// Transcript replayed in place of running MigTD, if any
+#[cfg(feature = "policy_v2")]
+static REPLAY_TRANSCRIPT_FILE: OnceLock<String> = OnceLock::new();
+
+// Interval between two LogArea mirror updates
+const LOGAREA_DUMP_INTERVAL: std::time::Duration = std::time::Duration::from_millis(200);
+
 /// AzCVMEmu entry point - standard Rust main function
 pub fn main() {
     // Initialize standard Rust logging for AzCVMEmu mode with info level by default
@@ -37,6 +59,11 @@ pub fn main() {
     td_shim_emu::event_log::init_event_log();
     // Parse command line arguments first so `-h` works without env vars/files
     parse_commandline_args();
+    // A replay only needs the transcript
+    #[cfg(feature = "policy_v2")]
+    if let Some(path) = REPLAY_TRANSCRIPT_FILE.get() {
+        process::exit(replay_transcript(path));
+    }
     // Initialize emulation layer (requires env vars/files); skipped if `-h` exited
     initialize_emulation();
 
@@ -152,12 +179,19 @@ fn runtime_main_emu() -> i32 {
     // Perform measurements (reusing from main.rs)
     do_measurements();
 
+    let level = log_control::apply_policy_cap();
+    log::info!("log level capped by policy to {}\n", level);
+
     // Create LogArea per vCPU
     match create_logarea() {
         Ok(_) => log::info!("LogArea created successfully\n"),
         Err(e) => log::error!("Failed to create logarea: {}\n", e as u8),
     }
 
+    if let Some(path) = LOGAREA_DUMP_FILE.get() {
+        start_logarea_mirror(path);
+    }
+
     // Register callback
     event::register_callback();
 
@@ -165,6 +199,39 @@ fn runtime_main_emu() -> i32 {
     handle_pre_mig_emu()
 }
 
+/// Periodically writes the emulated LogArea pages to `path` so that
+/// `migtd-logarea --follow` can tail them. The file is replaced atomically so
+/// the reader never sees a partial snapshot.
+fn start_logarea_mirror(path: &str) {
+    let path = std::path::PathBuf::from(path);
+    let mut tmp_path = path.clone().into_os_string();
+    tmp_path.push(".tmp");
+
+    std::thread::spawn(move || loop {
+        let snapshot = snapshot_logarea();
+        if let Err(e) =
+            std::fs::write(&tmp_path, &snapshot).and_then(|_| std::fs::rename(&tmp_path, &path))
+        {
+            log::error!("Failed to mirror LogArea to {}: {}\n", path.display(), e);
+            return;
+        }
+        std::thread::sleep(LOGAREA_DUMP_INTERVAL);
+    });
+}
+
+/// Writes a crash report for the panics which are not raised through
+/// `panic_with_guest_crash_reg_report`, which reports its own.
+fn install_crash_report_hook() {
+    let default_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        crash::report_panic(
+            &info.payload_as_str().unwrap_or("non-UTF8 message"),
+            info.location(),
+        );
+        default_hook(info);
+    }));
+}
+
 fn parse_commandline_args() {
     let args: Vec<String> = env::args().collect();
 
@@ -255,6 +322,34 @@ fn parse_commandline_args() {
                     process::exit(1);
                 }
             }
+            "--shutdown-timeout" | "-s" if i + 1 < args.len() => {
+                if let Ok(timeout) = args[i + 1].parse::<u32>() {
+                    let _ = SHUTDOWN_DRAIN_TIMEOUT.set(timeout);
+                    i += 2;
+                } else {
+                    println!("Invalid shutdown drain timeout value: {}", args[i + 1]);
+                    print_usage();
+                    process::exit(1);
+                }
+            }
+            "--logarea-file" | "-l" if i + 1 < args.len() => {
+                let _ = LOGAREA_DUMP_FILE.set(args[i + 1].clone());
+                i += 2;
+            }
+            "--crash-file" | "-c" if i + 1 < args.len() => {
+                crash::set_crash_report_file(args[i + 1].clone());
+                install_crash_report_hook();
+                i += 2;
+            }
+            "--transcript-file" if i + 1 < args.len() => {
+                transcript::set_transcript_file(args[i + 1].clone());
+                i += 2;
+            }
+            #[cfg(feature = "policy_v2")]
+            "--replay-transcript" if i + 1 < args.len() => {
+                let _ = REPLAY_TRANSCRIPT_FILE.set(args[i + 1].clone());
+                i += 2;
+            }
             "--help" | "-h" => {
                 help_requested = true;
                 i += 1;
@@ -369,6 +464,93 @@ fn parse_commandline_args() {
     );
 }
 
+/// Replays the peer authentication recorded in a transcript and returns 0 if
+/// its verdict matches the recorded one.
+#[cfg(feature = "policy_v2")]
+fn replay_transcript(path: &str) -> i32 {
+    use migtd::migration::transcript::Transcript;
+
+    let bundle = match std::fs::read(path) {
+        Ok(bundle) => bundle,
+        Err(e) => {
+            println!("Failed to read transcript {}: {}", path, e);
+            return 1;
+        }
+    };
+    let transcript = match Transcript::read_from(&bundle) {
+        Some(transcript) => transcript,
+        None => {
+            println!("Invalid transcript: {}", path);
+            return 1;
+        }
+    };
+    if transcript.flags & transcript::TRANSCRIPT_FLAG_TRUNCATED != 0 {
+        println!("Warning: the transcript is truncated");
+    }
+
+    println!(
+        "Migration request {} ({}):",
+        transcript.mig_request_id,
+        if transcript.is_src {
+            "source"
+        } else {
+            "destination"
+        }
+    );
+    match replay_authentication(&transcript) {
+        Ok((recorded, replayed)) => {
+            println!("  recorded verdict: {}", recorded);
+            println!("  replayed verdict: {}", replayed);
+            if recorded == replayed {
+                0
+            } else {
+                1
+            }
+        }
+        Err(e) => {
+            println!("  replay failed: {}", e);
+            1
+        }
+    }
+}
+
+// Returns the recorded and the replayed verdicts of the peer authentication
+#[cfg(feature = "policy_v2")]
+fn replay_authentication(transcript: &transcript::Transcript) -> Result<(String, String), String> {
+    use migtd::mig_policy;
+    use migtd::migration::transcript::RecordKind;
+    use policy::PolicyEvaluationInfo;
+
+    let record = |kind: RecordKind| {
+        transcript
+            .get(kind)
+            .ok_or_else(|| format!("no {} record", kind.as_str()))
+    };
+
+    // The local policy and issuer chain are read back from the emulated CFV
+    let local_policy = record(RecordKind::LocalPolicy)?;
+    let issuer_chain = record(RecordKind::PolicyIssuerChain)?;
+    td_shim_interface_emu::set_policy_data(local_policy);
+    td_shim_interface_emu::td_uefi_pi::fv::set_policy_issuer_chain_data(issuer_chain);
+    let local_policy: &'static [u8] = Box::leak(local_policy.to_vec().into_boxed_slice());
+    mig_policy::init_policy(local_policy, issuer_chain)
+        .map_err(|e| format!("invalid local policy {:?}", e))?;
+    let tcb_info = PolicyEvaluationInfo::from_json(record(RecordKind::LocalTcbInfo)?)
+        .map_err(|e| format!("invalid local TCB info {:?}", e))?;
+    mig_policy::init_tcb_info_from(tcb_info);
+
+    let result = mig_policy::authenticate_remote(
+        transcript.mig_request_id,
+        transcript.is_src,
+        record(RecordKind::PeerQuote)?,
+        record(RecordKind::PeerPolicy)?,
+        record(RecordKind::PeerEventLog)?,
+    );
+    let recorded = String::from_utf8_lossy(record(RecordKind::Verdict)?).into_owned();
+
+    Ok((recorded, transcript::verdict(&result)))
+}
+
 fn print_usage() {
     println!("MigTD AzCVMEmu Mode Usage:");
     println!();
@@ -388,6 +570,13 @@ fn print_usage() {
     println!("  --binding, -b HANDLE       Set binding handle as hex or decimal (default: 0x1234)");
     println!("  --dest-ip, -d IP           Set destination IP address for connection (default: 127.0.0.1)");
     println!("  --dest-port, -t PORT       Set destination port for connection (default: 8001)");
+    println!("  --shutdown-timeout, -s MS  Request a shutdown while the migration is in flight,");
+    println!("                             draining it for at most MS milliseconds");
+    println!("  --logarea-file, -l PATH    Mirror the emulated LogArea to PATH for migtd-logarea");
+    println!("  --crash-file, -c PATH      Write the crash report to PATH if MigTD panics");
+    println!("  --transcript-file PATH     Write the attestation transcripts to PATH");
+    #[cfg(feature = "policy_v2")]
+    println!("  --replay-transcript PATH   Replay the peer authentication of a transcript");
     println!("  --help, -h                 Show this help message");
     println!();
     println!("Examples:");
@@ -435,41 +624,69 @@ fn handle_pre_mig_emu() -> i32 {
 
                             let _ =
                                 report_status(status as u8, wfr_info.mig_request_id, &data).await;
+                            complete_request(wfr_info.mig_request_id);
                             log::info!("ReportStatus for Enable LogArea completed\n");
                             // Continue to process next request
                         }
                         WaitForRequestResponse::StartMigration(req) => {
                             log::info!("Processing StartMigration request\n");
-                            let mut data = Vec::new();
+                            let mig_request_id = req.mig_info.mig_request_id;
+
+                            let migration = async {
+                                let mut data = Vec::new();
 
-                            // Call exchange_msk() and log its immediate outcome
-                            let res = exchange_msk(&req, &mut data).await;
-                            match &res {
-                                Ok(_) => log::info!("exchange_msk() returned Ok\n"),
-                                Err(e) => {
-                                    log::error!("exchange_msk() returned error code {}\n", *e as u8)
+                                // Call exchange_msk() and log its immediate outcome
+                                let res =
+                                    cancelable(mig_request_id, exchange_msk(&req, &mut data)).await;
+                                match &res {
+                                    Ok(_) => log::info!("exchange_msk() returned Ok\n"),
+                                    Err(e) => {
+                                        log::error!(
+                                            "exchange_msk() returned error code {}\n",
+                                            *e as u8
+                                        )
+                                    }
                                 }
-                            }
-                            let status =
-                                res.map(|_| MigrationResult::Success).unwrap_or_else(|e| e);
+                                let status =
+                                    res.map(|_| MigrationResult::Success).unwrap_or_else(|e| e);
+
+                                // Report status back via vmcall-raw emulation, the data
+                                // ends with the timing record of the exchange
+                                if let Err(e) =
+                                    report_status(status as u8, mig_request_id, &data).await
+                                {
+                                    log::error!("report_status failed with code {}\n", e as u8);
+                                } else {
+                                    log::info!("report_status completed successfully\n");
+                                }
+                                complete_request(mig_request_id);
+                                status
+                            };
+
+                            let status = match SHUTDOWN_DRAIN_TIMEOUT.get() {
+                                Some(drain_timeout) => {
+                                    // Exercise the graceful shutdown with the key exchange in flight
+                                    let shutdown = ShutdownInfo {
+                                        mig_request_id: mig_request_id.wrapping_add(1),
+                                        drain_timeout: *drain_timeout,
+                                        reserved: [0; 4],
+                                    };
+                                    let (status, canceled) =
+                                        join(migration, drain_requests(&shutdown)).await;
+                                    log::info!(
+                                        "Shutdown completed, canceled requests: {:?}\n",
+                                        canceled
+                                    );
+                                    status
+                                }
+                                None => migration.await,
+                            };
+
+                            log::info!("Key exchange metrics:\n{}", *metrics::HISTOGRAMS.lock());
 
                             // Derive a numeric code without moving `status`
                             let status_code_u8 = status as u8;
 
-                            // Report status back via vmcall-raw emulation
-                            let empty_data = Vec::new();
-                            if let Err(e) = report_status(
-                                status_code_u8,
-                                req.mig_info.mig_request_id,
-                                &empty_data,
-                            )
-                            .await
-                            {
-                                log::error!("report_status failed with code {}\n", e as u8);
-                            } else {
-                                log::info!("report_status completed successfully\n");
-                            }
-
                             if status_code_u8 == MigrationResult::Success as u8 {
                                 log::info!("Migration key exchange successful!\n");
                                 return 0;
@@ -482,6 +699,41 @@ fn handle_pre_mig_emu() -> i32 {
                                 return status_code;
                             }
                         }
+                        WaitForRequestResponse::StartBatchMigration(req) => {
+                            log::info!("Processing StartBatchMigration request\n");
+                            let mut data = Vec::new();
+
+                            let status = match exchange_msk_batch(&req, &mut data).await {
+                                Ok(entry_status) => {
+                                    let mut status_data = batch_status_data(&req, &entry_status);
+                                    status_data.extend_from_slice(&data);
+                                    data = status_data;
+                                    entry_status
+                                        .into_iter()
+                                        .find(|s| *s != MigrationResult::Success)
+                                        .unwrap_or(MigrationResult::Success)
+                                }
+                                Err(e) => e,
+                            };
+
+                            if let Err(e) =
+                                report_status(status as u8, req.mig_request_id, &data).await
+                            {
+                                log::error!("report_status failed with code {}\n", e as u8);
+                            }
+                            complete_request(req.mig_request_id);
+
+                            if status == MigrationResult::Success {
+                                log::info!("Batch migration key exchange successful!\n");
+                                return 0;
+                            } else {
+                                log::error!(
+                                    "Batch migration key exchange failed with code: {}\n",
+                                    status as u8
+                                );
+                                return status as u8 as i32;
+                            }
+                        }
                         WaitForRequestResponse::GetTdReport(report_info) => {
                             log::info!("Processing GetReportData request\n");
                             log::info!("  Request ID: {}\n", report_info.mig_request_id);
@@ -542,8 +794,77 @@ fn handle_pre_mig_emu() -> i32 {
                                     );
                                 }
                             }
+                            complete_request(report_info.mig_request_id);
                             // Continue to process next request (migration)
                         }
+                        WaitForRequestResponse::Shutdown(info) => {
+                            log::info!("Processing Shutdown request\n");
+                            let canceled = drain_requests(&info).await;
+                            let mut data = Vec::new();
+                            for request_id in &canceled {
+                                data.extend_from_slice(&request_id.to_le_bytes());
+                            }
+
+                            if let Err(e) = report_status(
+                                MigrationResult::Success as u8,
+                                info.mig_request_id,
+                                &data,
+                            )
+                            .await
+                            {
+                                log::error!("report_status failed with code {}\n", e as u8);
+                            }
+                            complete_request(info.mig_request_id);
+                            log::info!("Shutdown completed, canceled requests: {:?}\n", canceled);
+                            return 0;
+                        }
+                        WaitForRequestResponse::GetMetrics(info) => {
+                            log::info!("Processing GetMetrics request\n");
+                            let data = metrics::histogram_data(info.reset != 0);
+                            if let Err(e) = report_status(
+                                MigrationResult::Success as u8,
+                                info.mig_request_id,
+                                &data,
+                            )
+                            .await
+                            {
+                                log::error!("report_status failed with code {}\n", e as u8);
+                            }
+                            complete_request(info.mig_request_id);
+                            // Continue to process next request
+                        }
+                        WaitForRequestResponse::SetLogLevel(info) => {
+                            log::info!("Processing SetLogLevel request\n");
+                            let data = log_control::set_log_level(&info).to_vec();
+                            if let Err(e) = report_status(
+                                MigrationResult::Success as u8,
+                                info.mig_request_id,
+                                &data,
+                            )
+                            .await
+                            {
+                                log::error!("report_status failed with code {}\n", e as u8);
+                            }
+                            complete_request(info.mig_request_id);
+                            // Continue to process next request
+                        }
+                        WaitForRequestResponse::GetTranscript(info) => {
+                            log::info!("Processing GetTranscript request\n");
+                            let (status, data) = match transcript::transcript_chunk(
+                                info.transcript_request_id,
+                                info.offset as usize,
+                            ) {
+                                Some(chunk) => (MigrationResult::Success, chunk),
+                                None => (MigrationResult::InvalidParameter, Vec::new()),
+                            };
+                            if let Err(e) =
+                                report_status(status as u8, info.mig_request_id, &data).await
+                            {
+                                log::error!("report_status failed with code {}\n", e as u8);
+                            }
+                            complete_request(info.mig_request_id);
+                            // Continue to process next request
+                        }
                     }
                 }
                 Err(e) => {
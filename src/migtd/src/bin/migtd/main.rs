@@ -19,13 +19,16 @@ use alloc::vec::Vec;
 use log::{error, Level};
 use log::{info, LevelFilter};
 use migtd::event_log::*;
-#[cfg(not(feature = "vmcall-raw"))]
-use migtd::migration::data::MigrationInformation;
-#[cfg(feature = "vmcall-raw")]
+use migtd::migration::budget::MAX_CONCURRENCY_REQUESTS;
 use migtd::migration::data::WaitForRequestResponse;
+use migtd::migration::log_control;
 #[cfg(feature = "vmcall-raw")]
 use migtd::migration::logging::*;
+#[cfg(feature = "vmcall-raw")]
+use migtd::migration::metrics;
 use migtd::migration::session::*;
+#[cfg(feature = "vmcall-raw")]
+use migtd::migration::transcript;
 use migtd::migration::MigrationResult;
 use migtd::{config, event_log, migration};
 #[cfg(feature = "vmcall-raw")]
@@ -69,7 +72,6 @@ fn dump_td_info_and_hash() {
         td_report.as_bytes().len()
     );
 
-    info!("td_info: {:?}\n", td_report.td_info);
     let mut hasher = Sha384::new();
     hasher.update(td_report.td_info.as_bytes());
 
@@ -91,6 +93,18 @@ pub extern "C" fn main() {
     runtime_main()
 }
 
+// Reports the panic to the VMM, the panics raised through
+// `panic_with_guest_crash_reg_report` are only reported once.
+#[cfg(all(not(feature = "AzCVMEmu"), not(test)))]
+#[panic_handler]
+fn panic(info: &core::panic::PanicInfo) -> ! {
+    migtd::driver::crash::report_panic(&info.message(), info.location());
+    log::error!("{}\n", info);
+    loop {
+        x86_64::instructions::hlt();
+    }
+}
+
 // AzCVMEmu entry point - standard Rust main function
 #[cfg(feature = "AzCVMEmu")]
 fn main() {
@@ -98,9 +112,11 @@ fn main() {
 }
 
 pub fn runtime_main() {
-    // Initialize logging with level filter. The actual log level is determined by
-    // compile-time feature flags.
-    let _ = td_logger::init(LevelFilter::Trace);
+    // Initialize logging with level filter. The maximum log level is determined by
+    // compile-time feature flags until the policy is verified, it is then capped
+    // by the `maxLogLevel` of the policy. The VMM may lower it with a SetLogLevel
+    // request.
+    let _ = log_control::init_runtime_logger(LevelFilter::Trace);
 
     // Create LogArea per vCPU
     #[cfg(feature = "vmcall-raw")]
@@ -114,6 +130,9 @@ pub fn runtime_main() {
     // Measure the input data
     do_measurements();
 
+    let level = log_control::apply_policy_cap();
+    info!("log level capped by policy to {}\n", level);
+
     migration::event::register_callback();
 
     // Query the capability of VMM
@@ -315,6 +334,13 @@ fn get_ca_and_measure(event_log: &mut [u8]) {
             panic!("Invalid root certificate");
         }
     }
+
+    // Verify the local quote with the root CA just installed, the handshakes
+    // reuse its verified report. A failure is not fatal as the first handshake
+    // verifies it again.
+    if let Err(e) = migtd::mig_policy::init_local_report() {
+        log::warn!("Failed to verify the local quote: {:?}\n", e);
+    }
 }
 
 #[cfg(feature = "policy_v2")]
@@ -351,12 +377,6 @@ fn initialize_policy() -> String {
 }
 
 fn handle_pre_mig() {
-    const MAX_CONCURRENCY_REQUESTS: usize = 12;
-
-    #[cfg(not(feature = "vmcall-raw"))]
-    // Set by `wait_for_request` async task when getting new request from VMM.
-    static PENDING_REQUEST: Mutex<Option<MigrationInformation>> = Mutex::new(None);
-    #[cfg(feature = "vmcall-raw")]
     // Set by `wait_for_request` async task when getting new request from VMM.
     static PENDING_REQUEST: Mutex<Option<WaitForRequestResponse>> = Mutex::new(None);
 
@@ -379,6 +399,11 @@ fn handle_pre_mig() {
             })
             .await;
 
+            // Stop accepting new requests once a shutdown has been requested
+            if is_shutting_down() {
+                break;
+            }
+
             if let Ok(request) = wait_for_request().await {
                 *PENDING_REQUEST.lock() = Some(request);
             }
@@ -397,29 +422,45 @@ fn handle_pre_mig() {
             async_runtime::add_task(async move {
                 #[cfg(not(feature = "vmcall-raw"))]
                 {
-                    let status = exchange_msk(&request, &mut data)
-                        .await
-                        .map(|_| MigrationResult::Success)
-                        .unwrap_or_else(|e| e);
-
-                    let _ =
-                        report_status(status as u8, request.mig_info.mig_request_id).map_err(|e| {
-                            log::error!(
-                                "Failed to report status for mig_request_id {}: {:?}\n",
+                    match request {
+                        WaitForRequestResponse::StartMigration(request) => {
+                            let status = cancelable(
                                 request.mig_info.mig_request_id,
-                                e
-                            );
-                        });
-                    REQUESTS.lock().remove(&request.mig_info.mig_request_id);
+                                exchange_msk(&request, &mut data),
+                            )
+                            .await
+                            .map(|_| MigrationResult::Success)
+                            .unwrap_or_else(|e| e);
+
+                            let _ = report_status(status as u8, request.mig_info.mig_request_id)
+                                .map_err(|e| {
+                                    log::error!(
+                                        "Failed to report status for mig_request_id {}: {:?}\n",
+                                        request.mig_info.mig_request_id,
+                                        e
+                                    );
+                                });
+                            complete_request(request.mig_info.mig_request_id);
+                        }
+                        WaitForRequestResponse::Shutdown(drain_timeout) => {
+                            let _ = shutdown(drain_timeout).await.map_err(|e| {
+                                log::error!("Failed to shut down: {:?}\n", e);
+                            });
+                            log::info!("Shutdown completed\n");
+                        }
+                    }
                 }
                 #[cfg(feature = "vmcall-raw")]
                 {
                     match request {
                         WaitForRequestResponse::StartMigration(wfr_info) => {
-                            let status = exchange_msk(&wfr_info, &mut data)
-                                .await
-                                .map(|_| MigrationResult::Success)
-                                .unwrap_or_else(|e| e);
+                            let status = cancelable(
+                                wfr_info.mig_info.mig_request_id,
+                                exchange_msk(&wfr_info, &mut data),
+                            )
+                            .await
+                            .map(|_| MigrationResult::Success)
+                            .unwrap_or_else(|e| e);
                             if status == MigrationResult::Success {
                                 entrylog(
                                     &format!("Successfully completed key exchange\n").into_bytes(),
@@ -427,18 +468,9 @@ fn handle_pre_mig() {
                                     wfr_info.mig_info.mig_request_id,
                                 );
                                 log::trace!("Successfully completed key exchange for wfr_info.mig_info.mig_request_id = {}\n", wfr_info.mig_info.mig_request_id);
-                            } else {
-                                entrylog(
-                                    &format!(
-                                        "Failure during key exchange, status code: {:x}\n",
-                                        status.clone() as u8
-                                    )
-                                    .into_bytes(),
-                                    Level::Error,
-                                    wfr_info.mig_info.mig_request_id,
-                                );
-                                log::error!("Failure during key exchange for wfr_info.mig_info.mig_request_id = {}, status code: {:x}\n", wfr_info.mig_info.mig_request_id, status.clone() as u8);
                             }
+                            // A failure is logged once, with the phase it failed in, by exchange_msk or
+                            // by cancelable
                             let _ = report_status(
                                 status as u8,
                                 wfr_info.mig_info.mig_request_id,
@@ -458,13 +490,16 @@ fn handle_pre_mig() {
                                 wfr_info.mig_info.mig_request_id,
                             );
                             log::trace!("ReportStatus for key exchange completed for wfr_info.mig_info.mig_request_id = {}\n", wfr_info.mig_info.mig_request_id);
-                            REQUESTS.lock().remove(&wfr_info.mig_info.mig_request_id);
+                            complete_request(wfr_info.mig_info.mig_request_id);
                         }
                         WaitForRequestResponse::GetTdReport(wfr_info) => {
-                            let status = get_tdreport(
-                                &wfr_info.reportdata,
-                                &mut data,
+                            let status = cancelable(
                                 wfr_info.mig_request_id,
+                                get_tdreport(
+                                    &wfr_info.reportdata,
+                                    &mut data,
+                                    wfr_info.mig_request_id,
+                                ),
                             )
                             .await
                             .map(|_| MigrationResult::Success)
@@ -496,13 +531,16 @@ fn handle_pre_mig() {
                                 wfr_info.mig_request_id,
                             );
                             log::trace!("ReportStatus for get TDREPORT completed for wfr_info.mig_request_id = {}\n", wfr_info.mig_request_id);
-                            REQUESTS.lock().remove(&wfr_info.mig_request_id);
+                            complete_request(wfr_info.mig_request_id);
                         }
                         WaitForRequestResponse::EnableLogArea(wfr_info) => {
-                            let status = enable_logarea(
-                                wfr_info.log_max_level,
+                            let status = cancelable(
                                 wfr_info.mig_request_id,
-                                &mut data,
+                                enable_logarea(
+                                    wfr_info.log_max_level,
+                                    wfr_info.mig_request_id,
+                                    &mut data,
+                                ),
                             )
                             .await
                             .map(|_| MigrationResult::Success)
@@ -544,7 +582,163 @@ fn handle_pre_mig() {
                                 wfr_info.mig_request_id,
                             );
                             log::trace!("ReportStatus for Enable LogArea completed for wfr_info.mig_request_id = {}\n", wfr_info.mig_request_id);
-                            REQUESTS.lock().remove(&wfr_info.mig_request_id);
+                            complete_request(wfr_info.mig_request_id);
+                        }
+                        WaitForRequestResponse::StartBatchMigration(wfr_info) => {
+                            let status = match cancelable(
+                                wfr_info.mig_request_id,
+                                exchange_msk_batch(&wfr_info, &mut data),
+                            )
+                            .await
+                            {
+                                Ok(entry_status) => {
+                                    // The status of each target TD is reported in the data,
+                                    // ahead of the timing record, the overall status is the
+                                    // first failure if any.
+                                    let mut status_data =
+                                        batch_status_data(&wfr_info, &entry_status);
+                                    status_data.extend_from_slice(&data);
+                                    data = status_data;
+                                    entry_status
+                                        .into_iter()
+                                        .find(|s| *s != MigrationResult::Success)
+                                        .unwrap_or(MigrationResult::Success)
+                                }
+                                Err(e) => e,
+                            };
+                            if status == MigrationResult::Success {
+                                entrylog(
+                                    &format!("Successfully completed batch key exchange\n")
+                                        .into_bytes(),
+                                    Level::Trace,
+                                    wfr_info.mig_request_id,
+                                );
+                                log::trace!("Successfully completed batch key exchange for wfr_info.mig_request_id = {}\n", wfr_info.mig_request_id);
+                            } else {
+                                entrylog(
+                                    &format!(
+                                        "Failure during batch key exchange, status code: {:x}\n",
+                                        status.clone() as u8
+                                    )
+                                    .into_bytes(),
+                                    Level::Error,
+                                    wfr_info.mig_request_id,
+                                );
+                                log::error!("Failure during batch key exchange for wfr_info.mig_request_id = {}, status code: {:x}\n", wfr_info.mig_request_id, status.clone() as u8);
+                            }
+                            let _ = report_status(status as u8, wfr_info.mig_request_id, &data)
+                                .await
+                                .map_err(|e| {
+                                    log::error!(
+                                        "Failed to report status for StartBatchMigration mig_request_id {}: {:?}\n",
+                                        wfr_info.mig_request_id,
+                                        e
+                                    );
+                                });
+                            complete_request(wfr_info.mig_request_id);
+                        }
+                        WaitForRequestResponse::Shutdown(wfr_info) => {
+                            let canceled = drain_requests(&wfr_info).await;
+                            // The ids of the canceled requests are returned to the VMM
+                            for request_id in &canceled {
+                                data.extend_from_slice(&request_id.to_le_bytes());
+                            }
+                            entrylog(
+                                &format!(
+                                    "Shutdown completed, {} requests canceled\n",
+                                    canceled.len()
+                                )
+                                .into_bytes(),
+                                Level::Info,
+                                wfr_info.mig_request_id,
+                            );
+                            log::info!("Shutdown completed for wfr_info.mig_request_id = {}, {} requests canceled\n", wfr_info.mig_request_id, canceled.len());
+                            let _ = report_status(
+                                MigrationResult::Success as u8,
+                                wfr_info.mig_request_id,
+                                &data,
+                            )
+                            .await
+                            .map_err(|e| {
+                                log::error!(
+                                    "Failed to report status for Shutdown mig_request_id {}: {:?}\n",
+                                    wfr_info.mig_request_id,
+                                    e
+                                );
+                            });
+                            complete_request(wfr_info.mig_request_id);
+                        }
+                        WaitForRequestResponse::GetMetrics(wfr_info) => {
+                            data = metrics::histogram_data(wfr_info.reset != 0);
+                            entrylog(
+                                &format!("Reporting key exchange metrics\n").into_bytes(),
+                                Level::Trace,
+                                wfr_info.mig_request_id,
+                            );
+                            log::trace!(
+                                "Reporting key exchange metrics for wfr_info.mig_request_id = {}\n",
+                                wfr_info.mig_request_id
+                            );
+                            let _ = report_status(
+                                MigrationResult::Success as u8,
+                                wfr_info.mig_request_id,
+                                &data,
+                            )
+                            .await
+                            .map_err(|e| {
+                                log::error!(
+                                    "Failed to report status for GetMetrics mig_request_id {}: {:?}\n",
+                                    wfr_info.mig_request_id,
+                                    e
+                                );
+                            });
+                            complete_request(wfr_info.mig_request_id);
+                        }
+                        WaitForRequestResponse::SetLogLevel(wfr_info) => {
+                            data = log_control::set_log_level(&wfr_info).to_vec();
+                            let _ = report_status(
+                                MigrationResult::Success as u8,
+                                wfr_info.mig_request_id,
+                                &data,
+                            )
+                            .await
+                            .map_err(|e| {
+                                log::error!(
+                                    "Failed to report status for SetLogLevel mig_request_id {}: {:?}\n",
+                                    wfr_info.mig_request_id,
+                                    e
+                                );
+                            });
+                            complete_request(wfr_info.mig_request_id);
+                        }
+                        WaitForRequestResponse::GetTranscript(wfr_info) => {
+                            let status = match transcript::transcript_chunk(
+                                wfr_info.transcript_request_id,
+                                wfr_info.offset as usize,
+                            ) {
+                                Some(chunk) => {
+                                    data = chunk;
+                                    MigrationResult::Success
+                                }
+                                None => {
+                                    log::warn!(
+                                        "No transcript of mig_request_id {} at offset {}\n",
+                                        wfr_info.transcript_request_id,
+                                        wfr_info.offset
+                                    );
+                                    MigrationResult::InvalidParameter
+                                }
+                            };
+                            let _ = report_status(status as u8, wfr_info.mig_request_id, &data)
+                                .await
+                                .map_err(|e| {
+                                    log::error!(
+                                        "Failed to report status for GetTranscript mig_request_id {}: {:?}\n",
+                                        wfr_info.mig_request_id,
+                                        e
+                                    );
+                                });
+                            complete_request(wfr_info.mig_request_id);
                         }
                     }
                 }
@@ -2,13 +2,27 @@
 //
 // SPDX-License-Identifier: BSD-2-Clause-Patent
 
-#![cfg(feature = "vmcall-raw")]
+//! Crash reporting.
+//!
+//! A crash report is a page describing why MigTD is going down, see
+//! `crash_report` for its layout. It is reported through the Hyper-V guest
+//! crash MSRs when the VMM supports them, or with the GHCI
+//! `TDG.VP.VMCALL<ReportFatalError>` otherwise.
 
+use alloc::format;
 use alloc::vec::Vec;
+use core::fmt;
+use core::panic::Location;
 use core::sync::atomic::{AtomicBool, Ordering};
 use raw_cpuid::{CpuId, CpuIdReader, CpuIdReaderNative, CpuIdResult};
 use td_payload::mm::shared::alloc_shared_pages;
+#[cfg(not(feature = "AzCVMEmu"))]
+use tdx_tdcall::{td_vmcall, TdVmcallArgs};
 use x86_64::registers::model_specific::Msr;
+
+#[cfg(feature = "vmcall-raw")]
+use crate::migration::logging;
+use crate::migration::metrics::{self, MigrationPhase};
 const CPUID_HYPERV_VENDOR_MAX_FUNCTION: u32 = 0x40000000;
 const CPUID_HYPERV_FEATURE_ID: u32 = 0x40000003;
 const GUEST_CRASH_MSR_SUPPORT_BIT: u32 = 1 << 10;
@@ -21,6 +35,21 @@ const MSR_CRASH_CTL: u32 = 0x40000105;
 static GUEST_CRASH_MSR_SUPPORTED: AtomicBool = AtomicBool::new(false);
 const PAGE_SIZE: usize = 0x1_000;
 
+/// Error code of the crash reports of the panics
+pub const CRASH_CODE_PANIC: u64 = 0xFF;
+
+/// Signature of a crash report
+pub const CRASH_REPORT_SIGNATURE: [u8; 8] = *b"MIGTDCRS";
+pub const CRASH_REPORT_VERSION: u32 = 1;
+pub const CRASH_REPORT_HEADER_SIZE: usize = 40;
+// Longer messages are truncated to leave room for the LogArea entries
+const CRASH_MESSAGE_MAX_SIZE: usize = 1024;
+// Request id and phase of a crash outside of a key exchange
+const CRASH_NO_REQUEST: u64 = u64::MAX;
+const CRASH_NO_PHASE: u8 = 0xFF;
+// Only the first crash is reported, e.g. not a panic raised while reporting
+static CRASH_REPORTED: AtomicBool = AtomicBool::new(false);
+
 pub fn guest_crash_reg_supported() -> bool {
     GUEST_CRASH_MSR_SUPPORTED.load(Ordering::SeqCst)
 }
@@ -57,7 +86,7 @@ pub fn update_guest_crash_reg_report(errorcode: u64, crash_message: Vec<u8>) {
         unsafe {
             // Error Code
             msr_crash_p0.write(errorcode);
-            if crash_message.len() > 0 && crash_message.len() < PAGE_SIZE {
+            if crash_message.len() > 0 && crash_message.len() <= PAGE_SIZE {
                 let data_buffer = match alloc_shared_pages(1) {
                     Some(addr) => addr,
                     None => {
@@ -83,3 +112,193 @@ pub fn update_guest_crash_reg_report(errorcode: u64, crash_message: Vec<u8>) {
         }
     }
 }
+
+/// Builds a crash report, little-endian:
+///
+/// signature (8) | version (u32) | report size (u32) | error code (u64) |
+/// request id (u64) | phase (u8) | entry count (u8) | reserved (2) |
+/// message size (u32) | message | LogArea entries
+///
+/// The request id is `u64::MAX` and the phase `0xFF` when MigTD did not
+/// crash during a key exchange. The entries are the latest LogArea entries,
+/// oldest first, in their LogArea format. The oldest ones are dropped to fit
+/// the report in a page.
+pub fn crash_report(
+    error_code: u64,
+    message: &[u8],
+    context: Option<(u64, MigrationPhase)>,
+    entries: &[Vec<u8>],
+) -> Vec<u8> {
+    let message = &message[..message.len().min(CRASH_MESSAGE_MAX_SIZE)];
+    let mut space = PAGE_SIZE - CRASH_REPORT_HEADER_SIZE - message.len();
+    let kept = entries
+        .iter()
+        .rev()
+        .take(u8::MAX as usize)
+        .take_while(|entry| match space.checked_sub(entry.len()) {
+            Some(left) => {
+                space = left;
+                true
+            }
+            None => false,
+        })
+        .count();
+    let entries = &entries[entries.len() - kept..];
+
+    let (request_id, phase) = context
+        .map(|(request_id, phase)| (request_id, phase as u8))
+        .unwrap_or((CRASH_NO_REQUEST, CRASH_NO_PHASE));
+    let size = PAGE_SIZE - space;
+
+    let mut report = Vec::with_capacity(size);
+    report.extend_from_slice(&CRASH_REPORT_SIGNATURE);
+    report.extend_from_slice(&CRASH_REPORT_VERSION.to_le_bytes());
+    report.extend_from_slice(&(size as u32).to_le_bytes());
+    report.extend_from_slice(&error_code.to_le_bytes());
+    report.extend_from_slice(&request_id.to_le_bytes());
+    report.push(phase);
+    report.push(kept as u8);
+    report.extend_from_slice(&[0u8; 2]);
+    report.extend_from_slice(&(message.len() as u32).to_le_bytes());
+    report.extend_from_slice(message);
+    for entry in entries {
+        report.extend_from_slice(entry);
+    }
+    report
+}
+
+/// Builds the crash report of the running MigTD: the key exchange which
+/// entered a phase last, if any, and the latest LogArea entries.
+pub fn collect_crash_report(error_code: u64, message: &[u8]) -> Vec<u8> {
+    #[cfg(feature = "vmcall-raw")]
+    let entries = logging::recent_entries();
+    #[cfg(not(feature = "vmcall-raw"))]
+    let entries = Vec::new();
+    crash_report(error_code, message, metrics::current_phase(), &entries)
+}
+
+/// Reports a crash to the VMM, through the guest crash MSRs if they are
+/// supported or with `TDG.VP.VMCALL<ReportFatalError>` otherwise.
+///
+/// Only the first crash is reported.
+pub fn report_crash(error_code: u64, report: &[u8]) {
+    if CRASH_REPORTED.swap(true, Ordering::SeqCst) {
+        return;
+    }
+    if guest_crash_reg_supported() {
+        update_guest_crash_reg_report(error_code, report.to_vec());
+        return;
+    }
+    #[cfg(not(feature = "AzCVMEmu"))]
+    report_fatal_error(error_code, report);
+    #[cfg(feature = "AzCVMEmu")]
+    write_crash_report_file(report);
+}
+
+/// Reports a panic which is not raised through
+/// `panic_with_guest_crash_reg_report`, which reports its own.
+pub fn report_panic(message: &dyn fmt::Display, location: Option<&Location<'_>>) {
+    let message = match location {
+        Some(location) => format!(
+            "Panic: msg: {} at location: {} line: {}",
+            message,
+            location.file(),
+            location.line()
+        ),
+        None => format!("Panic: msg: {}", message),
+    };
+    report_crash(
+        CRASH_CODE_PANIC,
+        &collect_crash_report(CRASH_CODE_PANIC, message.as_bytes()),
+    );
+}
+
+// The VMM is expected to terminate the TD, the report is only valid if the
+// error code has the GPA bit set.
+#[cfg(not(feature = "AzCVMEmu"))]
+fn report_fatal_error(error_code: u64, report: &[u8]) {
+    const TDVMCALL_REPORT_FATAL_ERROR: u64 = 0x10003;
+    const ERROR_CODE_MASK: u64 = 0xFFFF_FFFF;
+    const ERROR_CODE_GPA_VALID: u64 = 1 << 63;
+
+    let mut args = TdVmcallArgs {
+        r11: TDVMCALL_REPORT_FATAL_ERROR,
+        r12: error_code & ERROR_CODE_MASK,
+        ..Default::default()
+    };
+    if !report.is_empty() && report.len() <= PAGE_SIZE {
+        if let Some(data_buffer) = unsafe { alloc_shared_pages(1) } {
+            let data_buffer =
+                unsafe { core::slice::from_raw_parts_mut(data_buffer as *mut u8, PAGE_SIZE) };
+            data_buffer.fill(0);
+            data_buffer[..report.len()].copy_from_slice(report);
+            args.r12 |= ERROR_CODE_GPA_VALID;
+            args.r13 = data_buffer.as_ptr() as u64;
+        }
+    }
+    let _ = td_vmcall(&mut args);
+}
+
+#[cfg(feature = "AzCVMEmu")]
+static CRASH_REPORT_FILE: std::sync::OnceLock<std::string::String> = std::sync::OnceLock::new();
+
+/// Sets the file the crash report is written to in AzCVMEmu mode, in place
+/// of the crash MSRs.
+#[cfg(feature = "AzCVMEmu")]
+pub fn set_crash_report_file(path: std::string::String) {
+    let _ = CRASH_REPORT_FILE.set(path);
+}
+
+#[cfg(feature = "AzCVMEmu")]
+fn write_crash_report_file(report: &[u8]) {
+    if let Some(path) = CRASH_REPORT_FILE.get() {
+        if let Err(e) = std::fs::write(path, report) {
+            log::error!("Failed to write crash report to {}: {}\n", path, e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use alloc::vec;
+
+    #[test]
+    fn test_crash_report() {
+        let entries = vec![vec![1u8; 100], vec![2u8; 200]];
+        let report = crash_report(
+            0xFF,
+            b"Panic: msg: test",
+            Some((42, MigrationPhase::QuoteVerification)),
+            &entries,
+        );
+
+        assert_eq!(&report[..8], &CRASH_REPORT_SIGNATURE);
+        assert_eq!(&report[8..12], &CRASH_REPORT_VERSION.to_le_bytes());
+        assert_eq!(&report[12..16], &(report.len() as u32).to_le_bytes());
+        assert_eq!(&report[16..24], &0xFFu64.to_le_bytes());
+        assert_eq!(&report[24..32], &42u64.to_le_bytes());
+        assert_eq!(report[32], MigrationPhase::QuoteVerification as u8);
+        assert_eq!(report[33], 2);
+        assert_eq!(&report[36..40], &16u32.to_le_bytes());
+        assert_eq!(&report[40..56], b"Panic: msg: test");
+        assert_eq!(report[56], 1);
+        assert_eq!(report.len(), 56 + 300);
+    }
+
+    #[test]
+    fn test_crash_report_truncation() {
+        // Only the latest entries fitting in the page are kept
+        let entries = vec![vec![1u8; 2000], vec![2u8; 2000], vec![3u8; 500]];
+        let message = vec![b'a'; 2 * CRASH_MESSAGE_MAX_SIZE];
+        let report = crash_report(1, &message, None, &entries);
+
+        assert!(report.len() <= PAGE_SIZE);
+        assert_eq!(&report[24..32], &CRASH_NO_REQUEST.to_le_bytes());
+        assert_eq!(report[32], CRASH_NO_PHASE);
+        assert_eq!(report[33], 2);
+        let entries_start = CRASH_REPORT_HEADER_SIZE + CRASH_MESSAGE_MAX_SIZE;
+        assert_eq!(report[entries_start], 2);
+        assert_eq!(report[report.len() - 1], 3);
+    }
+}
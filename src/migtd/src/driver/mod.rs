@@ -2,7 +2,6 @@
 //
 // SPDX-License-Identifier: BSD-2-Clause-Patent
 
-#[cfg(feature = "vmcall-raw")]
 pub mod crash;
 #[cfg(feature = "virtio-serial")]
 pub mod serial;
@@ -28,10 +28,21 @@ fn timer_callback() {
     schedule_timeout(INTERVAL);
 }
 
-fn now() -> u64 {
+/// Returns the number of milliseconds elapsed since `init_sys_tick`
+#[cfg(not(feature = "AzCVMEmu"))]
+pub fn now() -> u64 {
     SYS_TICK.load(Ordering::SeqCst)
 }
 
+/// Returns the number of milliseconds elapsed since the first call (AzCVMEmu
+/// version, the timer interrupt is not emulated).
+#[cfg(feature = "AzCVMEmu")]
+pub fn now() -> u64 {
+    use std::{sync::OnceLock, time::Instant};
+    static START: OnceLock<Instant> = OnceLock::new();
+    START.get_or_init(Instant::now).elapsed().as_millis() as u64
+}
+
 /// Runs a given future with a timeout.
 #[cfg(not(feature = "AzCVMEmu"))]
 pub async fn with_timeout<F: Future>(timeout: Duration, fut: F) -> Result<F::Output, TimeoutError> {
@@ -3,9 +3,7 @@
 // SPDX-License-Identifier: BSD-2-Clause-Patent
 
 use crate::alloc::string::ToString;
-#[cfg(feature = "vmcall-raw")]
-use crate::driver::crash::update_guest_crash_reg_report;
-#[cfg(feature = "vmcall-raw")]
+use crate::driver::crash::{collect_crash_report, report_crash};
 use alloc::format;
 
 #[cfg(feature = "vmcall-raw")]
@@ -14,10 +12,12 @@ pub fn vmcall_raw_device_init() {
     vmcall_raw::transport::vmcall_raw_transport_init().expect("Failed to initialize vmcall-raw");
 }
 
+/// Reports a crash to the VMM and panics.
+///
+/// The crash report carries the message, the key exchange which entered a
+/// phase last and the latest LogArea entries, see `crash::crash_report`.
 #[track_caller]
-pub fn panic_with_guest_crash_reg_report(errorcode: u64, msg: &[u8]) {
-    #[cfg(not(feature = "vmcall-raw"))]
-    let _ = errorcode;
+pub fn panic_with_guest_crash_reg_report(errorcode: u64, msg: &[u8]) -> ! {
     let location = core::panic::Location::caller();
     let file = location.file();
     let line = location.line();
@@ -27,14 +27,11 @@ pub fn panic_with_guest_crash_reg_report(errorcode: u64, msg: &[u8]) {
         " non-UTF8 message".to_string()
     };
 
-    #[cfg(feature = "vmcall-raw")]
-    {
-        let crash_message = format!(
-            "Panic: msg: {} at location: {} line: {}",
-            panic_message, file, line
-        )
-        .into_bytes();
-        update_guest_crash_reg_report(errorcode, crash_message);
-    }
+    let crash_message = format!(
+        "Panic: msg: {} at location: {} line: {}",
+        panic_message, file, line
+    )
+    .into_bytes();
+    report_crash(errorcode, &collect_crash_report(errorcode, &crash_message));
     panic!("{} (at {}:{})", panic_message, file, line);
 }
@@ -22,25 +22,22 @@ use td_shim_interface::acpi::Ccel;
 use tdx_tdcall::tdx;
 use zerocopy::{AsBytes, FromBytes};
 
-pub const EV_EVENT_TAG: u32 = 0x00000006;
-pub const TEST_DISABLE_RA_AND_ACCEPT_ALL_EVENT: &[u8] = b"test_disable_ra_and_accept_all";
-
-// Event IDs that will be used to tag the event log
-pub const TAGGED_EVENT_ID_POLICY: u32 = 0x1;
-pub const TAGGED_EVENT_ID_ROOT_CA: u32 = 0x2;
-pub const TAGGED_EVENT_ID_POLICY_ISSUER_CHAIN: u32 = 0x3;
-pub const TAGGED_EVENT_ID_TEST: u32 = 0x32;
+pub use policy::{
+    policy_id_bytes, MigrationReceiptEvent, EV_EVENT_TAG, MR_INDEX_POLICY,
+    MR_INDEX_POLICY_ISSUER_CHAIN, MR_INDEX_ROOT_CA, MR_INDEX_RUNTIME, MR_INDEX_TEST_FEATURE,
+    TAGGED_EVENT_ID_MIGRATION_RECEIPT, TAGGED_EVENT_ID_POLICY, TAGGED_EVENT_ID_POLICY_ISSUER_CHAIN,
+    TAGGED_EVENT_ID_ROOT_CA, TAGGED_EVENT_ID_RUNTIME, TAGGED_EVENT_ID_TEST,
+};
 
-// MR index the event will be measured into
-pub const MR_INDEX_POLICY_ISSUER_CHAIN: u32 = 0x2;
-pub const MR_INDEX_POLICY: u32 = 0x3;
-pub const MR_INDEX_ROOT_CA: u32 = 0x3;
-pub const MR_INDEX_TEST_FEATURE: u32 = 0x3;
+pub const TEST_DISABLE_RA_AND_ACCEPT_ALL_EVENT: &[u8] = b"test_disable_ra_and_accept_all";
 
 const MAX_RTMR_INDEX: usize = 3;
 
 static CCEL: Once<Ccel> = Once::new();
 
+#[cfg(feature = "runtime_measurement")]
+static RUNTIME_EVENT_LOCK: spin::Mutex<()> = spin::Mutex::new(());
+
 pub struct TaggedEvent {
     event: Vec<u8>,
 }
@@ -117,6 +114,12 @@ pub fn write_tagged_event_log(
     let mut log_size = event_log_size(event_log).ok_or_else(|| anyhow!("Parsing event log"))?;
     let event = TaggedEvent::new(tagged_event_id, tagged_event_data);
 
+    // Never extend the RTMR with an event that can not be logged, the event log
+    // could not be replayed anymore
+    if event_log.len() < log_size + size_of::<CcEventHeader>() + event.as_bytes().len() {
+        return Err(anyhow!("Event log out of memory"));
+    }
+
     let digest = calculate_digest(hash_data)?;
     extend_rtmr(&digest, mr_index)?;
 
@@ -133,10 +136,6 @@ pub fn write_tagged_event_log(
         event_size: event.as_bytes().len() as u32,
     };
 
-    if event_log.len() < log_size + size_of::<CcEventHeader>() + event.as_bytes().len() {
-        return Err(anyhow!("Event log out of memory"));
-    }
-
     event_log[log_size..log_size + size_of::<CcEventHeader>()]
         .copy_from_slice(event_header.as_bytes());
     log_size += size_of::<CcEventHeader>();
@@ -146,6 +145,34 @@ pub fn write_tagged_event_log(
     Ok(log_size + event.as_bytes().len())
 }
 
+/// Measures an event received after boot into RTMR3 and appends it to the event
+/// log. The tag ID must be in `TAGGED_EVENT_ID_RUNTIME`.
+#[cfg(feature = "runtime_measurement")]
+pub fn write_runtime_event_log(tagged_event_id: u32, event_data: &[u8]) -> Result<()> {
+    if !TAGGED_EVENT_ID_RUNTIME.contains(&tagged_event_id) {
+        return Err(anyhow!("Invalid runtime event ID 0x{:x}", tagged_event_id));
+    }
+
+    // Concurrent requests must not interleave the RTMR extension and the logging
+    let _guard = RUNTIME_EVENT_LOCK.lock();
+    let event_log = get_event_log_mut().ok_or_else(|| anyhow!("Event log not found"))?;
+    write_tagged_event_log(
+        event_log,
+        MR_INDEX_RUNTIME,
+        event_data,
+        tagged_event_id,
+        event_data,
+    )?;
+
+    Ok(())
+}
+
+/// Measures the outcome of the migration of a target TD.
+#[cfg(feature = "runtime_measurement")]
+pub fn measure_migration_receipt(receipt: &MigrationReceiptEvent) -> Result<()> {
+    write_runtime_event_log(TAGGED_EVENT_ID_MIGRATION_RECEIPT, &receipt.to_bytes())
+}
+
 pub fn calculate_digest(hash_data: &[u8]) -> Result<[u8; SHA384_DIGEST_SIZE]> {
     let digest = digest_sha384(hash_data).map_err(|_| anyhow!("Calculate digest"))?;
 
@@ -175,8 +202,8 @@ pub(crate) fn parse_events(event_log: &[u8]) -> Option<BTreeMap<EventName, CcEve
     for (event_header, event_data) in reader.cc_events {
         match event_header.event_type {
             EV_EFI_PLATFORM_FIRMWARE_BLOB2 => {
-                let desc_size = event_data[0] as usize;
-                if &event_data[1..1 + desc_size] == PLATFORM_FIRMWARE_BLOB2_PAYLOAD {
+                let desc_size = *event_data.first()? as usize;
+                if event_data.get(1..1 + desc_size)? == PLATFORM_FIRMWARE_BLOB2_PAYLOAD {
                     map.insert(EventName::MigTdCore, CcEvent::new(event_header, None));
                 }
             }
@@ -199,7 +226,7 @@ pub(crate) fn parse_events(event_log: &[u8]) -> Option<BTreeMap<EventName, CcEve
                 }
             }
             EV_EVENT_TAG => {
-                let tag_id = u32::from_le_bytes(event_data[..4].try_into().ok()?);
+                let tag_id = u32::from_le_bytes(event_data.get(..4)?.try_into().ok()?);
                 if tag_id == TAGGED_EVENT_ID_POLICY {
                     map.insert(EventName::MigTdPolicy, CcEvent::new(event_header, None));
                 } else if tag_id == TAGGED_EVENT_ID_ROOT_CA {
@@ -219,6 +246,7 @@ pub(crate) fn parse_events(event_log: &[u8]) -> Option<BTreeMap<EventName, CcEve
 }
 
 pub fn verify_event_log(event_log: &[u8], report: &[u8]) -> Result<()> {
+    policy::validate_event_log(event_log).map_err(|e| anyhow!("Invalid event log: {:?}", e))?;
     replay_event_log_with_report(event_log, report)
 }
 
@@ -2,25 +2,356 @@
 //
 // SPDX-License-Identifier: BSD-2-Clause-Patent
 
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+use core::ops::Range;
+use crypto::{hash::digest_sha384, tls::KxGroup};
+use log::LevelFilter;
+use spin::Once;
+use zeroize::Zeroizing;
+
 #[cfg(not(feature = "policy_v2"))]
 pub use v1::*;
 
+/// Maximum runtime log level if the policy does not define `maxLogLevel`
+pub const DEFAULT_MAX_LOG_LEVEL: LevelFilter = LevelFilter::Info;
+
+/// Most verbose log level MigTD can be switched to at runtime, defined by the
+/// `maxLogLevel` of the policy. Logging is turned off if the policy cannot be
+/// read or if its level is invalid.
+///
+/// The policy is parsed by the first successful call only.
+pub fn max_log_level() -> LevelFilter {
+    static MAX_LOG_LEVEL: Once<LevelFilter> = Once::new();
+    match MAX_LOG_LEVEL.try_call_once(policy_log_level) {
+        Ok(level) => *level,
+        Err(e) => {
+            log::error!("max_log_level: failed to read the policy {:?}\n", e);
+            LevelFilter::Off
+        }
+    }
+}
+
+fn policy_log_level() -> Result<LevelFilter, policy::PolicyError> {
+    match policy_max_log_level()? {
+        Some(level) => Ok(level.parse().unwrap_or_else(|_| {
+            log::error!("max_log_level: invalid log level {}\n", level);
+            LevelFilter::Off
+        })),
+        None => Ok(DEFAULT_MAX_LOG_LEVEL),
+    }
+}
+
+/// Key exchange group of the RA-TLS handshakes, defined by the `keyExchange`
+/// of the policy. Both MigTDs only offer this group, so the handshake fails if
+/// their policies don't agree on it.
+///
+/// The policy does not change once MigTD is running, it is parsed by the first
+/// successful call only.
+pub fn kx_group() -> Result<KxGroup, policy::PolicyError> {
+    static KX_GROUP: Once<KxGroup> = Once::new();
+    KX_GROUP.try_call_once(policy_kx_group).copied()
+}
+
+fn policy_kx_group() -> Result<KxGroup, policy::PolicyError> {
+    let kx_group = match policy_key_exchange()? {
+        Some(name) => KxGroup::from_name(&name).ok_or_else(|| {
+            log::error!("kx_group: unknown key exchange {}\n", name);
+            policy::PolicyError::InvalidPolicy
+        })?,
+        None => KxGroup::default(),
+    };
+    if !kx_group.is_supported() {
+        log::error!("kx_group: {:?} is not supported by this build\n", kx_group);
+        return Err(policy::PolicyError::InvalidPolicy);
+    }
+    Ok(kx_group)
+}
+
+/// Lifetime of a verified peer cache entry in milliseconds
+pub const PEER_CACHE_TTL_MS: u64 = 10 * 60 * 1000;
+/// Maximum number of verified peers kept in the cache
+pub const PEER_CACHE_MAX_ENTRIES: usize = 16;
+
+// TD quote v4 layout: 48 bytes header, 584 bytes TD report body, 4 bytes signature
+// data length, then the ECDSA-P256 quote signature and the attestation key.
+const QUOTE_VERSION: u16 = 4;
+const QUOTE_ATTEST_KEY_TYPE_ECDSA_P256: u16 = 2;
+const R_QUOTE_SIGNED_DATA: Range<usize> = 0..632;
+const R_QUOTE_REPORT_DATA: Range<usize> = 568..632;
+const R_QUOTE_SIGNATURE: Range<usize> = 636..700;
+const R_QUOTE_ATTEST_KEY: Range<usize> = 700..764;
+// Offset of the report data in the verified report
+const R_REPORT_REPORT_DATA: Range<usize> = 520..584;
+
+/// Get the part of a quote that does not change across handshakes with the same
+/// TD, i.e. everything except the report data and the quote signature.
+///
+/// Only v4 quotes signed with an ECDSA-P256 attestation key can be cached. They
+/// are verified by QVL before being cached, a quote then only hits the cache if
+/// all the other bytes, including the attestation key, the QE report and the PCK
+/// certificate chain, are the ones QVL verified.
+fn quote_cache_material(quote: &[u8]) -> Result<Zeroizing<Vec<u8>>, policy::PolicyError> {
+    if quote.len() < R_QUOTE_ATTEST_KEY.end
+        || u16::from_le_bytes([quote[0], quote[1]]) != QUOTE_VERSION
+        || u16::from_le_bytes([quote[2], quote[3]]) != QUOTE_ATTEST_KEY_TYPE_ECDSA_P256
+    {
+        return Err(policy::PolicyError::InvalidQuote);
+    }
+    let mut material = Zeroizing::new(quote.to_vec());
+    material[R_QUOTE_REPORT_DATA].fill(0);
+    material[R_QUOTE_SIGNATURE].fill(0);
+    Ok(material)
+}
+
+/// Digest of the part of the quote that does not change across handshakes
+fn quote_cache_digest(quote: &[u8]) -> Result<Vec<u8>, policy::PolicyError> {
+    digest_sha384(&quote_cache_material(quote)?).map_err(|_| policy::PolicyError::HashCalculation)
+}
+
+/// Reuse the verified report of a quote which only differs from `quote` by its
+/// report data and signature.
+///
+/// The signature over the fresh report data is checked with the attestation key
+/// QVL verified, then the report data of the verified report is replaced with
+/// the one of `quote`. Its binding to the TLS key and the session is checked by
+/// the caller as for a report verified by QVL.
+fn reuse_verified_report(
+    mut verified_report: Vec<u8>,
+    quote: &[u8],
+) -> Result<Vec<u8>, policy::PolicyError> {
+    let attest_key = quote
+        .get(R_QUOTE_ATTEST_KEY)
+        .ok_or(policy::PolicyError::InvalidQuote)?;
+    let mut public_key = Vec::with_capacity(1 + attest_key.len());
+    // Uncompressed point
+    public_key.push(0x04);
+    public_key.extend_from_slice(attest_key);
+    crypto::ecdsa::ecdsa_verify_with_algorithm(
+        &public_key,
+        &quote[R_QUOTE_SIGNED_DATA],
+        &quote[R_QUOTE_SIGNATURE],
+        &ring::signature::ECDSA_P256_SHA256_FIXED,
+    )
+    .map_err(|_| {
+        log::error!("Quote signature verification failed\n");
+        policy::PolicyError::QuoteVerification
+    })?;
+
+    refresh_report_data(&mut verified_report, quote)?;
+    Ok(verified_report)
+}
+
+/// Replace the report data of a verified report with the one of `quote`
+fn refresh_report_data(
+    verified_report: &mut [u8],
+    quote: &[u8],
+) -> Result<(), policy::PolicyError> {
+    let report_data = quote
+        .get(R_QUOTE_REPORT_DATA)
+        .ok_or(policy::PolicyError::InvalidQuote)?;
+    verified_report
+        .get_mut(R_REPORT_REPORT_DATA)
+        .ok_or(policy::PolicyError::InvalidParameter)?
+        .copy_from_slice(report_data);
+    Ok(())
+}
+
+#[derive(Clone)]
+struct VerifiedPeer<T> {
+    value: T,
+    inserted_at: u64,
+}
+
+/// Bounded cache of the results of the peer quote verifications, so that QVL and
+/// the checks following it run once per peer rather than once per handshake.
+///
+/// The keys are digests covering at least the quote without its report data and
+/// signature. Entries expire after the TTL, and are dropped when the collaterals
+/// used for quote verification are installed.
+struct VerifiedPeerCache<T> {
+    ttl: u64,
+    max_entries: usize,
+    entries: BTreeMap<Vec<u8>, VerifiedPeer<T>>,
+}
+
+impl<T: Clone> VerifiedPeerCache<T> {
+    const fn new(ttl: u64, max_entries: usize) -> Self {
+        Self {
+            ttl,
+            max_entries,
+            entries: BTreeMap::new(),
+        }
+    }
+
+    fn get(&mut self, key: &[u8], now: u64) -> Option<T> {
+        let entry = self.entries.get(key)?;
+        if now.saturating_sub(entry.inserted_at) >= self.ttl {
+            self.entries.remove(key);
+            return None;
+        }
+        Some(entry.value.clone())
+    }
+
+    fn insert(&mut self, key: Vec<u8>, value: T, now: u64) {
+        if self.max_entries == 0 {
+            return;
+        }
+
+        // Drop the expired entries first, then the oldest ones if still full
+        let ttl = self.ttl;
+        self.entries
+            .retain(|_, e| now.saturating_sub(e.inserted_at) < ttl);
+        while self.entries.len() >= self.max_entries && !self.entries.contains_key(&key) {
+            let oldest = self
+                .entries
+                .iter()
+                .min_by_key(|(_, e)| e.inserted_at)
+                .map(|(k, _)| k.clone());
+            match oldest {
+                Some(k) => self.entries.remove(&k),
+                None => break,
+            };
+        }
+
+        self.entries.insert(
+            key,
+            VerifiedPeer {
+                value,
+                inserted_at: now,
+            },
+        );
+    }
+
+    fn clear(&mut self) {
+        self.entries.clear();
+    }
+}
+
 #[cfg(not(feature = "policy_v2"))]
 mod v1 {
-    use policy::verify_policy;
+    use alloc::string::String;
+    use alloc::vec::Vec;
+    use lazy_static::lazy_static;
     pub use policy::PolicyError;
+    use policy::{verify_policy, MigPolicy};
+    use spin::Mutex;
+    use zeroize::Zeroizing;
 
+    use super::{
+        quote_cache_digest, quote_cache_material, refresh_report_data, reuse_verified_report,
+        VerifiedPeerCache, PEER_CACHE_MAX_ENTRIES, PEER_CACHE_TTL_MS,
+    };
     use crate::{
         config::get_policy,
+        driver::ticks,
         event_log::{get_event_log, parse_events, verify_event_log},
+        migration::metrics::{self, MigrationPhase},
     };
 
+    /// Verified report of the local quote, with the quote material it was
+    /// verified from. Both are wiped when the report is replaced.
+    struct LocalReport {
+        quote_material: Zeroizing<Vec<u8>>,
+        verified_report: Zeroizing<Vec<u8>>,
+    }
+
+    lazy_static! {
+        // Replaced, rather than set once, when the local quote changes
+        static ref LOCAL_REPORT: Mutex<Option<LocalReport>> = Mutex::new(None);
+        // Verified reports of the peer quotes, keyed by the digest of the quote
+        // without its report data and signature
+        static ref VERIFIED_PEERS: Mutex<VerifiedPeerCache<Vec<u8>>> = Mutex::new(
+            VerifiedPeerCache::new(PEER_CACHE_TTL_MS, PEER_CACHE_MAX_ENTRIES)
+        );
+    }
+
+    /// Verify a quote of the local TD, so that the handshakes reuse its verified
+    /// report. Called at startup right after the root CA is installed, the reports
+    /// verified before are dropped.
+    ///
+    /// The root CA is the only collateral MigTD gives to the quote verification and
+    /// it cannot change while MigTD is running. A TCB recovery which only updates
+    /// the collaterals takes effect when MigTD restarts.
+    pub fn init_local_report() -> Result<(), PolicyError> {
+        LOCAL_REPORT.lock().take();
+        VERIFIED_PEERS.lock().clear();
+        let tdx_report = tdx_tdcall::tdreport::tdcall_report(&[0u8; 64])
+            .map_err(|_| PolicyError::GetTdxReport)?;
+        let quote = attestation::get_quote(tdx_report.as_bytes())
+            .map_err(|_| PolicyError::QuoteGeneration)?;
+        local_verified_report(&quote).map(|_| ())
+    }
+
+    /// Get the verified report of the local TD for a handshake sending `quote_local`.
+    ///
+    /// The cached report is reused as long as `quote_local` only differs from the
+    /// quote it was verified from by its report data and signature, its report
+    /// data is then replaced with the one of `quote_local`. Any other change of
+    /// the local quote, such as TCB SVNs raised by a TDX module update, verifies
+    /// `quote_local` again.
+    pub fn local_verified_report(quote_local: &[u8]) -> Result<Zeroizing<Vec<u8>>, PolicyError> {
+        let quote_material = match quote_cache_material(quote_local) {
+            Ok(quote_material) => quote_material,
+            // Quotes which cannot be cached are verified every time
+            Err(_) => {
+                return attestation::verify_quote(quote_local)
+                    .map(Zeroizing::new)
+                    .map_err(|_| PolicyError::QuoteVerification)
+            }
+        };
+        let mut local_report = LOCAL_REPORT.lock();
+        if let Some(cached) = local_report.as_ref() {
+            if cached.quote_material == quote_material {
+                let mut verified_report = cached.verified_report.clone();
+                refresh_report_data(&mut verified_report, quote_local)?;
+                return Ok(verified_report);
+            }
+            log::info!("Local quote changed, verifying it again\n");
+        }
+
+        let verified_report = attestation::verify_quote(quote_local)
+            .map(Zeroizing::new)
+            .map_err(|_| PolicyError::QuoteVerification)?;
+        *local_report = Some(LocalReport {
+            quote_material,
+            verified_report: verified_report.clone(),
+        });
+        Ok(verified_report)
+    }
+
+    /// Verify the quote of the peer, returning its verified report.
+    ///
+    /// A quote of a peer verified by QVL within `PEER_CACHE_TTL_MS` only has its
+    /// signature checked, see `reuse_verified_report`.
+    pub fn verify_peer_quote(quote_peer: &[u8]) -> Result<Vec<u8>, PolicyError> {
+        // Quotes which cannot be cached are verified by QVL every time
+        let cache_key = quote_cache_digest(quote_peer).ok();
+        let cached = cache_key
+            .as_ref()
+            .and_then(|key| VERIFIED_PEERS.lock().get(key, ticks::now()));
+        if let Some(verified_report) = cached {
+            return reuse_verified_report(verified_report, quote_peer);
+        }
+
+        let verified_report =
+            attestation::verify_quote(quote_peer).map_err(|_| PolicyError::QuoteVerification)?;
+        if let Some(key) = cache_key {
+            VERIFIED_PEERS
+                .lock()
+                .insert(key, verified_report.clone(), ticks::now());
+        }
+        Ok(verified_report)
+    }
+
     pub fn authenticate_policy(
+        mig_request_id: u64,
         is_src: bool,
         verified_report_local: &[u8],
         verified_report_peer: &[u8],
         event_log_peer: &[u8],
     ) -> Result<(), PolicyError> {
+        metrics::enter_phase(mig_request_id, MigrationPhase::PolicyEvaluation);
         let event_log = if let Some(event_log) = get_event_log() {
             event_log
         } else {
@@ -47,6 +378,16 @@ mod v1 {
             &event_log_peer,
         )
     }
+
+    pub(super) fn policy_max_log_level() -> Result<Option<String>, PolicyError> {
+        let policy = get_policy().ok_or(PolicyError::InvalidParameter)?;
+        Ok(MigPolicy::from_bytes(policy)?.max_log_level)
+    }
+
+    pub(super) fn policy_key_exchange() -> Result<Option<String>, PolicyError> {
+        let policy = get_policy().ok_or(PolicyError::InvalidParameter)?;
+        Ok(MigPolicy::from_bytes(policy)?.key_exchange)
+    }
 }
 
 #[cfg(feature = "policy_v2")]
@@ -58,17 +399,71 @@ mod v2 {
     use alloc::{string::String, string::ToString, vec::Vec};
     use attestation::verify_quote_with_collaterals;
     use chrono::DateTime;
-    use crypto::{crl::get_crl_number, pem_cert_to_der};
+    use crypto::{crl::get_crl_number, hash::digest_sha384, pem_cert_to_der};
     use lazy_static::lazy_static;
     use policy::*;
-    use spin::Once;
+    use spin::{Mutex, Once};
 
+    use super::{
+        quote_cache_digest, reuse_verified_report, VerifiedPeerCache, PEER_CACHE_MAX_ENTRIES,
+        PEER_CACHE_TTL_MS,
+    };
     use crate::config::get_policy_issuer_chain;
+    use crate::driver::ticks;
     use crate::event_log::{parse_events, verify_event_log};
+    use crate::migration::metrics::{self, MigrationPhase};
 
     lazy_static! {
         pub static ref LOCAL_TCB_INFO: Once<PolicyEvaluationInfo> = Once::new();
-        pub static ref VERIFIED_POLICY: Once<VerifiedPolicy<'static>> = Once::new();
+        pub static ref VERIFIED_POLICY: Once<VerifiedPolicy> = Once::new();
+        // Hash of the installed local policy, part of every peer cache key
+        static ref LOCAL_POLICY_HASH: Mutex<Option<Vec<u8>>> = Mutex::new(None);
+        static ref VERIFIED_PEERS: Mutex<VerifiedPeerCache<CachedPeer>> = Mutex::new(
+            VerifiedPeerCache::new(PEER_CACHE_TTL_MS, PEER_CACHE_MAX_ENTRIES)
+        );
+    }
+
+    /// Result of the authentication of a peer: the supplemental data QVL gives to
+    /// its quote and the evaluation data of its TCB.
+    #[derive(Clone)]
+    struct CachedPeer {
+        evaluation_data: PolicyEvaluationInfo,
+        suppl_data: Vec<u8>,
+    }
+
+    /// Drop all the cached peer authentications and bind the next ones to
+    /// `policy_json`. The collaterals used for quote verification are part of the
+    /// policy, this is called when `init_policy` installs them.
+    fn invalidate_peer_cache(policy_json: &[u8]) -> Result<(), PolicyError> {
+        let policy_hash = digest_sha384(policy_json).map_err(|_| PolicyError::HashCalculation)?;
+        *LOCAL_POLICY_HASH.lock() = Some(policy_hash);
+        VERIFIED_PEERS.lock().clear();
+        Ok(())
+    }
+
+    /// Key of the cached authentication of a peer: the SHA384 digest over the local
+    /// policy hash, the direction, the peer's quote without its report data and
+    /// signature, and the peer's event log and policy.
+    fn peer_cache_key(
+        is_src: bool,
+        quote: &[u8],
+        event_log: &[u8],
+        mig_policy: &[u8],
+    ) -> Result<Vec<u8>, PolicyError> {
+        let hash = |data: &[u8]| digest_sha384(data).map_err(|_| PolicyError::HashCalculation);
+
+        let mut material = Vec::new();
+        material.extend_from_slice(
+            LOCAL_POLICY_HASH
+                .lock()
+                .as_deref()
+                .ok_or(PolicyError::InvalidParameter)?,
+        );
+        material.push(is_src as u8);
+        material.extend_from_slice(&quote_cache_digest(quote)?);
+        material.extend_from_slice(&hash(event_log)?);
+        material.extend_from_slice(&hash(mig_policy)?);
+        hash(&material)
     }
 
     /// Initialize the global verified policy once
@@ -86,7 +481,10 @@ mod v2 {
             .map_err(|_| PolicyError::InvalidCollateral)?;
 
         VERIFIED_POLICY
-            .try_call_once(|| Ok(verified_policy))
+            .try_call_once(|| {
+                invalidate_peer_cache(policy_json)?;
+                Ok(verified_policy)
+            })
             .map(|p| p.get_version().to_string())
     }
 
@@ -106,6 +504,13 @@ mod v2 {
             .map(|_| ())
     }
 
+    /// Initialize the global local TCB info once with `info`, the TCB info of
+    /// the MigTD whose transcript is replayed
+    #[cfg(feature = "AzCVMEmu")]
+    pub fn init_tcb_info_from(info: PolicyEvaluationInfo) {
+        LOCAL_TCB_INFO.call_once(|| info);
+    }
+
     pub fn get_local_tcb_evaluation_info() -> Result<PolicyEvaluationInfo, PolicyError> {
         LOCAL_TCB_INFO
             .get()
@@ -115,100 +520,191 @@ mod v2 {
 
     /// Get reference to the global verified policy
     /// Returns None if the policy hasn't been initialized yet
-    pub fn get_verified_policy() -> Option<&'static VerifiedPolicy<'static>> {
+    pub fn get_verified_policy() -> Option<&'static VerifiedPolicy> {
         VERIFIED_POLICY.get()
     }
 
+    pub(super) fn policy_max_log_level() -> Result<Option<String>, PolicyError> {
+        let policy = get_verified_policy().ok_or(PolicyError::InvalidParameter)?;
+        Ok(policy
+            .policy_data
+            .get_max_log_level()
+            .map(|level| level.to_string()))
+    }
+
+    pub(super) fn policy_key_exchange() -> Result<Option<String>, PolicyError> {
+        let policy = get_verified_policy().ok_or(PolicyError::InvalidParameter)?;
+        Ok(policy
+            .policy_data
+            .get_key_exchange()
+            .map(|name| name.to_string()))
+    }
+
+    /// Authenticates the peer of the migration request `mig_request_id`,
+    /// returning the evaluation data of its TCB and the supplemental data of
+    /// its quote.
+    ///
+    /// The authentication of a peer is cached for `PEER_CACHE_TTL_MS`. A later
+    /// handshake with the same peer only checks the signature of its quote over
+    /// the fresh report data, see `reuse_verified_report`, and evaluates its TCB
+    /// against the local one again. QVL, the event log replay and the policy
+    /// signature check are skipped.
     pub fn authenticate_remote(
+        mig_request_id: u64,
         is_src: bool,
         quote_peer: &[u8],
         policy_peer: &[u8],
         event_log_peer: &[u8],
-    ) -> Result<Vec<u8>, PolicyError> {
+    ) -> Result<(PolicyEvaluationInfo, Vec<u8>), PolicyError> {
+        // Quotes which cannot be cached are authenticated in full every time
+        let cache_key = peer_cache_key(is_src, quote_peer, event_log_peer, policy_peer).ok();
+        let cached = cache_key
+            .as_ref()
+            .and_then(|key| VERIFIED_PEERS.lock().get(key, ticks::now()));
+        if let Some(peer) = cached {
+            let suppl_data = reuse_verified_report(peer.suppl_data, quote_peer)
+                .inspect_err(|e| log_peer_rejected(mig_request_id, quote_peer, e))?;
+            metrics::enter_phase(mig_request_id, MigrationPhase::PolicyEvaluation);
+            evaluate_remote_tcb(is_src, &peer.evaluation_data)
+                .inspect_err(|e| log_peer_rejected(mig_request_id, quote_peer, e))?;
+            return Ok((peer.evaluation_data, suppl_data));
+        }
+
+        let policy = get_verified_policy().ok_or(PolicyError::InvalidParameter)?;
+        let (fmspc, suppl_data) = verify_quote(quote_peer, policy.get_collaterals())
+            .map_err(|_| PolicyError::QuoteVerification)
+            .inspect_err(|e| log_peer_rejected(mig_request_id, quote_peer, e))?;
+        metrics::enter_phase(mig_request_id, MigrationPhase::PolicyEvaluation);
+
         let policy_issuer_chain = get_policy_issuer_chain().ok_or(PolicyError::InvalidParameter)?;
-        if is_src {
+        let evaluation_data = if is_src {
             authenticate_migration_dest(
-                quote_peer,
+                fmspc,
+                &suppl_data,
                 event_log_peer,
                 policy_peer,
                 policy_issuer_chain,
             )
         } else {
             authenticate_migration_source(
-                quote_peer,
+                fmspc,
+                &suppl_data,
                 event_log_peer,
                 policy_peer,
                 policy_issuer_chain,
             )
         }
+        .inspect_err(|e| log_peer_rejected(mig_request_id, quote_peer, e))?;
+
+        if let Some(key) = cache_key {
+            let peer = CachedPeer {
+                evaluation_data: evaluation_data.clone(),
+                suppl_data: suppl_data.clone(),
+            };
+            VERIFIED_PEERS.lock().insert(key, peer, ticks::now());
+        }
+        Ok((evaluation_data, suppl_data))
+    }
+
+    // Reports the platform of a rejected peer so that the VMM can aggregate the
+    // failures by platform.
+    fn log_peer_rejected(mig_request_id: u64, quote: &[u8], error: &PolicyError) {
+        #[cfg(feature = "vmcall-raw")]
+        if let Ok(fmspc) = get_fmspc_from_quote(quote) {
+            use crate::migration::logging::{entrylog_kv, LogValue, LOG_KEY_PEER_FMSPC};
+            entrylog_kv(
+                &alloc::format!("Peer rejected by policy: {:?}\n", error),
+                log::Level::Error,
+                mig_request_id,
+                &[(LOG_KEY_PEER_FMSPC, LogValue::Bytes(&fmspc))],
+            );
+        }
+        #[cfg(not(feature = "vmcall-raw"))]
+        let _ = (mig_request_id, quote, error);
     }
 
     fn authenticate_migration_dest(
-        quote_dst: &[u8],
+        fmspc: [u8; 6],
+        suppl_data_dst: &[u8],
         event_log_dst: &[u8],
         mig_policy_dst: &[u8],
         policy_issuer_chain: &[u8],
-    ) -> Result<Vec<u8>, PolicyError> {
-        let (evaluation_data_dst, verified_policy_dst, suppl_data) = authenticate_remote_common(
-            quote_dst,
+    ) -> Result<PolicyEvaluationInfo, PolicyError> {
+        let (evaluation_data_dst, verified_policy_dst) = authenticate_remote_common(
+            fmspc,
+            suppl_data_dst,
             event_log_dst,
             mig_policy_dst,
             policy_issuer_chain,
         )?;
-        let relative_reference = get_local_tcb_evaluation_info()?;
         let policy = get_verified_policy().ok_or(PolicyError::InvalidParameter)?;
 
-        policy
-            .policy_data
-            .evaluate_policy_common(&evaluation_data_dst, &relative_reference)?;
-        policy
-            .policy_data
-            .evaluate_policy_forward(&evaluation_data_dst, &relative_reference)?;
+        evaluate_remote_tcb(true, &evaluation_data_dst)?;
 
         // Verify the destination's policy against local policy
         verified_policy_dst
             .policy_data
             .evaluate_against_policy(&policy.policy_data)?;
 
-        Ok(suppl_data)
+        Ok(evaluation_data_dst)
     }
 
     fn authenticate_migration_source(
-        quote_src: &[u8],
+        fmspc: [u8; 6],
+        suppl_data_src: &[u8],
         event_log_src: &[u8],
         mig_policy_src: &[u8],
         policy_issuer_chain: &[u8],
-    ) -> Result<Vec<u8>, PolicyError> {
-        let (evaluation_data_src, _verified_policy_src, suppl_data) = authenticate_remote_common(
-            quote_src,
+    ) -> Result<PolicyEvaluationInfo, PolicyError> {
+        let (evaluation_data_src, _verified_policy_src) = authenticate_remote_common(
+            fmspc,
+            suppl_data_src,
             event_log_src,
             mig_policy_src,
             policy_issuer_chain,
         )?;
+
+        evaluate_remote_tcb(false, &evaluation_data_src)?;
+
+        Ok(evaluation_data_src)
+    }
+
+    /// Evaluate the peer's TCB against the local policy and the local TCB info. This is
+    /// cheap and depends on local state, so it is never cached.
+    fn evaluate_remote_tcb(
+        is_src: bool,
+        evaluation_data: &PolicyEvaluationInfo,
+    ) -> Result<(), PolicyError> {
         let relative_reference = get_local_tcb_evaluation_info()?;
         let policy = get_verified_policy().ok_or(PolicyError::InvalidParameter)?;
 
-        policy
-            .policy_data
-            .evaluate_policy_backward(&evaluation_data_src, &relative_reference)?;
-
-        Ok(suppl_data)
+        if is_src {
+            policy
+                .policy_data
+                .evaluate_policy_common(evaluation_data, &relative_reference)?;
+            policy
+                .policy_data
+                .evaluate_policy_forward(evaluation_data, &relative_reference)
+        } else {
+            policy
+                .policy_data
+                .evaluate_policy_backward(evaluation_data, &relative_reference)
+        }
     }
 
-    fn authenticate_remote_common<'p>(
-        quote: &[u8],
+    /// Authenticates the peer whose quote has been verified, `suppl_data` is the
+    /// supplemental data of the verified quote.
+    fn authenticate_remote_common(
+        fmspc: [u8; 6],
+        suppl_data: &[u8],
         event_log: &[u8],
-        mig_policy: &'p [u8],
+        mig_policy: &[u8],
         policy_issuer_chain: &[u8],
-    ) -> Result<(PolicyEvaluationInfo, VerifiedPolicy<'p>, Vec<u8>), PolicyError> {
+    ) -> Result<(PolicyEvaluationInfo, VerifiedPolicy), PolicyError> {
         let policy = get_verified_policy().ok_or(PolicyError::InvalidParameter)?;
         let unverified_policy = RawPolicyData::deserialize_from_json(mig_policy)?;
 
-        // 1. Verify quote & get supplemental data
-        let (fmspc, suppl_data) = verify_quote(quote, policy.get_collaterals())
-            .map_err(|_| PolicyError::QuoteVerification)?;
-
-        // 2. Verify the event log integrity
+        // 1. Verify the event log integrity
         verify_event_log(
             event_log,
             suppl_data
@@ -217,26 +713,26 @@ mod v2 {
         )
         .map_err(|_| PolicyError::InvalidEventLog)?;
 
-        // 3. Verify the integrity of migration policy, with the issuer chains from local policy
+        // 2. Verify the integrity of migration policy, with the issuer chains from local policy
         let verified_policy = unverified_policy.verify(
             policy_issuer_chain,
             Some(policy.servtd_identity_issuer_chain.as_bytes()),
             Some(policy.servtd_tcb_mapping_issuer_chain.as_bytes()),
         )?;
 
-        // 4. Check the integrity of the policy with its event log
+        // 3. Check the integrity of the policy with its event log
         let events = parse_events(event_log).ok_or(PolicyError::InvalidEventLog)?;
         check_policy_integrity(mig_policy, &events)?;
 
-        // 5. Get TCB evaluation info from the collaterals
+        // 4. Get TCB evaluation info from the collaterals
         let evaluation_data = setup_evaluation_data(
             fmspc,
-            &suppl_data,
+            suppl_data,
             &verified_policy,
             policy.get_collaterals(),
         )?;
 
-        Ok((evaluation_data, verified_policy, suppl_data))
+        Ok((evaluation_data, verified_policy))
     }
 
     fn verify_quote(
@@ -349,4 +845,151 @@ mod v2 {
         let iso_date = unix_to_iso8601(timestamp).unwrap();
         assert_eq!(iso_date, "2024-01-01T00:00:00Z");
     }
+
+    #[test]
+    fn test_peer_cache_key() {
+        invalidate_peer_cache(b"policy").unwrap();
+        let mut quote = super::test::test_quote();
+        let key = peer_cache_key(true, &quote, b"event log", b"peer policy").unwrap();
+
+        // The report data and the signature do not contribute to the cache key
+        quote[super::R_QUOTE_REPORT_DATA].fill(0x11);
+        quote[super::R_QUOTE_SIGNATURE].fill(0x22);
+        assert_eq!(
+            peer_cache_key(true, &quote, b"event log", b"peer policy").unwrap(),
+            key
+        );
+
+        // The direction, the rest of the quote, the peer's event log and policy
+        // and the local policy do
+        assert_ne!(
+            peer_cache_key(false, &quote, b"event log", b"peer policy").unwrap(),
+            key
+        );
+        quote[super::R_QUOTE_ATTEST_KEY.start] ^= 1;
+        assert_ne!(
+            peer_cache_key(true, &quote, b"event log", b"peer policy").unwrap(),
+            key
+        );
+        quote[super::R_QUOTE_ATTEST_KEY.start] ^= 1;
+        assert_ne!(
+            peer_cache_key(true, &quote, b"event log2", b"peer policy").unwrap(),
+            key
+        );
+        assert_ne!(
+            peer_cache_key(true, &quote, b"event log", b"peer policy2").unwrap(),
+            key
+        );
+        invalidate_peer_cache(b"policy2").unwrap();
+        assert_ne!(
+            peer_cache_key(true, &quote, b"event log", b"peer policy").unwrap(),
+            key
+        );
+        assert!(peer_cache_key(true, &quote[..700], b"event log", b"peer policy").is_err());
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use ring::rand::SystemRandom;
+    use ring::signature::{EcdsaKeyPair, KeyPair, ECDSA_P256_SHA256_FIXED_SIGNING};
+
+    /// A v4 quote whose report body is filled with 0x5a, not signed
+    pub(super) fn test_quote() -> Vec<u8> {
+        let mut quote = alloc::vec![0x5a; 1024];
+        quote[..2].copy_from_slice(&QUOTE_VERSION.to_le_bytes());
+        quote[2..4].copy_from_slice(&QUOTE_ATTEST_KEY_TYPE_ECDSA_P256.to_le_bytes());
+        quote
+    }
+
+    fn sign_quote(quote: &mut [u8], key: &EcdsaKeyPair) {
+        let rng = SystemRandom::new();
+        let signature = key.sign(&rng, &quote[R_QUOTE_SIGNED_DATA]).unwrap();
+        quote[R_QUOTE_SIGNATURE].copy_from_slice(signature.as_ref());
+        // Without the leading byte of the uncompressed point
+        quote[R_QUOTE_ATTEST_KEY].copy_from_slice(&key.public_key().as_ref()[1..]);
+    }
+
+    #[test]
+    fn test_quote_cache_material() {
+        let mut quote = test_quote();
+        let material = quote_cache_material(&quote).unwrap();
+
+        // Report data and signature do not contribute to the cache key
+        quote[R_QUOTE_REPORT_DATA].fill(0x11);
+        quote[R_QUOTE_SIGNATURE].fill(0x22);
+        assert_eq!(quote_cache_material(&quote).unwrap(), material);
+
+        // Measurements and the attestation key do
+        quote[R_QUOTE_ATTEST_KEY.start] ^= 1;
+        assert_ne!(quote_cache_material(&quote).unwrap(), material);
+        assert!(quote_cache_material(&quote[..R_QUOTE_ATTEST_KEY.end - 1]).is_err());
+
+        // Other quote versions and attestation key types are never cached
+        let mut v5 = test_quote();
+        v5[0] = 5;
+        assert!(quote_cache_material(&v5).is_err());
+        let mut p384 = test_quote();
+        p384[2] = 3;
+        assert!(quote_cache_material(&p384).is_err());
+    }
+
+    #[test]
+    fn test_reuse_verified_report() {
+        let rng = SystemRandom::new();
+        let pkcs8 = EcdsaKeyPair::generate_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, &rng).unwrap();
+        let key = EcdsaKeyPair::from_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, pkcs8.as_ref(), &rng)
+            .unwrap();
+        let report = alloc::vec![0u8; policy::REPORT_DATA_SIZE];
+
+        // A quote with fresh report data signed by the verified attestation key
+        let mut quote = test_quote();
+        quote[R_QUOTE_REPORT_DATA].fill(0x11);
+        sign_quote(&mut quote, &key);
+        let reused = reuse_verified_report(report.clone(), &quote).unwrap();
+        assert_eq!(reused[R_REPORT_REPORT_DATA], quote[R_QUOTE_REPORT_DATA]);
+
+        // Report data which is not covered by the signature is rejected
+        quote[R_QUOTE_REPORT_DATA].fill(0x33);
+        assert!(reuse_verified_report(report.clone(), &quote).is_err());
+
+        assert!(reuse_verified_report(alloc::vec![0u8; 64], &quote).is_err());
+        assert!(reuse_verified_report(report, &quote[..R_QUOTE_ATTEST_KEY.end - 1]).is_err());
+    }
+
+    #[test]
+    fn test_verified_peer_cache_ttl() {
+        let mut cache = VerifiedPeerCache::new(100, 4);
+        cache.insert(vec![1], 0xaa, 10);
+
+        assert_eq!(cache.get(&[1], 50), Some(0xaa));
+        assert!(cache.get(&[2], 50).is_none());
+        assert!(cache.get(&[1], 110).is_none());
+        assert!(cache.entries.is_empty());
+    }
+
+    #[test]
+    fn test_verified_peer_cache_eviction() {
+        let mut cache = VerifiedPeerCache::new(1000, 2);
+        cache.insert(vec![1], 1, 0);
+        cache.insert(vec![2], 2, 1);
+        cache.insert(vec![3], 3, 2);
+
+        assert_eq!(cache.entries.len(), 2);
+        assert!(cache.get(&[1], 3).is_none());
+        assert!(cache.get(&[2], 3).is_some());
+        assert!(cache.get(&[3], 3).is_some());
+
+        // Re-inserting an existing key must not evict another entry
+        cache.insert(vec![3], 3, 4);
+        assert!(cache.get(&[2], 5).is_some());
+
+        cache.clear();
+        assert!(cache.get(&[3], 5).is_none());
+
+        let mut disabled = VerifiedPeerCache::new(1000, 0);
+        disabled.insert(vec![1], 1, 0);
+        assert!(disabled.get(&[1], 0).is_none());
+    }
 }
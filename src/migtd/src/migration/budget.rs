@@ -0,0 +1,281 @@
+// Copyright (c) 2025 Intel Corporation
+//
+// SPDX-License-Identifier: BSD-2-Clause-Patent
+
+//! Per-request heap budget.
+//!
+//! This is accounting only: buffers are still allocated from the MigTD heap,
+//! but each in-flight request is given a fixed budget and every buffer whose
+//! size is chosen by the peer or the VMM is charged against the budget of
+//! the request it belongs to before it is allocated. This covers the
+//! pre-session data, the TLS record buffers and the peer certificate with
+//! its quote and event log. A misbehaving request is refused with
+//! `MigrationResult::OutOfResource` instead of exhausting the heap and
+//! hitting the allocator's panic handler. The budget is returned to the pool
+//! when the request completes.
+
+use crate::migration::MigrationResult;
+use crate::HEAP_SIZE;
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+use crypto::tls::TLS_RECORD_BUFFERS_SIZE;
+use lazy_static::lazy_static;
+use spin::Mutex;
+
+type Result<T> = core::result::Result<T, MigrationResult>;
+
+/// Maximum number of requests handled concurrently
+pub const MAX_CONCURRENCY_REQUESTS: usize = 12;
+
+/// Heap kept aside for the global state: policy, collaterals, event log and
+/// the async runtime.
+pub const HEAP_RESERVED_SIZE: usize = 0x10_0000;
+
+/// Heap budget of each request
+pub const REQUEST_HEAP_BUDGET: usize = (HEAP_SIZE - HEAP_RESERVED_SIZE) / MAX_CONCURRENCY_REQUESTS;
+
+/// Heap used by one secure session besides the peer certificate: the TLS
+/// record buffers, the local certificate and the handshake state.
+pub const SECURE_SESSION_FOOTPRINT: usize = TLS_RECORD_BUFFERS_SIZE + 0x1_0000;
+
+const _: () = assert!(REQUEST_HEAP_BUDGET >= SECURE_SESSION_FOOTPRINT);
+
+lazy_static! {
+    pub static ref HEAP_BUDGET: Mutex<HeapBudget> = Mutex::new(HeapBudget::new(
+        MAX_CONCURRENCY_REQUESTS * REQUEST_HEAP_BUDGET,
+        REQUEST_HEAP_BUDGET
+    ));
+}
+
+/// Book-keeping of the heap charged by the in-flight requests.
+pub struct HeapBudget {
+    capacity: usize,
+    per_request: usize,
+    // Bytes charged by each admitted request, keyed by request id
+    used: BTreeMap<u64, usize>,
+}
+
+impl HeapBudget {
+    pub const fn new(capacity: usize, per_request: usize) -> Self {
+        Self {
+            capacity,
+            per_request,
+            used: BTreeMap::new(),
+        }
+    }
+
+    /// Reserves a budget for `request_id`.
+    pub fn admit(&mut self, request_id: u64) -> Result<()> {
+        if self.used.contains_key(&request_id) {
+            log::error!("admit: request {} is already admitted\n", request_id);
+            return Err(MigrationResult::InvalidParameter);
+        }
+        if (self.used.len() + 1) * self.per_request > self.capacity {
+            log::error!(
+                "admit: no heap budget left for request {}, {} requests in flight\n",
+                request_id,
+                self.used.len()
+            );
+            return Err(MigrationResult::OutOfResource);
+        }
+        self.used.insert(request_id, 0);
+        Ok(())
+    }
+
+    /// Accounts `bytes` against the budget of `request_id`.
+    pub fn charge(&mut self, request_id: u64, bytes: usize) -> Result<()> {
+        let per_request = self.per_request;
+        let used = self.used.get_mut(&request_id).ok_or_else(|| {
+            log::error!("charge: request {} is not admitted\n", request_id);
+            MigrationResult::OutOfResource
+        })?;
+        let total = used
+            .checked_add(bytes)
+            .filter(|total| *total <= per_request)
+            .ok_or_else(|| {
+                log::error!(
+                    "charge: request {} exceeds its heap budget, used {:x} requested {:x}\n",
+                    request_id,
+                    used,
+                    bytes
+                );
+                MigrationResult::OutOfResource
+            })?;
+        *used = total;
+        Ok(())
+    }
+
+    /// Charges and allocates a zeroed buffer of `len` bytes for `request_id`.
+    pub fn alloc_zeroed(&mut self, request_id: u64, len: usize) -> Result<Vec<u8>> {
+        self.charge(request_id, len)?;
+        let mut buffer = Vec::new();
+        buffer
+            .try_reserve_exact(len)
+            .map_err(|_| MigrationResult::OutOfResource)?;
+        buffer.resize(len, 0);
+        Ok(buffer)
+    }
+
+    /// Bytes left in the budget of `request_id`.
+    pub fn remaining(&self, request_id: u64) -> usize {
+        self.used
+            .get(&request_id)
+            .map(|used| self.per_request - used)
+            .unwrap_or(0)
+    }
+
+    /// Returns the budget of `request_id` to the pool.
+    pub fn release(&mut self, request_id: u64) {
+        self.used.remove(&request_id);
+    }
+
+    /// Number of requests holding a budget.
+    pub fn in_flight(&self) -> usize {
+        self.used.len()
+    }
+}
+
+/// Accounts `bytes` against the budget of `request_id` in the global
+/// `HEAP_BUDGET`, for the buffers received after the request is admitted.
+pub fn charge(request_id: u64, bytes: usize) -> Result<()> {
+    HEAP_BUDGET.lock().charge(request_id, bytes)
+}
+
+/// Budget of one request in the global `HEAP_BUDGET`, released on drop.
+pub struct RequestArena {
+    request_id: u64,
+}
+
+impl RequestArena {
+    pub fn admit(request_id: u64) -> Result<Self> {
+        HEAP_BUDGET.lock().admit(request_id)?;
+        Ok(Self { request_id })
+    }
+
+    pub fn request_id(&self) -> u64 {
+        self.request_id
+    }
+
+    pub fn charge(&self, bytes: usize) -> Result<()> {
+        charge(self.request_id, bytes)
+    }
+
+    pub fn alloc_zeroed(&self, len: usize) -> Result<Vec<u8>> {
+        HEAP_BUDGET.lock().alloc_zeroed(self.request_id, len)
+    }
+}
+
+impl Drop for RequestArena {
+    fn drop(&mut self) {
+        HEAP_BUDGET.lock().release(self.request_id);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_budget_admission() {
+        let mut budget = HeapBudget::new(2 * 0x1000, 0x1000);
+
+        assert!(budget.admit(1).is_ok());
+        assert_eq!(budget.admit(1), Err(MigrationResult::InvalidParameter));
+        assert!(budget.admit(2).is_ok());
+        assert_eq!(budget.admit(3), Err(MigrationResult::OutOfResource));
+
+        budget.release(1);
+        assert!(budget.admit(3).is_ok());
+        assert_eq!(budget.in_flight(), 2);
+    }
+
+    #[test]
+    fn test_budget_charge() {
+        let mut budget = HeapBudget::new(0x1000, 0x1000);
+
+        assert_eq!(budget.charge(1, 1), Err(MigrationResult::OutOfResource));
+        budget.admit(1).unwrap();
+        assert!(budget.charge(1, 0x800).is_ok());
+        assert_eq!(budget.remaining(1), 0x800);
+        assert_eq!(budget.charge(1, 0x801), Err(MigrationResult::OutOfResource));
+        assert_eq!(
+            budget.charge(1, usize::MAX),
+            Err(MigrationResult::OutOfResource)
+        );
+        // A refused charge leaves the budget untouched
+        assert_eq!(budget.remaining(1), 0x800);
+        assert_eq!(budget.alloc_zeroed(1, 0x800).unwrap().len(), 0x800);
+        assert_eq!(budget.remaining(1), 0);
+    }
+
+    #[test]
+    fn test_max_concurrency_sessions() {
+        // Heap capped at exactly the budgets of the concurrent requests
+        let mut budget = HeapBudget::new(
+            MAX_CONCURRENCY_REQUESTS * REQUEST_HEAP_BUDGET,
+            REQUEST_HEAP_BUDGET,
+        );
+        let mut sessions = Vec::new();
+
+        for request_id in 0..MAX_CONCURRENCY_REQUESTS as u64 {
+            budget.admit(request_id).unwrap();
+            budget.charge(request_id, SECURE_SESSION_FOOTPRINT).unwrap();
+            let remote_policy = budget
+                .alloc_zeroed(request_id, budget.remaining(request_id))
+                .unwrap();
+            sessions.push(remote_policy);
+        }
+        assert_eq!(budget.in_flight(), MAX_CONCURRENCY_REQUESTS);
+
+        // One request more is refused cleanly, as is an oversized peer buffer
+        assert_eq!(
+            budget.admit(MAX_CONCURRENCY_REQUESTS as u64),
+            Err(MigrationResult::OutOfResource)
+        );
+        assert_eq!(
+            budget.alloc_zeroed(0, u32::MAX as usize).err(),
+            Some(MigrationResult::OutOfResource)
+        );
+
+        for request_id in 0..MAX_CONCURRENCY_REQUESTS as u64 {
+            budget.release(request_id);
+        }
+        drop(sessions);
+        assert_eq!(budget.in_flight(), 0);
+        assert!(budget.admit(MAX_CONCURRENCY_REQUESTS as u64).is_ok());
+    }
+
+    #[test]
+    fn test_request_arena_release() {
+        let request_id = u64::MAX - 1;
+        let arena = RequestArena::admit(request_id).unwrap();
+        assert!(RequestArena::admit(request_id).is_err());
+        assert!(arena.charge(REQUEST_HEAP_BUDGET + 1).is_err());
+        drop(arena);
+        assert!(RequestArena::admit(request_id).is_ok());
+    }
+
+    #[test]
+    fn test_oversized_peer_buffer() {
+        let request_id = u64::MAX - 2;
+        let arena = RequestArena::admit(request_id).unwrap();
+        arena.charge(SECURE_SESSION_FOOTPRINT).unwrap();
+
+        // A peer certificate carrying a quote and an event log that don't fit
+        // in what is left of the budget
+        let peer_cert_size = REQUEST_HEAP_BUDGET - SECURE_SESSION_FOOTPRINT + 1;
+        assert_eq!(
+            charge(request_id, peer_cert_size),
+            Err(MigrationResult::OutOfResource)
+        );
+        assert_eq!(
+            arena.alloc_zeroed(peer_cert_size).err(),
+            Some(MigrationResult::OutOfResource)
+        );
+        assert!(charge(request_id, peer_cert_size - 1).is_ok());
+        drop(arena);
+
+        // Nothing can be charged once the request completed
+        assert_eq!(charge(request_id, 1), Err(MigrationResult::OutOfResource));
+    }
+}
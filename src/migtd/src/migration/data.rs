@@ -2,10 +2,14 @@
 //
 // SPDX-License-Identifier: BSD-2-Clause-Patent
 
+#[cfg(feature = "vmcall-raw")]
+use super::log_control::LogControl;
 use super::*;
 #[cfg(feature = "vmcall-raw")]
 use bitfield_struct::bitfield;
 use core::convert::TryInto;
+#[cfg(not(feature = "vmcall-raw"))]
+use core::time::Duration;
 use core::{mem::size_of, slice::from_raw_parts, slice::from_raw_parts_mut};
 use r_efi::efi::Guid;
 #[cfg(not(feature = "vmcall-raw"))]
@@ -14,11 +18,18 @@ use td_shim_interface::td_uefi_pi::{
     pi::hob::{GuidExtension, Header, HOB_TYPE_END_OF_HOB_LIST, HOB_TYPE_GUID_EXTENSION},
 };
 use zerocopy::{AsBytes, FromBytes, FromZeroes};
+use zeroize::{Zeroize, ZeroizeOnDrop};
 
 pub const QUERY_COMMAND: u8 = 0;
 pub const MIG_COMMAND_SHUT_DOWN: u8 = 0;
 pub const MIG_COMMAND_WAIT: u8 = 1;
 pub const MIG_COMMAND_REPORT_STATUS: u8 = 2;
+/// Operation of a wait for request response carrying a `SetLogLevel` request
+pub const MIG_OPERATION_SET_LOG_LEVEL: u8 = 2;
+/// Operation of a wait for request response carrying a shutdown request. Its
+/// data is the time in milliseconds given to the in-flight requests to
+/// complete, as a 32-bit little endian value.
+pub const MIG_OPERATION_SHUT_DOWN: u8 = 3;
 
 pub struct VmcallServiceCommand<'a> {
     data: &'a mut [u8],
@@ -202,6 +213,9 @@ pub struct ServiceMigReportStatusResponse {
     pub reserved: [u8; 2],
 }
 
+/// The key is wiped when it goes out of scope, including on early returns of the
+/// key exchange.
+#[derive(Zeroize, ZeroizeOnDrop)]
 pub struct MigrationSessionKey {
     pub fields: [u64; 4],
 }
@@ -220,7 +234,15 @@ impl MigrationSessionKey {
     }
 
     pub fn clear(&mut self) {
-        self.fields.fill(0);
+        self.zeroize();
+    }
+}
+
+impl core::fmt::Debug for MigrationSessionKey {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_tuple("MigrationSessionKey")
+            .field(&redact::Redacted(self.as_bytes()))
+            .finish()
     }
 }
 
@@ -258,6 +280,18 @@ pub enum WaitForRequestResponse {
     StartMigration(MigrationInformation),
     GetTdReport(ReportInfo),
     EnableLogArea(EnableLogAreaInfo),
+    StartBatchMigration(BatchMigrationInformation),
+    Shutdown(ShutdownInfo),
+    GetMetrics(GetMetricsInfo),
+    SetLogLevel(LogControl),
+    GetTranscript(GetTranscriptInfo),
+}
+
+#[cfg(not(feature = "vmcall-raw"))]
+pub enum WaitForRequestResponse {
+    StartMigration(MigrationInformation),
+    // Time given to the in-flight requests to complete before they are canceled
+    Shutdown(Duration),
 }
 
 pub struct MigrationInformation {
@@ -277,6 +311,75 @@ impl MigrationInformation {
     }
 }
 
+/// Maximum number of target TDs carried by one batch migration request, bounded by
+/// the size of the shared request buffer.
+#[cfg(feature = "vmcall-raw")]
+pub const MAX_BATCH_MIGRATION_ENTRIES: usize = 64;
+
+#[cfg(feature = "vmcall-raw")]
+pub struct BatchMigrationInformation {
+    pub mig_request_id: u64,
+    pub migration_source: u8,
+    // One entry per target TD, all of them share the request ID of the batch
+    pub entries: Vec<MigtdMigrationInformation>,
+}
+
+#[cfg(feature = "vmcall-raw")]
+impl BatchMigrationInformation {
+    pub fn is_src(&self) -> bool {
+        self.migration_source == 1
+    }
+}
+
+/// Per target TD status of a batch migration request, reported to VMM in the data
+/// buffer of ReportStatus in the same order as the request entries.
+#[cfg(feature = "vmcall-raw")]
+#[repr(C)]
+#[derive(Debug, FromZeroes, FromBytes, AsBytes)]
+pub struct BatchMigrationStatus {
+    pub target_td_uuid: [u64; 4],
+    pub binding_handle: u64,
+    pub status: u8,
+    pub reserved: [u8; 7],
+}
+
+/// Parse the data of a StartBatchMigration request, which is a `BatchMigrationHeader`
+/// followed by `count` `BatchMigrationEntry`.
+#[cfg(feature = "vmcall-raw")]
+pub fn read_batch_mig_info(data: &[u8]) -> Option<BatchMigrationInformation> {
+    let header: BatchMigrationHeader = data.pread_with(0, scroll::LE).ok()?;
+    let count = header.count as usize;
+    if count == 0 || count > MAX_BATCH_MIGRATION_ENTRIES {
+        return None;
+    }
+
+    let header_size = size_of::<BatchMigrationHeader>();
+    let entry_size = size_of::<BatchMigrationEntry>();
+    if data.len() != header_size + count * entry_size {
+        return None;
+    }
+
+    let mut entries = Vec::with_capacity(count);
+    for idx in 0..count {
+        let entry: BatchMigrationEntry = data
+            .pread_with(header_size + idx * entry_size, scroll::LE)
+            .ok()?;
+        entries.push(MigtdMigrationInformation {
+            mig_request_id: header.mig_request_id,
+            migration_source: header.migration_source,
+            target_td_uuid: entry.target_td_uuid,
+            binding_handle: entry.binding_handle,
+            ..Default::default()
+        });
+    }
+
+    Some(BatchMigrationInformation {
+        mig_request_id: header.mig_request_id,
+        migration_source: header.migration_source,
+        entries,
+    })
+}
+
 #[cfg(not(feature = "vmcall-raw"))]
 pub fn read_mig_info(hob: &[u8]) -> Option<MigrationInformation> {
     let mut offset = 0;
@@ -792,3 +895,62 @@ mod test {
         }
     }
 }
+
+#[cfg(test)]
+#[cfg(feature = "vmcall-raw")]
+mod test_batch {
+    use super::*;
+    use scroll::Pwrite;
+
+    fn build_batch_request(count: u32, entries: usize) -> Vec<u8> {
+        let header_size = size_of::<BatchMigrationHeader>();
+        let entry_size = size_of::<BatchMigrationEntry>();
+        let mut buf = vec![0u8; header_size + entries * entry_size];
+
+        let header = BatchMigrationHeader {
+            mig_request_id: 0x42,
+            migration_source: 1,
+            reserved: [0; 3],
+            count,
+        };
+        buf.pwrite_with(header, 0, scroll::LE).unwrap();
+        for idx in 0..entries {
+            let entry = BatchMigrationEntry {
+                target_td_uuid: [idx as u64; 4],
+                binding_handle: 0x1000 + idx as u64,
+            };
+            buf.pwrite_with(entry, header_size + idx * entry_size, scroll::LE)
+                .unwrap();
+        }
+        buf
+    }
+
+    #[test]
+    fn test_read_batch_mig_info() {
+        let buf = build_batch_request(3, 3);
+        let info = read_batch_mig_info(&buf).unwrap();
+        assert!(info.is_src());
+        assert_eq!(info.mig_request_id, 0x42);
+        assert_eq!(info.entries.len(), 3);
+        for (idx, entry) in info.entries.iter().enumerate() {
+            assert_eq!(entry.mig_request_id, 0x42);
+            assert_eq!(entry.migration_source, 1);
+            assert_eq!(entry.target_td_uuid, [idx as u64; 4]);
+            assert_eq!(entry.binding_handle, 0x1000 + idx as u64);
+        }
+    }
+
+    #[test]
+    fn test_read_batch_mig_info_invalid() {
+        // Count does not match the length of the data
+        assert!(read_batch_mig_info(&build_batch_request(3, 2)).is_none());
+        assert!(read_batch_mig_info(&build_batch_request(2, 3)).is_none());
+        // Empty batch
+        assert!(read_batch_mig_info(&build_batch_request(0, 0)).is_none());
+        // Too many entries
+        let count = MAX_BATCH_MIGRATION_ENTRIES + 1;
+        assert!(read_batch_mig_info(&build_batch_request(count as u32, count)).is_none());
+        // Truncated header
+        assert!(read_batch_mig_info(&[0u8; 8]).is_none());
+    }
+}
@@ -0,0 +1,350 @@
+// Copyright (c) 2025 Intel Corporation
+//
+// SPDX-License-Identifier: BSD-2-Clause-Patent
+
+//! Runtime control of the log level.
+//!
+//! The VMM can change the log level of MigTD with the `SetLogLevel` request,
+//! whatever the transport. The request carries a default level and optional
+//! per-target levels, where a target is a module path such as `migtd::ratls`
+//! which also covers its submodules.
+//!
+//! The requested levels are capped by the `maxLogLevel` of the MigTD policy
+//! and by the compile-time maximum level, so that an image built or signed
+//! for production can never be switched into verbose logging.
+
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicU8, Ordering};
+use lazy_static::lazy_static;
+use log::{LevelFilter, Metadata, Record, SetLoggerError};
+use spin::Mutex;
+
+/// Size of the fixed part of a `SetLogLevel` request
+pub const LOG_CONTROL_HEADER_SIZE: usize = 16;
+/// Maximum number of target filters of a `SetLogLevel` request
+pub const LOG_CONTROL_MAX_FILTERS: usize = 16;
+
+/// Level applied to the records of a target and of its submodules
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LogTargetFilter {
+    pub target: String,
+    pub level: LevelFilter,
+}
+
+/// `SetLogLevel` request
+///
+/// The request is laid out as follows, all the fields being little-endian:
+/// - mig_request_id: u64
+/// - max_level: u8, level of the records without a matching target filter
+/// - filter_count: u8, number of target filters, at most 16
+/// - reserved: [u8; 6]
+/// - filter_count times: level u8, target length u8, UTF-8 target
+///
+/// The levels are encoded as 0 (off), 1 (error), 2 (warn), 3 (info),
+/// 4 (debug) and 5 (trace).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LogControl {
+    pub mig_request_id: u64,
+    pub max_level: LevelFilter,
+    pub filters: Vec<LogTargetFilter>,
+}
+
+impl LogControl {
+    pub fn read_from(data: &[u8]) -> Option<Self> {
+        if data.len() < LOG_CONTROL_HEADER_SIZE {
+            return None;
+        }
+        let mig_request_id = u64::from_le_bytes(data[0..8].try_into().ok()?);
+        let max_level = u8_to_level_filter(data[8])?;
+        let filter_count = data[9] as usize;
+        if filter_count > LOG_CONTROL_MAX_FILTERS {
+            return None;
+        }
+
+        let mut filters = Vec::with_capacity(filter_count);
+        let mut offset = LOG_CONTROL_HEADER_SIZE;
+        for _ in 0..filter_count {
+            let header = data.get(offset..offset + 2)?;
+            let level = u8_to_level_filter(header[0])?;
+            let length = header[1] as usize;
+            offset += 2;
+
+            let target = core::str::from_utf8(data.get(offset..offset + length)?).ok()?;
+            if target.is_empty() {
+                return None;
+            }
+            filters.push(LogTargetFilter {
+                target: String::from(target),
+                level,
+            });
+            offset += length;
+        }
+
+        Some(Self {
+            mig_request_id,
+            max_level,
+            filters,
+        })
+    }
+}
+
+pub fn u8_to_level_filter(value: u8) -> Option<LevelFilter> {
+    match value {
+        0 => Some(LevelFilter::Off),
+        1 => Some(LevelFilter::Error),
+        2 => Some(LevelFilter::Warn),
+        3 => Some(LevelFilter::Info),
+        4 => Some(LevelFilter::Debug),
+        5 => Some(LevelFilter::Trace),
+        _ => None,
+    }
+}
+
+pub fn level_filter_to_u8(level: LevelFilter) -> u8 {
+    match level {
+        LevelFilter::Off => 0,
+        LevelFilter::Error => 1,
+        LevelFilter::Warn => 2,
+        LevelFilter::Info => 3,
+        LevelFilter::Debug => 4,
+        LevelFilter::Trace => 5,
+    }
+}
+
+/// Level of the records without a matching target filter
+static DEFAULT_LEVEL: AtomicU8 = AtomicU8::new(5);
+
+lazy_static! {
+    static ref TARGET_FILTERS: Mutex<Vec<LogTargetFilter>> = Mutex::new(Vec::new());
+}
+
+/// Returns whether `target` is `filter` or one of its submodules
+fn target_matches(filter: &str, target: &str) -> bool {
+    target
+        .strip_prefix(filter)
+        .is_some_and(|rest| rest.is_empty() || rest.starts_with("::"))
+}
+
+/// Level of `target`, given by its most specific filter if any
+fn target_level(filters: &[LogTargetFilter], default: LevelFilter, target: &str) -> LevelFilter {
+    filters
+        .iter()
+        .filter(|f| target_matches(&f.target, target))
+        .max_by_key(|f| f.target.len())
+        .map_or(default, |f| f.level)
+}
+
+/// Caps the levels of `control` with `cap` and the compile-time maximum level
+fn capped(control: &LogControl, cap: LevelFilter) -> (LevelFilter, Vec<LogTargetFilter>) {
+    let cap = cap.min(log::STATIC_MAX_LEVEL);
+    let filters = control
+        .filters
+        .iter()
+        .map(|f| LogTargetFilter {
+            target: f.target.clone(),
+            level: f.level.min(cap),
+        })
+        .collect();
+
+    (control.max_level.min(cap), filters)
+}
+
+/// Applies a `SetLogLevel` request, capping its levels with `cap`, and returns
+/// the default level in effect.
+pub fn apply_log_control(control: &LogControl, cap: LevelFilter) -> LevelFilter {
+    let (default, filters) = capped(control, cap);
+    let is_capped = default != control.max_level
+        || filters
+            .iter()
+            .zip(&control.filters)
+            .any(|(c, f)| c.level != f.level);
+    if is_capped {
+        log::warn!(
+            "apply_log_control: requested levels capped to {}\n",
+            cap.min(log::STATIC_MAX_LEVEL)
+        );
+    }
+
+    // The log macros check the global maximum level before calling the
+    // logger, so it must allow the most verbose of the filters.
+    let max_level = filters.iter().map(|f| f.level).fold(default, Ord::max);
+
+    DEFAULT_LEVEL.store(level_filter_to_u8(default), Ordering::SeqCst);
+    *TARGET_FILTERS.lock() = filters;
+    log::set_max_level(max_level);
+
+    default
+}
+
+/// Applies a `SetLogLevel` request capped by the `maxLogLevel` of the policy.
+///
+/// Returns the data reported for the request: the default level in effect and
+/// the most verbose level which can be set.
+pub fn set_log_level(control: &LogControl) -> [u8; 2] {
+    let cap = crate::mig_policy::max_log_level().min(log::STATIC_MAX_LEVEL);
+    let level = apply_log_control(control, cap);
+    log::info!("set_log_level: log level set to {}\n", level);
+
+    [level_filter_to_u8(level), level_filter_to_u8(cap)]
+}
+
+/// Caps the levels in effect with the `maxLogLevel` of the policy and returns
+/// the default level.
+///
+/// The logger is initialized before the policy is verified, so this must be
+/// called once the policy is available.
+pub fn apply_policy_cap() -> LevelFilter {
+    let cap = crate::mig_policy::max_log_level().min(log::STATIC_MAX_LEVEL);
+    let default = u8_to_level_filter(DEFAULT_LEVEL.load(Ordering::SeqCst))
+        .unwrap_or(LevelFilter::Off)
+        .min(cap);
+
+    let mut filters = TARGET_FILTERS.lock();
+    for filter in filters.iter_mut() {
+        filter.level = filter.level.min(cap);
+    }
+    let max_level = filters.iter().map(|f| f.level).fold(default, Ord::max);
+
+    DEFAULT_LEVEL.store(level_filter_to_u8(default), Ordering::SeqCst);
+    log::set_max_level(max_level.min(log::max_level()));
+
+    default
+}
+
+/// Logger filtering the records with the level set by `SetLogLevel`
+pub struct RuntimeLogger;
+
+impl log::Log for RuntimeLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        let default =
+            u8_to_level_filter(DEFAULT_LEVEL.load(Ordering::SeqCst)).unwrap_or(LevelFilter::Off);
+        // Do not wait for the filters if the record is logged while they are
+        // being replaced.
+        let level = match TARGET_FILTERS.try_lock() {
+            Some(filters) => target_level(&filters, default, metadata.target()),
+            None => default,
+        };
+
+        metadata.level() <= level
+    }
+
+    fn log(&self, record: &Record) {
+        if self.enabled(record.metadata()) {
+            let msg = format!("{}", record.args());
+            #[cfg(not(test))]
+            td_logger::dbg_write_string(&msg);
+            #[cfg(test)]
+            let _ = msg;
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+static RUNTIME_LOGGER: RuntimeLogger = RuntimeLogger;
+
+/// Initialize the runtime logger as the global logger with the given level
+pub fn init_runtime_logger(level: LevelFilter) -> core::result::Result<(), SetLoggerError> {
+    DEFAULT_LEVEL.store(level_filter_to_u8(level), Ordering::SeqCst);
+    log::set_logger(&RUNTIME_LOGGER).map(|()| log::set_max_level(level))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn request(max_level: u8, filters: &[(u8, &str)]) -> Vec<u8> {
+        let mut data = Vec::new();
+        data.extend_from_slice(&42u64.to_le_bytes());
+        data.push(max_level);
+        data.push(filters.len() as u8);
+        data.extend_from_slice(&[0u8; 6]);
+        for (level, target) in filters {
+            data.push(*level);
+            data.push(target.len() as u8);
+            data.extend_from_slice(target.as_bytes());
+        }
+        data
+    }
+
+    #[test]
+    fn test_read_log_control() {
+        let data = request(2, &[(4, "migtd::ratls"), (0, "rustls")]);
+        let control = LogControl::read_from(&data).unwrap();
+        assert_eq!(control.mig_request_id, 42);
+        assert_eq!(control.max_level, LevelFilter::Warn);
+        assert_eq!(
+            control.filters,
+            [
+                LogTargetFilter {
+                    target: String::from("migtd::ratls"),
+                    level: LevelFilter::Debug,
+                },
+                LogTargetFilter {
+                    target: String::from("rustls"),
+                    level: LevelFilter::Off,
+                },
+            ]
+        );
+
+        // Invalid level, truncated target and empty target
+        assert!(LogControl::read_from(&request(6, &[])).is_none());
+        assert!(LogControl::read_from(&data[..data.len() - 1]).is_none());
+        assert!(LogControl::read_from(&request(3, &[(3, "")])).is_none());
+        assert!(LogControl::read_from(&data[..LOG_CONTROL_HEADER_SIZE - 1]).is_none());
+    }
+
+    #[test]
+    fn test_target_level() {
+        let control = LogControl::read_from(&request(
+            2,
+            &[(4, "migtd::ratls"), (1, "migtd::ratls::server_client")],
+        ))
+        .unwrap();
+        let filters = &control.filters;
+
+        assert_eq!(
+            target_level(filters, LevelFilter::Warn, "migtd::ratls"),
+            LevelFilter::Debug
+        );
+        assert_eq!(
+            target_level(filters, LevelFilter::Warn, "migtd::ratls::cert"),
+            LevelFilter::Debug
+        );
+        assert_eq!(
+            target_level(filters, LevelFilter::Warn, "migtd::ratls::server_client"),
+            LevelFilter::Error
+        );
+        // A filter does not match a module sharing its prefix
+        assert_eq!(
+            target_level(filters, LevelFilter::Warn, "migtd::ratlsx"),
+            LevelFilter::Warn
+        );
+        assert_eq!(
+            target_level(filters, LevelFilter::Warn, "migtd::migration"),
+            LevelFilter::Warn
+        );
+    }
+
+    #[test]
+    fn test_capped() {
+        let control =
+            LogControl::read_from(&request(5, &[(5, "migtd::ratls"), (1, "rustls")])).unwrap();
+        let (default, filters) = capped(&control, LevelFilter::Info);
+
+        assert_eq!(default, LevelFilter::Info.min(log::STATIC_MAX_LEVEL));
+        assert_eq!(
+            filters[0].level,
+            LevelFilter::Info.min(log::STATIC_MAX_LEVEL)
+        );
+        assert_eq!(
+            filters[1].level,
+            LevelFilter::Error.min(log::STATIC_MAX_LEVEL)
+        );
+
+        let (default, _) = capped(&control, LevelFilter::Off);
+        assert_eq!(default, LevelFilter::Off);
+    }
+}
@@ -7,6 +7,7 @@
 use crate::migration::MigrationResult;
 #[cfg(test)]
 use alloc::boxed::Box;
+use alloc::collections::VecDeque;
 use alloc::format;
 use alloc::vec::Vec;
 use core::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, AtomicU8, Ordering};
@@ -24,7 +25,8 @@ use zerocopy::{transmute_ref, AsBytes, FromBytes, FromZeroes};
 const PAGE_SIZE: usize = 0x1_000;
 #[cfg(not(test))]
 const TDCALL_STATUS_SUCCESS: u64 = 0;
-const MIGRATION_REQUEST_ID_SENTINEL: u64 = 0xFFFF_FFFF_FFFF_FFFF;
+/// Request id of the entries not bound to a migration request
+pub const MIGRATION_REQUEST_ID_SENTINEL: u64 = 0xFFFF_FFFF_FFFF_FFFF;
 
 type Result<T> = core::result::Result<T, MigrationResult>;
 
@@ -36,10 +38,34 @@ struct LoggingInformation {
     maxloglevel: AtomicU8,
 }
 
+/// "MigTD LogArea 2", version 2 adds the `format` of the log entries
 pub const LOGAREA_SIGNATURE: [u8; 16] = [
-    0x4d, 0x69, 0x67, 0x54, 0x44, 0x20, 0x4c, 0x6f, 0x67, 0x41, 0x72, 0x65, 0x61, 0x20, 0x31, 0x00,
+    0x4d, 0x69, 0x67, 0x54, 0x44, 0x20, 0x4c, 0x6f, 0x67, 0x41, 0x72, 0x65, 0x61, 0x20, 0x32, 0x00,
 ];
 
+/// The entry is a free-form UTF-8 message
+pub const LOG_ENTRY_FORMAT_TEXT: u8 = 0;
+/// The entry is a message followed by key-value pairs, see `encode_kv_entry`
+pub const LOG_ENTRY_FORMAT_KV: u8 = 1;
+
+// Type tags of the values in a key-value entry
+const KV_TYPE_U64: u8 = 1;
+const KV_TYPE_I64: u8 = 2;
+const KV_TYPE_BOOL: u8 = 3;
+const KV_TYPE_STR: u8 = 4;
+const KV_TYPE_BYTES: u8 = 5;
+
+/// Well-known keys of the key-value entries
+pub const LOG_KEY_REQUEST_ID: &str = "migration_request_id";
+/// Migration phase the entry belongs to, a string
+pub const LOG_KEY_PHASE: &str = "phase";
+/// `MigrationResult` reported for the request, an integer
+pub const LOG_KEY_ERROR_CODE: &str = "error_code";
+/// FMSPC of the peer platform, 6 bytes
+pub const LOG_KEY_PEER_FMSPC: &str = "peer_fmspc";
+/// Elapsed time in milliseconds, an integer
+pub const LOG_KEY_DURATION_MS: &str = "duration_ms";
+
 #[repr(C, packed)]
 #[derive(AsBytes, FromBytes, FromZeroes, Debug)]
 pub struct LogAreaBufferHeader {
@@ -62,7 +88,8 @@ pub struct LogEntryHeader {
     pub log_entry_id: u64,
     pub mig_request_id: u64,
     pub loglevel: u8,
-    pub reserved: [u8; 3],
+    pub format: u8,
+    pub reserved: [u8; 2],
     pub length: u32,
 }
 
@@ -101,6 +128,40 @@ lazy_static! {
         maxloglevel: AtomicU8::new(0),
     };
     static ref LOGAREAPTR: Mutex<Vec<usize>> = Mutex::new(Vec::new());
+    // Copies of the latest entries written with `entrylog` or `entrylog_kv`,
+    // the context of a crash report
+    static ref RECENT_ENTRIES: Mutex<VecDeque<Vec<u8>>> =
+        Mutex::new(VecDeque::with_capacity(RECENT_ENTRIES_COUNT));
+}
+
+/// Number of the latest LogArea entries kept for the crash reports
+pub const RECENT_ENTRIES_COUNT: usize = 16;
+
+fn remember_entry(header: &[u8], msg: &[u8]) {
+    let mut recent = RECENT_ENTRIES.lock();
+    if recent.len() == RECENT_ENTRIES_COUNT {
+        recent.pop_front();
+    }
+    let mut entry = Vec::with_capacity(header.len() + msg.len());
+    entry.extend_from_slice(header);
+    entry.extend_from_slice(msg);
+    recent.push_back(entry);
+}
+
+/// Returns the latest LogArea entries, oldest first, each one being a
+/// `LogEntryHeader` followed by its payload.
+///
+/// Only the entries written with `entrylog` or `entrylog_kv` are kept: their
+/// content is checked for sensitive data, see `redact`, unlike the records of
+/// the `log` macros which may format any `Debug` output.
+///
+/// Nothing is returned if the entries are being written, e.g. when MigTD
+/// panics while logging.
+pub fn recent_entries() -> Vec<Vec<u8>> {
+    RECENT_ENTRIES
+        .try_lock()
+        .map(|recent| recent.iter().cloned().collect())
+        .unwrap_or_default()
 }
 
 pub fn create_logarea() -> Result<()> {
@@ -147,6 +208,22 @@ pub fn create_logarea() -> Result<()> {
     Ok(())
 }
 
+/// Copies the LogArea buffers of all the vCPUs, concatenated in vCPU order.
+///
+/// In AzCVMEmu mode the log area lives in process memory, this is what host
+/// tools read in place of the shared pages.
+#[cfg(feature = "AzCVMEmu")]
+pub fn snapshot_logarea() -> Vec<u8> {
+    let logareavector = LOGAREAPTR.lock();
+    let mut snapshot = Vec::with_capacity(logareavector.len() * PAGE_SIZE);
+    for data_buffer in logareavector.iter() {
+        let data_buffer =
+            unsafe { core::slice::from_raw_parts(*data_buffer as *const u8, PAGE_SIZE) };
+        snapshot.extend_from_slice(data_buffer);
+    }
+    snapshot
+}
+
 pub async fn enable_logarea(log_max_level: u8, request_id: u64, data: &mut Vec<u8>) -> Result<()> {
     let padding: u32 = 0;
     let num_vcpus: u32 = LOGGING_INFORMATION.num_vcpus.load(Ordering::SeqCst);
@@ -252,7 +329,95 @@ pub async fn enable_logarea(log_max_level: u8, request_id: u64, data: &mut Vec<u
     }
 }
 
+/// Value of a key-value pair of a structured log entry
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LogValue<'a> {
+    U64(u64),
+    I64(i64),
+    Bool(bool),
+    Str(&'a str),
+    Bytes(&'a [u8]),
+}
+
+/// Builds the payload of a `LOG_ENTRY_FORMAT_KV` entry:
+///
+/// message length (u16) | message | pair count (u8) | pairs
+///
+/// where each pair is key length (u8) | key | type (u8) | value. Integers are
+/// 8 bytes little-endian, a bool is one byte, strings and byte arrays are
+/// prefixed with their length as u16. Pairs which do not fit are dropped.
+#[derive(Default)]
+pub struct KvEntryEncoder {
+    pairs: Vec<u8>,
+    count: u8,
+}
+
+impl KvEntryEncoder {
+    pub fn push(&mut self, key: &str, value: LogValue) {
+        if self.count == u8::MAX || key.len() > u8::MAX as usize {
+            return;
+        }
+        let (tag, bytes) = match value {
+            LogValue::U64(v) => (KV_TYPE_U64, v.to_le_bytes().to_vec()),
+            LogValue::I64(v) => (KV_TYPE_I64, v.to_le_bytes().to_vec()),
+            LogValue::Bool(v) => (KV_TYPE_BOOL, [v as u8].to_vec()),
+            LogValue::Str(v) => (KV_TYPE_STR, length_prefixed(v.as_bytes())),
+            LogValue::Bytes(v) => (KV_TYPE_BYTES, length_prefixed(v)),
+        };
+        self.pairs.push(key.len() as u8);
+        self.pairs.extend_from_slice(key.as_bytes());
+        self.pairs.push(tag);
+        self.pairs.extend_from_slice(&bytes);
+        self.count += 1;
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    pub fn finish(self, msg: &[u8]) -> Vec<u8> {
+        let mut entry = length_prefixed(msg);
+        entry.push(self.count);
+        entry.extend_from_slice(&self.pairs);
+        entry
+    }
+}
+
+fn length_prefixed(data: &[u8]) -> Vec<u8> {
+    let data = &data[..data.len().min(u16::MAX as usize)];
+    let mut bytes = Vec::with_capacity(2 + data.len());
+    bytes.extend_from_slice(&(data.len() as u16).to_le_bytes());
+    bytes.extend_from_slice(data);
+    bytes
+}
+
+/// Encodes a message and its key-value pairs as a `LOG_ENTRY_FORMAT_KV` payload.
+pub fn encode_kv_entry(msg: &[u8], kvs: &[(&str, LogValue)]) -> Vec<u8> {
+    let mut encoder = KvEntryEncoder::default();
+    for (key, value) in kvs {
+        encoder.push(key, *value);
+    }
+    encoder.finish(msg)
+}
+
+/// Writes a structured entry carrying `kvs` so that the VMM can aggregate
+/// the entries without parsing the message.
+pub fn entrylog_kv(msg: &str, loglevel: Level, request_id: u64, kvs: &[(&str, LogValue)]) {
+    write_logentry(
+        &encode_kv_entry(msg.as_bytes(), kvs),
+        LOG_ENTRY_FORMAT_KV,
+        loglevel,
+        request_id,
+        true,
+    );
+}
+
 pub fn entrylog(msg: &Vec<u8>, loglevel: Level, request_id: u64) {
+    write_logentry(msg, LOG_ENTRY_FORMAT_TEXT, loglevel, request_id, true);
+}
+
+// `remember` keeps a copy of the entry for the crash reports
+fn write_logentry(msg: &[u8], format: u8, loglevel: Level, request_id: u64, remember: bool) {
     let logarea_created: bool = LOGGING_INFORMATION.logarea_created.load(Ordering::SeqCst);
     let log_max_level: u8 = LOGGING_INFORMATION.maxloglevel.load(Ordering::SeqCst);
     let log_level = loglevel_to_u8(loglevel);
@@ -352,13 +517,17 @@ pub fn entrylog(msg: &Vec<u8>, loglevel: Level, request_id: u64) {
                             log_entry_id: LOGGING_INFORMATION.logentry_id.load(Ordering::SeqCst),
                             mig_request_id: request_id,
                             loglevel: log_level,
-                            reserved: [0, 0, 0],
+                            format,
+                            reserved: [0, 0],
                             length: msg.len() as u32,
                         };
 
                         let bytes: &[u8] = logentryhdr.as_bytes();
                         data_buffer[currentendoffset..currentendoffset + LOGENTRYHEADERSIZE]
                             .copy_from_slice(&bytes[0..bytes.len()]);
+                        if remember {
+                            remember_entry(bytes, msg);
+                        }
                         currentendoffset += LOGENTRYHEADERSIZE;
                         data_buffer[currentendoffset..currentendoffset + msg.len()]
                             .copy_from_slice(&msg[0..msg.len()]);
@@ -448,13 +617,17 @@ pub fn entrylog(msg: &Vec<u8>, loglevel: Level, request_id: u64) {
                     log_entry_id: LOGGING_INFORMATION.logentry_id.load(Ordering::SeqCst),
                     mig_request_id: request_id,
                     loglevel: log_level,
-                    reserved: [0, 0, 0],
+                    format,
+                    reserved: [0, 0],
                     length: msg.len() as u32,
                 };
 
                 let bytes: &[u8] = logentryhdr.as_bytes();
                 data_buffer[currentendoffset..currentendoffset + LOGENTRYHEADERSIZE]
                     .copy_from_slice(&bytes[0..bytes.len()]);
+                if remember {
+                    remember_entry(bytes, msg);
+                }
                 currentendoffset += LOGENTRYHEADERSIZE;
                 data_buffer[currentendoffset..currentendoffset + msg.len()]
                     .copy_from_slice(&msg[0..msg.len()]);
@@ -480,13 +653,35 @@ struct RequestIdVisitor {
 
 impl VisitSource<'_> for RequestIdVisitor {
     fn visit_pair(&mut self, key: Key<'_>, value: Value<'_>) -> core::result::Result<(), Error> {
-        if key.as_str() == "migration_request_id" {
+        if key.as_str() == LOG_KEY_REQUEST_ID {
             self.mig_request_id = Some(value.to_u64().unwrap_or(MIGRATION_REQUEST_ID_SENTINEL));
         }
         Ok(())
     }
 }
 
+// Encodes the key-value pairs of a record, the request id goes to the entry header
+impl VisitSource<'_> for KvEntryEncoder {
+    fn visit_pair(&mut self, key: Key<'_>, value: Value<'_>) -> core::result::Result<(), Error> {
+        let key = key.as_str();
+        if key == LOG_KEY_REQUEST_ID {
+            return Ok(());
+        }
+        if let Some(v) = value.to_u64() {
+            self.push(key, LogValue::U64(v));
+        } else if let Some(v) = value.to_i64() {
+            self.push(key, LogValue::I64(v));
+        } else if let Some(v) = value.to_bool() {
+            self.push(key, LogValue::Bool(v));
+        } else if let Some(v) = value.to_borrowed_str() {
+            self.push(key, LogValue::Str(v));
+        } else {
+            self.push(key, LogValue::Str(&format!("{}", value)));
+        }
+        Ok(())
+    }
+}
+
 /// Custom logger that routes log messages to structured logging
 pub struct VmmLoggerBackend;
 
@@ -512,7 +707,25 @@ impl log::Log for VmmLoggerBackend {
                 .mig_request_id
                 .unwrap_or(MIGRATION_REQUEST_ID_SENTINEL);
 
-            entrylog(&msg.into_bytes(), record.level(), mig_request_id);
+            let mut encoder = KvEntryEncoder::default();
+            let _ = record.key_values().visit(&mut encoder);
+            if encoder.is_empty() {
+                write_logentry(
+                    msg.as_bytes(),
+                    LOG_ENTRY_FORMAT_TEXT,
+                    record.level(),
+                    mig_request_id,
+                    false,
+                );
+            } else {
+                write_logentry(
+                    &encoder.finish(msg.as_bytes()),
+                    LOG_ENTRY_FORMAT_KV,
+                    record.level(),
+                    mig_request_id,
+                    false,
+                );
+            }
 
             // Also output to debug console for development (skip in test mode to avoid issues)
             #[cfg(not(test))]
@@ -1079,6 +1292,45 @@ mod test {
         }
     }
 
+    #[test]
+    fn test_encode_kv_entry() {
+        let entry = encode_kv_entry(
+            b"msg",
+            &[
+                (LOG_KEY_ERROR_CODE, LogValue::U64(0x11)),
+                ("delta", LogValue::I64(-1)),
+                ("cached", LogValue::Bool(true)),
+                (LOG_KEY_PEER_FMSPC, LogValue::Bytes(&[1, 2, 3, 4, 5, 6])),
+            ],
+        );
+
+        let mut expected = Vec::new();
+        expected.extend_from_slice(&[3, 0]);
+        expected.extend_from_slice(b"msg");
+        expected.push(4);
+        expected.push(10);
+        expected.extend_from_slice(b"error_code");
+        expected.push(KV_TYPE_U64);
+        expected.extend_from_slice(&0x11u64.to_le_bytes());
+        expected.push(5);
+        expected.extend_from_slice(b"delta");
+        expected.push(KV_TYPE_I64);
+        expected.extend_from_slice(&(-1i64).to_le_bytes());
+        expected.push(6);
+        expected.extend_from_slice(b"cached");
+        expected.extend_from_slice(&[KV_TYPE_BOOL, 1]);
+        expected.push(10);
+        expected.extend_from_slice(b"peer_fmspc");
+        expected.extend_from_slice(&[KV_TYPE_BYTES, 6, 0, 1, 2, 3, 4, 5, 6]);
+        assert_eq!(entry, expected);
+
+        // Pairs beyond the count limit are dropped
+        let kvs: Vec<(&str, LogValue)> = (0..300).map(|_| ("k", LogValue::Bool(false))).collect();
+        let entry = encode_kv_entry(b"", &kvs);
+        assert_eq!(entry[2], u8::MAX);
+        assert_eq!(entry.len(), 3 + u8::MAX as usize * 4);
+    }
+
     #[tokio::test]
     async fn test_log_info() {
         // cargo test --features vmcall-raw --lib migration::logging::test::test_log_info -- --nocapture
@@ -1113,6 +1365,9 @@ mod test {
         // call log::info macro with migration request id
         log::info!(migration_request_id = 12345; "Test message 2");
 
+        // call log::error macro with structured key-values
+        log::error!(migration_request_id = 7, error_code = 3, phase = "connect"; "Test message 3");
+
         // Validate buffer structure
         let mut logareavector = LOGAREAPTR.lock();
         let data_buffer_ptr = logareavector[0];
@@ -1179,6 +1434,33 @@ mod test {
             core::str::from_utf8(second_entry_message_bytes).unwrap(),
             "Test message 2\n"
         );
+        assert_eq!(header_2.format, LOG_ENTRY_FORMAT_TEXT);
+
+        // Check the structured entry
+        let third_log_entry_start = second_entry_message_end + size_of::<LogEntryHeader>();
+        let header_bytes_3 = &data_buffer[second_entry_message_end..third_log_entry_start];
+        let header_3: &LogEntryHeader =
+            unsafe { &*(header_bytes_3.as_ptr() as *const LogEntryHeader) };
+        let mig_request_id_3 = header_3.mig_request_id;
+        let length_3 = header_3.length as usize;
+
+        assert_eq!(mig_request_id_3, 7);
+        assert_eq!(header_3.format, LOG_ENTRY_FORMAT_KV);
+        assert_eq!(
+            &data_buffer[third_log_entry_start..third_log_entry_start + length_3],
+            encode_kv_entry(
+                b"Test message 3\n",
+                &[
+                    (LOG_KEY_ERROR_CODE, LogValue::U64(3)),
+                    (LOG_KEY_PHASE, LogValue::Str("connect")),
+                ]
+            )
+        );
+
+        // The records of the log macros are not kept for the crash reports
+        assert!(!recent_entries()
+            .iter()
+            .any(|entry| entry.ends_with(b"Test message 1\n")));
 
         logareavector.clear();
         unsafe {
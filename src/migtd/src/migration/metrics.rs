@@ -0,0 +1,459 @@
+// Copyright (c) 2025 Intel Corporation
+//
+// SPDX-License-Identifier: BSD-2-Clause-Patent
+
+//! Per-phase timing of the key exchanges.
+//!
+//! A key exchange runs inside `with_metrics`, which keeps its record while
+//! the request is in flight. The code working for the request, including the
+//! peer certificate verification called by the TLS handshake, marks the
+//! phases of the record with `enter_phase` and the request id, as several
+//! requests are polled concurrently.
+//!
+//! The durations of the completed exchanges are aggregated into histograms
+//! which the VMM can query with the `GetMetrics` request.
+
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+use core::fmt;
+use core::future::Future;
+use lazy_static::lazy_static;
+use spin::Mutex;
+
+use crate::driver::ticks;
+
+/// Phases of a key exchange
+///
+/// The discriminants and names are reported to the VMM in the LogArea, the
+/// metrics record and the crash report, and decoded by `migtd-logarea`: a
+/// new phase is appended, existing ones are never renumbered.
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MigrationPhase {
+    /// Admission of the request and connection of the transport
+    Connect = 0,
+    /// Exchange of the migration policies ahead of the secure session
+    PreSession = 1,
+    /// Generation of the local quote and certificate
+    QuoteGeneration = 2,
+    /// Secure session establishment, excluding the verification of the peer
+    SecureSession = 3,
+    /// Verification of the peer quote and event log
+    QuoteVerification = 4,
+    /// Evaluation of the peer TCB against the migration policy
+    PolicyEvaluation = 5,
+    /// Migration version negotiation and MSK programming
+    SetMsk = 6,
+}
+
+pub const PHASE_COUNT: usize = 7;
+
+pub const PHASES: [MigrationPhase; PHASE_COUNT] = [
+    MigrationPhase::Connect,
+    MigrationPhase::PreSession,
+    MigrationPhase::QuoteGeneration,
+    MigrationPhase::SecureSession,
+    MigrationPhase::QuoteVerification,
+    MigrationPhase::PolicyEvaluation,
+    MigrationPhase::SetMsk,
+];
+
+impl MigrationPhase {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            MigrationPhase::Connect => "connect",
+            MigrationPhase::PreSession => "pre_session",
+            MigrationPhase::QuoteGeneration => "quote_generation",
+            MigrationPhase::SecureSession => "secure_session",
+            MigrationPhase::QuoteVerification => "quote_verification",
+            MigrationPhase::PolicyEvaluation => "policy_evaluation",
+            MigrationPhase::SetMsk => "set_msk",
+        }
+    }
+
+    /// Key of the phase duration in the structured LogArea entries
+    pub fn duration_key(&self) -> &'static str {
+        match self {
+            MigrationPhase::Connect => "connect_ms",
+            MigrationPhase::PreSession => "pre_session_ms",
+            MigrationPhase::QuoteGeneration => "quote_generation_ms",
+            MigrationPhase::SecureSession => "secure_session_ms",
+            MigrationPhase::QuoteVerification => "quote_verification_ms",
+            MigrationPhase::PolicyEvaluation => "policy_evaluation_ms",
+            MigrationPhase::SetMsk => "set_msk_ms",
+        }
+    }
+}
+
+/// Signature of the metrics record appended to the `StartMigration` status data
+pub const METRICS_RECORD_SIGNATURE: [u8; 8] = *b"MIGTDMTR";
+
+/// Size of a serialized `MigrationMetrics`
+pub const METRICS_RECORD_SIZE: usize = 32 + PHASE_COUNT * 8;
+
+/// Timing of one key exchange.
+///
+/// A phase may be entered several times, e.g. the secure session is resumed
+/// after the peer verification, its durations are accumulated.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MigrationMetrics {
+    pub request_id: u64,
+    /// Phase being run, the phase the exchange failed in once it is finished
+    pub phase: MigrationPhase,
+    pub durations_ms: [u64; PHASE_COUNT],
+    pub total_ms: u64,
+    started_at: u64,
+    phase_started_at: u64,
+}
+
+impl MigrationMetrics {
+    pub fn new(request_id: u64, now: u64) -> Self {
+        Self {
+            request_id,
+            phase: MigrationPhase::Connect,
+            durations_ms: [0; PHASE_COUNT],
+            total_ms: 0,
+            started_at: now,
+            phase_started_at: now,
+        }
+    }
+
+    /// Ends the current phase and starts `phase`.
+    pub fn enter(&mut self, phase: MigrationPhase, now: u64) {
+        self.close_phase(now);
+        self.phase = phase;
+    }
+
+    /// Ends the current phase and the exchange.
+    pub fn finish(&mut self, now: u64) {
+        self.close_phase(now);
+        self.total_ms = now.saturating_sub(self.started_at);
+    }
+
+    fn close_phase(&mut self, now: u64) {
+        self.durations_ms[self.phase as usize] += now.saturating_sub(self.phase_started_at);
+        self.phase_started_at = now;
+    }
+
+    /// Serializes the record, little-endian:
+    ///
+    /// signature (8) | request id (u64) | total ms (u64) | phase (u8) |
+    /// phase count (u8) | reserved (6) | duration ms of each phase (u64)
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(METRICS_RECORD_SIZE);
+        bytes.extend_from_slice(&METRICS_RECORD_SIGNATURE);
+        bytes.extend_from_slice(&self.request_id.to_le_bytes());
+        bytes.extend_from_slice(&self.total_ms.to_le_bytes());
+        bytes.push(self.phase as u8);
+        bytes.push(PHASE_COUNT as u8);
+        bytes.extend_from_slice(&[0u8; 6]);
+        for duration in self.durations_ms {
+            bytes.extend_from_slice(&duration.to_le_bytes());
+        }
+        bytes
+    }
+}
+
+impl fmt::Display for MigrationMetrics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "total {} ms", self.total_ms)?;
+        for phase in PHASES {
+            write!(
+                f,
+                ", {} {} ms",
+                phase.as_str(),
+                self.durations_ms[phase as usize]
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Upper bounds in milliseconds of the histogram buckets, the last bucket
+/// holds the longer durations.
+pub const HISTOGRAM_BUCKETS_MS: [u64; 16] = [
+    1,
+    2,
+    5,
+    10,
+    20,
+    50,
+    100,
+    200,
+    500,
+    1_000,
+    2_000,
+    5_000,
+    10_000,
+    20_000,
+    50_000,
+    u64::MAX,
+];
+
+/// Signature of the histograms reported for the `GetMetrics` request
+pub const HISTOGRAM_SIGNATURE: [u8; 8] = *b"MIGTDHST";
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Histogram {
+    pub count: u64,
+    pub sum_ms: u64,
+    pub max_ms: u64,
+    pub buckets: [u64; HISTOGRAM_BUCKETS_MS.len()],
+}
+
+impl Histogram {
+    pub fn record(&mut self, duration_ms: u64) {
+        let bucket = HISTOGRAM_BUCKETS_MS
+            .iter()
+            .position(|bound| duration_ms < *bound)
+            .unwrap_or(HISTOGRAM_BUCKETS_MS.len() - 1);
+        self.buckets[bucket] += 1;
+        self.count += 1;
+        self.sum_ms = self.sum_ms.saturating_add(duration_ms);
+        self.max_ms = self.max_ms.max(duration_ms);
+    }
+}
+
+/// Durations of the completed key exchanges: one histogram per phase, then
+/// one for the whole exchange.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct PhaseHistograms {
+    pub phases: [Histogram; PHASE_COUNT],
+    pub total: Histogram,
+}
+
+impl PhaseHistograms {
+    pub fn record(&mut self, metrics: &MigrationMetrics) {
+        for (histogram, duration) in self.phases.iter_mut().zip(metrics.durations_ms) {
+            histogram.record(duration);
+        }
+        self.total.record(metrics.total_ms);
+    }
+
+    /// Serializes the histograms, little-endian:
+    ///
+    /// signature (8) | bucket count (u8) | histogram count (u8) | reserved (6) |
+    /// bucket upper bounds (u64) | histograms
+    ///
+    /// where each histogram, in phase order then the whole exchange, is
+    /// count (u64) | sum ms (u64) | max ms (u64) | bucket counts (u64).
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(&HISTOGRAM_SIGNATURE);
+        bytes.push(HISTOGRAM_BUCKETS_MS.len() as u8);
+        bytes.push(PHASE_COUNT as u8 + 1);
+        bytes.extend_from_slice(&[0u8; 6]);
+        for bound in HISTOGRAM_BUCKETS_MS {
+            bytes.extend_from_slice(&bound.to_le_bytes());
+        }
+        for histogram in self.phases.iter().chain(core::iter::once(&self.total)) {
+            bytes.extend_from_slice(&histogram.count.to_le_bytes());
+            bytes.extend_from_slice(&histogram.sum_ms.to_le_bytes());
+            bytes.extend_from_slice(&histogram.max_ms.to_le_bytes());
+            for bucket in histogram.buckets {
+                bytes.extend_from_slice(&bucket.to_le_bytes());
+            }
+        }
+        bytes
+    }
+}
+
+impl fmt::Display for PhaseHistograms {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let series = PHASES
+            .iter()
+            .map(|phase| (phase.as_str(), &self.phases[*phase as usize]))
+            .chain(core::iter::once(("total", &self.total)));
+        for (name, histogram) in series {
+            let average = histogram.sum_ms.checked_div(histogram.count).unwrap_or(0);
+            writeln!(
+                f,
+                "{}: count {} avg {} ms max {} ms",
+                name, histogram.count, average, histogram.max_ms
+            )?;
+        }
+        Ok(())
+    }
+}
+
+lazy_static! {
+    static ref IN_FLIGHT_METRICS: Mutex<BTreeMap<u64, MigrationMetrics>> =
+        Mutex::new(BTreeMap::new());
+    pub static ref HISTOGRAMS: Mutex<PhaseHistograms> = Mutex::new(PhaseHistograms::default());
+}
+
+// Record of an in-flight request, dropped with the request if it is canceled
+struct InFlightMetrics {
+    request_id: u64,
+}
+
+impl InFlightMetrics {
+    fn register(request_id: u64) -> Self {
+        IN_FLIGHT_METRICS
+            .lock()
+            .insert(request_id, MigrationMetrics::new(request_id, ticks::now()));
+        Self { request_id }
+    }
+
+    fn finish(self) -> MigrationMetrics {
+        let mut metrics = IN_FLIGHT_METRICS
+            .lock()
+            .remove(&self.request_id)
+            .unwrap_or_else(|| MigrationMetrics::new(self.request_id, ticks::now()));
+        metrics.finish(ticks::now());
+        HISTOGRAMS.lock().record(&metrics);
+        metrics
+    }
+}
+
+impl Drop for InFlightMetrics {
+    fn drop(&mut self) {
+        IN_FLIGHT_METRICS.lock().remove(&self.request_id);
+    }
+}
+
+/// Runs the key exchange of `request_id` and returns its timing along with
+/// its output.
+pub async fn with_metrics<F: Future>(request_id: u64, future: F) -> (F::Output, MigrationMetrics) {
+    let in_flight = InFlightMetrics::register(request_id);
+    let output = future.await;
+    (output, in_flight.finish())
+}
+
+/// Starts `phase` for `request_id`, ignored if the request is not timed.
+pub fn enter_phase(request_id: u64, phase: MigrationPhase) {
+    if let Some(metrics) = IN_FLIGHT_METRICS.lock().get_mut(&request_id) {
+        metrics.enter(phase, ticks::now());
+    }
+}
+
+/// Returns the request which entered a phase last and that phase, if any.
+///
+/// The requests are polled concurrently, so this is the best guess of the
+/// request MigTD works for, reported in the crash report. Nothing is
+/// returned if the metrics are being updated, e.g. when MigTD panics in
+/// `enter_phase`.
+pub fn current_phase() -> Option<(u64, MigrationPhase)> {
+    let in_flight = IN_FLIGHT_METRICS.try_lock()?;
+    in_flight
+        .values()
+        .max_by_key(|metrics| metrics.phase_started_at)
+        .map(|metrics| (metrics.request_id, metrics.phase))
+}
+
+/// Serialized histograms of the completed key exchanges, optionally reset
+/// once they have been read.
+pub fn histogram_data(reset: bool) -> Vec<u8> {
+    let mut histograms = HISTOGRAMS.lock();
+    let data = histograms.to_bytes();
+    if reset {
+        *histograms = PhaseHistograms::default();
+    }
+    data
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_phase_durations() {
+        let mut metrics = MigrationMetrics::new(1, 100);
+        metrics.enter(MigrationPhase::QuoteGeneration, 110);
+        metrics.enter(MigrationPhase::SecureSession, 150);
+        metrics.enter(MigrationPhase::QuoteVerification, 160);
+        metrics.enter(MigrationPhase::PolicyEvaluation, 200);
+        metrics.enter(MigrationPhase::SecureSession, 205);
+        metrics.enter(MigrationPhase::SetMsk, 230);
+        metrics.finish(231);
+
+        assert_eq!(metrics.durations_ms, [10, 0, 40, 35, 40, 5, 1]);
+        assert_eq!(metrics.total_ms, 131);
+        assert_eq!(metrics.phase, MigrationPhase::SetMsk);
+
+        let bytes = metrics.to_bytes();
+        assert_eq!(bytes.len(), METRICS_RECORD_SIZE);
+        assert_eq!(&bytes[..8], &METRICS_RECORD_SIGNATURE);
+        assert_eq!(&bytes[16..24], &131u64.to_le_bytes());
+        assert_eq!(bytes[24], MigrationPhase::SetMsk as u8);
+        assert_eq!(bytes[25], PHASE_COUNT as u8);
+        assert_eq!(&bytes[32 + 3 * 8..32 + 4 * 8], &35u64.to_le_bytes());
+    }
+
+    #[test]
+    fn test_phase_names() {
+        let names = [
+            "connect",
+            "pre_session",
+            "quote_generation",
+            "secure_session",
+            "quote_verification",
+            "policy_evaluation",
+            "set_msk",
+        ];
+        for (index, phase) in PHASES.iter().enumerate() {
+            assert_eq!(*phase as usize, index);
+            assert_eq!(phase.as_str(), names[index]);
+            assert_eq!(phase.duration_key(), alloc::format!("{}_ms", names[index]));
+        }
+    }
+
+    #[test]
+    fn test_histograms() {
+        let mut histogram = Histogram::default();
+        histogram.record(0);
+        histogram.record(1);
+        histogram.record(999);
+        histogram.record(u64::MAX);
+        assert_eq!(histogram.buckets[0], 1);
+        assert_eq!(histogram.buckets[1], 1);
+        assert_eq!(histogram.buckets[9], 1);
+        assert_eq!(histogram.buckets[15], 1);
+        assert_eq!(histogram.count, 4);
+        assert_eq!(histogram.max_ms, u64::MAX);
+        assert_eq!(histogram.sum_ms, u64::MAX);
+
+        let mut histograms = PhaseHistograms::default();
+        let mut metrics = MigrationMetrics::new(1, 0);
+        metrics.enter(MigrationPhase::SecureSession, 30);
+        metrics.finish(40);
+        histograms.record(&metrics);
+        assert_eq!(
+            histograms.phases[MigrationPhase::Connect as usize].buckets[4],
+            1
+        );
+        assert_eq!(histograms.total.sum_ms, 40);
+
+        let bytes = histograms.to_bytes();
+        let buckets = HISTOGRAM_BUCKETS_MS.len();
+        assert_eq!(
+            bytes.len(),
+            16 + buckets * 8 + (PHASE_COUNT + 1) * (3 + buckets) * 8
+        );
+        assert_eq!(&bytes[..8], &HISTOGRAM_SIGNATURE);
+        assert_eq!(bytes[9], PHASE_COUNT as u8 + 1);
+    }
+
+    #[tokio::test]
+    async fn test_with_metrics() {
+        // Phases of requests which are not timed are ignored
+        enter_phase(42, MigrationPhase::SetMsk);
+
+        let ((output, first), second) = with_metrics(43, async {
+            with_metrics(42, async {
+                enter_phase(42, MigrationPhase::QuoteVerification);
+                enter_phase(43, MigrationPhase::SetMsk);
+                7
+            })
+            .await
+        })
+        .await;
+        assert_eq!(output, 7);
+        assert_eq!(first.request_id, 42);
+        assert_eq!(first.phase, MigrationPhase::QuoteVerification);
+        assert_eq!(second.request_id, 43);
+        assert_eq!(second.phase, MigrationPhase::SetMsk);
+        assert!(IN_FLIGHT_METRICS.lock().get(&42).is_none());
+        assert!(IN_FLIGHT_METRICS.lock().get(&43).is_none());
+    }
+}
@@ -2,20 +2,21 @@
 //
 // SPDX-License-Identifier: BSD-2-Clause-Patent
 
+pub mod budget;
 pub mod data;
 pub mod event;
+pub mod log_control;
 pub mod logging;
+pub mod metrics;
+pub mod redact;
 #[cfg(feature = "main")]
 pub mod session;
+pub mod transcript;
 
 use crate::driver::ticks::TimeoutError;
 use crate::ratls::RatlsError;
-use crate::ratls::{
-    INVALID_MIG_POLICY_ERROR, MIG_POLICY_UNSATISFIED_ERROR, MUTUAL_ATTESTATION_ERROR,
-};
-use alloc::string::ToString;
 use alloc::vec::Vec;
-use crypto::Error as CryptoError;
+use crypto::{Error as CryptoError, PeerCertError};
 use r_efi::efi::Guid;
 use rust_std_stub::io;
 use scroll::{Pread, Pwrite};
@@ -99,7 +100,7 @@ pub struct MigtdMigrationInformation {
 }
 
 #[repr(C)]
-#[derive(Debug, Pread, Pwrite)]
+#[derive(Pread, Pwrite)]
 #[cfg(feature = "vmcall-raw")]
 pub struct ReportInfo {
     // ID for the migration request, which can be used in TDG.VP.VMCALL
@@ -108,6 +109,43 @@ pub struct ReportInfo {
     pub reportdata: [u8; 64],
 }
 
+#[cfg(feature = "vmcall-raw")]
+impl core::fmt::Debug for ReportInfo {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("ReportInfo")
+            .field("mig_request_id", &self.mig_request_id)
+            .field("reportdata", &redact::Redacted(&self.reportdata))
+            .finish()
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Pread, Pwrite)]
+#[cfg(feature = "vmcall-raw")]
+pub struct BatchMigrationHeader {
+    // ID for the batch migration request, the status of all the target TDs is
+    // reported with it
+    pub mig_request_id: u64,
+
+    // If set, current MigTD is MigTD-s else current MigTD is MigTD-d
+    pub migration_source: u8,
+    pub reserved: [u8; 3],
+
+    // Number of BatchMigrationEntry following the header
+    pub count: u32,
+}
+
+#[repr(C)]
+#[derive(Debug, Pread, Pwrite)]
+#[cfg(feature = "vmcall-raw")]
+pub struct BatchMigrationEntry {
+    // UUID of target TD
+    pub target_td_uuid: [u64; 4],
+
+    // Binding handle for the MigTD and the target TD
+    pub binding_handle: u64,
+}
+
 #[repr(C)]
 #[derive(Debug, Pread, Pwrite)]
 #[cfg(feature = "vmcall-raw")]
@@ -119,6 +157,49 @@ pub struct EnableLogAreaInfo {
     pub reserved: [u8; 7],
 }
 
+#[repr(C)]
+#[derive(Debug, Pread, Pwrite)]
+#[cfg(feature = "vmcall-raw")]
+pub struct ShutdownInfo {
+    // ID for the shutdown request, which can be used in TDG.VP.VMCALL
+    // <Service.MigTD.ReportStatus>
+    pub mig_request_id: u64,
+
+    // Time in milliseconds given to the in-flight requests to complete before
+    // they are canceled
+    pub drain_timeout: u32,
+    pub reserved: [u8; 4],
+}
+
+#[repr(C)]
+#[derive(Debug, Pread, Pwrite)]
+#[cfg(feature = "vmcall-raw")]
+pub struct GetMetricsInfo {
+    // ID for the metrics request, which can be used in TDG.VP.VMCALL
+    // <Service.MigTD.ReportStatus>
+    pub mig_request_id: u64,
+
+    // Reset the histograms once they have been reported when non-zero
+    pub reset: u8,
+    pub reserved: [u8; 7],
+}
+
+#[repr(C)]
+#[derive(Debug, Pread, Pwrite)]
+#[cfg(feature = "vmcall-raw")]
+pub struct GetTranscriptInfo {
+    // ID for the transcript request, which can be used in TDG.VP.VMCALL
+    // <Service.MigTD.ReportStatus>
+    pub mig_request_id: u64,
+
+    // ID of the migration request whose transcript is read
+    pub transcript_request_id: u64,
+
+    // Offset in the transcript of the chunk to read
+    pub offset: u32,
+    pub reserved: [u8; 4],
+}
+
 #[repr(C)]
 #[derive(Debug, Pread, Pwrite)]
 pub struct MigtdStreamSocketInfo {
@@ -216,7 +297,8 @@ impl From<RatlsError> for MigrationResult {
             RatlsError::Crypto(_)
             | RatlsError::X509(_)
             | RatlsError::InvalidEventlog
-            | RatlsError::InvalidPolicy => MigrationResult::SecureSessionError,
+            | RatlsError::InvalidPolicy
+            | RatlsError::InvalidTime => MigrationResult::SecureSessionError,
             RatlsError::TdxModule(_) => MigrationResult::TdxModuleError,
             RatlsError::GetQuote | RatlsError::VerifyQuote => {
                 MigrationResult::MutualAttestationError
@@ -225,43 +307,35 @@ impl From<RatlsError> for MigrationResult {
     }
 }
 
+impl From<PeerCertError> for MigrationResult {
+    fn from(e: PeerCertError) -> Self {
+        match e {
+            PeerCertError::MutualAttestation => MigrationResult::MutualAttestationError,
+            PeerCertError::MismatchPublicKey | PeerCertError::OutsideValidity => {
+                MigrationResult::SecureSessionError
+            }
+            PeerCertError::InvalidPolicy(_) => MigrationResult::InvalidPolicyError,
+            PeerCertError::PolicyUnsatisfied(_) => MigrationResult::PolicyUnsatisfiedError,
+            PeerCertError::OutOfResource => MigrationResult::OutOfResource,
+        }
+    }
+}
+
 impl From<CryptoError> for MigrationResult {
     fn from(e: CryptoError) -> Self {
         match e {
-            CryptoError::TlsVerifyPeerCert(desc) => {
-                if desc.as_str() == MIG_POLICY_UNSATISFIED_ERROR {
-                    MigrationResult::PolicyUnsatisfiedError
-                } else if desc.as_str() == INVALID_MIG_POLICY_ERROR {
-                    MigrationResult::InvalidPolicyError
-                } else if desc.as_str() == MUTUAL_ATTESTATION_ERROR {
-                    MigrationResult::MutualAttestationError
-                } else {
-                    MigrationResult::SecureSessionError
-                }
-            }
+            CryptoError::TlsVerifyPeerCert(e) => e.into(),
+            CryptoError::TlsTransport => MigrationResult::NetworkError,
             _ => MigrationResult::SecureSessionError,
         }
     }
 }
 
+// Only the transports report `io::Error`, failures of the secure session are
+// `CryptoError`s.
 impl From<io::Error> for MigrationResult {
-    fn from(e: io::Error) -> Self {
-        match e.kind() {
-            io::ErrorKind::InvalidData => {
-                let desc = e.to_string();
-
-                if desc.contains(MIG_POLICY_UNSATISFIED_ERROR) {
-                    MigrationResult::PolicyUnsatisfiedError
-                } else if desc.contains(INVALID_MIG_POLICY_ERROR) {
-                    MigrationResult::InvalidPolicyError
-                } else if desc.contains(MUTUAL_ATTESTATION_ERROR) {
-                    MigrationResult::MutualAttestationError
-                } else {
-                    MigrationResult::SecureSessionError
-                }
-            }
-            _ => MigrationResult::NetworkError,
-        }
+    fn from(_: io::Error) -> Self {
+        MigrationResult::NetworkError
     }
 }
 
@@ -282,3 +356,56 @@ impl From<TimeoutError> for MigrationResult {
         MigrationResult::NetworkError
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ratls::VerifyPeerError;
+    use policy::PolicyError;
+
+    #[test]
+    fn test_verify_peer_error_result() {
+        let cases = [
+            (
+                VerifyPeerError::MutualAttestation,
+                MigrationResult::MutualAttestationError,
+            ),
+            (
+                VerifyPeerError::MismatchPublicKey,
+                MigrationResult::SecureSessionError,
+            ),
+            (
+                VerifyPeerError::OutsideValidity,
+                MigrationResult::SecureSessionError,
+            ),
+            (
+                VerifyPeerError::InvalidPolicy(PolicyError::PolicyHashMismatch),
+                MigrationResult::InvalidPolicyError,
+            ),
+            (
+                VerifyPeerError::PolicyUnsatisfied(PolicyError::SvnMismatch),
+                MigrationResult::PolicyUnsatisfiedError,
+            ),
+            (
+                VerifyPeerError::OutOfResource,
+                MigrationResult::OutOfResource,
+            ),
+        ];
+        for (reason, expected) in cases {
+            assert_eq!(MigrationResult::from(CryptoError::from(reason)), expected);
+        }
+
+        assert_eq!(
+            MigrationResult::from(CryptoError::TlsTransport),
+            MigrationResult::NetworkError
+        );
+
+        // The policy error is carried as its variant
+        assert_eq!(
+            PeerCertError::from(VerifyPeerError::PolicyUnsatisfied(
+                PolicyError::PlatformNotFound("fmspc".into())
+            )),
+            PeerCertError::PolicyUnsatisfied(crypto::PolicyErrorCode::PlatformNotFound)
+        );
+    }
+}
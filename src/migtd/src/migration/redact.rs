@@ -0,0 +1,96 @@
+// Copyright (c) 2025 Intel Corporation
+//
+// SPDX-License-Identifier: BSD-2-Clause-Patent
+
+//! Redaction of sensitive data in the logs.
+//!
+//! Types holding key material, quotes or report data implement a `Debug`
+//! which does not print their content. Sensitive bytes held by other types are
+//! formatted with `Redacted`, which only prints their size.
+//!
+//! The migration session key and the certificate signing key only print their
+//! type, and the types holding them leave them out of their `Debug`. The tests
+//! of this module check the `Debug` output of each of these types.
+
+use core::fmt;
+
+/// Formats sensitive bytes, such as a quote or report data, with their size only
+pub struct Redacted<'a>(pub &'a [u8]);
+
+impl fmt::Debug for Redacted<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "<redacted {} bytes>", self.0.len())
+    }
+}
+
+impl fmt::Display for Redacted<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self, f)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::migration::data::MigrationSessionKey;
+    use alloc::format;
+    use crypto::ecdsa::EcdsaPk;
+
+    #[test]
+    fn test_redacted() {
+        let report_data = [0x5au8; 64];
+        assert_eq!(
+            format!("{:?}", Redacted(&report_data)),
+            "<redacted 64 bytes>"
+        );
+        assert_eq!(format!("{}", Redacted(&[])), "<redacted 0 bytes>");
+    }
+
+    #[test]
+    fn test_migration_session_key_debug() {
+        let mut key = MigrationSessionKey::new();
+        key.fields = [0x5a5a_5a5a_5a5a_5a5a; 4];
+        assert_eq!(
+            format!("{:?}", key),
+            "MigrationSessionKey(<redacted 32 bytes>)"
+        );
+    }
+
+    #[test]
+    fn test_ecdsa_pk_debug() {
+        let signing_key = EcdsaPk::new().unwrap();
+        assert_eq!(format!("{:?}", signing_key), "EcdsaPk(<redacted>)");
+    }
+
+    #[cfg(feature = "main")]
+    #[test]
+    fn test_exchange_information_debug() {
+        use crate::migration::session::ExchangeInformation;
+
+        let mut info = ExchangeInformation {
+            min_ver: 1,
+            max_ver: 2,
+            ..Default::default()
+        };
+        info.key.fields = [0x5a5a_5a5a_5a5a_5a5a; 4];
+        assert_eq!(
+            format!("{:?}", info),
+            "ExchangeInformation { min_ver: 1, max_ver: 2, .. }"
+        );
+    }
+
+    #[cfg(feature = "vmcall-raw")]
+    #[test]
+    fn test_report_info_debug() {
+        use crate::migration::ReportInfo;
+
+        let info = ReportInfo {
+            mig_request_id: 7,
+            reportdata: [0x5a; 64],
+        };
+        assert_eq!(
+            format!("{:?}", info),
+            "ReportInfo { mig_request_id: 7, reportdata: <redacted 64 bytes> }"
+        );
+    }
+}
@@ -9,12 +9,16 @@ use alloc::boxed::Box;
 use alloc::collections::BTreeSet;
 #[cfg(feature = "policy_v2")]
 use async_io::{AsyncRead, AsyncWrite};
-#[cfg(feature = "vmcall-raw")]
-use core::sync::atomic::AtomicBool;
-#[cfg(any(feature = "vmcall-interrupt", feature = "vmcall-raw"))]
-use core::sync::atomic::Ordering;
+use core::sync::atomic::{AtomicBool, Ordering};
+use core::task::{Poll, Waker};
 use core::time::Duration;
-use core::{future::poll_fn, mem::size_of, task::Poll};
+use core::{
+    future::{poll_fn, Future},
+    mem::size_of,
+    pin::pin,
+};
+#[cfg(feature = "runtime_measurement")]
+use crypto::SHA384_DIGEST_SIZE;
 #[cfg(any(feature = "vmcall-interrupt", feature = "vmcall-raw"))]
 use event::VMCALL_SERVICE_FLAG;
 use lazy_static::lazy_static;
@@ -30,14 +34,33 @@ use tdx_tdcall::{
 #[cfg(feature = "vmcall-raw")]
 use tdx_tdcall::{tdreport::TdxReport, tdreport::TD_REPORT_ADDITIONAL_DATA_SIZE};
 use zerocopy::AsBytes;
+use zeroize::{Zeroize, ZeroizeOnDrop};
 type Result<T> = core::result::Result<T, MigrationResult>;
 
+use super::budget::{RequestArena, SECURE_SESSION_FOOTPRINT};
+use super::log_control::LogControl;
 #[cfg(feature = "vmcall-raw")]
 use super::logging::entrylog;
+#[cfg(all(feature = "main", feature = "vmcall-raw"))]
+use super::logging::{
+    entrylog_kv, LogValue, LOG_KEY_DURATION_MS, LOG_KEY_ERROR_CODE, LOG_KEY_PHASE,
+};
+#[cfg(feature = "main")]
+use super::metrics::{self, MigrationPhase};
+#[cfg(all(feature = "main", feature = "vmcall-raw"))]
+use super::metrics::{MigrationMetrics, PHASES};
+#[cfg(feature = "policy_v2")]
+use super::transcript::{self, RecordKind};
 use super::{data::*, *};
 use crate::driver::ticks::with_timeout;
+#[cfg(feature = "runtime_measurement")]
+use crate::event_log;
 #[cfg(not(feature = "spdm_attestation"))]
 use crate::ratls;
+#[cfg(feature = "policy_v2")]
+use crate::ratls::{
+    target_tds_digest, HelloContext, SessionBinding, SESSION_NONCE_SIZE, TARGET_TDS_DIGEST_SIZE,
+};
 #[cfg(feature = "spdm_attestation")]
 use crate::spdm;
 
@@ -56,9 +79,17 @@ const GSM_FIELD_MAX_IMPORT_VERSION: u64 = 0x2000000100000004;
 
 #[cfg(feature = "vmcall-raw")]
 #[repr(C, align(1024))]
-#[derive(Debug)]
 struct TdxReportBuf(TdxReport);
 
+#[cfg(feature = "vmcall-raw")]
+impl core::fmt::Debug for TdxReportBuf {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_tuple("TdxReportBuf")
+            .field(&redact::Redacted(self.0.as_bytes()))
+            .finish()
+    }
+}
+
 #[cfg(feature = "vmcall-raw")]
 #[repr(C, align(64))]
 struct AdditionalDataBuf([u8; TD_REPORT_ADDITIONAL_DATA_SIZE]);
@@ -76,6 +107,11 @@ pub enum DataStatusOperation {
     StartMigration = 1,
     GetReportData = 3,
     EnableLogArea = 4,
+    StartBatchMigration = 5,
+    Shutdown = 6,
+    GetMetrics = 7,
+    SetLogLevel = 8,
+    GetTranscript = 9,
 }
 
 #[cfg(feature = "vmcall-raw")]
@@ -92,13 +128,34 @@ lazy_static! {
     pub static ref REQUESTS: Mutex<BTreeSet<u64>> = Mutex::new(BTreeSet::new());
 }
 
-#[derive(Default)]
+lazy_static! {
+    // Requests still in flight when the drain of a shutdown timed out
+    static ref CANCELED_REQUESTS: Mutex<BTreeSet<u64>> = Mutex::new(BTreeSet::new());
+}
+
+// Set once a shutdown request is received, no new request is accepted after it
+static SHUTTING_DOWN: AtomicBool = AtomicBool::new(false);
+
+// Woken each time a request completes while a shutdown drains the requests
+static DRAIN_WAKER: Mutex<Option<Waker>> = Mutex::new(None);
+
+#[derive(Default, Zeroize, ZeroizeOnDrop)]
 pub struct ExchangeInformation {
     pub min_ver: u16,
     pub max_ver: u16,
     pub key: MigrationSessionKey,
 }
 
+impl core::fmt::Debug for ExchangeInformation {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        // The key is left out, not even its size is printed
+        f.debug_struct("ExchangeInformation")
+            .field("min_ver", &self.min_ver)
+            .field("max_ver", &self.max_ver)
+            .finish_non_exhaustive()
+    }
+}
+
 #[cfg(not(feature = "spdm_attestation"))]
 impl ExchangeInformation {
     fn as_bytes(&self) -> &[u8] {
@@ -393,6 +450,168 @@ pub async fn wait_for_request() -> Result<WaitForRequestResponse> {
                 REQUESTS.lock().insert(mig_request_id);
                 Poll::Ready(Ok(WaitForRequestResponse::EnableLogArea(wfr_info)))
             }
+        } else if operation == DataStatusOperation::StartBatchMigration as u8 {
+            if data_length as usize > data_buffer.len() - reqbufferhdrlen {
+                entrylog(&format!("wait_for_request: StartBatchMigration operation data length too large - {:x}\n", data_length).into_bytes(), Level::Debug, DEFAULT_MIGREQUEST_ID);
+                log::debug!("wait_for_request: StartBatchMigration operation data length too large - {}\n", data_length);
+                return Poll::Pending;
+            }
+            let slice = &data_buffer[reqbufferhdrlen..reqbufferhdrlen + data_length as usize];
+            let wfr_info = if let Some(wfr_info) = read_batch_mig_info(slice) {
+                wfr_info
+            } else {
+                let mig_request_id = if data_length >= size_of::<u64>() as u32 {
+                    u64::from_le_bytes(slice[0..8].try_into().unwrap())
+                } else {
+                    DEFAULT_MIGREQUEST_ID
+                };
+                entrylog(&format!("wait_for_request: StartBatchMigration operation invalid data - length {:x}\n", data_length).into_bytes(), Level::Debug, mig_request_id);
+                log::debug!("wait_for_request: StartBatchMigration operation invalid data - length {}\n", data_length);
+                return Poll::Pending;
+            };
+            let mig_request_id = wfr_info.mig_request_id;
+
+            VMCALL_MIG_REPORTSTATUS_FLAGS
+                .lock()
+                .insert(mig_request_id, AtomicBool::new(false));
+
+            if REQUESTS.lock().contains(&mig_request_id) {
+                Poll::Pending
+            } else {
+                REQUESTS.lock().insert(mig_request_id);
+                Poll::Ready(Ok(WaitForRequestResponse::StartBatchMigration(wfr_info)))
+            }
+        } else if operation == DataStatusOperation::Shutdown as u8 {
+            let expected_datalength = size_of::<ShutdownInfo>();
+            if data_length != expected_datalength as u32 {
+                if data_length >= size_of::<u64>() as u32 {
+                    let slice = &data_buffer[reqbufferhdrlen..reqbufferhdrlen + data_length as usize];
+                    let mig_request_id = u64::from_le_bytes(slice[0..8].try_into().unwrap());
+                    entrylog(&format!("wait_for_request: Shutdown operation incorrect data length - expected {:x} actual {:x}\n", expected_datalength, data_length).into_bytes(), Level::Debug, mig_request_id);
+                } else {
+                    entrylog(&format!("wait_for_request: Shutdown operation incorrect data length - expected {:x} actual {:x}\n", expected_datalength, data_length).into_bytes(), Level::Debug, DEFAULT_MIGREQUEST_ID);
+                }
+                log::debug!("wait_for_request: Shutdown operation incorrect data length - expected {} actual {}\n", expected_datalength, data_length);
+                return Poll::Pending;
+            }
+
+            let slice = &data_buffer[reqbufferhdrlen..reqbufferhdrlen + data_length as usize];
+            let mig_request_id = u64::from_le_bytes(slice[0..8].try_into().unwrap());
+
+            VMCALL_MIG_REPORTSTATUS_FLAGS
+                .lock()
+                .insert(mig_request_id, AtomicBool::new(false));
+
+            let wfr_info = ShutdownInfo {
+                mig_request_id,
+                drain_timeout: u32::from_le_bytes(slice[8..12].try_into().unwrap()),
+                reserved: slice[12..16].try_into().unwrap(),
+            };
+
+            if REQUESTS.lock().contains(&mig_request_id) {
+                Poll::Pending
+            } else {
+                REQUESTS.lock().insert(mig_request_id);
+                Poll::Ready(Ok(WaitForRequestResponse::Shutdown(wfr_info)))
+            }
+        } else if operation == DataStatusOperation::GetMetrics as u8 {
+            let expected_datalength = size_of::<GetMetricsInfo>();
+            if data_length != expected_datalength as u32 {
+                if data_length >= size_of::<u64>() as u32 {
+                    let slice = &data_buffer[reqbufferhdrlen..reqbufferhdrlen + data_length as usize];
+                    let mig_request_id = u64::from_le_bytes(slice[0..8].try_into().unwrap());
+                    entrylog(&format!("wait_for_request: GetMetrics operation incorrect data length - expected {:x} actual {:x}\n", expected_datalength, data_length).into_bytes(), Level::Debug, mig_request_id);
+                } else {
+                    entrylog(&format!("wait_for_request: GetMetrics operation incorrect data length - expected {:x} actual {:x}\n", expected_datalength, data_length).into_bytes(), Level::Debug, DEFAULT_MIGREQUEST_ID);
+                }
+                log::debug!("wait_for_request: GetMetrics operation incorrect data length - expected {} actual {}\n", expected_datalength, data_length);
+                return Poll::Pending;
+            }
+
+            let slice = &data_buffer[reqbufferhdrlen..reqbufferhdrlen + data_length as usize];
+            let mig_request_id = u64::from_le_bytes(slice[0..8].try_into().unwrap());
+
+            VMCALL_MIG_REPORTSTATUS_FLAGS
+                .lock()
+                .insert(mig_request_id, AtomicBool::new(false));
+
+            let wfr_info = GetMetricsInfo {
+                mig_request_id,
+                reset: slice[8],
+                reserved: slice[9..16].try_into().unwrap(),
+            };
+
+            if REQUESTS.lock().contains(&mig_request_id) {
+                Poll::Pending
+            } else {
+                REQUESTS.lock().insert(mig_request_id);
+                Poll::Ready(Ok(WaitForRequestResponse::GetMetrics(wfr_info)))
+            }
+        } else if operation == DataStatusOperation::SetLogLevel as u8 {
+            if data_length as usize > data_buffer.len() - reqbufferhdrlen {
+                entrylog(&format!("wait_for_request: SetLogLevel operation data length too large - {:x}\n", data_length).into_bytes(), Level::Debug, DEFAULT_MIGREQUEST_ID);
+                log::debug!("wait_for_request: SetLogLevel operation data length too large - {}\n", data_length);
+                return Poll::Pending;
+            }
+            let slice = &data_buffer[reqbufferhdrlen..reqbufferhdrlen + data_length as usize];
+            let wfr_info = if let Some(wfr_info) = LogControl::read_from(slice) {
+                wfr_info
+            } else {
+                let mig_request_id = if data_length >= size_of::<u64>() as u32 {
+                    u64::from_le_bytes(slice[0..8].try_into().unwrap())
+                } else {
+                    DEFAULT_MIGREQUEST_ID
+                };
+                entrylog(&format!("wait_for_request: SetLogLevel operation invalid data - length {:x}\n", data_length).into_bytes(), Level::Debug, mig_request_id);
+                log::debug!("wait_for_request: SetLogLevel operation invalid data - length {}\n", data_length);
+                return Poll::Pending;
+            };
+            let mig_request_id = wfr_info.mig_request_id;
+
+            VMCALL_MIG_REPORTSTATUS_FLAGS
+                .lock()
+                .insert(mig_request_id, AtomicBool::new(false));
+
+            if REQUESTS.lock().contains(&mig_request_id) {
+                Poll::Pending
+            } else {
+                REQUESTS.lock().insert(mig_request_id);
+                Poll::Ready(Ok(WaitForRequestResponse::SetLogLevel(wfr_info)))
+            }
+        } else if operation == DataStatusOperation::GetTranscript as u8 {
+            let expected_datalength = size_of::<GetTranscriptInfo>();
+            if data_length != expected_datalength as u32 {
+                if data_length >= size_of::<u64>() as u32 {
+                    let slice = &data_buffer[reqbufferhdrlen..reqbufferhdrlen + data_length as usize];
+                    let mig_request_id = u64::from_le_bytes(slice[0..8].try_into().unwrap());
+                    entrylog(&format!("wait_for_request: GetTranscript operation incorrect data length - expected {:x} actual {:x}\n", expected_datalength, data_length).into_bytes(), Level::Debug, mig_request_id);
+                } else {
+                    entrylog(&format!("wait_for_request: GetTranscript operation incorrect data length - expected {:x} actual {:x}\n", expected_datalength, data_length).into_bytes(), Level::Debug, DEFAULT_MIGREQUEST_ID);
+                }
+                log::debug!("wait_for_request: GetTranscript operation incorrect data length - expected {} actual {}\n", expected_datalength, data_length);
+                return Poll::Pending;
+            }
+
+            let slice = &data_buffer[reqbufferhdrlen..reqbufferhdrlen + data_length as usize];
+            let mig_request_id = u64::from_le_bytes(slice[0..8].try_into().unwrap());
+
+            VMCALL_MIG_REPORTSTATUS_FLAGS
+                .lock()
+                .insert(mig_request_id, AtomicBool::new(false));
+
+            let wfr_info = GetTranscriptInfo {
+                mig_request_id,
+                transcript_request_id: u64::from_le_bytes(slice[8..16].try_into().unwrap()),
+                offset: u32::from_le_bytes(slice[16..20].try_into().unwrap()),
+                reserved: slice[20..24].try_into().unwrap(),
+            };
+
+            if REQUESTS.lock().contains(&mig_request_id) {
+                Poll::Pending
+            } else {
+                REQUESTS.lock().insert(mig_request_id);
+                Poll::Ready(Ok(WaitForRequestResponse::GetTranscript(wfr_info)))
+            }
         } else {
             Poll::Pending
         }
@@ -401,7 +620,7 @@ pub async fn wait_for_request() -> Result<WaitForRequestResponse> {
 }
 
 #[cfg(not(feature = "vmcall-raw"))]
-pub async fn wait_for_request() -> Result<MigrationInformation> {
+pub async fn wait_for_request() -> Result<WaitForRequestResponse> {
     // Allocate shared page for command and response buffer
     let mut cmd_mem = SharedMemory::new(1).ok_or(MigrationResult::OutOfResource)?;
     let mut rsp_mem = SharedMemory::new(1).ok_or(MigrationResult::OutOfResource)?;
@@ -474,8 +693,31 @@ pub async fn wait_for_request() -> Result<MigrationInformation> {
                 Poll::Pending
             } else {
                 REQUESTS.lock().insert(request_id);
-                Poll::Ready(Ok(mig_info))
+                Poll::Ready(Ok(WaitForRequestResponse::StartMigration(mig_info)))
+            }
+        } else if wfr.operation == MIG_OPERATION_SHUT_DOWN {
+            let drain_timeout = private_mem[24 + size_of::<ServiceMigWaitForReqResponse>()..]
+                .get(..4)
+                .and_then(|data| data.try_into().ok())
+                .map(u32::from_le_bytes)
+                .ok_or_else(|| {
+                    log::error!("wait_for_request: invalid shutdown request\n");
+                    MigrationResult::InvalidParameter
+                })?;
+            // No new request is accepted from now on, the in-flight ones are
+            // drained by `shutdown`
+            SHUTTING_DOWN.store(true, Ordering::SeqCst);
+            Poll::Ready(Ok(WaitForRequestResponse::Shutdown(Duration::from_millis(
+                drain_timeout as u64,
+            ))))
+        } else if wfr.operation == MIG_OPERATION_SET_LOG_LEVEL {
+            // The log level is set in place, the VMM only waits for its status
+            if let Err(e) =
+                set_log_level(&private_mem[24 + size_of::<ServiceMigWaitForReqResponse>()..])
+            {
+                log::error!("wait_for_request: SetLogLevel failed {:?}\n", e);
             }
+            Poll::Pending
         } else if wfr.operation == 0 {
             Poll::Pending
         } else {
@@ -485,7 +727,39 @@ pub async fn wait_for_request() -> Result<MigrationInformation> {
     .await
 }
 
-pub fn shutdown() -> Result<()> {
+#[cfg(not(feature = "vmcall-raw"))]
+fn set_log_level(data: &[u8]) -> Result<()> {
+    let (status, request_id) = match LogControl::read_from(data) {
+        Some(control) => {
+            log_control::set_log_level(&control);
+            (MigrationResult::Success, control.mig_request_id)
+        }
+        None => {
+            log::error!("set_log_level: invalid SetLogLevel request\n");
+            let request_id = data
+                .get(..8)
+                .ok_or(MigrationResult::InvalidParameter)?
+                .try_into()
+                .map(u64::from_le_bytes)
+                .map_err(|_| MigrationResult::InvalidParameter)?;
+            (MigrationResult::InvalidParameter, request_id)
+        }
+    };
+
+    report_operation_status(MIG_OPERATION_SET_LOG_LEVEL, status as u8, request_id)
+}
+
+/// Shuts MigTD down on a shutdown request of the VMM over the vsock or serial
+/// transport: the in-flight requests are drained for at most `drain_timeout`
+/// and canceled afterwards, reporting `MigrationResult::VmmCanceled`, before
+/// the VMM is notified with `MIG_COMMAND_SHUT_DOWN`.
+#[cfg(not(feature = "vmcall-raw"))]
+pub async fn shutdown(drain_timeout: Duration) -> Result<()> {
+    let canceled = drain(None, drain_timeout).await;
+    if !canceled.is_empty() {
+        log::error!("shutdown: requests {:x?} canceled\n", canceled);
+    }
+
     // Allocate shared page for command and response buffer
     let mut cmd_mem = SharedMemory::new(1).ok_or_else(|| {
         log::error!("shutdown: Failed to allocate command shared memory\n");
@@ -519,6 +793,126 @@ pub fn shutdown() -> Result<()> {
     Ok(())
 }
 
+/// Returns true once a shutdown request has been received.
+pub fn is_shutting_down() -> bool {
+    SHUTTING_DOWN.load(Ordering::SeqCst)
+}
+
+/// Runs the handler of `request_id` to completion, unless MigTD is shutting
+/// down. A request received after the shutdown is refused and a request still
+/// in flight when the drain times out is canceled, both with
+/// `MigrationResult::VmmCanceled`. The handler is dropped on cancellation,
+/// which zeroizes the session keys and buffers it holds.
+pub async fn cancelable<T, F: Future<Output = Result<T>>>(request_id: u64, future: F) -> Result<T> {
+    if is_shutting_down() {
+        log::error!(
+            "cancelable: request {} refused, MigTD is shutting down\n",
+            request_id
+        );
+        return Err(MigrationResult::VmmCanceled);
+    }
+
+    let mut future = pin!(future);
+    poll_fn(|cx| {
+        if CANCELED_REQUESTS.lock().contains(&request_id) {
+            log::error!("cancelable: request {} canceled by shutdown\n", request_id);
+            return Poll::Ready(Err(MigrationResult::VmmCanceled));
+        }
+        future.as_mut().poll(cx)
+    })
+    .await
+}
+
+/// Marks `request_id` as completed once its status is reported, which lets
+/// a pending shutdown drain proceed.
+pub fn complete_request(request_id: u64) {
+    REQUESTS.lock().remove(&request_id);
+    CANCELED_REQUESTS.lock().remove(&request_id);
+    if let Some(waker) = DRAIN_WAKER.lock().take() {
+        waker.wake();
+    }
+}
+
+// The shutdown request itself, if any, is not drained
+fn in_flight_requests(shutdown_request_id: Option<u64>) -> Vec<u64> {
+    REQUESTS
+        .lock()
+        .iter()
+        .copied()
+        .filter(|id| Some(*id) != shutdown_request_id)
+        .collect()
+}
+
+async fn wait_for_in_flight_requests(shutdown_request_id: Option<u64>, timeout: Duration) -> bool {
+    let drained = poll_fn(|cx| {
+        // Register before checking so that a completion in between is not missed
+        *DRAIN_WAKER.lock() = Some(cx.waker().clone());
+        if in_flight_requests(shutdown_request_id).is_empty() {
+            DRAIN_WAKER.lock().take();
+            Poll::Ready(())
+        } else {
+            // Woken by `complete_request`
+            Poll::Pending
+        }
+    });
+    let drained = with_timeout(timeout, drained).await.is_ok();
+    DRAIN_WAKER.lock().take();
+    drained
+}
+
+// Stops accepting requests and drains the in-flight ones, see `drain_requests`.
+async fn drain(shutdown_request_id: Option<u64>, drain_timeout: Duration) -> Vec<u64> {
+    // Time given to the canceled requests to report their final status
+    const CANCEL_TIMEOUT: Duration = Duration::from_secs(5); // 5 seconds
+
+    SHUTTING_DOWN.store(true, Ordering::SeqCst);
+    if wait_for_in_flight_requests(shutdown_request_id, drain_timeout).await {
+        return Vec::new();
+    }
+
+    let canceled = in_flight_requests(shutdown_request_id);
+    log::error!(
+        "drain: drain timed out, canceling requests {:?}\n",
+        canceled
+    );
+    CANCELED_REQUESTS.lock().extend(canceled.iter().copied());
+
+    if !wait_for_in_flight_requests(shutdown_request_id, CANCEL_TIMEOUT).await {
+        log::error!("drain: canceled requests did not report their status\n");
+    }
+    canceled
+}
+
+/// Handles a shutdown request: no new request is accepted from now on and the
+/// in-flight requests are given `drain_timeout` milliseconds to complete. The
+/// remaining ones are canceled and report `MigrationResult::VmmCanceled` as
+/// their final status.
+///
+/// Returns the ids of the canceled requests.
+#[cfg(feature = "vmcall-raw")]
+pub async fn drain_requests(info: &ShutdownInfo) -> Vec<u64> {
+    entrylog(
+        &format!(
+            "Shutdown requested, draining {} in-flight requests\n",
+            in_flight_requests(Some(info.mig_request_id)).len()
+        )
+        .into_bytes(),
+        Level::Info,
+        info.mig_request_id,
+    );
+
+    let drain_timeout = Duration::from_millis(info.drain_timeout as u64);
+    let canceled = drain(Some(info.mig_request_id), drain_timeout).await;
+    if !canceled.is_empty() {
+        entrylog(
+            &format!("Drain timed out, canceled requests {:x?}\n", canceled).into_bytes(),
+            Level::Error,
+            info.mig_request_id,
+        );
+    }
+    canceled
+}
+
 #[cfg(feature = "vmcall-raw")]
 pub async fn get_tdreport(
     additional_data: &[u8; TD_REPORT_ADDITIONAL_DATA_SIZE],
@@ -674,6 +1068,11 @@ pub async fn report_status(status: u8, request_id: u64, data: &Vec<u8>) -> Resul
 
 #[cfg(not(feature = "vmcall-raw"))]
 pub fn report_status(status: u8, request_id: u64) -> Result<()> {
+    report_operation_status(1, status, request_id)
+}
+
+#[cfg(not(feature = "vmcall-raw"))]
+fn report_operation_status(operation: u8, status: u8, request_id: u64) -> Result<()> {
     // Allocate shared page for command and response buffer
     let mut cmd_mem = SharedMemory::new(1).ok_or(MigrationResult::OutOfResource)?;
     let mut rsp_mem = SharedMemory::new(1).ok_or(MigrationResult::OutOfResource)?;
@@ -685,7 +1084,7 @@ pub fn report_status(status: u8, request_id: u64) -> Result<()> {
     let rs = ServiceMigReportStatusCommand {
         version: 0,
         command: MIG_COMMAND_REPORT_STATUS,
-        operation: 1,
+        operation,
         status,
         mig_request_id: request_id,
     };
@@ -756,24 +1155,39 @@ impl PreSessionMessage {
 }
 
 #[cfg(feature = "policy_v2")]
+#[repr(C)]
 struct HelloPacketPayload {
     magic_word: [u8; 4],
     lowest_supported_version: u16,
     highest_supported_version: u16,
+    // Random nonce the RA-TLS quote of the peer must commit to
+    nonce: [u8; SESSION_NONCE_SIZE],
+    // Migration request of the sender, bound to the RA-TLS quotes as well
+    request_id: u64,
+    // Digest of the target TDs of the request, must match on both sides
+    target_tds: [u8; TARGET_TDS_DIGEST_SIZE],
 }
 
 #[cfg(feature = "policy_v2")]
 impl HelloPacketPayload {
-    const HELLO_PACKET_PAYLOAD_SIZE: usize = 8;
+    // The magic word and the supported versions are common to all versions
+    const HELLO_PACKET_PREFIX_SIZE: usize = 8;
+    const HELLO_PACKET_PAYLOAD_SIZE: usize =
+        Self::HELLO_PACKET_PREFIX_SIZE + SESSION_NONCE_SIZE + 8 + TARGET_TDS_DIGEST_SIZE;
     const HELLO_PACKET_MAGIC_WORD: [u8; 4] = [b'M', b'G', b'T', b'D'];
-    const LOWEST_VERSION: u16 = 0x0100;
-    const HIGHEST_VERSION: u16 = 0x0100;
+    // Version 0x0101 adds the nonce, version 0x0102 the request ID and the
+    // target TDs. Both are required, older peers are rejected as unsupported.
+    const LOWEST_VERSION: u16 = 0x0102;
+    const HIGHEST_VERSION: u16 = 0x0102;
 
-    pub const fn new() -> Self {
+    pub const fn new(context: &HelloContext, target_tds: [u8; TARGET_TDS_DIGEST_SIZE]) -> Self {
         Self {
             magic_word: Self::HELLO_PACKET_MAGIC_WORD,
             lowest_supported_version: Self::LOWEST_VERSION,
             highest_supported_version: Self::HIGHEST_VERSION,
+            nonce: context.nonce,
+            request_id: context.request_id,
+            target_tds,
         }
     }
 
@@ -781,25 +1195,50 @@ impl HelloPacketPayload {
         unsafe { core::slice::from_raw_parts(self as *const Self as *const u8, size_of::<Self>()) }
     }
 
-    pub fn read_from_bytes(bytes: &[u8]) -> Option<Self> {
-        if bytes.len() < size_of::<Self>() {
+    /// Reads the hello packet of the peer. The payload of an older version is
+    /// shorter, such a peer is reported as unsupported rather than as sending
+    /// an invalid packet.
+    pub fn read_from_bytes(bytes: &[u8]) -> Result<Self> {
+        if bytes.len() < Self::HELLO_PACKET_PREFIX_SIZE {
             log::error!(
                 "HelloPacketPayload: Insufficient bytes to read header bytes.len() = {}\n",
                 bytes.len()
             );
-            return None;
+            return Err(MigrationResult::InvalidParameter);
         }
-        let payload = HelloPacketPayload {
+        let mut payload = HelloPacketPayload {
             magic_word: bytes[..4].try_into().unwrap(),
             lowest_supported_version: u16::from_le_bytes(bytes[4..6].try_into().unwrap()),
             highest_supported_version: u16::from_le_bytes(bytes[6..8].try_into().unwrap()),
+            nonce: [0u8; SESSION_NONCE_SIZE],
+            request_id: 0,
+            target_tds: [0u8; TARGET_TDS_DIGEST_SIZE],
         };
 
         if payload.magic_word != HelloPacketPayload::HELLO_PACKET_MAGIC_WORD {
             log::error!("HelloPacketPayload: Invalid magic word in hello packet\n");
-            return None;
+            return Err(MigrationResult::InvalidParameter);
+        }
+        if payload.negotiate_supported_version().is_none() {
+            log::error!(
+                "HelloPacketPayload: peer supports versions 0x{:04x} to 0x{:04x} only\n",
+                payload.lowest_supported_version,
+                payload.highest_supported_version
+            );
+            return Err(MigrationResult::Unsupported);
+        }
+        if bytes.len() != Self::HELLO_PACKET_PAYLOAD_SIZE {
+            log::error!(
+                "HelloPacketPayload: Invalid length in hello packet bytes.len() = {}\n",
+                bytes.len()
+            );
+            return Err(MigrationResult::InvalidParameter);
         }
-        Some(payload)
+
+        payload.nonce = bytes[8..40].try_into().unwrap();
+        payload.request_id = u64::from_le_bytes(bytes[40..48].try_into().unwrap());
+        payload.target_tds = bytes[48..48 + TARGET_TDS_DIGEST_SIZE].try_into().unwrap();
+        Ok(payload)
     }
 
     fn negotiate_supported_version(&self) -> Option<u16> {
@@ -876,6 +1315,7 @@ async fn send_pre_session_data_packet<T: AsyncRead + AsyncWrite + Unpin>(
 #[cfg(feature = "policy_v2")]
 async fn receive_pre_session_data_packet<T: AsyncRead + AsyncWrite + Unpin>(
     transport: &mut T,
+    arena: &RequestArena,
 ) -> Result<Vec<u8>> {
     let mut header_buffer = [0u8; size_of::<PreSessionMessage>()];
     receive_pre_session_data(transport, &mut header_buffer)
@@ -895,7 +1335,8 @@ async fn receive_pre_session_data_packet<T: AsyncRead + AsyncWrite + Unpin>(
     }
 
     let pre_session_data_payload_size = header.length as usize;
-    let mut pre_session_data_payload = vec![0u8; pre_session_data_payload_size];
+    // The payload size is chosen by the peer, draw it from the request arena
+    let mut pre_session_data_payload = arena.alloc_zeroed(pre_session_data_payload_size)?;
     receive_pre_session_data(transport, &mut pre_session_data_payload)
         .await
         .map_err(|e| {
@@ -955,11 +1396,15 @@ async fn receive_start_session_packet<T: AsyncRead + AsyncWrite + Unpin>(
 }
 
 #[cfg(feature = "policy_v2")]
-async fn send_hello_packet<T: AsyncRead + AsyncWrite + Unpin>(transport: &mut T) -> Result<()> {
+async fn send_hello_packet<T: AsyncRead + AsyncWrite + Unpin>(
+    transport: &mut T,
+    context: &HelloContext,
+    target_tds: [u8; TARGET_TDS_DIGEST_SIZE],
+) -> Result<()> {
     let header = PreSessionMessage {
         r#type: PreSessionMessage::HELLO_PACKET_TYPE,
         reserved: [0u8; 3],
-        length: 8,
+        length: HelloPacketPayload::HELLO_PACKET_PAYLOAD_SIZE as u32,
     };
     send_pre_session_data(transport, header.as_bytes())
         .await
@@ -968,7 +1413,12 @@ async fn send_hello_packet<T: AsyncRead + AsyncWrite + Unpin>(transport: &mut T)
             e
         })?;
 
-    let payload = HelloPacketPayload::new();
+    let payload = HelloPacketPayload::new(context, target_tds);
+    transcript::record(
+        context.request_id,
+        RecordKind::LocalHello,
+        payload.as_bytes(),
+    );
     send_pre_session_data(transport, payload.as_bytes())
         .await
         .map_err(|e| {
@@ -980,6 +1430,7 @@ async fn send_hello_packet<T: AsyncRead + AsyncWrite + Unpin>(transport: &mut T)
 #[cfg(feature = "policy_v2")]
 async fn receive_hello_packet<T: AsyncRead + AsyncWrite + Unpin>(
     transport: &mut T,
+    mig_request_id: u64,
 ) -> Result<HelloPacketPayload> {
     let mut header_buffer = [0u8; size_of::<PreSessionMessage>()];
     receive_pre_session_data(transport, &mut header_buffer)
@@ -999,63 +1450,119 @@ async fn receive_hello_packet<T: AsyncRead + AsyncWrite + Unpin>(
         log::error!("PreSessionMessage: Invalid type in hello packet\n");
         return Err(MigrationResult::InvalidParameter);
     }
-    if header.length as usize != HelloPacketPayload::HELLO_PACKET_PAYLOAD_SIZE {
+    // Older versions send a shorter payload, it is received to negotiate
+    // the version
+    let length = header.length as usize;
+    if !(HelloPacketPayload::HELLO_PACKET_PREFIX_SIZE
+        ..=HelloPacketPayload::HELLO_PACKET_PAYLOAD_SIZE)
+        .contains(&length)
+    {
         log::error!("PreSessionMessage: Invalid length in hello packet\n");
         return Err(MigrationResult::InvalidParameter);
     }
 
     // Receive hello packet payload
-    let mut hello_payload = vec![0u8; HelloPacketPayload::HELLO_PACKET_PAYLOAD_SIZE];
+    let mut hello_payload = vec![0u8; length];
     receive_pre_session_data(transport, &mut hello_payload)
         .await
         .map_err(|e| {
             log::error!("receive_hello_packet payload: Network error: {:?}\n", e);
             e
         })?;
+    transcript::record(mig_request_id, RecordKind::PeerHello, &hello_payload);
 
-    HelloPacketPayload::read_from_bytes(&hello_payload)
-        .ok_or(MigrationResult::InvalidParameter)
-        .map_err(|_| {
-            log::error!("receive_hello_packet: Failed to read HelloPacketPayload\n");
-            MigrationResult::InvalidParameter
-        })
+    HelloPacketPayload::read_from_bytes(&hello_payload).map_err(|e| {
+        log::error!("receive_hello_packet: Failed to read HelloPacketPayload\n");
+        e
+    })
 }
 
-// Exchange hello packet and negotiate a pre-session message version
+// Exchange hello packet, negotiate a pre-session message version and return
+// it along with the context of the session. The peer must serve the same
+// target TDs.
 #[cfg(feature = "policy_v2")]
 async fn exchange_hello_packet<T: AsyncRead + AsyncWrite + Unpin>(
     transport: &mut T,
-) -> Result<u16> {
-    send_hello_packet(transport).await.map_err(|e| {
-        log::error!("exchange_hello_packet: send_hello_packet error: {:?}\n", e);
-        e
+    mig_request_id: u64,
+    target_td_uuids: &[[u64; 4]],
+) -> Result<(u16, SessionBinding)> {
+    let mut local = HelloContext {
+        nonce: [0u8; SESSION_NONCE_SIZE],
+        request_id: mig_request_id,
+    };
+    crypto::rand::get_random(&mut local.nonce).map_err(|e| {
+        log::error!("exchange_hello_packet: get_random error: {:?}\n", e);
+        MigrationResult::OutOfResource
     })?;
-    let remote = receive_hello_packet(transport).await.map_err(|e| {
-        log::error!(
-            "exchange_hello_packet: receive_hello_packet error: {:?}\n",
-            e
-        );
-        e
+    let target_tds = target_tds_digest(target_td_uuids).map_err(|e| {
+        log::error!("exchange_hello_packet: target_tds_digest error: {:?}\n", e);
+        MigrationResult::InvalidParameter
     })?;
+    send_hello_packet(transport, &local, target_tds)
+        .await
+        .map_err(|e| {
+            log::error!("exchange_hello_packet: send_hello_packet error: {:?}\n", e);
+            e
+        })?;
+    let remote = receive_hello_packet(transport, mig_request_id)
+        .await
+        .map_err(|e| {
+            log::error!(
+                "exchange_hello_packet: receive_hello_packet error: {:?}\n",
+                e
+            );
+            e
+        })?;
 
-    remote
+    // Checked when the hello packet is read
+    let version = remote
         .negotiate_supported_version()
-        .ok_or(MigrationResult::InvalidParameter)
+        .ok_or(MigrationResult::Unsupported)?;
+    if remote.target_tds != target_tds {
+        log::error!(
+            "exchange_hello_packet: peer of request {} serves other target TDs\n",
+            remote.request_id
+        );
+        return Err(MigrationResult::InvalidParameter);
+    }
+    let binding = SessionBinding {
+        local,
+        peer: HelloContext {
+            nonce: remote.nonce,
+            request_id: remote.request_id,
+        },
+        target_tds,
+    };
+    Ok((version, binding))
+}
+
+// Data exchanged ahead of the secure session
+#[cfg(feature = "policy_v2")]
+struct PreSessionData {
+    remote_policy: Vec<u8>,
+    // The SPDM session does not use the context of the hello packets
+    #[cfg_attr(feature = "spdm_attestation", allow(dead_code))]
+    binding: SessionBinding,
 }
 
 #[cfg(feature = "policy_v2")]
 async fn pre_session_data_exchange<T: AsyncRead + AsyncWrite + Unpin>(
     transport: &mut T,
-) -> Result<Vec<u8>> {
+    arena: &RequestArena,
+    target_td_uuids: &[[u64; 4]],
+) -> Result<PreSessionData> {
     use crate::config;
 
-    let version = exchange_hello_packet(transport).await.map_err(|e| {
-        log::error!(
-            "pre_session_data_exchange: exchange_hello_packet error: {:?}\n",
+    let mig_request_id = arena.request_id();
+    let (version, binding) = exchange_hello_packet(transport, mig_request_id, target_td_uuids)
+        .await
+        .map_err(|e| {
+            log::error!(
+                "pre_session_data_exchange: exchange_hello_packet error: {:?}\n",
+                e
+            );
             e
-        );
-        e
-    })?;
+        })?;
     log::info!("Pre-Session-Message Version: 0x{:04x}\n", version);
 
     let policy = config::get_policy()
@@ -1064,6 +1571,7 @@ async fn pre_session_data_exchange<T: AsyncRead + AsyncWrite + Unpin>(
             log::error!("pre_session_data_exchange: get_policy error: {:?}\n", e);
             e
         })?;
+    transcript::record(mig_request_id, RecordKind::LocalPolicy, policy);
     send_pre_session_data_packet(policy, transport)
         .await
         .map_err(|e| {
@@ -1073,7 +1581,7 @@ async fn pre_session_data_exchange<T: AsyncRead + AsyncWrite + Unpin>(
             );
             e
         })?;
-    let remote_policy = receive_pre_session_data_packet(transport)
+    let remote_policy = receive_pre_session_data_packet(transport, arena)
         .await
         .map_err(|e| {
             log::error!(
@@ -1082,6 +1590,7 @@ async fn pre_session_data_exchange<T: AsyncRead + AsyncWrite + Unpin>(
             );
             e
         })?;
+    transcript::record(mig_request_id, RecordKind::PeerPolicy, &remote_policy);
 
     send_start_session_packet(transport).await.map_err(|e| {
         log::error!(
@@ -1098,13 +1607,151 @@ async fn pre_session_data_exchange<T: AsyncRead + AsyncWrite + Unpin>(
         e
     })?;
 
-    Ok(remote_policy)
+    Ok(PreSessionData {
+        remote_policy,
+        binding,
+    })
 }
 
+/// Measures the receipt of the migration of a target TD into RTMR3, so that it
+/// is visible to a relying party attesting MigTD afterwards.
+///
+/// Only the target TDs whose key has been written get a receipt, failed
+/// requests are not measured so that the VMM cannot fill the event log with
+/// them.
+#[cfg(all(feature = "main", feature = "runtime_measurement"))]
+fn measure_migration_receipt(
+    mig_info: &MigtdMigrationInformation,
+    result: MigrationResult,
+    peer_policy_digest: Option<[u8; SHA384_DIGEST_SIZE]>,
+    peer: Option<&crate::ratls::VerifiedPeer>,
+) {
+    // The claims about the peer are only verified by RA-TLS, the SPDM session
+    // falls back to the local TCB evaluation data
+    #[cfg(feature = "policy_v2")]
+    let tcb_evaluation_number = peer
+        .and_then(|peer| peer.evaluation.tcb_evaluation_number)
+        .or_else(|| {
+            crate::mig_policy::get_local_tcb_evaluation_info()
+                .ok()
+                .and_then(|info| info.tcb_evaluation_number)
+        })
+        .unwrap_or(0);
+    #[cfg(feature = "policy_v2")]
+    let peer_policy_id = peer.and_then(|peer| event_log::policy_id_bytes(&peer.policy_id));
+    #[cfg(not(feature = "policy_v2"))]
+    let (tcb_evaluation_number, peer_policy_id) = {
+        let _ = peer;
+        (0, None)
+    };
+
+    let receipt = event_log::MigrationReceiptEvent {
+        mig_request_id: mig_info.mig_request_id,
+        target_td_uuid: mig_info.target_td_uuid,
+        migration_source: mig_info.migration_source,
+        result: result as u8,
+        tcb_evaluation_number,
+        peer_policy_digest: peer_policy_digest.unwrap_or([0u8; SHA384_DIGEST_SIZE]),
+        peer_policy_id: peer_policy_id.unwrap_or_default(),
+    };
+    if let Err(e) = event_log::measure_migration_receipt(&receipt) {
+        log::error!(
+            "Failed to measure the receipt of migration {}: {:?}\n",
+            mig_info.mig_request_id,
+            e
+        );
+    }
+}
+
+// Writes the outcome and the phase durations of a key exchange as a
+// structured LogArea entry
+#[cfg(all(feature = "main", feature = "vmcall-raw"))]
+fn log_exchange_result(result: &Result<()>, metrics: &MigrationMetrics) {
+    let (msg, level, status) = match result {
+        Ok(()) => (
+            "Key exchange completed\n",
+            Level::Info,
+            MigrationResult::Success,
+        ),
+        Err(e) => ("Key exchange failed\n", Level::Error, *e),
+    };
+    let mut kvs = vec![
+        (LOG_KEY_PHASE, LogValue::Str(metrics.phase.as_str())),
+        (LOG_KEY_ERROR_CODE, LogValue::U64(status as u64)),
+        (LOG_KEY_DURATION_MS, LogValue::U64(metrics.total_ms)),
+    ];
+    for phase in PHASES {
+        kvs.push((
+            phase.duration_key(),
+            LogValue::U64(metrics.durations_ms[phase as usize]),
+        ));
+    }
+    entrylog_kv(msg, level, metrics.request_id, &kvs);
+}
+
+// Claims about the peer verified by the RA-TLS handshake of `channel`. The
+// handshake is done once data has been read from the channel, the claims are
+// then always available.
+#[cfg(not(feature = "spdm_attestation"))]
+fn verified_peer<T: AsyncRead + AsyncWrite + Unpin>(
+    channel: &ratls::RatlsChannel<T>,
+    mig_request_id: u64,
+) -> Result<ratls::VerifiedPeer> {
+    let peer = channel.peer_claims().ok_or_else(|| {
+        log::error!(
+            "No verified claims about the peer of migration {}\n",
+            mig_request_id
+        );
+        MigrationResult::SecureSessionError
+    })?;
+    #[cfg(feature = "policy_v2")]
+    log::info!(
+        "Peer of migration {} verified: policy {}, FMSPC {:02x?}, TCB status {:?}, MigTD SVN {:?}, MigTD TCB status {:?}\n",
+        mig_request_id,
+        peer.policy_id,
+        peer.evaluation.fmspc,
+        peer.evaluation.tcb_status,
+        peer.evaluation.migtd_isvsvn,
+        peer.evaluation.migtd_tcb_status
+    );
+    #[cfg(not(feature = "policy_v2"))]
+    log::info!("Peer of migration {} verified\n", mig_request_id);
+    Ok(peer)
+}
+
+/// Runs the key exchange of a migration request.
+///
+/// With `vmcall-raw`, the timing record of the exchange is appended to the
+/// `data` reported to the VMM, see `MigrationMetrics::to_bytes`.
 #[cfg(feature = "main")]
 pub async fn exchange_msk(info: &MigrationInformation, data: &mut Vec<u8>) -> Result<()> {
+    let (result, metrics) = metrics::with_metrics(
+        info.mig_info.mig_request_id,
+        exchange_msk_phases(info, data),
+    )
+    .await;
+    log::info!(
+        "Key exchange of migration {} in phase {}: {}\n",
+        metrics.request_id,
+        metrics.phase.as_str(),
+        metrics
+    );
+
+    #[cfg(feature = "vmcall-raw")]
+    {
+        log_exchange_result(&result, &metrics);
+        data.extend_from_slice(&metrics.to_bytes());
+    }
+    result
+}
+
+#[cfg(feature = "main")]
+async fn exchange_msk_phases(info: &MigrationInformation, data: &mut Vec<u8>) -> Result<()> {
     #[cfg(not(feature = "vmcall-raw"))]
     let _ = data;
+    let arena = RequestArena::admit(info.mig_info.mig_request_id)?;
+    #[cfg(feature = "policy_v2")]
+    let capture = transcript::Capture::begin(info.mig_info.mig_request_id, info.is_src());
     #[cfg(feature = "policy_v2")]
     let mut transport;
     #[cfg(not(feature = "policy_v2"))]
@@ -1167,11 +1814,17 @@ pub async fn exchange_msk(info: &MigrationInformation, data: &mut Vec<u8>) -> Re
 
     // Exchange policy firstly because of the message size limitation of TLS protocol
     #[cfg(feature = "policy_v2")]
+    metrics::enter_phase(info.mig_info.mig_request_id, MigrationPhase::PreSession);
+    #[cfg(feature = "policy_v2")]
     const PRE_SESSION_TIMEOUT: Duration = Duration::from_secs(60); // 60 seconds
     #[cfg(feature = "policy_v2")]
-    let remote_policy = Box::pin(with_timeout(
+    let pre_session = Box::pin(with_timeout(
         PRE_SESSION_TIMEOUT,
-        pre_session_data_exchange(&mut transport),
+        pre_session_data_exchange(
+            &mut transport,
+            &arena,
+            core::slice::from_ref(&info.mig_info.target_td_uuid),
+        ),
     ))
     .await
     .map_err(|e| {
@@ -1185,6 +1838,17 @@ pub async fn exchange_msk(info: &MigrationInformation, data: &mut Vec<u8>) -> Re
         log::error!("exchange_msk: pre_session_data_exchange error: {:?}\n", e);
         e
     })?;
+    #[cfg(feature = "policy_v2")]
+    let remote_policy = pre_session.remote_policy;
+
+    // The v1 policy of the peer is not exchanged, it is only in its event log
+    #[cfg(all(feature = "runtime_measurement", feature = "policy_v2"))]
+    let peer_policy_digest = event_log::calculate_digest(&remote_policy).ok();
+    #[cfg(all(feature = "runtime_measurement", not(feature = "policy_v2")))]
+    let peer_policy_digest = None;
+
+    metrics::enter_phase(info.mig_info.mig_request_id, MigrationPhase::SecureSession);
+    arena.charge(SECURE_SESSION_FOOTPRINT)?;
 
     #[cfg(not(feature = "spdm_attestation"))]
     {
@@ -1198,12 +1862,21 @@ pub async fn exchange_msk(info: &MigrationInformation, data: &mut Vec<u8>) -> Re
             })?;
 
         // Establish TLS layer connection and negotiate the MSK
-        if info.is_src() {
+        let peer = if info.is_src() {
             // TLS client
+            metrics::enter_phase(
+                info.mig_info.mig_request_id,
+                MigrationPhase::QuoteGeneration,
+            );
             let mut ratls_client = ratls::client(
                 transport,
+                info.mig_info.mig_request_id,
                 #[cfg(feature = "policy_v2")]
                 remote_policy,
+                #[cfg(feature = "policy_v2")]
+                pre_session.binding,
+                #[cfg(feature = "policy_v2")]
+                capture.as_ref().map(transcript::Capture::sink),
                 #[cfg(feature = "vmcall-raw")]
                 data,
             )
@@ -1222,6 +1895,7 @@ pub async fn exchange_msk(info: &MigrationInformation, data: &mut Vec<u8>) -> Re
                 );
                 MigrationResult::SecureSessionError
             })?;
+            metrics::enter_phase(info.mig_info.mig_request_id, MigrationPhase::SecureSession);
 
             // MigTD-S send Migration Session Forward key to peer
             with_timeout(
@@ -1250,6 +1924,7 @@ pub async fn exchange_msk(info: &MigrationInformation, data: &mut Vec<u8>) -> Re
                 log::error!("exchange_msk: ratls_client.read error: {:?}\n", e);
                 e
             })?;
+            let peer = verified_peer(&ratls_client, info.mig_info.mig_request_id)?;
             if size < size_of::<ExchangeInformation>() {
                 #[cfg(feature = "vmcall-raw")]
                 data.extend_from_slice(
@@ -1294,12 +1969,22 @@ pub async fn exchange_msk(info: &MigrationInformation, data: &mut Vec<u8>) -> Re
                     );
                     MigrationResult::InvalidParameter
                 })?;
+            peer
         } else {
             // TLS server
+            metrics::enter_phase(
+                info.mig_info.mig_request_id,
+                MigrationPhase::QuoteGeneration,
+            );
             let mut ratls_server = ratls::server(
                 transport,
+                info.mig_info.mig_request_id,
                 #[cfg(feature = "policy_v2")]
                 remote_policy,
+                #[cfg(feature = "policy_v2")]
+                pre_session.binding,
+                #[cfg(feature = "policy_v2")]
+                capture.as_ref().map(transcript::Capture::sink),
             )
             .map_err(|_| {
                 #[cfg(feature = "vmcall-raw")]
@@ -1316,6 +2001,7 @@ pub async fn exchange_msk(info: &MigrationInformation, data: &mut Vec<u8>) -> Re
                 );
                 MigrationResult::SecureSessionError
             })?;
+            metrics::enter_phase(info.mig_info.mig_request_id, MigrationPhase::SecureSession);
 
             with_timeout(
                 TLS_TIMEOUT,
@@ -1343,6 +2029,7 @@ pub async fn exchange_msk(info: &MigrationInformation, data: &mut Vec<u8>) -> Re
                 log::error!("exchange_msk: ratls_server.read error: {:?}\n", e);
                 e
             })?;
+            let peer = verified_peer(&ratls_server, info.mig_info.mig_request_id)?;
             if size < size_of::<ExchangeInformation>() {
                 #[cfg(feature = "vmcall-raw")]
                 data.extend_from_slice(&format!("Error: exchange_msk(): Incorrect ExchangeInformation size Migration ID: {:x}. Size - Expected: {:x} Actual: {:x}\n", info.mig_info.mig_request_id, size_of::<ExchangeInformation>(), size).into_bytes());
@@ -1362,21 +2049,16 @@ pub async fn exchange_msk(info: &MigrationInformation, data: &mut Vec<u8>) -> Re
                     log::error!("exchange_msk: Failed to transport in vmcall_raw_instance with Migration ID: {} errorcode: {}\n", info.mig_info.mig_request_id, e);
                     MigrationResult::InvalidParameter
                 })?;
-        }
+            peer
+        };
 
-        let mig_ver = cal_mig_version(info.is_src(), &exchange_information, &remote_information)
-            .map_err(|e| {
-                log::error!("exchange_msk: cal_mig_version error: {:?}\n", e);
-                e
-            })?;
-        set_mig_version(&info.mig_info, mig_ver).map_err(|e| {
-            log::error!("exchange_msk: set_mig_version error: {:?}\n", e);
-            e
-        })?;
-        write_msk(&info.mig_info, &remote_information.key).map_err(|e| {
-            log::error!("exchange_msk: write_msk error: {:?}\n", e);
-            e
-        })?;
+        metrics::enter_phase(info.mig_info.mig_request_id, MigrationPhase::SetMsk);
+        set_exchanged_key(
+            &info.mig_info,
+            info.is_src(),
+            &exchange_information,
+            &remote_information,
+        )?;
 
         log::info!("Set MSK and report status\n");
         #[cfg(feature = "vmcall-raw")]
@@ -1390,6 +2072,16 @@ pub async fn exchange_msk(info: &MigrationInformation, data: &mut Vec<u8>) -> Re
         }
         exchange_information.key.clear();
         remote_information.key.clear();
+
+        #[cfg(feature = "runtime_measurement")]
+        measure_migration_receipt(
+            &info.mig_info,
+            MigrationResult::Success,
+            peer_policy_digest,
+            Some(&peer),
+        );
+        #[cfg(not(feature = "runtime_measurement"))]
+        let _ = peer;
     }
 
     #[cfg(feature = "spdm_attestation")]
@@ -1459,9 +2151,399 @@ pub async fn exchange_msk(info: &MigrationInformation, data: &mut Vec<u8>) -> Re
         }
     }
 
+    #[cfg(all(feature = "runtime_measurement", feature = "spdm_attestation"))]
+    measure_migration_receipt(
+        &info.mig_info,
+        MigrationResult::Success,
+        peer_policy_digest,
+        None,
+    );
+
     Ok(())
 }
 
+#[cfg(all(feature = "vmcall-raw", not(feature = "spdm_attestation")))]
+#[repr(C)]
+#[derive(Default)]
+struct BatchExchangeHeader {
+    count: u32,
+    reserved: u32,
+}
+
+#[cfg(all(feature = "vmcall-raw", not(feature = "spdm_attestation")))]
+impl BatchExchangeHeader {
+    fn as_bytes(&self) -> &[u8] {
+        unsafe { core::slice::from_raw_parts(self as *const Self as *const u8, size_of::<Self>()) }
+    }
+
+    fn as_bytes_mut(&mut self) -> &mut [u8] {
+        unsafe { core::slice::from_raw_parts_mut(self as *mut Self as *mut u8, size_of::<Self>()) }
+    }
+}
+
+/// Entry of the batch key exchange, one per target TD.
+///
+/// The target TD is identified by its UUID: the binding handle is local to each
+/// MigTD, so it cannot pair the entries of the two sides.
+#[cfg(all(feature = "vmcall-raw", not(feature = "spdm_attestation")))]
+#[repr(C)]
+#[derive(Default, Zeroize, ZeroizeOnDrop)]
+struct BatchExchangeInformation {
+    // Index of the target TD in the batch
+    index: u32,
+    // MigrationResult of the local preparation, the key is only valid on success
+    status: u8,
+    reserved: [u8; 3],
+    // UUID of the target TD, both MigTDs must list the same TDs in the same order
+    target_td_uuid: [u64; 4],
+    info: ExchangeInformation,
+}
+
+#[cfg(all(feature = "vmcall-raw", not(feature = "spdm_attestation")))]
+impl BatchExchangeInformation {
+    // Reads the local key and migration versions of the target TD, a failure is
+    // reported to the peer in `status` so that both sides skip this target TD.
+    fn prepare(index: usize, mig_info: &MigtdMigrationInformation, is_src: bool) -> Self {
+        let mut entry = Self {
+            index: index as u32,
+            target_td_uuid: mig_info.target_td_uuid,
+            ..Default::default()
+        };
+        match exchange_info(mig_info, is_src) {
+            Ok(info) => entry.info = info,
+            Err(e) => entry.status = e as u8,
+        }
+        entry
+    }
+
+    fn as_bytes(&self) -> &[u8] {
+        unsafe { core::slice::from_raw_parts(self as *const Self as *const u8, size_of::<Self>()) }
+    }
+
+    fn as_bytes_mut(&mut self) -> &mut [u8] {
+        unsafe { core::slice::from_raw_parts_mut(self as *mut Self as *mut u8, size_of::<Self>()) }
+    }
+}
+
+/// Serialize the per target TD status of a batch migration request into the data
+/// reported to VMM.
+#[cfg(feature = "vmcall-raw")]
+pub fn batch_status_data(info: &BatchMigrationInformation, status: &[MigrationResult]) -> Vec<u8> {
+    let mut data = Vec::new();
+    for (entry, status) in info.entries.iter().zip(status.iter()) {
+        let status = BatchMigrationStatus {
+            target_td_uuid: entry.target_td_uuid,
+            binding_handle: entry.binding_handle,
+            status: *status as u8,
+            reserved: [0u8; 7],
+        };
+        data.extend_from_slice(status.as_bytes());
+    }
+    data
+}
+
+/// Run one mutual attestation with the peer MigTD and exchange a distinct MSK for
+/// each target TD in the batch inside the same secure session.
+///
+/// Returns the status of every target TD, in the order of the request entries. An
+/// error is returned if the secure session could not be established, or if the
+/// two MigTDs do not list the same target TDs in the same order, in which case
+/// none of the keys has been written.
+///
+/// The timing record of the exchange is appended to the `data` reported to
+/// the VMM, see `MigrationMetrics::to_bytes`.
+///
+/// Batch migration requires RA-TLS: `spdm_attestation` builds return
+/// `MigrationResult::Unsupported`.
+#[cfg(all(feature = "vmcall-raw", not(feature = "spdm_attestation")))]
+pub async fn exchange_msk_batch(
+    info: &BatchMigrationInformation,
+    data: &mut Vec<u8>,
+) -> Result<Vec<MigrationResult>> {
+    let (result, metrics) =
+        metrics::with_metrics(info.mig_request_id, exchange_msk_batch_phases(info, data)).await;
+    log::info!(
+        "Batch key exchange of migration {} in phase {}: {}\n",
+        metrics.request_id,
+        metrics.phase.as_str(),
+        metrics
+    );
+    log_exchange_result(&result.as_ref().map(|_| ()).map_err(|e| *e), &metrics);
+    data.extend_from_slice(&metrics.to_bytes());
+    result
+}
+
+#[cfg(all(feature = "vmcall-raw", not(feature = "spdm_attestation")))]
+async fn exchange_msk_batch_phases(
+    info: &BatchMigrationInformation,
+    data: &mut Vec<u8>,
+) -> Result<Vec<MigrationResult>> {
+    use vmcall_raw::stream::VmcallRaw;
+    const TLS_TIMEOUT: Duration = Duration::from_secs(60); // 60 seconds
+
+    let arena = RequestArena::admit(info.mig_request_id)?;
+    #[cfg(feature = "policy_v2")]
+    let capture = transcript::Capture::begin(info.mig_request_id, info.is_src());
+    let mut vmcall_raw_instance = VmcallRaw::new_with_mid(info.mig_request_id).map_err(|e| {
+        data.extend_from_slice(&format!("Error: exchange_msk_batch(): Failed to create vmcall_raw_instance with Migration ID: {:x} errorcode: {}\n", info.mig_request_id, e).into_bytes());
+        log::error!("exchange_msk_batch: Failed to create vmcall_raw_instance with Migration ID: {} errorcode: {:?}\n", info.mig_request_id, e);
+        MigrationResult::InvalidParameter
+    })?;
+    vmcall_raw_instance.connect().await.map_err(|e| {
+        data.extend_from_slice(&format!("Error: exchange_msk_batch(): Failed to connect vmcall_raw_instance with Migration ID: {:x} errorcode: {}\n", info.mig_request_id, e).into_bytes());
+        log::error!("exchange_msk_batch: Failed to connect vmcall_raw_instance with Migration ID: {} errorcode: {:?}\n", info.mig_request_id, e);
+        MigrationResult::InvalidParameter
+    })?;
+    #[cfg(feature = "policy_v2")]
+    let mut transport = vmcall_raw_instance;
+    #[cfg(not(feature = "policy_v2"))]
+    let transport = vmcall_raw_instance;
+
+    #[cfg(feature = "policy_v2")]
+    metrics::enter_phase(info.mig_request_id, MigrationPhase::PreSession);
+    #[cfg(feature = "policy_v2")]
+    let pre_session = {
+        const PRE_SESSION_TIMEOUT: Duration = Duration::from_secs(60); // 60 seconds
+        let target_td_uuids: Vec<[u64; 4]> = info
+            .entries
+            .iter()
+            .map(|entry| entry.target_td_uuid)
+            .collect();
+        Box::pin(with_timeout(
+            PRE_SESSION_TIMEOUT,
+            pre_session_data_exchange(&mut transport, &arena, &target_td_uuids),
+        ))
+        .await
+        .map_err(|e| {
+            log::error!(
+                "exchange_msk_batch: pre_session_data_exchange timeout error: {:?}\n",
+                e
+            );
+            e
+        })?
+        .map_err(|e| {
+            log::error!(
+                "exchange_msk_batch: pre_session_data_exchange error: {:?}\n",
+                e
+            );
+            e
+        })?
+    };
+    #[cfg(feature = "policy_v2")]
+    let remote_policy = pre_session.remote_policy;
+
+    // The v1 policy of the peer is not exchanged, it is only in its event log
+    #[cfg(all(feature = "runtime_measurement", feature = "policy_v2"))]
+    let peer_policy_digest = event_log::calculate_digest(&remote_policy).ok();
+    #[cfg(all(feature = "runtime_measurement", not(feature = "policy_v2")))]
+    let peer_policy_digest = None;
+
+    metrics::enter_phase(info.mig_request_id, MigrationPhase::SecureSession);
+    arena.charge(SECURE_SESSION_FOOTPRINT)?;
+    let mut channel = if info.is_src() {
+        metrics::enter_phase(info.mig_request_id, MigrationPhase::QuoteGeneration);
+        ratls::client(
+            transport,
+            info.mig_request_id,
+            #[cfg(feature = "policy_v2")]
+            remote_policy,
+            #[cfg(feature = "policy_v2")]
+            pre_session.binding,
+            #[cfg(feature = "policy_v2")]
+            capture.as_ref().map(transcript::Capture::sink),
+            data,
+        )
+    } else {
+        ratls::server(
+            transport,
+            info.mig_request_id,
+            #[cfg(feature = "policy_v2")]
+            remote_policy,
+            #[cfg(feature = "policy_v2")]
+            pre_session.binding,
+            #[cfg(feature = "policy_v2")]
+            capture.as_ref().map(transcript::Capture::sink),
+        )
+    }
+    .map_err(|_| {
+        data.extend_from_slice(
+            &format!(
+                "Error: exchange_msk_batch(): Failed in ratls transport. Migration ID: {:x}\n",
+                info.mig_request_id
+            )
+            .into_bytes(),
+        );
+        log::error!(
+            "exchange_msk_batch(): Failed in ratls transport. Migration ID: {}\n",
+            info.mig_request_id
+        );
+        MigrationResult::SecureSessionError
+    })?;
+    metrics::enter_phase(info.mig_request_id, MigrationPhase::SecureSession);
+
+    // Both sides must agree on the number of target TDs, they are then paired by index
+    let local_header = BatchExchangeHeader {
+        count: info.entries.len() as u32,
+        reserved: 0,
+    };
+    let mut remote_header = BatchExchangeHeader::default();
+    with_timeout(TLS_TIMEOUT, channel.write(local_header.as_bytes()))
+        .await
+        .map_err(|e| {
+            log::error!("exchange_msk_batch: channel.write timeout error: {:?}\n", e);
+            e
+        })?
+        .map_err(|e| {
+            log::error!("exchange_msk_batch: channel.write error: {:?}\n", e);
+            e
+        })?;
+    let size = with_timeout(TLS_TIMEOUT, channel.read(remote_header.as_bytes_mut()))
+        .await
+        .map_err(|e| {
+            log::error!("exchange_msk_batch: channel.read timeout error: {:?}\n", e);
+            e
+        })?
+        .map_err(|e| {
+            log::error!("exchange_msk_batch: channel.read error: {:?}\n", e);
+            e
+        })?;
+    let peer = verified_peer(&channel, info.mig_request_id)?;
+    #[cfg(not(feature = "runtime_measurement"))]
+    let _ = peer;
+    if size < size_of::<BatchExchangeHeader>() {
+        log::error!("exchange_msk_batch(): Incorrect BatchExchangeHeader size Migration ID: {}. Size - Expected: {} Actual: {}\n", info.mig_request_id, size_of::<BatchExchangeHeader>(), size);
+        return Err(MigrationResult::NetworkError);
+    }
+    if remote_header.count != local_header.count {
+        data.extend_from_slice(&format!("Error: exchange_msk_batch(): Mismatched number of target TDs Migration ID: {:x}. Local: {:x} Remote: {:x}\n", info.mig_request_id, local_header.count, remote_header.count).into_bytes());
+        log::error!("exchange_msk_batch(): Mismatched number of target TDs Migration ID: {}. Local: {} Remote: {}\n", info.mig_request_id, local_header.count, remote_header.count);
+        return Err(MigrationResult::InvalidParameter);
+    }
+
+    // Entries are exchanged in lockstep, the keys are only written once every
+    // target TD is known to be listed at the same index on both sides
+    let mut exchanged = Vec::with_capacity(info.entries.len());
+    for (index, mig_info) in info.entries.iter().enumerate() {
+        let exchange_information =
+            BatchExchangeInformation::prepare(index, mig_info, info.is_src());
+        if exchange_information.status != MigrationResult::Success as u8 {
+            log::error!(
+                "exchange_msk_batch: exchange_info error: {:x} for index = {}\n",
+                exchange_information.status,
+                index
+            );
+        }
+
+        let mut remote_information = BatchExchangeInformation::default();
+        with_timeout(TLS_TIMEOUT, channel.write(exchange_information.as_bytes()))
+            .await
+            .map_err(|e| {
+                log::error!("exchange_msk_batch: channel.write timeout error: {:?}\n", e);
+                e
+            })?
+            .map_err(|e| {
+                log::error!("exchange_msk_batch: channel.write error: {:?}\n", e);
+                e
+            })?;
+        let size = with_timeout(TLS_TIMEOUT, channel.read(remote_information.as_bytes_mut()))
+            .await
+            .map_err(|e| {
+                log::error!("exchange_msk_batch: channel.read timeout error: {:?}\n", e);
+                e
+            })?
+            .map_err(|e| {
+                log::error!("exchange_msk_batch: channel.read error: {:?}\n", e);
+                e
+            })?;
+        if size < size_of::<BatchExchangeInformation>() || remote_information.index != index as u32
+        {
+            log::error!("exchange_msk_batch(): Incorrect BatchExchangeInformation Migration ID: {}. Index: {} Size: {}\n", info.mig_request_id, index, size);
+            return Err(MigrationResult::NetworkError);
+        }
+        exchanged.push((exchange_information, remote_information));
+    }
+
+    if let Some(index) = exchanged
+        .iter()
+        .position(|(local, remote)| local.target_td_uuid != remote.target_td_uuid)
+    {
+        data.extend_from_slice(&format!("Error: exchange_msk_batch(): Mismatched target TD Migration ID: {:x}. Index: {:x}\n", info.mig_request_id, index).into_bytes());
+        log::error!(
+            "exchange_msk_batch(): Mismatched target TD Migration ID: {}. Index: {}\n",
+            info.mig_request_id,
+            index
+        );
+        return Err(MigrationResult::InvalidParameter);
+    }
+
+    metrics::enter_phase(info.mig_request_id, MigrationPhase::SetMsk);
+    let mut status = Vec::with_capacity(info.entries.len());
+    for (index, (mig_info, (exchange_information, remote_information))) in
+        info.entries.iter().zip(exchanged.iter()).enumerate()
+    {
+        let result = if exchange_information.status != MigrationResult::Success as u8 {
+            MigrationResult::try_from(exchange_information.status)
+                .unwrap_or(MigrationResult::InvalidParameter)
+        } else if remote_information.status != MigrationResult::Success as u8 {
+            // The peer failed to prepare this target TD, skip it on both sides
+            MigrationResult::try_from(remote_information.status)
+                .unwrap_or(MigrationResult::InvalidParameter)
+        } else {
+            set_exchanged_key(
+                mig_info,
+                info.is_src(),
+                &exchange_information.info,
+                &remote_information.info,
+            )
+            .map(|_| MigrationResult::Success)
+            .unwrap_or_else(|e| e)
+        };
+
+        #[cfg(feature = "runtime_measurement")]
+        if result == MigrationResult::Success {
+            measure_migration_receipt(mig_info, result, peer_policy_digest, Some(&peer));
+        }
+
+        entrylog(
+            &format!(
+                "Batch MSK exchange index {:x} status {:x}\n",
+                index, result as u8
+            )
+            .into_bytes(),
+            Level::Info,
+            info.mig_request_id,
+        );
+        status.push(result);
+    }
+    // Zeroizes the exchanged keys
+    drop(exchanged);
+
+    channel.transport_mut().shutdown().await.map_err(|e| {
+        data.extend_from_slice(&format!("Error: exchange_msk_batch(): Failed to transport in vmcall_raw_instance with Migration ID: {:x} errorcode: {}\n", info.mig_request_id, e).into_bytes());
+        log::error!("exchange_msk_batch: Failed to transport in vmcall_raw_instance with Migration ID: {} errorcode: {}\n", info.mig_request_id, e);
+        MigrationResult::InvalidParameter
+    })?;
+
+    Ok(status)
+}
+
+/// Batch migration is not supported with SPDM based attestation, the request
+/// fails with `MigrationResult::Unsupported` and none of the keys is written.
+#[cfg(all(feature = "vmcall-raw", feature = "spdm_attestation"))]
+pub async fn exchange_msk_batch(
+    info: &BatchMigrationInformation,
+    data: &mut Vec<u8>,
+) -> Result<Vec<MigrationResult>> {
+    data.extend_from_slice(
+        &format!(
+            "Error: exchange_msk_batch(): Batch migration is not supported with SPDM. Migration ID: {:x}\n",
+            info.mig_request_id
+        )
+        .into_bytes(),
+    );
+    Err(MigrationResult::Unsupported)
+}
+
 pub fn exchange_info(
     mig_info: &MigtdMigrationInformation,
     is_src: bool,
@@ -1516,6 +2598,28 @@ pub fn exchange_info(
     Ok(exchange_info)
 }
 
+/// Negotiates the migration version with the information received from the peer
+/// MigTD and writes its key for the target TD of `mig_info`.
+pub fn set_exchanged_key(
+    mig_info: &MigtdMigrationInformation,
+    is_src: bool,
+    local_info: &ExchangeInformation,
+    remote_info: &ExchangeInformation,
+) -> Result<()> {
+    let mig_ver = cal_mig_version(is_src, local_info, remote_info).map_err(|e| {
+        log::error!("set_exchanged_key: cal_mig_version error: {:?}\n", e);
+        e
+    })?;
+    set_mig_version(mig_info, mig_ver).map_err(|e| {
+        log::error!("set_exchanged_key: set_mig_version error: {:?}\n", e);
+        e
+    })?;
+    write_msk(mig_info, &remote_info.key).map_err(|e| {
+        log::error!("set_exchanged_key: write_msk error: {:?}\n", e);
+        e
+    })
+}
+
 fn read_msk(mig_info: &MigtdMigrationInformation, msk: &mut MigrationSessionKey) -> Result<()> {
     for idx in 0..msk.fields.len() {
         let ret = tdx::tdcall_servtd_rd(
@@ -1540,7 +2644,7 @@ pub fn write_msk(mig_info: &MigtdMigrationInformation, msk: &MigrationSessionKey
             &mig_info.target_td_uuid,
         )
         .map_err(|e| {
-            log::error!("write_msk: tdcall_servtd_wr failed with error: {:?} for mig_info.binding_handle = {}, idx = {}, value = {}\n", e, mig_info.binding_handle, idx, msk.fields[idx]);
+            log::error!("write_msk: tdcall_servtd_wr failed with error: {:?} for mig_info.binding_handle = {}, idx = {}\n", e, mig_info.binding_handle, idx);
             MigrationResult::TdxModuleError
         })?;
     }
@@ -1628,8 +2732,9 @@ pub fn set_mig_version(mig_info: &MigtdMigrationInformation, mig_ver: u16) -> Re
 #[cfg(test)]
 mod test {
     use crate::migration::{session::cal_mig_version, MigrationResult};
+    use alloc::boxed::Box;
 
-    use super::ExchangeInformation;
+    use super::{size_of, ExchangeInformation, MigrationSessionKey};
 
     #[test]
     fn test_cal_mig_version() {
@@ -1691,4 +2796,108 @@ mod test {
         let result = cal_mig_version(true, &local_info, &remote_info);
         assert!(matches!(result, Ok(6)));
     }
+
+    // Run `f` on a value placed in a heap slot owned by the test, drop the value as an
+    // early return would do and scan the slot for any leftover of the session key.
+    fn audit_key_after_drop<T>(value: T, key_offset: usize, f: impl FnOnce(&mut T) -> bool) {
+        use core::mem::MaybeUninit;
+        use core::slice::from_raw_parts;
+
+        let mut slot: Box<MaybeUninit<T>> = Box::new(MaybeUninit::uninit());
+        let failed = f(slot.write(value));
+        assert!(failed);
+        unsafe { slot.assume_init_drop() };
+
+        let key = unsafe {
+            from_raw_parts(
+                (slot.as_ptr() as *const u8).add(key_offset),
+                size_of::<MigrationSessionKey>(),
+            )
+        };
+        assert!(key.iter().all(|b| *b == 0));
+    }
+
+    #[test]
+    fn test_exchange_information_zeroize_on_drop() {
+        const SECRET: u64 = 0x5a5a_a5a5_5a5a_a5a5;
+
+        let mut local_info = ExchangeInformation::default();
+        local_info.min_ver = 2;
+        local_info.max_ver = 2;
+        local_info.key.fields = [SECRET; 4];
+
+        let remote_info = ExchangeInformation::default();
+        audit_key_after_drop(
+            local_info,
+            core::mem::offset_of!(ExchangeInformation, key),
+            // The version negotiation fails after the keys have been loaded
+            |local| cal_mig_version(true, local, &remote_info).is_err(),
+        );
+
+        let key = MigrationSessionKey {
+            fields: [SECRET; 4],
+        };
+        audit_key_after_drop(key, 0, |key| key.fields[0] == SECRET);
+    }
+
+    #[cfg(feature = "policy_v2")]
+    #[test]
+    fn test_hello_packet_payload() {
+        use super::{
+            target_tds_digest, HelloContext, HelloPacketPayload, MigrationResult,
+            SESSION_NONCE_SIZE,
+        };
+
+        let context = HelloContext {
+            nonce: [0xa5; SESSION_NONCE_SIZE],
+            request_id: 42,
+        };
+        let target_tds = target_tds_digest(&[[1, 2, 3, 4], [5, 6, 7, 8]]).unwrap();
+        let payload = HelloPacketPayload::new(&context, target_tds);
+        let bytes = payload.as_bytes();
+        assert_eq!(HelloPacketPayload::HELLO_PACKET_PAYLOAD_SIZE, 96);
+        assert_eq!(bytes.len(), HelloPacketPayload::HELLO_PACKET_PAYLOAD_SIZE);
+
+        let remote = HelloPacketPayload::read_from_bytes(bytes).unwrap();
+        assert_eq!(remote.nonce, context.nonce);
+        assert_eq!(remote.request_id, 42);
+        assert_eq!(remote.target_tds, target_tds);
+        assert_eq!(remote.negotiate_supported_version(), Some(0x0102));
+
+        // A peer of an older version does not overlap
+        let mut old = HelloPacketPayload::new(&context, target_tds);
+        old.lowest_supported_version = 0x0100;
+        old.highest_supported_version = 0x0101;
+        assert_eq!(old.negotiate_supported_version(), None);
+
+        // and its shorter hello packet is reported as unsupported
+        let bytes = old.as_bytes();
+        assert_eq!(
+            HelloPacketPayload::read_from_bytes(&bytes[..8 + SESSION_NONCE_SIZE]).err(),
+            Some(MigrationResult::Unsupported)
+        );
+
+        // A hello packet of the current version without nonce is rejected
+        let bytes = payload.as_bytes();
+        assert_eq!(
+            HelloPacketPayload::read_from_bytes(&bytes[..8]).err(),
+            Some(MigrationResult::InvalidParameter)
+        );
+    }
+
+    #[test]
+    fn test_complete_request() {
+        use super::{complete_request, in_flight_requests, CANCELED_REQUESTS, REQUESTS};
+
+        let request_id = u64::MAX - 3;
+        REQUESTS.lock().insert(request_id);
+        CANCELED_REQUESTS.lock().insert(request_id);
+        assert!(in_flight_requests(None).contains(&request_id));
+        assert!(!in_flight_requests(Some(request_id)).contains(&request_id));
+
+        // A canceled request is forgotten once it reported its status
+        complete_request(request_id);
+        assert!(!in_flight_requests(None).contains(&request_id));
+        assert!(!CANCELED_REQUESTS.lock().contains(&request_id));
+    }
 }
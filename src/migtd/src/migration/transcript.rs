@@ -0,0 +1,487 @@
+// Copyright (c) 2025 Intel Corporation
+//
+// SPDX-License-Identifier: BSD-2-Clause-Patent
+
+//! Capture of the attestation transcripts for post-mortem analysis.
+//!
+//! When the `transcriptCapture` field of the policy is set, MigTD records the
+//! non-secret inputs of the peer authentication of each key exchange: the
+//! hello packets, both pre-session policies, the peer certificate with its
+//! quote and event log, the local TCB evaluation data, the policy issuer chain
+//! and the verdict of `mig_policy::authenticate_remote`. The VMM can retrieve
+//! the latest transcripts with the `GetTranscript` request, and a host tool can
+//! replay them to reproduce the verdict.
+//!
+//! Only the data exchanged in the clear or carried by the peer certificate is
+//! recorded. Key material must never be given to `record`.
+
+use crate::migration::budget;
+use alloc::collections::{BTreeMap, VecDeque};
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use lazy_static::lazy_static;
+use policy::PolicyError;
+use spin::Mutex;
+
+/// Signature of a serialized transcript
+pub const TRANSCRIPT_SIGNATURE: [u8; 8] = *b"MIGTDTRS";
+/// Version of the transcript format
+pub const TRANSCRIPT_VERSION: u32 = 1;
+/// Size of the header of a serialized transcript
+pub const TRANSCRIPT_HEADER_SIZE: usize = 32;
+/// Size of the header of a transcript record
+pub const TRANSCRIPT_RECORD_HEADER_SIZE: usize = 8;
+/// Maximum size of a serialized transcript, the records which do not fit are
+/// dropped and the transcript is flagged as truncated
+pub const TRANSCRIPT_MAX_SIZE: usize = 0x20000;
+/// Number of completed transcripts kept for the VMM. They outlive their
+/// request and are held in the heap reserved for the global state.
+pub const TRANSCRIPT_MAX_COUNT: usize = 2;
+
+const _: () = assert!(TRANSCRIPT_MAX_COUNT * TRANSCRIPT_MAX_SIZE <= budget::HEAP_RESERVED_SIZE / 4);
+/// Size of the transcript chunk reported for a `GetTranscript` request
+pub const TRANSCRIPT_CHUNK_SIZE: usize = 0xF00;
+
+/// The transcript lacks records which did not fit in `TRANSCRIPT_MAX_SIZE`
+pub const TRANSCRIPT_FLAG_TRUNCATED: u8 = 1;
+
+/// Kinds of the transcript records
+#[repr(u16)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RecordKind {
+    /// Hello packet payload sent to the peer
+    LocalHello = 1,
+    /// Hello packet payload received from the peer
+    PeerHello = 2,
+    /// Migration policy sent to the peer
+    LocalPolicy = 3,
+    /// Migration policy received from the peer
+    PeerPolicy = 4,
+    /// DER certificate of the peer
+    PeerCertificate = 5,
+    /// Quote extension of the peer certificate
+    PeerQuote = 6,
+    /// Event log extension of the peer certificate
+    PeerEventLog = 7,
+    /// PEM chain of the policy issuer
+    PolicyIssuerChain = 8,
+    /// JSON `PolicyEvaluationInfo` of the local TCB
+    LocalTcbInfo = 9,
+    /// UTF-8 verdict of the peer authentication, see `verdict`
+    Verdict = 10,
+}
+
+impl RecordKind {
+    pub fn from_u16(value: u16) -> Option<Self> {
+        match value {
+            1 => Some(RecordKind::LocalHello),
+            2 => Some(RecordKind::PeerHello),
+            3 => Some(RecordKind::LocalPolicy),
+            4 => Some(RecordKind::PeerPolicy),
+            5 => Some(RecordKind::PeerCertificate),
+            6 => Some(RecordKind::PeerQuote),
+            7 => Some(RecordKind::PeerEventLog),
+            8 => Some(RecordKind::PolicyIssuerChain),
+            9 => Some(RecordKind::LocalTcbInfo),
+            10 => Some(RecordKind::Verdict),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RecordKind::LocalHello => "local_hello",
+            RecordKind::PeerHello => "peer_hello",
+            RecordKind::LocalPolicy => "local_policy",
+            RecordKind::PeerPolicy => "peer_policy",
+            RecordKind::PeerCertificate => "peer_certificate",
+            RecordKind::PeerQuote => "peer_quote",
+            RecordKind::PeerEventLog => "peer_event_log",
+            RecordKind::PolicyIssuerChain => "policy_issuer_chain",
+            RecordKind::LocalTcbInfo => "local_tcb_info",
+            RecordKind::Verdict => "verdict",
+        }
+    }
+}
+
+/// Attestation transcript of one key exchange
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Transcript {
+    pub mig_request_id: u64,
+    pub is_src: bool,
+    pub flags: u8,
+    pub records: Vec<(RecordKind, Vec<u8>)>,
+    size: usize,
+}
+
+impl Transcript {
+    pub fn new(mig_request_id: u64, is_src: bool) -> Self {
+        Self {
+            mig_request_id,
+            is_src,
+            flags: 0,
+            records: Vec::new(),
+            size: TRANSCRIPT_HEADER_SIZE,
+        }
+    }
+
+    /// Appends a record, unless the transcript would exceed
+    /// `TRANSCRIPT_MAX_SIZE`.
+    pub fn push(&mut self, kind: RecordKind, data: &[u8]) {
+        if !self.fits(data.len()) {
+            self.flags |= TRANSCRIPT_FLAG_TRUNCATED;
+            return;
+        }
+        self.size += TRANSCRIPT_RECORD_HEADER_SIZE + data.len();
+        self.records.push((kind, data.to_vec()));
+    }
+
+    fn fits(&self, len: usize) -> bool {
+        self.size + TRANSCRIPT_RECORD_HEADER_SIZE + len <= TRANSCRIPT_MAX_SIZE
+    }
+
+    /// Returns the data of the first record of `kind`
+    pub fn get(&self, kind: RecordKind) -> Option<&[u8]> {
+        self.records
+            .iter()
+            .find(|(k, _)| *k == kind)
+            .map(|(_, data)| data.as_slice())
+    }
+
+    /// Serializes the transcript, all the fields being little-endian:
+    /// - signature: [u8; 8], `MIGTDTRS`
+    /// - version: u32
+    /// - size: u32, size of the serialized transcript
+    /// - mig_request_id: u64
+    /// - is_src: u8, 1 for the source MigTD
+    /// - flags: u8
+    /// - record_count: u16
+    /// - reserved: [u8; 4]
+    /// - record_count times: kind u16, reserved u16, length u32, data
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(self.size);
+        bytes.extend_from_slice(&TRANSCRIPT_SIGNATURE);
+        bytes.extend_from_slice(&TRANSCRIPT_VERSION.to_le_bytes());
+        bytes.extend_from_slice(&(self.size as u32).to_le_bytes());
+        bytes.extend_from_slice(&self.mig_request_id.to_le_bytes());
+        bytes.push(self.is_src as u8);
+        bytes.push(self.flags);
+        bytes.extend_from_slice(&(self.records.len() as u16).to_le_bytes());
+        bytes.extend_from_slice(&[0u8; 4]);
+        for (kind, data) in &self.records {
+            bytes.extend_from_slice(&(*kind as u16).to_le_bytes());
+            bytes.extend_from_slice(&[0u8; 2]);
+            bytes.extend_from_slice(&(data.len() as u32).to_le_bytes());
+            bytes.extend_from_slice(data);
+        }
+        bytes
+    }
+
+    /// Parses a serialized transcript. Records of unknown kinds are skipped.
+    pub fn read_from(data: &[u8]) -> Option<Self> {
+        if data.len() < TRANSCRIPT_HEADER_SIZE || data[0..8] != TRANSCRIPT_SIGNATURE {
+            return None;
+        }
+        let version = u32::from_le_bytes(data[8..12].try_into().ok()?);
+        let size = u32::from_le_bytes(data[12..16].try_into().ok()?) as usize;
+        if version != TRANSCRIPT_VERSION || size != data.len() {
+            return None;
+        }
+
+        let mut transcript = Transcript::new(
+            u64::from_le_bytes(data[16..24].try_into().ok()?),
+            data[24] != 0,
+        );
+        transcript.flags = data[25];
+        let record_count = u16::from_le_bytes(data[26..28].try_into().ok()?);
+
+        let mut offset = TRANSCRIPT_HEADER_SIZE;
+        for _ in 0..record_count {
+            let header = data.get(offset..offset + TRANSCRIPT_RECORD_HEADER_SIZE)?;
+            let kind = u16::from_le_bytes(header[0..2].try_into().ok()?);
+            let length = u32::from_le_bytes(header[4..8].try_into().ok()?) as usize;
+            offset += TRANSCRIPT_RECORD_HEADER_SIZE;
+
+            let record = data.get(offset..offset.checked_add(length)?)?;
+            if let Some(kind) = RecordKind::from_u16(kind) {
+                transcript.records.push((kind, record.to_vec()));
+            }
+            offset += length;
+        }
+        if offset != size {
+            return None;
+        }
+        transcript.size = size;
+
+        Some(transcript)
+    }
+}
+
+/// Verdict recorded for the result of `mig_policy::authenticate_remote`,
+/// compared by the replay.
+pub fn verdict<T>(result: &Result<T, PolicyError>) -> String {
+    match result {
+        Ok(_) => String::from("Ok"),
+        Err(e) => format!("Err({:?})", e),
+    }
+}
+
+lazy_static! {
+    static ref IN_FLIGHT_TRANSCRIPTS: Mutex<BTreeMap<u64, Transcript>> =
+        Mutex::new(BTreeMap::new());
+    // Serialized once completed, keyed by migration request id
+    static ref COMPLETED_TRANSCRIPTS: Mutex<VecDeque<(u64, Vec<u8>)>> =
+        Mutex::new(VecDeque::new());
+}
+
+/// Returns whether the policy enables the transcript capture
+#[cfg(feature = "policy_v2")]
+pub fn is_enabled() -> bool {
+    crate::mig_policy::get_verified_policy()
+        .is_some_and(|policy| policy.policy_data.is_transcript_capture_enabled())
+}
+
+/// Returns whether the policy enables the transcript capture, which is only
+/// supported by the policy v2
+#[cfg(not(feature = "policy_v2"))]
+pub fn is_enabled() -> bool {
+    false
+}
+
+/// Capture of the transcript of an in-flight key exchange, completed when it
+/// is dropped, whatever the outcome of the exchange.
+///
+/// The request `mig_request_id` must hold a heap budget: the records are
+/// charged to it.
+pub struct Capture {
+    mig_request_id: u64,
+}
+
+impl Capture {
+    /// Starts the capture of the transcript of `mig_request_id` if the policy
+    /// enables it.
+    pub fn begin(mig_request_id: u64, is_src: bool) -> Option<Self> {
+        if !is_enabled() {
+            return None;
+        }
+
+        IN_FLIGHT_TRANSCRIPTS
+            .lock()
+            .insert(mig_request_id, Transcript::new(mig_request_id, is_src));
+        record_evaluation_inputs(mig_request_id);
+        Some(Self { mig_request_id })
+    }
+
+    /// Sink recording into the captured transcript, handed over to the
+    /// components which evaluate the peer.
+    pub fn sink(&self) -> Sink {
+        Sink {
+            mig_request_id: self.mig_request_id,
+        }
+    }
+}
+
+/// Sink of the records of a captured transcript, see `Capture::sink`.
+#[derive(Debug, Clone, Copy)]
+pub struct Sink {
+    mig_request_id: u64,
+}
+
+impl Sink {
+    /// Appends a record to the transcript, see `record`.
+    pub fn record(&self, kind: RecordKind, data: &[u8]) {
+        record(self.mig_request_id, kind, data);
+    }
+}
+
+impl Drop for Capture {
+    fn drop(&mut self) {
+        let transcript = IN_FLIGHT_TRANSCRIPTS.lock().remove(&self.mig_request_id);
+        if let Some(transcript) = transcript {
+            complete(transcript.mig_request_id, transcript.to_bytes());
+        }
+    }
+}
+
+// Keeps the serialized transcript for the `GetTranscript` requests
+fn complete(mig_request_id: u64, bytes: Vec<u8>) {
+    #[cfg(feature = "AzCVMEmu")]
+    write_transcript_file(&bytes);
+
+    let mut completed = COMPLETED_TRANSCRIPTS.lock();
+    completed.retain(|(id, _)| *id != mig_request_id);
+    if completed.len() == TRANSCRIPT_MAX_COUNT {
+        completed.pop_front();
+    }
+    completed.push_back((mig_request_id, bytes));
+}
+
+// Local inputs of the peer authentication which the replay cannot recover
+// from the peer data
+#[cfg(feature = "policy_v2")]
+fn record_evaluation_inputs(mig_request_id: u64) {
+    if let Some(chain) = crate::config::get_policy_issuer_chain() {
+        record(mig_request_id, RecordKind::PolicyIssuerChain, chain);
+    }
+    match crate::mig_policy::get_local_tcb_evaluation_info().and_then(|info| info.to_json()) {
+        Ok(info) => record(mig_request_id, RecordKind::LocalTcbInfo, &info),
+        Err(e) => log::warn!("Transcript: no local TCB info {:?}\n", e),
+    }
+}
+
+#[cfg(not(feature = "policy_v2"))]
+fn record_evaluation_inputs(_mig_request_id: u64) {}
+
+/// Appends a record to the transcript of `mig_request_id`, if it is captured.
+///
+/// The copy of the data is charged to the heap budget of the request, a
+/// record which does not fit in the budget is dropped and the transcript
+/// flagged as truncated.
+pub fn record(mig_request_id: u64, kind: RecordKind, data: &[u8]) {
+    if let Some(transcript) = IN_FLIGHT_TRANSCRIPTS.lock().get_mut(&mig_request_id) {
+        if transcript.fits(data.len())
+            && budget::charge(mig_request_id, TRANSCRIPT_RECORD_HEADER_SIZE + data.len()).is_err()
+        {
+            log::warn!("Transcript: {} record dropped\n", kind.as_str());
+            transcript.flags |= TRANSCRIPT_FLAG_TRUNCATED;
+            return;
+        }
+        transcript.push(kind, data);
+    }
+}
+
+/// Returns the data reported for a `GetTranscript` request: the size of the
+/// transcript (u32) followed by up to `TRANSCRIPT_CHUNK_SIZE` bytes from
+/// `offset`.
+pub fn transcript_chunk(mig_request_id: u64, offset: usize) -> Option<Vec<u8>> {
+    let completed = COMPLETED_TRANSCRIPTS.lock();
+    let (_, transcript) = completed.iter().find(|(id, _)| *id == mig_request_id)?;
+    let chunk = transcript.get(offset..)?;
+    let chunk = &chunk[..chunk.len().min(TRANSCRIPT_CHUNK_SIZE)];
+
+    let mut data = Vec::with_capacity(4 + chunk.len());
+    data.extend_from_slice(&(transcript.len() as u32).to_le_bytes());
+    data.extend_from_slice(chunk);
+    Some(data)
+}
+
+#[cfg(feature = "AzCVMEmu")]
+static TRANSCRIPT_FILE: std::sync::OnceLock<std::string::String> = std::sync::OnceLock::new();
+
+/// Sets the file the completed transcripts are written to in AzCVMEmu mode,
+/// each one replacing the previous one.
+#[cfg(feature = "AzCVMEmu")]
+pub fn set_transcript_file(path: std::string::String) {
+    let _ = TRANSCRIPT_FILE.set(path);
+}
+
+#[cfg(feature = "AzCVMEmu")]
+fn write_transcript_file(transcript: &[u8]) {
+    if let Some(path) = TRANSCRIPT_FILE.get() {
+        if let Err(e) = std::fs::write(path, transcript) {
+            log::error!("Failed to write transcript to {}: {}\n", path, e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn transcript() -> Transcript {
+        let mut transcript = Transcript::new(42, true);
+        transcript.push(RecordKind::LocalHello, b"hello");
+        transcript.push(RecordKind::PeerPolicy, b"{\"policyData\": {}}");
+        transcript.push(RecordKind::PeerQuote, &[0x5a; 64]);
+        transcript.push(RecordKind::Verdict, b"Ok");
+        transcript
+    }
+
+    #[test]
+    fn test_transcript_bytes() {
+        let transcript = transcript();
+        let bytes = transcript.to_bytes();
+        assert_eq!(&bytes[0..8], b"MIGTDTRS");
+        assert_eq!(
+            u32::from_le_bytes(bytes[12..16].try_into().unwrap()) as usize,
+            bytes.len()
+        );
+        assert_eq!(Transcript::read_from(&bytes).unwrap(), transcript);
+        assert_eq!(transcript.get(RecordKind::PeerQuote), Some(&[0x5a; 64][..]));
+        assert_eq!(transcript.get(RecordKind::PeerEventLog), None);
+
+        // Truncated, bad signature and bad size
+        assert!(Transcript::read_from(&bytes[..bytes.len() - 1]).is_none());
+        let mut invalid = bytes.clone();
+        invalid[0] = b'X';
+        assert!(Transcript::read_from(&invalid).is_none());
+        let mut invalid = bytes.clone();
+        invalid[12] ^= 1;
+        assert!(Transcript::read_from(&invalid).is_none());
+
+        // Records of an unknown kind are skipped
+        let mut unknown = bytes.clone();
+        unknown[TRANSCRIPT_HEADER_SIZE] = 0xff;
+        let parsed = Transcript::read_from(&unknown).unwrap();
+        assert_eq!(parsed.records.len(), 3);
+        assert_eq!(parsed.get(RecordKind::LocalHello), None);
+    }
+
+    #[test]
+    fn test_transcript_max_size() {
+        let mut transcript = Transcript::new(1, false);
+        transcript.push(RecordKind::PeerPolicy, &[0; TRANSCRIPT_MAX_SIZE / 2]);
+        transcript.push(RecordKind::LocalPolicy, &[0; TRANSCRIPT_MAX_SIZE / 2]);
+        transcript.push(RecordKind::Verdict, b"Ok");
+        assert_eq!(transcript.flags, TRANSCRIPT_FLAG_TRUNCATED);
+        assert_eq!(transcript.records.len(), 2);
+        assert!(transcript.to_bytes().len() <= TRANSCRIPT_MAX_SIZE);
+    }
+
+    #[test]
+    fn test_transcript_chunk() {
+        let bytes = transcript().to_bytes();
+        complete(42, bytes.clone());
+
+        let chunk = transcript_chunk(42, 0).unwrap();
+        assert_eq!(
+            u32::from_le_bytes(chunk[0..4].try_into().unwrap()) as usize,
+            bytes.len()
+        );
+        assert_eq!(&chunk[4..], &bytes[..]);
+        assert_eq!(&transcript_chunk(42, 10).unwrap()[4..], &bytes[10..]);
+        assert_eq!(transcript_chunk(42, bytes.len()).unwrap().len(), 4);
+        assert!(transcript_chunk(42, bytes.len() + 1).is_none());
+        assert!(transcript_chunk(43, 0).is_none());
+    }
+
+    #[test]
+    fn test_record_charged() {
+        let arena = budget::RequestArena::admit(44).unwrap();
+        let remaining = budget::HEAP_BUDGET.lock().remaining(44);
+        IN_FLIGHT_TRANSCRIPTS
+            .lock()
+            .insert(44, Transcript::new(44, false));
+
+        record(44, RecordKind::PeerPolicy, &[0; 0x100]);
+        let remaining = remaining - TRANSCRIPT_RECORD_HEADER_SIZE - 0x100;
+        assert_eq!(budget::HEAP_BUDGET.lock().remaining(44), remaining);
+
+        // A record beyond the budget of the request is dropped
+        arena.charge(remaining - 0x10).unwrap();
+        record(44, RecordKind::PeerQuote, &[0; 0x100]);
+        let transcript = IN_FLIGHT_TRANSCRIPTS.lock().remove(&44).unwrap();
+        assert_eq!(transcript.records.len(), 1);
+        assert_eq!(transcript.flags, TRANSCRIPT_FLAG_TRUNCATED);
+    }
+
+    #[test]
+    fn test_verdict() {
+        assert_eq!(verdict::<()>(&Ok(())), "Ok");
+        assert_eq!(
+            verdict::<()>(&Err(PolicyError::InvalidPolicy)),
+            "Err(InvalidPolicy)"
+        );
+    }
+}
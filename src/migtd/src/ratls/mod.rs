@@ -4,8 +4,9 @@
 
 use crypto::{
     x509::{DerError, ObjectIdentifier},
-    Error as CryptoError,
+    Error as CryptoError, PeerCertError, PolicyErrorCode,
 };
+use policy::PolicyError;
 use tdx_tdcall::TdCallError;
 
 #[cfg(feature = "main")]
@@ -22,6 +23,7 @@ pub enum RatlsError {
     X509(DerError),
     InvalidEventlog,
     InvalidPolicy,
+    InvalidTime,
 }
 
 impl From<TdCallError> for RatlsError {
@@ -42,6 +44,78 @@ impl From<DerError> for RatlsError {
     }
 }
 
+/// Reason for rejecting the RA-TLS certificate of the peer
+#[derive(Debug, Clone)]
+pub enum VerifyPeerError {
+    /// The local or the peer quote can't be verified
+    MutualAttestation,
+    /// The certificate key isn't the one bound in the peer quote
+    MismatchPublicKey,
+    /// The certificate isn't valid at the current time
+    OutsideValidity,
+    /// The migration policy is invalid
+    InvalidPolicy(PolicyError),
+    /// The peer doesn't satisfy the migration policy
+    PolicyUnsatisfied(PolicyError),
+    /// The certificate doesn't fit in the heap budget of the request
+    OutOfResource,
+}
+
+impl From<VerifyPeerError> for PeerCertError {
+    fn from(value: VerifyPeerError) -> Self {
+        match value {
+            VerifyPeerError::MutualAttestation => PeerCertError::MutualAttestation,
+            VerifyPeerError::MismatchPublicKey => PeerCertError::MismatchPublicKey,
+            VerifyPeerError::OutsideValidity => PeerCertError::OutsideValidity,
+            VerifyPeerError::InvalidPolicy(e) => {
+                PeerCertError::InvalidPolicy(policy_error_code(&e))
+            }
+            VerifyPeerError::PolicyUnsatisfied(e) => {
+                PeerCertError::PolicyUnsatisfied(policy_error_code(&e))
+            }
+            VerifyPeerError::OutOfResource => PeerCertError::OutOfResource,
+        }
+    }
+}
+
+fn policy_error_code(e: &PolicyError) -> PolicyErrorCode {
+    match e {
+        PolicyError::FailGetReport => PolicyErrorCode::FailGetReport,
+        PolicyError::InvalidParameter => PolicyErrorCode::InvalidParameter,
+        PolicyError::InvalidPolicy => PolicyErrorCode::InvalidPolicy,
+        PolicyError::InvalidEventLog => PolicyErrorCode::InvalidEventLog,
+        PolicyError::PlatformNotFound(_) => PolicyErrorCode::PlatformNotFound,
+        PolicyError::PlatformNotMatch(..) => PolicyErrorCode::PlatformNotMatch,
+        PolicyError::UnqualifiedPlatformInfo => PolicyErrorCode::UnqualifiedPlatformInfo,
+        PolicyError::UnqualifiedQeInfo => PolicyErrorCode::UnqualifiedQeInfo,
+        PolicyError::UnqualifiedTdxModuleInfo => PolicyErrorCode::UnqualifiedTdxModuleInfo,
+        PolicyError::UnqualifiedMigTdInfo => PolicyErrorCode::UnqualifiedMigTdInfo,
+        PolicyError::Crypto => PolicyErrorCode::Crypto,
+        PolicyError::SignatureVerificationFailed => PolicyErrorCode::SignatureVerificationFailed,
+        PolicyError::InvalidCollateral => PolicyErrorCode::InvalidCollateral,
+        PolicyError::InvalidOperation => PolicyErrorCode::InvalidOperation,
+        PolicyError::InvalidReference => PolicyErrorCode::InvalidReference,
+        PolicyError::InvalidServtdIdentity => PolicyErrorCode::InvalidServtdIdentity,
+        PolicyError::InvalidServtdTcbMapping => PolicyErrorCode::InvalidServtdTcbMapping,
+        PolicyError::InvalidPolicyIssuers => PolicyErrorCode::InvalidPolicyIssuers,
+        PolicyError::PolicyHashMismatch => PolicyErrorCode::PolicyHashMismatch,
+        PolicyError::InvalidQuote => PolicyErrorCode::InvalidQuote,
+        PolicyError::SvnMismatch => PolicyErrorCode::SvnMismatch,
+        PolicyError::TcbEvaluation => PolicyErrorCode::TcbEvaluation,
+        PolicyError::CrlEvaluation => PolicyErrorCode::CrlEvaluation,
+        PolicyError::HashCalculation => PolicyErrorCode::HashCalculation,
+        PolicyError::QuoteVerification => PolicyErrorCode::QuoteVerification,
+        PolicyError::QuoteGeneration => PolicyErrorCode::QuoteGeneration,
+        PolicyError::GetTdxReport => PolicyErrorCode::GetTdxReport,
+    }
+}
+
+impl From<VerifyPeerError> for CryptoError {
+    fn from(value: VerifyPeerError) -> Self {
+        CryptoError::TlsVerifyPeerCert(value.into())
+    }
+}
+
 pub const BASIC_CONSTRAINTS: ObjectIdentifier = ObjectIdentifier::new_unwrap("2.5.29.19");
 pub const SUBJECT_KEY_IDENTIFIER: ObjectIdentifier = ObjectIdentifier::new_unwrap("2.5.29.14");
 pub const AUTHORITY_KEY_IDENTIFIER: ObjectIdentifier = ObjectIdentifier::new_unwrap("2.5.29.35");
@@ -68,8 +142,3 @@ pub const KEY_USAGE_EXTENSION: ObjectIdentifier = ObjectIdentifier::new_unwrap("
 pub const SERVER_AUTH: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.3.6.1.5.5.7.3.1");
 pub const CLIENT_AUTH: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.3.6.1.5.5.7.3.2");
 pub const ID_EC_SIG_OID: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.2.840.10045.4.3.3");
-
-pub const MIG_POLICY_UNSATISFIED_ERROR: &str = "PolicyUnsatisfiedError";
-pub const INVALID_MIG_POLICY_ERROR: &str = "InvalidPolicyError";
-pub const MUTUAL_ATTESTATION_ERROR: &str = "MutualAttestationError";
-pub const MISMATCH_PUBLIC_KEY: &str = "MismatchPublicKeyError";
@@ -2,12 +2,16 @@
 //
 // SPDX-License-Identifier: BSD-2-Clause-Patent
 
+use alloc::string::String;
+use alloc::sync::Arc;
 use alloc::vec::Vec;
 use async_io::{AsyncRead, AsyncWrite};
+use core::fmt;
+use core::time::Duration;
 use crypto::{
     ecdsa::EcdsaPk,
     hash::digest_sha384,
-    tls::{SecureChannel, TlsConfig},
+    tls::{PeerVerifier, SecureChannel, TlsConfig},
     x509::{
         AlgorithmIdentifier, AnyRef, BitStringRef, Certificate, CertificateBuilder, Decode, Encode,
         ExtendedKeyUsage, Extension, Extensions, Tag,
@@ -15,32 +19,276 @@ use crypto::{
     Error as CryptoError,
 };
 
+use zeroize::{Zeroize, Zeroizing};
+
 use super::*;
 #[cfg(feature = "policy_v2")]
 use crate::config::get_policy;
 use crate::event_log::get_event_log;
+use crate::migration::metrics::{self, MigrationPhase};
+use crate::migration::redact::Redacted;
+#[cfg(feature = "policy_v2")]
+use crate::migration::transcript;
 use verify::*;
 
 type Result<T> = core::result::Result<T, RatlsError>;
 
+/// Size of the nonces exchanged in the pre-session hello
+pub const SESSION_NONCE_SIZE: usize = 32;
+
+/// Size of the digest of the target TDs exchanged in the pre-session hello
+pub const TARGET_TDS_DIGEST_SIZE: usize = 48;
+
+/// Context announced by one MigTD in the pre-session hello.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HelloContext {
+    /// Random nonce of the session
+    pub nonce: [u8; SESSION_NONCE_SIZE],
+    /// ID of the migration request served by the MigTD, assigned by its VMM
+    pub request_id: u64,
+}
+
+/// Session context exchanged in the pre-session hello.
+///
+/// The quote of each RA-TLS certificate commits to the context of both
+/// MigTDs, so that a leaked certificate and its quote cannot be replayed in
+/// another session, for another migration request or for other target TDs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SessionBinding {
+    /// Context sent to the peer
+    pub local: HelloContext,
+    /// Context received from the peer
+    pub peer: HelloContext,
+    /// Digest of the target TDs of the request, see `target_tds_digest`.
+    /// Both MigTDs serve the same target TDs.
+    pub target_tds: [u8; TARGET_TDS_DIGEST_SIZE],
+}
+
+impl SessionBinding {
+    /// Report data of the quote of the local RA-TLS certificate
+    pub fn local_report_data(&self, public_key: &[u8], is_src: bool) -> crypto::Result<Vec<u8>> {
+        bound_report_data(
+            public_key,
+            &self.peer,
+            &self.local,
+            &self.target_tds,
+            is_src,
+        )
+    }
+
+    /// Report data expected in the quote of the RA-TLS certificate of the
+    /// peer, `is_src` being the role of the local MigTD
+    pub fn peer_report_data(&self, public_key: &[u8], is_src: bool) -> crypto::Result<Vec<u8>> {
+        bound_report_data(
+            public_key,
+            &self.local,
+            &self.peer,
+            &self.target_tds,
+            !is_src,
+        )
+    }
+}
+
+/// Digest of the target TDs of a migration request: SHA-384 of their UUIDs in
+/// the order of the request.
+pub fn target_tds_digest(
+    target_td_uuids: &[[u64; 4]],
+) -> crypto::Result<[u8; TARGET_TDS_DIGEST_SIZE]> {
+    let mut material = Vec::with_capacity(target_td_uuids.len() * 32);
+    for uuid in target_td_uuids {
+        for word in uuid {
+            material.extend_from_slice(&word.to_le_bytes());
+        }
+    }
+    let mut digest = [0u8; TARGET_TDS_DIGEST_SIZE];
+    digest.copy_from_slice(&digest_sha384(&material)?);
+    Ok(digest)
+}
+
+// Report data of the quote of an RA-TLS certificate bound to a session:
+// SHA-384(public key || peer nonce || own nonce || peer request ID ||
+// own request ID || target TDs digest || role), the request IDs being little
+// endian and the role being 1 for the source MigTD and 0 for the destination.
+fn bound_report_data(
+    public_key: &[u8],
+    peer: &HelloContext,
+    own: &HelloContext,
+    target_tds: &[u8],
+    is_src: bool,
+) -> crypto::Result<Vec<u8>> {
+    let mut material =
+        Vec::with_capacity(public_key.len() + 2 * (SESSION_NONCE_SIZE + 8) + target_tds.len() + 1);
+    material.extend_from_slice(public_key);
+    material.extend_from_slice(&peer.nonce);
+    material.extend_from_slice(&own.nonce);
+    material.extend_from_slice(&peer.request_id.to_le_bytes());
+    material.extend_from_slice(&own.request_id.to_le_bytes());
+    material.extend_from_slice(target_tds);
+    material.push(is_src as u8);
+    digest_sha384(&material)
+}
+
+/// Claims about the peer established while verifying its RA-TLS certificate,
+/// returned by `SecureChannel::peer_claims` once the handshake is done.
+#[derive(Clone)]
+pub struct VerifiedPeer {
+    /// Evaluation data of the peer TCB: FMSPC, TCB status, MigTD SVN...
+    #[cfg(feature = "policy_v2")]
+    pub evaluation: policy::PolicyEvaluationInfo,
+    /// Id of the verified policy of the peer
+    #[cfg(feature = "policy_v2")]
+    pub policy_id: String,
+    /// Supplemental data of the verified peer quote, starting with its TD
+    /// report
+    pub suppl_data: Vec<u8>,
+}
+
+impl fmt::Debug for VerifiedPeer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut s = f.debug_struct("VerifiedPeer");
+        #[cfg(feature = "policy_v2")]
+        s.field("evaluation", &self.evaluation);
+        #[cfg(feature = "policy_v2")]
+        s.field("policy_id", &self.policy_id);
+        s.field("suppl_data", &Redacted(&self.suppl_data));
+        s.finish()
+    }
+}
+
+/// RA-TLS session whose peer is verified by `RatlsVerifier`
+pub type RatlsChannel<T> = SecureChannel<T, VerifiedPeer>;
+
+/// Verifier of the RA-TLS certificate of the peer.
+///
+/// With policy v1 the peer is authenticated against the verified report of
+/// the local quote, with policy v2 against the policy of the peer and the
+/// context of the session. The inputs and the verdict of the evaluation are
+/// recorded into `trace` if the transcript of the request is captured.
+struct RatlsVerifier {
+    // MigTD-src acts as TLS client
+    is_client: bool,
+    // The peer certificate is charged to the heap budget of this request
+    request_id: u64,
+    #[cfg(not(feature = "policy_v2"))]
+    verified_report_local: Zeroizing<Vec<u8>>,
+    #[cfg(feature = "policy_v2")]
+    remote_policy: Vec<u8>,
+    #[cfg(feature = "policy_v2")]
+    binding: SessionBinding,
+    #[cfg(feature = "policy_v2")]
+    trace: Option<transcript::Sink>,
+}
+
+impl RatlsVerifier {
+    #[cfg(not(feature = "policy_v2"))]
+    fn new(is_client: bool, request_id: u64, quote_local: &[u8]) -> Result<Self> {
+        // The local report is not used if the remote attestation is disabled
+        let verified_report_local = if cfg!(feature = "test_disable_ra_and_accept_all") {
+            Zeroizing::new(Vec::new())
+        } else {
+            crate::mig_policy::local_verified_report(quote_local).map_err(|e| {
+                log::error!("Failed to verify the local quote. Error: {:?}\n", e);
+                RatlsError::VerifyQuote
+            })?
+        };
+        Ok(Self {
+            is_client,
+            request_id,
+            verified_report_local,
+        })
+    }
+
+    #[cfg(feature = "policy_v2")]
+    fn new(
+        is_client: bool,
+        request_id: u64,
+        remote_policy: Vec<u8>,
+        binding: SessionBinding,
+        trace: Option<transcript::Sink>,
+    ) -> Self {
+        Self {
+            is_client,
+            request_id,
+            remote_policy,
+            binding,
+            trace,
+        }
+    }
+
+    #[cfg(feature = "policy_v2")]
+    fn record(&self, kind: transcript::RecordKind, data: &[u8]) {
+        if let Some(trace) = &self.trace {
+            trace.record(kind, data);
+        }
+    }
+}
+
+impl Zeroize for RatlsVerifier {
+    fn zeroize(&mut self) {
+        #[cfg(not(feature = "policy_v2"))]
+        self.verified_report_local.zeroize();
+        #[cfg(feature = "policy_v2")]
+        self.remote_policy.zeroize();
+    }
+}
+
+impl fmt::Debug for RatlsVerifier {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut s = f.debug_struct("RatlsVerifier");
+        s.field("is_client", &self.is_client);
+        s.field("request_id", &self.request_id);
+        #[cfg(not(feature = "policy_v2"))]
+        s.field(
+            "verified_report_local",
+            &Redacted(&self.verified_report_local),
+        );
+        #[cfg(feature = "policy_v2")]
+        s.field("remote_policy", &Redacted(&self.remote_policy));
+        #[cfg(feature = "policy_v2")]
+        s.field("binding", &self.binding);
+        #[cfg(feature = "policy_v2")]
+        s.field("trace", &self.trace);
+        s.finish()
+    }
+}
+
+impl PeerVerifier for RatlsVerifier {
+    type Claims = VerifiedPeer;
+
+    // The handshake resumes once the peer is verified, a rejected peer keeps
+    // the verification phase it failed in.
+    fn verify(&self, cert: &[u8]) -> core::result::Result<VerifiedPeer, CryptoError> {
+        metrics::enter_phase(self.request_id, MigrationPhase::QuoteVerification);
+        charge_peer_buffer(self, cert.len())?;
+        let peer = verify_peer_cert(self, cert)?;
+        metrics::enter_phase(self.request_id, MigrationPhase::SecureSession);
+        Ok(peer)
+    }
+}
+
 #[cfg(not(feature = "policy_v2"))]
-pub fn server<T: AsyncRead + AsyncWrite + Unpin>(stream: T) -> Result<SecureChannel<T>> {
+pub fn server<T: AsyncRead + AsyncWrite + Unpin>(
+    stream: T,
+    request_id: u64,
+) -> Result<RatlsChannel<T>> {
     let signing_key = EcdsaPk::new().map_err(|e| {
         log::error!("server EcdsaPk::new() failed with error {:?}\n", e);
         e
     })?;
-    let (certs, quote) = gen_cert(&signing_key).map_err(|e| {
+    let (certs, quote) = gen_cert(&signing_key, false, request_id, None).map_err(|e| {
         log::error!("server gen_cert() failed with error {:?}\n", e);
         e
     })?;
     let certs = vec![certs];
 
     // Server verifies certificate of client
-    let config = TlsConfig::new(certs, signing_key, verify_client_cert, quote).map_err(|e| {
+    let verifier = RatlsVerifier::new(false, request_id, &quote)?;
+    let mut config = TlsConfig::new(certs, signing_key, Arc::new(verifier)).map_err(|e| {
         log::error!("server TlsConfig::new() failed with error {:?}\n", e);
         e
     })?;
 
+    set_kx_group(&mut config)?;
     config.tls_server(stream).map_err(|e| {
         log::error!("server tls_server() failed with error {:?}\n", e);
         e.into()
@@ -50,8 +298,11 @@ pub fn server<T: AsyncRead + AsyncWrite + Unpin>(stream: T) -> Result<SecureChan
 #[cfg(feature = "policy_v2")]
 pub fn server<T: AsyncRead + AsyncWrite + Unpin>(
     stream: T,
+    request_id: u64,
     remote_policy: Vec<u8>,
-) -> Result<SecureChannel<T>> {
+    binding: SessionBinding,
+    trace: Option<transcript::Sink>,
+) -> Result<RatlsChannel<T>> {
     let signing_key = EcdsaPk::new().map_err(|e| {
         log::error!(
             "server policy_v2 EcdsaPk::new() failed with error {:?}\n",
@@ -59,21 +310,23 @@ pub fn server<T: AsyncRead + AsyncWrite + Unpin>(
         );
         e
     })?;
-    let (certs, _quote) = gen_cert(&signing_key).map_err(|e| {
-        log::error!("server policy_v2 gen_cert() failed with error {:?}\n", e);
-        e
-    })?;
+    let (certs, _quote) =
+        gen_cert(&signing_key, false, request_id, Some(&binding)).map_err(|e| {
+            log::error!("server policy_v2 gen_cert() failed with error {:?}\n", e);
+            e
+        })?;
     let certs = vec![certs];
 
     // Server verifies certificate of client
-    let config =
-        TlsConfig::new(certs, signing_key, verify_client_cert, remote_policy).map_err(|e| {
-            log::error!(
-                "server policy_v2 TlsConfig::new() failed with error {:?}\n",
-                e
-            );
+    let verifier = RatlsVerifier::new(false, request_id, remote_policy, binding, trace);
+    let mut config = TlsConfig::new(certs, signing_key, Arc::new(verifier)).map_err(|e| {
+        log::error!(
+            "server policy_v2 TlsConfig::new() failed with error {:?}\n",
             e
-        })?;
+        );
+        e
+    })?;
+    set_kx_group(&mut config)?;
     config.tls_server(stream).map_err(|e| {
         log::error!("server policy_v2 tls_server() failed with error {:?}\n", e);
         e.into()
@@ -83,23 +336,26 @@ pub fn server<T: AsyncRead + AsyncWrite + Unpin>(
 #[cfg(not(feature = "policy_v2"))]
 pub fn client<T: AsyncRead + AsyncWrite + Unpin>(
     stream: T,
+    request_id: u64,
     #[cfg(feature = "vmcall-raw")] data: &mut Vec<u8>,
-) -> Result<SecureChannel<T>> {
+) -> Result<RatlsChannel<T>> {
     let signing_key = EcdsaPk::new().map_err(|e| {
         log::error!("client EcdsaPk::new() failed with error {:?}\n", e);
         e
     })?;
-    let (certs, quote) = gen_cert(&signing_key).map_err(|e| {
+    let (certs, quote) = gen_cert(&signing_key, true, request_id, None).map_err(|e| {
         log::error!("client gen_cert() failed with error {:?}\n", e);
         e
     })?;
     let certs = vec![certs];
 
     // Client verifies certificate of server
-    let config = TlsConfig::new(certs, signing_key, verify_server_cert, quote).map_err(|e| {
+    let verifier = RatlsVerifier::new(true, request_id, &quote)?;
+    let mut config = TlsConfig::new(certs, signing_key, Arc::new(verifier)).map_err(|e| {
         log::error!("client TlsConfig::new() failed with error {:?}\n", e);
         e
     })?;
+    set_kx_group(&mut config)?;
     config.tls_client(stream).map_err(|e| {
         #[cfg(feature = "vmcall-raw")]
         data.extend_from_slice(
@@ -120,9 +376,12 @@ pub fn client<T: AsyncRead + AsyncWrite + Unpin>(
 #[cfg(feature = "policy_v2")]
 pub fn client<T: AsyncRead + AsyncWrite + Unpin>(
     stream: T,
+    request_id: u64,
     remote_policy: Vec<u8>,
+    binding: SessionBinding,
+    trace: Option<transcript::Sink>,
     #[cfg(feature = "vmcall-raw")] data: &mut Vec<u8>,
-) -> Result<SecureChannel<T>> {
+) -> Result<RatlsChannel<T>> {
     let signing_key = EcdsaPk::new().map_err(|e| {
         log::error!(
             "client policy_v2 EcdsaPk::new() failed with error {:?}\n",
@@ -130,21 +389,23 @@ pub fn client<T: AsyncRead + AsyncWrite + Unpin>(
         );
         e
     })?;
-    let (certs, _quote) = gen_cert(&signing_key).map_err(|e| {
-        log::error!("client policy_v2 gen_cert() failed with error {:?}\n", e);
-        e
-    })?;
+    let (certs, _quote) =
+        gen_cert(&signing_key, true, request_id, Some(&binding)).map_err(|e| {
+            log::error!("client policy_v2 gen_cert() failed with error {:?}\n", e);
+            e
+        })?;
     let certs = vec![certs];
 
     // Client verifies certificate of server
-    let config =
-        TlsConfig::new(certs, signing_key, verify_server_cert, remote_policy).map_err(|e| {
-            log::error!(
-                "client policy_v2 TlsConfig::new() failed with error {:?}\n",
-                e
-            );
+    let verifier = RatlsVerifier::new(true, request_id, remote_policy, binding, trace);
+    let mut config = TlsConfig::new(certs, signing_key, Arc::new(verifier)).map_err(|e| {
+        log::error!(
+            "client policy_v2 TlsConfig::new() failed with error {:?}\n",
             e
-        })?;
+        );
+        e
+    })?;
+    set_kx_group(&mut config)?;
     config.tls_client(stream).map_err(|e| {
         #[cfg(feature = "vmcall-raw")]
         data.extend_from_slice(
@@ -159,7 +420,65 @@ pub fn client<T: AsyncRead + AsyncWrite + Unpin>(
     })
 }
 
-fn gen_cert(signing_key: &EcdsaPk) -> Result<(Vec<u8>, Vec<u8>)> {
+/// RA-TLS certificates are valid from `CERT_VALIDITY_MARGIN` before their
+/// generation until `CERT_VALIDITY` after it.
+///
+/// A peer certificate valid for longer than this period is rejected, as well
+/// as a certificate which is not valid at the current time, give or take
+/// `CERT_CLOCK_SKEW`. This limits how long a leaked certificate key can be
+/// used.
+pub const CERT_VALIDITY_MARGIN: Duration = Duration::from_secs(5 * 60);
+pub const CERT_VALIDITY: Duration = Duration::from_secs(10 * 60);
+
+/// Tolerated difference between the clocks of the MigTDs when checking the
+/// validity period of a peer certificate. The time of MigTD is read from an
+/// RTC controlled by the VMM, so the clocks of both MigTDs may differ.
+pub const CERT_CLOCK_SKEW: Duration = Duration::from_secs(5 * 60);
+
+const CERT_SERIAL_NUMBER_SIZE: usize = 16;
+
+fn set_kx_group(config: &mut TlsConfig<VerifiedPeer>) -> Result<()> {
+    let kx_group = crate::mig_policy::kx_group().map_err(|e| {
+        log::error!("kx_group failed with error {:?}\n", e);
+        RatlsError::InvalidPolicy
+    })?;
+    config.set_kx_group(kx_group).map_err(|e| {
+        log::error!("set_kx_group failed with error {:?}\n", e);
+        e.into()
+    })
+}
+
+// Random serial number, kept positive and without leading zero
+fn cert_serial_number() -> Result<[u8; CERT_SERIAL_NUMBER_SIZE]> {
+    let mut serial_number = [0u8; CERT_SERIAL_NUMBER_SIZE];
+    crypto::rand::get_random(&mut serial_number).map_err(|e| {
+        log::error!("gen_cert get_random failed with error {:?}\n", e);
+        e
+    })?;
+    serial_number[0] = (serial_number[0] & 0x7f) | 0x40;
+    Ok(serial_number)
+}
+
+fn cert_common_name(is_src: bool, request_id: u64) -> String {
+    let role = if is_src { "src" } else { "dst" };
+    format!("MigTD-{} request {}", role, request_id)
+}
+
+// The quote is bound to the session if `binding` holds its context, policy v1
+// does not exchange a hello and binds the quote to the public key only.
+fn gen_cert(
+    signing_key: &EcdsaPk,
+    is_src: bool,
+    request_id: u64,
+    binding: Option<&SessionBinding>,
+) -> Result<(Vec<u8>, Zeroizing<Vec<u8>>)> {
+    let now = crypto::tls::current_time().ok_or_else(|| {
+        log::error!("gen_cert failed to get the current time.\n");
+        RatlsError::InvalidTime
+    })?;
+    let serial_number = cert_serial_number()?;
+    let common_name = cert_common_name(is_src, request_id);
+
     let algorithm = AlgorithmIdentifier {
         algorithm: ID_EC_PUBKEY_OID,
         parameters: Some(AnyRef::new(
@@ -201,7 +520,7 @@ fn gen_cert(signing_key: &EcdsaPk) -> Result<(Vec<u8>, Vec<u8>)> {
             );
             e
         })?;
-    let quote = gen_quote(&pub_key).map_err(|e| {
+    let quote = gen_quote(&pub_key, binding.map(|b| (b, is_src))).map_err(|e| {
         log::error!("gen_cert gen_quote() failed with error {:?}\n", e);
         e
     })?;
@@ -229,14 +548,22 @@ fn gen_cert(signing_key: &EcdsaPk) -> Result<(Vec<u8>, Vec<u8>)> {
             log::error!("gen_cert CertificateBuilder::new failed with error {:?}\n", e);
             e
         })?
-        // 1970-01-01T00:00:00Z
-        .set_not_before(core::time::Duration::new(0, 0))
+        .set_serial_number(&serial_number)
+        .map_err(|e| {
+            log::error!("gen_cert set_serial_number failed with error {:?}\n", e);
+            e
+        })?
+        .add_common_name(&common_name)
+        .map_err(|e| {
+            log::error!("gen_cert add_common_name failed with error {:?}\n", e);
+            e
+        })?
+        .set_not_before(now.saturating_sub(CERT_VALIDITY_MARGIN))
         .map_err(|e| {
             log::error!("gen_cert set_not_before failed with error {:?}\n", e);
             e
         })?
-        // 9999-12-31T23:59:59Z
-        .set_not_after(core::time::Duration::new(253402300799, 0))
+        .set_not_after(now + CERT_VALIDITY)
         .map_err(|e| {
             log::error!("gen_cert set_not_after failed with error {:?}\n", e);
             e
@@ -336,13 +663,18 @@ fn gen_cert(signing_key: &EcdsaPk) -> Result<(Vec<u8>, Vec<u8>)> {
         })?;
 
     let mut x509_certificate = x509_builder.build();
-    let tbs = x509_certificate.tbs_certificate.to_der().map_err(|e| {
-        log::error!(
-            "gen_cert x509_certificate.tbs_certificate.to_der failed with error {:?}.\n",
+    // The encoded TBS certificate carries a copy of the quote
+    let tbs = x509_certificate
+        .tbs_certificate
+        .to_der()
+        .map(Zeroizing::new)
+        .map_err(|e| {
+            log::error!(
+                "gen_cert x509_certificate.tbs_certificate.to_der failed with error {:?}.\n",
+                e
+            );
             e
-        );
-        e
-    })?;
+        })?;
     let signature = signing_key.sign(&tbs).map_err(|e| {
         log::error!("gen_cert signing_key.sign failed with error {:?}.\n", e);
         e
@@ -367,8 +699,15 @@ fn gen_cert(signing_key: &EcdsaPk) -> Result<(Vec<u8>, Vec<u8>)> {
     ))
 }
 
-fn gen_quote(public_key: &[u8]) -> Result<Vec<u8>> {
-    let hash = digest_sha384(public_key).map_err(|e| {
+fn gen_quote(
+    public_key: &[u8],
+    binding: Option<(&SessionBinding, bool)>,
+) -> Result<Zeroizing<Vec<u8>>> {
+    let hash = match binding {
+        Some((binding, is_src)) => binding.local_report_data(public_key, is_src),
+        None => digest_sha384(public_key),
+    }
+    .map_err(|e| {
         log::error!("Failed to compute SHA384 digest: {:?}\n", e);
         e
     })?;
@@ -380,45 +719,39 @@ fn gen_quote(public_key: &[u8]) -> Result<Vec<u8>> {
         log::error!("Failed to get TD report via tdcall. Error: {:?}\n", e);
         e
     })?;
+    additional_data.zeroize();
 
-    attestation::get_quote(td_report.as_bytes()).map_err(|e| {
-        log::error!("Failed to get quote from TD report. Error: {:?}\n", e);
-        RatlsError::GetQuote
-    })
-}
-
-fn verify_server_cert(cert: &[u8], quote: &[u8]) -> core::result::Result<(), CryptoError> {
-    verify_peer_cert(true, cert, quote)
-}
-
-fn verify_client_cert(cert: &[u8], quote: &[u8]) -> core::result::Result<(), CryptoError> {
-    verify_peer_cert(false, cert, quote)
+    attestation::get_quote(td_report.as_bytes())
+        .map(Zeroizing::new)
+        .map_err(|e| {
+            log::error!("Failed to get quote from TD report. Error: {:?}\n", e);
+            RatlsError::GetQuote
+        })
 }
 
 #[cfg(not(feature = "test_disable_ra_and_accept_all"))]
 mod verify {
     use super::*;
     use crate::mig_policy;
+    #[cfg(feature = "policy_v2")]
+    use crate::migration::transcript::RecordKind;
 
-    use alloc::string::ToString;
     use crypto::ecdsa::ecdsa_verify;
     use crypto::{Error as CryptoError, Result as CryptoResult};
     use policy::PolicyError;
+    #[cfg(feature = "policy_v2")]
+    use policy::RawPolicyData;
 
     #[cfg(not(feature = "policy_v2"))]
     pub fn verify_peer_cert(
-        is_client: bool,
+        verifier: &RatlsVerifier,
         cert: &[u8],
-        quote_local: &[u8],
-    ) -> core::result::Result<(), CryptoError> {
-        let verified_report_local = attestation::verify_quote(quote_local).map_err(|e| {
-            log::error!("Mutual attestation error {:?}.\n", e);
-            CryptoError::TlsVerifyPeerCert(MUTUAL_ATTESTATION_ERROR.to_string())
-        })?;
+    ) -> core::result::Result<VerifiedPeer, CryptoError> {
         let cert = Certificate::from_der(cert).map_err(|e| {
             log::error!("Failed to parse certificate from DER. Error: {:?}\n", e);
             CryptoError::ParseCertificate
         })?;
+        check_validity(&cert)?;
         let extensions = cert.tbs_certificate.extensions.as_ref().ok_or_else(|| {
             log::error!("Failed to get certificate extensions.\n");
             CryptoError::ParseCertificate
@@ -439,17 +772,19 @@ mod verify {
             log::error!("Failed to find event log extension.\n");
             CryptoError::ParseCertificate
         })?;
+        // The quote is copied by the verification, the event log parsed
+        charge_peer_buffer(verifier, quote_report.len() + event_log.len())?;
 
-        if let Ok(verified_report_peer) = attestation::verify_quote(quote_report) {
-            verify_signature(&cert, verified_report_peer.as_slice()).map_err(|e| {
+        if let Ok(verified_report_peer) = mig_policy::verify_peer_quote(quote_report) {
+            verify_signature(&cert, verified_report_peer.as_slice(), None).map_err(|e| {
                 log::error!("Failed to verify signature: {:?}\n", e);
                 e
             })?;
 
-            // MigTD-src acts as TLS client
             let policy_check_result = mig_policy::authenticate_policy(
-                is_client,
-                verified_report_local.as_slice(),
+                verifier.request_id,
+                verifier.is_client,
+                verifier.verified_report_local.as_slice(),
                 verified_report_peer.as_slice(),
                 event_log,
             );
@@ -459,34 +794,28 @@ mod verify {
                 log::error!("{:x?}\n", e);
             }
 
-            policy_check_result.map_err(|e| match e {
-                PolicyError::InvalidPolicy => {
-                    log::error!("Invalid migration policy.\n");
-                    CryptoError::TlsVerifyPeerCert(INVALID_MIG_POLICY_ERROR.to_string())
-                }
-                _ => {
-                    log::error!("Migration policy unsatisfied.\n");
-                    CryptoError::TlsVerifyPeerCert(MIG_POLICY_UNSATISFIED_ERROR.to_string())
-                }
+            policy_check_result.map_err(policy_error)?;
+            Ok(VerifiedPeer {
+                suppl_data: verified_report_peer,
             })
         } else {
             log::error!("Mutual attestation error.\n");
-            Err(CryptoError::TlsVerifyPeerCert(
-                MUTUAL_ATTESTATION_ERROR.to_string(),
-            ))
+            Err(VerifyPeerError::MutualAttestation.into())
         }
     }
 
     #[cfg(feature = "policy_v2")]
     pub fn verify_peer_cert(
-        is_client: bool,
+        verifier: &RatlsVerifier,
         cert: &[u8],
-        policy: &[u8],
-    ) -> core::result::Result<(), CryptoError> {
+    ) -> core::result::Result<VerifiedPeer, CryptoError> {
+        let policy = verifier.remote_policy.as_slice();
+        verifier.record(RecordKind::PeerCertificate, cert);
         let cert = Certificate::from_der(cert).map_err(|_| {
             log::error!("Failed to parse certificate from DER.\n");
             CryptoError::ParseCertificate
         })?;
+        check_validity(&cert)?;
 
         let extensions = cert.tbs_certificate.extensions.as_ref().ok_or_else(|| {
             log::error!("Failed to get certificate extensions.\n");
@@ -512,38 +841,71 @@ mod verify {
                 log::error!("Failed to find expected policy hash extension.\n");
                 CryptoError::ParseCertificate
             })?;
+        // The quote is copied by the verification, the event log parsed
+        charge_peer_buffer(verifier, quote_report.len() + event_log.len())?;
+        verifier.record(RecordKind::PeerQuote, quote_report);
+        verifier.record(RecordKind::PeerEventLog, event_log);
 
         let exact_policy_hash = digest_sha384(policy)?;
         if expected_policy_hash != exact_policy_hash.as_slice() {
             log::error!("Invalid migration policy.\n");
-            return Err(CryptoError::TlsVerifyPeerCert(
-                INVALID_MIG_POLICY_ERROR.to_string(),
-            ));
+            return Err(VerifyPeerError::InvalidPolicy(PolicyError::PolicyHashMismatch).into());
         }
-        // MigTD-src acts as TLS client
-        let policy_check_result =
-            mig_policy::authenticate_remote(is_client, quote_report, policy, event_log);
+        let policy_check_result = mig_policy::authenticate_remote(
+            verifier.request_id,
+            verifier.is_client,
+            quote_report,
+            policy,
+            event_log,
+        );
+        verifier.record(
+            RecordKind::Verdict,
+            transcript::verdict(&policy_check_result).as_bytes(),
+        );
 
         if let Err(e) = &policy_check_result {
             log::error!("Policy check failed, below is the detail information:\n");
             log::error!("{:x?}\n", e);
         }
 
-        let suppl_data = policy_check_result.map_err(|e| match e {
+        let (evaluation, suppl_data) = policy_check_result.map_err(policy_error)?;
+
+        verify_signature(
+            &cert,
+            suppl_data.as_slice(),
+            Some((&verifier.binding, verifier.is_client)),
+        )?;
+        // The policy has been verified by `authenticate_remote`
+        let policy_id = RawPolicyData::deserialize_from_json(policy)
+            .and_then(|policy| policy.get_policy_id())
+            .map_err(policy_error)?;
+        Ok(VerifiedPeer {
+            evaluation,
+            policy_id,
+            suppl_data,
+        })
+    }
+
+    fn policy_error(e: PolicyError) -> CryptoError {
+        match e {
             PolicyError::InvalidPolicy => {
                 log::error!("Invalid migration policy.\n");
-                CryptoError::TlsVerifyPeerCert(INVALID_MIG_POLICY_ERROR.to_string())
+                VerifyPeerError::InvalidPolicy(e).into()
             }
             _ => {
                 log::error!("Migration policy unsatisfied.\n");
-                CryptoError::TlsVerifyPeerCert(MIG_POLICY_UNSATISFIED_ERROR.to_string())
+                VerifyPeerError::PolicyUnsatisfied(e).into()
             }
-        })?;
-
-        verify_signature(&cert, suppl_data.as_slice())
+        }
     }
 
-    fn verify_signature(cert: &Certificate, verified_report: &[u8]) -> CryptoResult<()> {
+    // The report data of the peer quote is bound to the session if `binding`
+    // holds its context and whether the local MigTD is the source.
+    fn verify_signature(
+        cert: &Certificate,
+        verified_report: &[u8],
+        binding: Option<(&SessionBinding, bool)>,
+    ) -> CryptoResult<()> {
         let public_key = cert
             .tbs_certificate
             .subject_public_key_info
@@ -561,14 +923,18 @@ mod verify {
             log::error!("Failed to get signature bytes from certificate.\n");
             CryptoError::ParseCertificate
         })?;
-        verify_public_key(verified_report, public_key).map_err(|e| {
+        verify_public_key(verified_report, public_key, binding).map_err(|e| {
             log::error!("Public key verification failed: {:?}\n", e);
             e
         })?;
         ecdsa_verify(public_key, &tbs, signature)
     }
 
-    fn verify_public_key(verified_report: &[u8], public_key: &[u8]) -> CryptoResult<()> {
+    fn verify_public_key(
+        verified_report: &[u8],
+        public_key: &[u8],
+        binding: Option<(&SessionBinding, bool)>,
+    ) -> CryptoResult<()> {
         if cfg!(feature = "AzCVMEmu") {
             // In AzCVMEmu mode, REPORTDATA is constructed differently.
             // Bypass public key hash check in this development environment.
@@ -588,19 +954,27 @@ mod verify {
         const PUBLIC_KEY_HASH_SIZE: usize = 48;
 
         let report_data = &verified_report[520..520 + PUBLIC_KEY_HASH_SIZE];
-        let digest = digest_sha384(public_key).map_err(|e| {
+        // The peer commits to our context, its own context and its role
+        let digest = match binding {
+            Some((binding, is_src)) => binding.peer_report_data(public_key, is_src),
+            None => digest_sha384(public_key),
+        }
+        .map_err(|e| {
             log::error!("Failed to compute SHA384 digest: {:?}\n", e);
             e
         })?;
 
         if report_data == digest.as_slice() {
-            Ok(())
+            return Ok(());
+        }
+        // A quote bound to the public key only comes from a policy v1 MigTD,
+        // which cannot be bound to the session
+        if binding.is_some() && digest_sha384(public_key)?.as_slice() == report_data {
+            log::error!("Peer quote is not bound to the session, policy v1 peers are rejected.\n");
         } else {
             log::error!("Public key verification failed in TD report.\n");
-            Err(CryptoError::TlsVerifyPeerCert(
-                MISMATCH_PUBLIC_KEY.to_string(),
-            ))
         }
+        Err(VerifyPeerError::MismatchPublicKey.into())
     }
 }
 
@@ -610,10 +984,9 @@ mod verify {
     use super::*;
 
     pub fn verify_peer_cert(
-        _is_client: bool,
+        _verifier: &RatlsVerifier,
         cert: &[u8],
-        _quote_local: &[u8],
-    ) -> core::result::Result<(), CryptoError> {
+    ) -> core::result::Result<VerifiedPeer, CryptoError> {
         let cert = Certificate::from_der(cert).map_err(|_| CryptoError::ParseCertificate)?;
 
         let extensions = cert
@@ -631,10 +1004,68 @@ mod verify {
 
         // As the remote attestation is disabled, the certificate can't be verified. Aways return
         // success for test purpose.
-        Ok(())
+        Ok(VerifiedPeer {
+            #[cfg(feature = "policy_v2")]
+            evaluation: policy::PolicyEvaluationInfo::default(),
+            #[cfg(feature = "policy_v2")]
+            policy_id: String::new(),
+            suppl_data: Vec::new(),
+        })
     }
 }
 
+// Peer-sized buffers are charged to the heap budget of the request before
+// they are copied or parsed.
+fn charge_peer_buffer(
+    verifier: &RatlsVerifier,
+    bytes: usize,
+) -> core::result::Result<(), CryptoError> {
+    crate::migration::budget::charge(verifier.request_id, bytes).map_err(|_| {
+        log::error!(
+            "Peer buffer of {:x} bytes exceeds the heap budget of request {:x}\n",
+            bytes,
+            verifier.request_id
+        );
+        VerifyPeerError::OutOfResource.into()
+    })
+}
+
+// Rejects certificates that are expired, not yet valid or valid for longer
+// than the certificates issued by `gen_cert`.
+#[cfg(not(feature = "test_disable_ra_and_accept_all"))]
+fn check_validity(cert: &Certificate) -> core::result::Result<(), CryptoError> {
+    let validity = &cert.tbs_certificate.validity;
+    check_validity_period(
+        validity.not_before().to_unix_duration(),
+        validity.not_after().to_unix_duration(),
+        crypto::tls::current_time(),
+    )
+}
+
+// See `CERT_VALIDITY_MARGIN`, `now` is the current time if known. Without
+// the current time, the certificate is rejected: MigTD can't generate its own
+// certificate either.
+#[cfg(not(feature = "test_disable_ra_and_accept_all"))]
+fn check_validity_period(
+    not_before: Duration,
+    not_after: Duration,
+    now: Option<Duration>,
+) -> core::result::Result<(), CryptoError> {
+    if not_after < not_before || not_after - not_before > CERT_VALIDITY_MARGIN + CERT_VALIDITY {
+        log::error!("Certificate validity period is invalid or too long.\n");
+        return Err(VerifyPeerError::OutsideValidity.into());
+    }
+    let now = now.ok_or_else(|| {
+        log::error!("Failed to get the current time to check the certificate validity.\n");
+        CryptoError::from(VerifyPeerError::OutsideValidity)
+    })?;
+    if now + CERT_CLOCK_SKEW < not_before || now > not_after + CERT_CLOCK_SKEW {
+        log::error!("Certificate is not valid at the current time.\n");
+        return Err(VerifyPeerError::OutsideValidity.into());
+    }
+    Ok(())
+}
+
 fn check_migtd_eku(extensions: &Extensions) -> core::result::Result<(), CryptoError> {
     for extn in extensions.get() {
         if extn.extn_id == EXTENDED_KEY_USAGE {
@@ -663,3 +1094,108 @@ fn find_extension<'a>(extensions: &'a Extensions, id: &ObjectIdentifier) -> Opti
         }
     })
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_session_binding() {
+        let src_context = HelloContext {
+            nonce: [1; SESSION_NONCE_SIZE],
+            request_id: 7,
+        };
+        let dst_context = HelloContext {
+            nonce: [2; SESSION_NONCE_SIZE],
+            request_id: 9,
+        };
+        let target_tds = target_tds_digest(&[[1, 2, 3, 4]]).unwrap();
+        let src = SessionBinding {
+            local: src_context,
+            peer: dst_context,
+            target_tds,
+        };
+        let dst = SessionBinding {
+            local: dst_context,
+            peer: src_context,
+            target_tds,
+        };
+        let public_key = [0x5a; 97];
+
+        // Each side expects the report data computed by its peer
+        let src_report_data = src.local_report_data(&public_key, true).unwrap();
+        assert_eq!(
+            dst.peer_report_data(&public_key, false).unwrap(),
+            src_report_data
+        );
+        assert_eq!(
+            src.peer_report_data(&public_key, true).unwrap(),
+            dst.local_report_data(&public_key, false).unwrap()
+        );
+        assert_ne!(digest_sha384(&public_key).unwrap(), src_report_data);
+
+        // Another request or other target TDs do not match
+        let mut other = dst;
+        other.peer.request_id = 8;
+        assert_ne!(
+            other.peer_report_data(&public_key, false).unwrap(),
+            src_report_data
+        );
+        let mut other = dst;
+        other.target_tds = target_tds_digest(&[[1, 2, 3, 5]]).unwrap();
+        assert_ne!(
+            other.peer_report_data(&public_key, false).unwrap(),
+            src_report_data
+        );
+        assert_ne!(
+            target_tds_digest(&[[1, 2, 3, 4], [5, 6, 7, 8]]).unwrap(),
+            target_tds_digest(&[[5, 6, 7, 8], [1, 2, 3, 4]]).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_cert_serial_number() {
+        let first = cert_serial_number().unwrap();
+        let second = cert_serial_number().unwrap();
+        assert_ne!(first, second);
+        for serial_number in [first, second] {
+            // Positive, and encoded on all its bytes
+            assert_eq!(serial_number[0] & 0xc0, 0x40);
+        }
+    }
+
+    #[test]
+    fn test_cert_common_name() {
+        assert_eq!(cert_common_name(true, 7), "MigTD-src request 7");
+        assert_eq!(
+            cert_common_name(false, u64::MAX),
+            "MigTD-dst request 18446744073709551615"
+        );
+    }
+
+    #[cfg(not(feature = "test_disable_ra_and_accept_all"))]
+    #[test]
+    fn test_check_validity_period() {
+        let now = Duration::from_secs(1_700_000_000);
+        let not_before = now - CERT_VALIDITY_MARGIN;
+        let not_after = now + CERT_VALIDITY;
+        let is_valid_at = |now| check_validity_period(not_before, not_after, Some(now)).is_ok();
+        assert!(is_valid_at(now));
+
+        // The clocks of the MigTDs may differ by up to the tolerated skew
+        let second = Duration::from_secs(1);
+        assert!(is_valid_at(not_before - CERT_CLOCK_SKEW));
+        assert!(!is_valid_at(not_before - CERT_CLOCK_SKEW - second));
+        assert!(is_valid_at(not_after + CERT_CLOCK_SKEW));
+        assert!(!is_valid_at(not_after + CERT_CLOCK_SKEW + second));
+        assert!(!is_valid_at(Duration::ZERO));
+
+        // The validity can't be checked without the current time
+        assert!(check_validity_period(not_before, not_after, None).is_err());
+
+        // Certificates valid for longer than the RA-TLS ones are rejected
+        let too_long = not_after + second;
+        assert!(check_validity_period(not_before, too_long, Some(now)).is_err());
+        assert!(check_validity_period(not_after, not_before, Some(now)).is_err());
+    }
+}
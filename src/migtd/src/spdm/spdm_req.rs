@@ -22,6 +22,7 @@ use spdmlib::{
     requester::RequesterContext,
 };
 use spin::Mutex;
+use zeroize::Zeroizing;
 extern crate alloc;
 use alloc::sync::Arc;
 use log::error;
@@ -101,6 +102,7 @@ pub async fn spdm_requester_transfer_msk(
     Box::pin(send_and_receive_sdm_migration_attest_info(
         spdm_requester,
         session_id,
+        mig_info.mig_request_id,
         #[cfg(feature = "policy_v2")]
         remote_policy,
     ))
@@ -285,6 +287,7 @@ async fn send_and_receive_pub_key(spdm_requester: &mut RequesterContext) -> Spdm
 pub async fn send_and_receive_sdm_migration_attest_info(
     spdm_requester: &mut RequesterContext,
     session_id: u32,
+    mig_request_id: u64,
     #[cfg(feature = "policy_v2")] remote_policy: Vec<u8>,
 ) -> SpdmResult {
     if spdm_requester.common.provision_info.my_pub_key.is_none()
@@ -293,6 +296,9 @@ pub async fn send_and_receive_sdm_migration_attest_info(
         error!("Cannot transfer attestation info without provisioning my_pub_key.\n");
         return Err(SPDM_STATUS_UNSUPPORTED_CAP);
     }
+    // Only used to account the verification of the peer to its request
+    #[cfg(feature = "test_disable_ra_and_accept_all")]
+    let _ = mig_request_id;
 
     let mut vendor_id = [0u8; MAX_SPDM_VENDOR_DEFINED_VENDOR_ID_LEN];
     vendor_id[..VDM_MESSAGE_VENDOR_ID_LEN].copy_from_slice(&VDM_MESSAGE_VENDOR_ID);
@@ -517,6 +523,7 @@ pub async fn send_and_receive_sdm_migration_attest_info(
     #[cfg(not(feature = "test_disable_ra_and_accept_all"))]
     {
         let policy_check_result = mig_policy::authenticate_policy(
+            mig_request_id,
             true,
             verified_report_local.as_slice(),
             verified_report_peer.as_slice(),
@@ -561,6 +568,7 @@ pub async fn send_and_receive_sdm_migration_attest_info(
         #[cfg(not(feature = "test_disable_ra_and_accept_all"))]
         {
             let policy_check_result = mig_policy::authenticate_remote(
+                mig_request_id,
                 true,
                 quote_dst_vec.as_slice(),
                 &remote_policy,
@@ -661,8 +669,9 @@ async fn send_and_receive_sdm_exchange_migration_info(
         None,
     );
 
-    let mut send_buffer = [0u8; config::MAX_SPDM_MSG_SIZE];
-    let mut writer = Writer::init(&mut send_buffer);
+    // The request and response carry the migration session keys
+    let mut send_buffer = Zeroizing::new([0u8; config::MAX_SPDM_MSG_SIZE]);
+    let mut writer = Writer::init(&mut *send_buffer);
 
     let request_header = SpdmMessageHeader {
         version: spdm_requester.common.negotiate_info.spdm_version_sel,
@@ -680,12 +689,12 @@ async fn send_and_receive_sdm_exchange_migration_info(
         .map_err(|_| SPDM_STATUS_BUFFER_FULL)?;
     send_used += request_payload.spdm_encode(&mut spdm_requester.common, &mut writer)?;
 
-    let mut receive_buffer = [0u8; config::MAX_SPDM_MSG_SIZE];
+    let mut receive_buffer = Zeroizing::new([0u8; config::MAX_SPDM_MSG_SIZE]);
     let response = spdm_requester
         .send_spdm_vendor_defined_request_ex(
             session_id,
             &send_buffer[..send_used],
-            &mut receive_buffer,
+            &mut *receive_buffer,
         )
         .await?;
 
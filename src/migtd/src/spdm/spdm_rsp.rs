@@ -343,6 +343,16 @@ pub fn handle_exchange_mig_attest_info_req(
         error!("Cannot transfer attestation info before key exchange.\n");
         return Err(SPDM_STATUS_INVALID_STATE_LOCAL);
     }
+    // The verification of the peer is accounted to its migration request
+    #[cfg(not(feature = "test_disable_ra_and_accept_all"))]
+    let mig_request_id = {
+        let mut app_context_reader =
+            Reader::init(responder_context.common.app_context_data_buffer.as_ref());
+        SpdmAppContextData::read(&mut app_context_reader)
+            .ok_or(SPDM_STATUS_INVALID_MSG_SIZE)?
+            .migration_info
+            .mig_request_id
+    };
     let session_id = session_id.unwrap();
 
     let session = responder_context
@@ -489,6 +499,7 @@ pub fn handle_exchange_mig_attest_info_req(
     #[cfg(not(feature = "test_disable_ra_and_accept_all"))]
     {
         let policy_check_result = mig_policy::authenticate_policy(
+            mig_request_id,
             false,
             verified_report_local.as_slice(),
             verified_report_peer.as_slice(),
@@ -537,6 +548,7 @@ pub fn handle_exchange_mig_attest_info_req(
         #[cfg(not(feature = "test_disable_ra_and_accept_all"))]
         {
             let policy_check_result = mig_policy::authenticate_remote(
+                mig_request_id,
                 false,
                 quote_src_vec.as_slice(),
                 remote_policy,
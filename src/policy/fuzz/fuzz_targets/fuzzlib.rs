@@ -3,8 +3,12 @@
 // SPDX-License-Identifier: BSD-2-Clause-Patent
 
 use serde_json;
-use policy::MigPolicy;
+use policy::{validate_event_log, MigPolicy};
 
 pub fn fuzz_policy(data: &[u8]) {
     let _ = serde_json::from_slice::<MigPolicy>(data);
 }
+
+pub fn fuzz_event_log(data: &[u8]) {
+    let _ = validate_event_log(data);
+}
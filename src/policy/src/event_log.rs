@@ -0,0 +1,659 @@
+// Copyright (c) 2025 Intel Corporation
+//
+// SPDX-License-Identifier: BSD-2-Clause-Patent
+
+use alloc::{collections::BTreeSet, string::String, vec::Vec};
+use cc_measurement::{
+    log::CcEventLogReader, CcEventHeader, EV_EFI_PLATFORM_FIRMWARE_BLOB2, EV_PLATFORM_CONFIG_FLAGS,
+    TPML_ALG_SHA384,
+};
+use core::{convert::TryInto, ops::Range};
+use crypto::{hash::digest_sha384, SHA384_DIGEST_SIZE};
+use td_shim::event_log::{
+    PLATFORM_CONFIG_SECURE_AUTHORITY, PLATFORM_CONFIG_SVN, PLATFORM_FIRMWARE_BLOB2_PAYLOAD,
+};
+
+use crate::{EventName, PolicyError};
+
+pub const EV_EVENT_TAG: u32 = 0x00000006;
+
+// Event IDs that will be used to tag the event log
+pub const TAGGED_EVENT_ID_POLICY: u32 = 0x1;
+pub const TAGGED_EVENT_ID_ROOT_CA: u32 = 0x2;
+pub const TAGGED_EVENT_ID_POLICY_ISSUER_CHAIN: u32 = 0x3;
+pub const TAGGED_EVENT_ID_TEST: u32 = 0x32;
+
+// MR index the event will be measured into
+pub const MR_INDEX_POLICY_ISSUER_CHAIN: u32 = 0x2;
+pub const MR_INDEX_POLICY: u32 = 0x3;
+pub const MR_INDEX_ROOT_CA: u32 = 0x3;
+pub const MR_INDEX_TEST_FEATURE: u32 = 0x3;
+
+// Tag IDs of the events measured after boot, apart from the boot time tags
+pub const TAGGED_EVENT_ID_RUNTIME: Range<u32> = 0x1000..0x2000;
+pub const TAGGED_EVENT_ID_POLICY_UPGRADE: u32 = 0x1000;
+pub const TAGGED_EVENT_ID_COLLATERAL_REFRESH: u32 = 0x1001;
+pub const TAGGED_EVENT_ID_MIGRATION_RECEIPT: u32 = 0x1002;
+
+// Runtime events are all measured into RTMR3
+pub const MR_INDEX_RUNTIME: u32 = 0x4;
+
+// MR index td-shim measures the MigTD core and its configuration into
+const MR_INDEX_MIGTD_CORE: u32 = 0x2;
+const MR_INDEX_PLATFORM_CONFIG: u32 = 0x1;
+
+// Tag ID and data length precede the data of a tagged event
+const TAGGED_EVENT_HEADER_SIZE: usize = 8;
+// Descriptor and info length precede the info of a platform config event
+const PLATFORM_CONFIG_HEADER_SIZE: usize = 20;
+
+struct TaggedEventSpec {
+    tag_id: u32,
+    mr_index: u32,
+    // Whether the digest is the hash of the event data
+    measured: bool,
+}
+
+// Tagged events extended by MigTD, in the order they are measured
+#[cfg(not(feature = "policy_v2"))]
+const TAGGED_EVENTS: &[TaggedEventSpec] = &[
+    TaggedEventSpec {
+        tag_id: TAGGED_EVENT_ID_POLICY,
+        mr_index: MR_INDEX_POLICY,
+        measured: true,
+    },
+    TaggedEventSpec {
+        tag_id: TAGGED_EVENT_ID_ROOT_CA,
+        mr_index: MR_INDEX_ROOT_CA,
+        measured: true,
+    },
+];
+
+// Tagged events extended by MigTD, in the order they are measured. The policy
+// event carries the policy version, its digest is checked against the policy
+// received from the peer.
+#[cfg(feature = "policy_v2")]
+const TAGGED_EVENTS: &[TaggedEventSpec] = &[
+    TaggedEventSpec {
+        tag_id: TAGGED_EVENT_ID_POLICY_ISSUER_CHAIN,
+        mr_index: MR_INDEX_POLICY_ISSUER_CHAIN,
+        measured: true,
+    },
+    TaggedEventSpec {
+        tag_id: TAGGED_EVENT_ID_POLICY,
+        mr_index: MR_INDEX_POLICY,
+        measured: false,
+    },
+];
+
+// A MigTD built with `test_disable_ra_and_accept_all` only logs this event
+const TEST_FEATURE_EVENT: TaggedEventSpec = TaggedEventSpec {
+    tag_id: TAGGED_EVENT_ID_TEST,
+    mr_index: MR_INDEX_TEST_FEATURE,
+    measured: true,
+};
+
+/// Strictly validates the structure of a MigTD event log before it is replayed:
+/// - every event is extended into one of the RTMRs
+/// - the td-shim events MigTD relies on and the tagged events are logged once
+///   and into the RTMR they belong to
+/// - the data of a tagged event hashes to its digest
+/// - the tagged events follow the firmware events, in the order MigTD measures
+///   them, and no tagged event is unknown
+/// - the runtime events trail the boot time events and are extended into RTMR3,
+///   any tag of `TAGGED_EVENT_ID_RUNTIME` is accepted
+pub fn validate_event_log(event_log: &[u8]) -> Result<(), PolicyError> {
+    let reader = CcEventLogReader::new(event_log).ok_or(PolicyError::InvalidEventLog)?;
+    let mut firmware_events = BTreeSet::new();
+    // Position in `TAGGED_EVENTS` of the last tagged event
+    let mut last_tagged: Option<usize> = None;
+    let mut test_feature = false;
+    let mut runtime = false;
+
+    for (event_header, event_data) in reader.cc_events {
+        if !(1..=4).contains(&event_header.mr_index) {
+            return Err(PolicyError::InvalidEventLog);
+        }
+
+        if event_header.event_type == EV_EVENT_TAG {
+            let (tag_id, data) = parse_tagged_event(event_data)?;
+            if TAGGED_EVENT_ID_RUNTIME.contains(&tag_id) {
+                if event_header.mr_index != MR_INDEX_RUNTIME {
+                    return Err(PolicyError::InvalidEventLog);
+                }
+                verify_event_digest(&event_header, data)?;
+                runtime = true;
+                continue;
+            }
+            if runtime {
+                return Err(PolicyError::InvalidEventLog);
+            }
+
+            let spec = if tag_id == TAGGED_EVENT_ID_TEST {
+                if test_feature || last_tagged.is_some() {
+                    return Err(PolicyError::InvalidEventLog);
+                }
+                test_feature = true;
+                &TEST_FEATURE_EVENT
+            } else {
+                let position = TAGGED_EVENTS
+                    .iter()
+                    .position(|spec| spec.tag_id == tag_id)
+                    .ok_or(PolicyError::InvalidEventLog)?;
+                // Rejects duplicated and out of order events at once
+                if test_feature || last_tagged.is_some_and(|last| position <= last) {
+                    return Err(PolicyError::InvalidEventLog);
+                }
+                last_tagged = Some(position);
+                &TAGGED_EVENTS[position]
+            };
+
+            if event_header.mr_index != spec.mr_index {
+                return Err(PolicyError::InvalidEventLog);
+            }
+            if spec.measured {
+                verify_event_digest(&event_header, data)?;
+            }
+        } else {
+            // Firmware events are all logged before MigTD starts
+            if test_feature || last_tagged.is_some() || runtime {
+                return Err(PolicyError::InvalidEventLog);
+            }
+            if let Some((name, mr_index)) =
+                parse_firmware_event(event_header.event_type, event_data)?
+            {
+                if event_header.mr_index != mr_index || !firmware_events.insert(name) {
+                    return Err(PolicyError::InvalidEventLog);
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn parse_tagged_event(event_data: &[u8]) -> Result<(u32, &[u8]), PolicyError> {
+    if event_data.len() < TAGGED_EVENT_HEADER_SIZE {
+        return Err(PolicyError::InvalidEventLog);
+    }
+    let tag_id = u32::from_le_bytes(event_data[..4].try_into().unwrap());
+    let data_size = u32::from_le_bytes(event_data[4..8].try_into().unwrap()) as usize;
+    if event_data.len() - TAGGED_EVENT_HEADER_SIZE != data_size {
+        return Err(PolicyError::InvalidEventLog);
+    }
+
+    Ok((tag_id, &event_data[TAGGED_EVENT_HEADER_SIZE..]))
+}
+
+// Returns the name and expected MR index of the td-shim events used by the policy
+fn parse_firmware_event(
+    event_type: u32,
+    event_data: &[u8],
+) -> Result<Option<(EventName, u32)>, PolicyError> {
+    match event_type {
+        EV_EFI_PLATFORM_FIRMWARE_BLOB2 => {
+            let desc_size = *event_data.first().ok_or(PolicyError::InvalidEventLog)? as usize;
+            let desc = event_data
+                .get(1..1 + desc_size)
+                .ok_or(PolicyError::InvalidEventLog)?;
+            if desc == PLATFORM_FIRMWARE_BLOB2_PAYLOAD {
+                return Ok(Some((EventName::MigTdCore, MR_INDEX_MIGTD_CORE)));
+            }
+        }
+        EV_PLATFORM_CONFIG_FLAGS => {
+            let name = if event_data.starts_with(PLATFORM_CONFIG_SECURE_AUTHORITY) {
+                EventName::SecureBootKey
+            } else if event_data.starts_with(PLATFORM_CONFIG_SVN) {
+                EventName::MigTdCoreSvn
+            } else {
+                return Ok(None);
+            };
+            if event_data.len() < PLATFORM_CONFIG_HEADER_SIZE {
+                return Err(PolicyError::InvalidEventLog);
+            }
+            let info_size = u32::from_le_bytes(event_data[16..20].try_into().unwrap()) as usize;
+            if event_data.len() - PLATFORM_CONFIG_HEADER_SIZE < info_size {
+                return Err(PolicyError::InvalidEventLog);
+            }
+            return Ok(Some((name, MR_INDEX_PLATFORM_CONFIG)));
+        }
+        _ => {}
+    }
+
+    Ok(None)
+}
+
+fn verify_event_digest(event_header: &CcEventHeader, data: &[u8]) -> Result<(), PolicyError> {
+    let digest = &event_header.digest;
+    if digest.count != 1 || digest.digests[0].hash_alg != TPML_ALG_SHA384 {
+        return Err(PolicyError::InvalidEventLog);
+    }
+    let hash = digest_sha384(data).map_err(|_| PolicyError::HashCalculation)?;
+    if hash.as_slice() != digest.digests[0].digest.sha384.as_slice() {
+        return Err(PolicyError::InvalidEventLog);
+    }
+
+    Ok(())
+}
+
+/// Data of a `TAGGED_EVENT_ID_POLICY_UPGRADE` event.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PolicyUpgradeEvent {
+    /// SHA384 digest of the policy that became active
+    pub policy_digest: [u8; SHA384_DIGEST_SIZE],
+    /// Version of the policy that became active
+    pub version: String,
+}
+
+impl PolicyUpgradeEvent {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = self.policy_digest.to_vec();
+        bytes.extend_from_slice(self.version.as_bytes());
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        let version = core::str::from_utf8(bytes.get(SHA384_DIGEST_SIZE..)?).ok()?;
+        Some(Self {
+            policy_digest: bytes[..SHA384_DIGEST_SIZE].try_into().ok()?,
+            version: String::from(version),
+        })
+    }
+}
+
+/// Data of a `TAGGED_EVENT_ID_COLLATERAL_REFRESH` event.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CollateralRefreshEvent {
+    /// TCB evaluation data number of the collaterals that became active
+    pub tcb_evaluation_number: u32,
+    /// SHA384 digest of the collaterals that became active
+    pub collaterals_digest: [u8; SHA384_DIGEST_SIZE],
+}
+
+impl CollateralRefreshEvent {
+    pub const SIZE: usize = 4 + SHA384_DIGEST_SIZE;
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = self.tcb_evaluation_number.to_le_bytes().to_vec();
+        bytes.extend_from_slice(&self.collaterals_digest);
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() != Self::SIZE {
+            return None;
+        }
+        Some(Self {
+            tcb_evaluation_number: u32::from_le_bytes(bytes[..4].try_into().ok()?),
+            collaterals_digest: bytes[4..].try_into().ok()?,
+        })
+    }
+}
+
+/// Data of a `TAGGED_EVENT_ID_MIGRATION_RECEIPT` event, measured once the
+/// migration session key of a target TD has been negotiated.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MigrationReceiptEvent {
+    pub mig_request_id: u64,
+    pub target_td_uuid: [u64; 4],
+    /// Non zero if this MigTD is the migration source
+    pub migration_source: u8,
+    /// `MigrationResult` reported for the target TD
+    pub result: u8,
+    /// TCB evaluation data number of the collaterals used to verify the peer,
+    /// zero if unknown
+    pub tcb_evaluation_number: u32,
+    /// SHA384 digest of the policy of the peer, zero if unknown
+    pub peer_policy_digest: [u8; SHA384_DIGEST_SIZE],
+    /// UUID of the policy the peer was verified with, zero if unknown, see
+    /// `policy_id_bytes`
+    pub peer_policy_id: [u8; POLICY_ID_SIZE],
+}
+
+/// Size of the UUID of a policy
+pub const POLICY_ID_SIZE: usize = 16;
+
+/// Bytes of the policy id `id`, a UUID such as
+/// "9D50F353-27B6-44FE-9EF4-2969F9533969", in the order of its hex digits.
+/// Returns `None` if the id is not a UUID.
+pub fn policy_id_bytes(id: &str) -> Option<[u8; POLICY_ID_SIZE]> {
+    let groups: Vec<&str> = id.split('-').collect();
+    let lengths = [8, 4, 4, 4, 12];
+    if groups.len() != lengths.len()
+        || groups.iter().zip(lengths.iter()).any(|(group, len)| {
+            group.len() != *len || !group.bytes().all(|b| b.is_ascii_hexdigit())
+        })
+    {
+        return None;
+    }
+
+    let digits = groups.concat();
+    let mut bytes = [0u8; POLICY_ID_SIZE];
+    for (i, byte) in bytes.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&digits[2 * i..2 * i + 2], 16).ok()?;
+    }
+    Some(bytes)
+}
+
+impl MigrationReceiptEvent {
+    pub const SIZE: usize = 48 + SHA384_DIGEST_SIZE + POLICY_ID_SIZE;
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(Self::SIZE);
+        bytes.extend_from_slice(&self.mig_request_id.to_le_bytes());
+        for uuid in self.target_td_uuid {
+            bytes.extend_from_slice(&uuid.to_le_bytes());
+        }
+        bytes.push(self.migration_source);
+        bytes.push(self.result);
+        bytes.extend_from_slice(&[0u8; 2]);
+        bytes.extend_from_slice(&self.tcb_evaluation_number.to_le_bytes());
+        bytes.extend_from_slice(&self.peer_policy_digest);
+        bytes.extend_from_slice(&self.peer_policy_id);
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() != Self::SIZE {
+            return None;
+        }
+        let u64_at = |offset: usize| -> Option<u64> {
+            Some(u64::from_le_bytes(
+                bytes[offset..offset + 8].try_into().ok()?,
+            ))
+        };
+        Some(Self {
+            mig_request_id: u64_at(0)?,
+            target_td_uuid: [u64_at(8)?, u64_at(16)?, u64_at(24)?, u64_at(32)?],
+            migration_source: bytes[40],
+            result: bytes[41],
+            tcb_evaluation_number: u32::from_le_bytes(bytes[44..48].try_into().ok()?),
+            peer_policy_digest: bytes[48..48 + SHA384_DIGEST_SIZE].try_into().ok()?,
+            peer_policy_id: bytes[48 + SHA384_DIGEST_SIZE..].try_into().ok()?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use alloc::vec::Vec;
+    use cc_measurement::UefiPlatformFirmwareBlob2;
+    use td_shim::event_log::TdShimPlatformConfigInfoHeader;
+
+    const EV_NO_ACTION: u32 = 0x3;
+
+    fn event_log_header() -> Vec<u8> {
+        // TCG_PCR_EVENT header of the specification ID event
+        let mut log = Vec::new();
+        log.extend_from_slice(&0u32.to_le_bytes());
+        log.extend_from_slice(&EV_NO_ACTION.to_le_bytes());
+        log.extend_from_slice(&[0u8; 20]);
+        log.extend_from_slice(&0u32.to_le_bytes());
+        log
+    }
+
+    fn push_event(
+        log: &mut Vec<u8>,
+        mr_index: u32,
+        event_type: u32,
+        hash_data: &[u8],
+        event_data: &[u8],
+    ) {
+        let digest = digest_sha384(hash_data).unwrap();
+        log.extend_from_slice(&mr_index.to_le_bytes());
+        log.extend_from_slice(&event_type.to_le_bytes());
+        log.extend_from_slice(&1u32.to_le_bytes());
+        log.extend_from_slice(&TPML_ALG_SHA384.to_le_bytes());
+        log.extend_from_slice(&digest);
+        log.extend_from_slice(&(event_data.len() as u32).to_le_bytes());
+        log.extend_from_slice(event_data);
+    }
+
+    fn tagged_event(tag_id: u32, data: &[u8]) -> Vec<u8> {
+        let mut event = Vec::new();
+        event.extend_from_slice(&tag_id.to_le_bytes());
+        event.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        event.extend_from_slice(data);
+        event
+    }
+
+    fn push_tagged_event(log: &mut Vec<u8>, mr_index: u32, tag_id: u32, data: &[u8]) {
+        push_event(
+            log,
+            mr_index,
+            EV_EVENT_TAG,
+            data,
+            &tagged_event(tag_id, data),
+        );
+    }
+
+    fn push_firmware_events(log: &mut Vec<u8>) {
+        let payload = [0xffu8; 256];
+        let blob2 = UefiPlatformFirmwareBlob2::new(
+            PLATFORM_FIRMWARE_BLOB2_PAYLOAD,
+            payload.as_ptr() as u64,
+            payload.len() as u64,
+        )
+        .unwrap();
+        push_event(
+            log,
+            MR_INDEX_MIGTD_CORE,
+            EV_EFI_PLATFORM_FIRMWARE_BLOB2,
+            &payload,
+            blob2.as_bytes(),
+        );
+
+        let svn = 0xfu64.to_le_bytes();
+        let config_header =
+            TdShimPlatformConfigInfoHeader::new(PLATFORM_CONFIG_SVN, svn.len() as u32).unwrap();
+        let mut svn_event = config_header.as_bytes().to_vec();
+        svn_event.extend_from_slice(&svn);
+        push_event(
+            log,
+            MR_INDEX_PLATFORM_CONFIG,
+            EV_PLATFORM_CONFIG_FLAGS,
+            &svn,
+            &svn_event,
+        );
+    }
+
+    fn valid_event_log() -> Vec<u8> {
+        let mut log = event_log_header();
+        push_firmware_events(&mut log);
+        for spec in TAGGED_EVENTS {
+            push_tagged_event(&mut log, spec.mr_index, spec.tag_id, b"migtd");
+        }
+        log
+    }
+
+    #[test]
+    fn test_validate_event_log() {
+        assert!(validate_event_log(&valid_event_log()).is_ok());
+
+        let mut log = event_log_header();
+        push_tagged_event(
+            &mut log,
+            MR_INDEX_TEST_FEATURE,
+            TAGGED_EVENT_ID_TEST,
+            b"test_disable_ra_and_accept_all",
+        );
+        assert!(validate_event_log(&log).is_ok());
+    }
+
+    #[test]
+    fn test_validate_event_log_duplicated_event() {
+        let mut log = valid_event_log();
+        let last = TAGGED_EVENTS.last().unwrap();
+        push_tagged_event(&mut log, last.mr_index, last.tag_id, b"migtd");
+        assert!(validate_event_log(&log).is_err());
+
+        let mut log = event_log_header();
+        push_firmware_events(&mut log);
+        push_firmware_events(&mut log);
+        assert!(validate_event_log(&log).is_err());
+    }
+
+    #[test]
+    fn test_validate_event_log_digest_mismatch() {
+        let spec = TAGGED_EVENTS.iter().find(|spec| spec.measured).unwrap();
+        let mut log = event_log_header();
+        push_event(
+            &mut log,
+            spec.mr_index,
+            EV_EVENT_TAG,
+            b"something else",
+            &tagged_event(spec.tag_id, b"migtd"),
+        );
+        assert!(validate_event_log(&log).is_err());
+
+        // Data length does not match the event size
+        let mut event = tagged_event(spec.tag_id, b"migtd");
+        event[4] += 1;
+        let mut log = event_log_header();
+        push_event(&mut log, spec.mr_index, EV_EVENT_TAG, b"migtd", &event);
+        assert!(validate_event_log(&log).is_err());
+    }
+
+    #[test]
+    fn test_validate_event_log_unexpected_rtmr() {
+        let spec = &TAGGED_EVENTS[0];
+        let mut log = event_log_header();
+        push_tagged_event(&mut log, spec.mr_index - 1, spec.tag_id, b"migtd");
+        assert!(validate_event_log(&log).is_err());
+
+        let mut log = event_log_header();
+        push_event(&mut log, 5, EV_PLATFORM_CONFIG_FLAGS, b"x", b"x");
+        assert!(validate_event_log(&log).is_err());
+    }
+
+    #[test]
+    fn test_validate_event_log_order() {
+        // Tagged events in reverse order
+        let mut log = event_log_header();
+        for spec in TAGGED_EVENTS.iter().rev() {
+            push_tagged_event(&mut log, spec.mr_index, spec.tag_id, b"migtd");
+        }
+        assert!(validate_event_log(&log).is_err());
+
+        // Firmware event after the tagged events
+        let mut log = valid_event_log();
+        push_firmware_events(&mut log);
+        assert!(validate_event_log(&log).is_err());
+
+        // Test feature mixed with the other tagged events
+        let mut log = valid_event_log();
+        push_tagged_event(
+            &mut log,
+            MR_INDEX_TEST_FEATURE,
+            TAGGED_EVENT_ID_TEST,
+            b"test",
+        );
+        assert!(validate_event_log(&log).is_err());
+    }
+
+    #[test]
+    fn test_validate_event_log_unknown_tag() {
+        let mut log = event_log_header();
+        push_tagged_event(&mut log, 3, 0xdead, b"migtd");
+        assert!(validate_event_log(&log).is_err());
+
+        // Truncated tagged event
+        let mut log = event_log_header();
+        push_event(&mut log, 3, EV_EVENT_TAG, b"", &[1, 0]);
+        assert!(validate_event_log(&log).is_err());
+    }
+
+    #[test]
+    fn test_validate_event_log_runtime_events() {
+        let receipt = MigrationReceiptEvent {
+            mig_request_id: 1,
+            target_td_uuid: [1, 2, 3, 4],
+            migration_source: 1,
+            result: 0,
+            tcb_evaluation_number: 17,
+            peer_policy_digest: [0xa5; SHA384_DIGEST_SIZE],
+            peer_policy_id: [0x3c; POLICY_ID_SIZE],
+        };
+        let mut log = valid_event_log();
+        push_tagged_event(
+            &mut log,
+            MR_INDEX_RUNTIME,
+            TAGGED_EVENT_ID_MIGRATION_RECEIPT,
+            &receipt.to_bytes(),
+        );
+        // Runtime tags unknown to this version are accepted
+        push_tagged_event(&mut log, MR_INDEX_RUNTIME, 0x1fff, b"migtd");
+        assert!(validate_event_log(&log).is_ok());
+
+        // Boot time events can not follow the runtime events
+        let mut bad = log.clone();
+        push_tagged_event(&mut bad, 3, TAGGED_EVENTS[0].tag_id, b"migtd");
+        assert!(validate_event_log(&bad).is_err());
+        let mut bad = log.clone();
+        push_firmware_events(&mut bad);
+        assert!(validate_event_log(&bad).is_err());
+
+        // Runtime events are only extended into RTMR3
+        let mut bad = valid_event_log();
+        push_tagged_event(&mut bad, 3, TAGGED_EVENT_ID_POLICY_UPGRADE, b"migtd");
+        assert!(validate_event_log(&bad).is_err());
+
+        let mut bad = valid_event_log();
+        push_event(
+            &mut bad,
+            MR_INDEX_RUNTIME,
+            EV_EVENT_TAG,
+            b"tampered",
+            &tagged_event(TAGGED_EVENT_ID_MIGRATION_RECEIPT, &receipt.to_bytes()),
+        );
+        assert!(validate_event_log(&bad).is_err());
+    }
+
+    #[test]
+    fn test_runtime_event_data() {
+        let receipt = MigrationReceiptEvent {
+            mig_request_id: 0x1122334455667788,
+            target_td_uuid: [1, 2, 3, 4],
+            migration_source: 0,
+            result: 4,
+            tcb_evaluation_number: 17,
+            peer_policy_digest: [0xa5; SHA384_DIGEST_SIZE],
+            peer_policy_id: [0x3c; POLICY_ID_SIZE],
+        };
+        let bytes = receipt.to_bytes();
+        assert_eq!(bytes.len(), MigrationReceiptEvent::SIZE);
+        assert_eq!(MigrationReceiptEvent::from_bytes(&bytes), Some(receipt));
+        assert!(MigrationReceiptEvent::from_bytes(&bytes[1..]).is_none());
+
+        assert_eq!(
+            policy_id_bytes("9D50F353-27B6-44FE-9EF4-2969F9533969"),
+            Some([
+                0x9d, 0x50, 0xf3, 0x53, 0x27, 0xb6, 0x44, 0xfe, 0x9e, 0xf4, 0x29, 0x69, 0xf9, 0x53,
+                0x39, 0x69
+            ])
+        );
+        assert!(policy_id_bytes("9D50F35327B644FE9EF42969F9533969").is_none());
+        assert!(policy_id_bytes("9D50F353-27B6-44FE-9EF4-2969F953396Z").is_none());
+        assert!(policy_id_bytes("+D50F353-27B6-44FE-9EF4-2969F9533969").is_none());
+
+        let refresh = CollateralRefreshEvent {
+            tcb_evaluation_number: 17,
+            collaterals_digest: [0x5a; SHA384_DIGEST_SIZE],
+        };
+        assert_eq!(
+            CollateralRefreshEvent::from_bytes(&refresh.to_bytes()),
+            Some(refresh)
+        );
+
+        let upgrade = PolicyUpgradeEvent {
+            policy_digest: [0x5a; SHA384_DIGEST_SIZE],
+            version: String::from("1.1"),
+        };
+        assert_eq!(
+            PolicyUpgradeEvent::from_bytes(&upgrade.to_bytes()),
+            Some(upgrade)
+        );
+        assert!(PolicyUpgradeEvent::from_bytes(&[0u8; 16]).is_none());
+    }
+}
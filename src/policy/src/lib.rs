@@ -5,6 +5,8 @@
 #![cfg_attr(not(any(test, feature = "AzCVMEmu")), no_std)]
 extern crate alloc;
 
+mod event_log;
+pub use event_log::*;
 #[cfg(not(feature = "policy_v2"))]
 mod v1;
 #[cfg(not(feature = "policy_v2"))]
@@ -21,7 +23,7 @@ use core::ops::Range;
 pub const REPORT_DATA_SIZE: usize = 774;
 
 #[repr(C)]
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum PolicyError {
     FailGetReport,
     InvalidParameter,
@@ -40,6 +42,7 @@ pub enum PolicyError {
     InvalidReference,
     InvalidServtdIdentity,
     InvalidServtdTcbMapping,
+    InvalidPolicyIssuers,
     PolicyHashMismatch,
     InvalidQuote,
     SvnMismatch,
@@ -10,15 +10,41 @@ use serde::{
 };
 use td_shim_interface::td_uefi_pi::pi::guid::Guid;
 
+use crate::PolicyError;
+
 #[derive(Debug, Deserialize)]
 pub struct MigPolicy {
     #[serde(rename = "id", with = "guid_serde")]
     pub _id: Guid,
     #[serde(rename = "policy")]
     pub blocks: Vec<Policy>,
+    /// Most verbose log level MigTD can be switched to at runtime
+    #[serde(rename = "maxLogLevel")]
+    pub max_log_level: Option<String>,
+    /// Key exchange group of the RA-TLS handshakes
+    #[serde(rename = "keyExchange")]
+    pub key_exchange: Option<String>,
 }
 
 impl MigPolicy {
+    pub fn from_bytes(policy: &[u8]) -> Result<Self, PolicyError> {
+        // Remove the trailing zeros inside the utf8 string,
+        // otherwise serde deserialize will fail
+        let policy = core::str::from_utf8(policy)
+            .map_err(|_| PolicyError::InvalidPolicy)?
+            .trim_matches(char::from(0));
+
+        serde_json::from_str::<MigPolicy>(policy).map_err(|_| PolicyError::InvalidPolicy)
+    }
+
+    pub fn get_max_log_level(&self) -> Option<&str> {
+        self.max_log_level.as_deref()
+    }
+
+    pub fn get_key_exchange(&self) -> Option<&str> {
+        self.key_exchange.as_deref()
+    }
+
     pub fn get_platform_info_policy(&self) -> Vec<&PlatformInfo> {
         self.blocks
             .iter()
@@ -404,6 +430,30 @@ mod test {
         assert!(result.is_err());
     }
 
+    #[test]
+    fn test_policy_max_log_level() {
+        let policy = MigPolicy::from_bytes(include_bytes!("../../test/policy.json")).unwrap();
+        assert!(policy.get_max_log_level().is_none());
+
+        let mut json = String::from(include_str!("../../test/policy.json").trim_end());
+        json.truncate(json.len() - 1);
+        json.push_str(",\"maxLogLevel\":\"warn\"}\0\0");
+        let policy = MigPolicy::from_bytes(json.as_bytes()).unwrap();
+        assert_eq!(policy.get_max_log_level(), Some("warn"));
+    }
+
+    #[test]
+    fn test_policy_key_exchange() {
+        let policy = MigPolicy::from_bytes(include_bytes!("../../test/policy.json")).unwrap();
+        assert!(policy.get_key_exchange().is_none());
+
+        let mut json = String::from(include_str!("../../test/policy.json").trim_end());
+        json.truncate(json.len() - 1);
+        json.push_str(",\"keyExchange\":\"x25519mlkem768\"}");
+        let policy = MigPolicy::from_bytes(json.as_bytes()).unwrap();
+        assert_eq!(policy.get_key_exchange(), Some("x25519mlkem768"));
+    }
+
     #[test]
     fn test_interger_equal() {
         let equal: usize = 1;
@@ -59,18 +59,7 @@ pub fn verify_policy(
         return Err(PolicyError::InvalidParameter);
     }
 
-    // Remove the trailing zeros inside the utf8 string,
-    // otherwise serde deserialize will fail
-    let input = core::str::from_utf8(policy);
-    let policy = match input {
-        Ok(s) => s.trim_matches(char::from(0)),
-        Err(_) => return Err(PolicyError::InvalidPolicy),
-    };
-
-    let policy = match serde_json::from_str::<MigPolicy>(policy) {
-        Ok(policy) => policy,
-        Err(_) => return Err(PolicyError::InvalidPolicy),
-    };
+    let policy = MigPolicy::from_bytes(policy)?;
 
     let report_local = Report::new(report)?;
     let report_peer = Report::new(report_peer)?;
@@ -0,0 +1,359 @@
+// Copyright (c) 2025 Intel Corporation
+//
+// SPDX-License-Identifier: BSD-2-Clause-Patent
+
+//! Envelopes of the signed JSON objects: the policy data, the ServTD identity
+//! and the ServTD TCB mapping.
+//!
+//! Two envelopes are accepted:
+//! - the legacy `{ "<name>": <object>, "signature": "<hex>" }`, signed over the
+//!   exact bytes of the object
+//! - a JWS (RFC 7515) in general or flattened JSON serialization, or in
+//!   compact serialization as a JSON string. The `alg` header parameter must
+//!   be protected. If the header carries an `x5c` chain, its first certificate
+//!   must be the leaf of the issuer chain. The `kid` header parameter is only
+//!   informational.
+//!
+//! Only the general JSON serialization carries several signatures, e.g. for
+//! a policy signed by m of n issuers.
+
+use alloc::{borrow::Cow, collections::BTreeMap, string::String, vec, vec::Vec};
+use base64::engine::general_purpose::{STANDARD, URL_SAFE_NO_PAD};
+use base64::Engine;
+use crypto::signature::SignatureAlgorithm;
+use serde::Deserialize;
+use serde_json::value::RawValue;
+
+use crate::{v2::hex_string_to_bytes, PolicyError};
+
+const SIGNATURE_KEY: &str = "signature";
+
+#[derive(Debug)]
+pub(crate) struct SignedObject<'a> {
+    payload: Cow<'a, [u8]>,
+    signatures: Vec<Signature>,
+}
+
+#[derive(Debug)]
+struct Signature {
+    signature: Vec<u8>,
+    format: Format,
+}
+
+#[derive(Debug)]
+enum Format {
+    Legacy,
+    Jws {
+        signing_input: Vec<u8>,
+        algorithm: SignatureAlgorithm,
+        x5c_leaf: Option<Vec<u8>>,
+    },
+}
+
+#[derive(Deserialize)]
+struct GeneralJws {
+    payload: String,
+    signatures: Vec<JwsSignature>,
+}
+
+#[derive(Deserialize)]
+struct JwsSignature {
+    protected: String,
+    signature: String,
+}
+
+#[derive(Deserialize)]
+struct FlattenedJws {
+    protected: String,
+    payload: String,
+    signature: String,
+}
+
+#[derive(Deserialize)]
+struct JwsHeader {
+    alg: String,
+    x5c: Option<Vec<String>>,
+    crit: Option<Vec<String>>,
+}
+
+impl<'a> SignedObject<'a> {
+    /// Parses the envelope of the object `name`, the name is only used by the
+    /// legacy envelope.
+    pub(crate) fn parse(json: &'a str, name: &str) -> Option<Self> {
+        if let Ok(compact) = serde_json::from_str::<String>(json) {
+            let mut parts = compact.split('.');
+            match (parts.next(), parts.next(), parts.next(), parts.next()) {
+                (Some(protected), Some(payload), Some(signature), None) => {
+                    Self::from_jws(payload, &[(protected, signature)])
+                }
+                _ => None,
+            }
+        } else if let Ok(jws) = serde_json::from_str::<GeneralJws>(json) {
+            let signatures: Vec<(&str, &str)> = jws
+                .signatures
+                .iter()
+                .map(|s| (s.protected.as_str(), s.signature.as_str()))
+                .collect();
+            Self::from_jws(&jws.payload, &signatures)
+        } else if let Ok(jws) = serde_json::from_str::<FlattenedJws>(json) {
+            Self::from_jws(&jws.payload, &[(&jws.protected, &jws.signature)])
+        } else {
+            Self::from_legacy(json, name)
+        }
+    }
+
+    fn from_legacy(json: &'a str, name: &str) -> Option<Self> {
+        let fields: BTreeMap<String, &'a RawValue> = serde_json::from_str(json).ok()?;
+        let payload = fields.get(name)?.get().as_bytes();
+        let signature = serde_json::from_str::<String>(fields.get(SIGNATURE_KEY)?.get()).ok()?;
+
+        Some(Self {
+            payload: Cow::Borrowed(payload),
+            signatures: vec![Signature {
+                signature: hex_string_to_bytes(&signature).ok()?,
+                format: Format::Legacy,
+            }],
+        })
+    }
+
+    /// `signatures` are the (protected header, signature) pairs of the JWS
+    fn from_jws(payload: &str, signatures: &[(&str, &str)]) -> Option<Self> {
+        if signatures.is_empty() {
+            return None;
+        }
+
+        Some(Self {
+            payload: Cow::Owned(URL_SAFE_NO_PAD.decode(payload).ok()?),
+            signatures: signatures
+                .iter()
+                .map(|(protected, signature)| Signature::from_jws(protected, payload, signature))
+                .collect::<Option<Vec<_>>>()?,
+        })
+    }
+
+    /// Returns the signed object, not trusted before `verify()` succeeds
+    pub(crate) fn payload(&self) -> &[u8] {
+        &self.payload
+    }
+
+    /// Verifies that one of the signatures is made by the issuer
+    pub(crate) fn verify(
+        &self,
+        issuer_chain: &[u8],
+        allowed: &[SignatureAlgorithm],
+    ) -> Result<(), PolicyError> {
+        self.verify_threshold(&[issuer_chain], 1, allowed)
+    }
+
+    /// Verifies that at least `threshold` of the issuers made one of the
+    /// signatures each, a signature is only counted for one issuer.
+    pub(crate) fn verify_threshold(
+        &self,
+        issuer_chains: &[&[u8]],
+        threshold: usize,
+        allowed: &[SignatureAlgorithm],
+    ) -> Result<(), PolicyError> {
+        let mut used = vec![false; self.signatures.len()];
+        let mut signers = 0;
+
+        for issuer_chain in issuer_chains {
+            let signature = (0..self.signatures.len()).find(|&i| {
+                !used[i]
+                    && self.signatures[i]
+                        .verify(issuer_chain, &self.payload, allowed)
+                        .is_ok()
+            });
+            if let Some(i) = signature {
+                used[i] = true;
+                signers += 1;
+            }
+        }
+
+        if threshold == 0 || signers < threshold {
+            return Err(PolicyError::SignatureVerificationFailed);
+        }
+
+        Ok(())
+    }
+}
+
+impl Signature {
+    fn from_jws(protected: &str, payload: &str, signature: &str) -> Option<Self> {
+        let header = URL_SAFE_NO_PAD.decode(protected).ok()?;
+        let header = serde_json::from_slice::<JwsHeader>(&header).ok()?;
+        // None of the JWS extensions is understood
+        if header.crit.is_some() {
+            return None;
+        }
+        let algorithm = SignatureAlgorithm::from_name(&header.alg)?;
+        let x5c_leaf = match header.x5c {
+            Some(x5c) => Some(STANDARD.decode(x5c.first()?).ok()?),
+            None => None,
+        };
+
+        let mut signing_input = Vec::with_capacity(protected.len() + payload.len() + 1);
+        signing_input.extend_from_slice(protected.as_bytes());
+        signing_input.push(b'.');
+        signing_input.extend_from_slice(payload.as_bytes());
+
+        Some(Self {
+            signature: URL_SAFE_NO_PAD.decode(signature).ok()?,
+            format: Format::Jws {
+                signing_input,
+                algorithm,
+                x5c_leaf,
+            },
+        })
+    }
+
+    fn verify(
+        &self,
+        issuer_chain: &[u8],
+        payload: &[u8],
+        allowed: &[SignatureAlgorithm],
+    ) -> Result<(), PolicyError> {
+        match &self.format {
+            Format::Legacy => crypto::verify_cert_chain_and_signature_with_algorithms(
+                issuer_chain,
+                payload,
+                &self.signature,
+                allowed,
+            ),
+            Format::Jws {
+                signing_input,
+                algorithm,
+                x5c_leaf,
+            } => {
+                if let Some(x5c_leaf) = x5c_leaf {
+                    let leaf = crypto::pem_cert_to_der(issuer_chain)
+                        .map_err(|_| PolicyError::SignatureVerificationFailed)?;
+                    if leaf.as_ref() != x5c_leaf.as_slice() {
+                        return Err(PolicyError::SignatureVerificationFailed);
+                    }
+                }
+                crypto::verify_cert_chain_and_jws_signature(
+                    issuer_chain,
+                    signing_input,
+                    &self.signature,
+                    *algorithm,
+                    allowed,
+                )
+            }
+        }
+        .map_err(|_| PolicyError::SignatureVerificationFailed)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const HEADER: &str = r#"{"alg":"ES384","kid":"policy-issuer"}"#;
+    const PAYLOAD: &str = r#"{"id":"test"}"#;
+
+    fn encode(data: &str) -> String {
+        URL_SAFE_NO_PAD.encode(data)
+    }
+
+    #[test]
+    fn test_parse_legacy() {
+        let json = r#"{"policyData":{"id":"test"},"signature":"0A0b"}"#;
+        let signed = SignedObject::parse(json, "policyData").unwrap();
+        assert_eq!(signed.payload(), PAYLOAD.as_bytes());
+        assert_eq!(signed.signatures.len(), 1);
+        assert_eq!(signed.signatures[0].signature, [0x0a, 0x0b]);
+        assert!(matches!(signed.signatures[0].format, Format::Legacy));
+
+        assert!(SignedObject::parse(json, "tdIdentity").is_none());
+    }
+
+    #[test]
+    fn test_parse_jws() {
+        let protected = encode(HEADER);
+        let payload = encode(PAYLOAD);
+        let signature = URL_SAFE_NO_PAD.encode([1u8, 2, 3]);
+        let signing_input = alloc::format!("{}.{}", protected, payload);
+
+        let flattened = alloc::format!(
+            r#"{{"protected":"{}","payload":"{}","signature":"{}"}}"#,
+            protected,
+            payload,
+            signature
+        );
+        let compact = alloc::format!(r#""{}.{}.{}""#, protected, payload, signature);
+        for json in [flattened, compact] {
+            let signed = SignedObject::parse(&json, "policyData").unwrap();
+            assert_eq!(signed.payload(), PAYLOAD.as_bytes());
+            assert_eq!(signed.signatures.len(), 1);
+            assert_eq!(signed.signatures[0].signature, [1, 2, 3]);
+            match &signed.signatures[0].format {
+                Format::Jws {
+                    signing_input: input,
+                    algorithm,
+                    x5c_leaf,
+                } => {
+                    assert_eq!(input, signing_input.as_bytes());
+                    assert_eq!(*algorithm, SignatureAlgorithm::EcdsaP384Sha384);
+                    assert!(x5c_leaf.is_none());
+                }
+                Format::Legacy => panic!("unexpected legacy envelope"),
+            }
+        }
+    }
+
+    #[test]
+    fn test_parse_general_jws() {
+        let payload = encode(PAYLOAD);
+        let second = encode(r#"{"alg":"PS384"}"#);
+        let json = alloc::format!(
+            r#"{{"payload":"{}","signatures":[{{"protected":"{}","header":{{"kid":"a"}},"signature":"AQID"}},{{"protected":"{}","signature":"BAU"}}]}}"#,
+            payload,
+            encode(HEADER),
+            second
+        );
+        let signed = SignedObject::parse(&json, "policyData").unwrap();
+        assert_eq!(signed.payload(), PAYLOAD.as_bytes());
+        assert_eq!(signed.signatures.len(), 2);
+        assert_eq!(signed.signatures[1].signature, [4, 5]);
+        match &signed.signatures[1].format {
+            Format::Jws {
+                signing_input,
+                algorithm,
+                ..
+            } => {
+                let expected = alloc::format!("{}.{}", second, payload);
+                assert_eq!(signing_input, expected.as_bytes());
+                assert_eq!(*algorithm, SignatureAlgorithm::RsaPssSha384);
+            }
+            Format::Legacy => panic!("unexpected legacy envelope"),
+        }
+
+        // One invalid signature invalidates the whole envelope
+        let json = alloc::format!(
+            r#"{{"payload":"{}","signatures":[{{"protected":"{}","signature":"AQID"}},{{"protected":"{}","signature":"BAU"}}]}}"#,
+            payload,
+            encode(HEADER),
+            encode(r#"{"alg":"none"}"#)
+        );
+        assert!(SignedObject::parse(&json, "policyData").is_none());
+
+        let json = alloc::format!(r#"{{"payload":"{}","signatures":[]}}"#, payload);
+        assert!(SignedObject::parse(&json, "policyData").is_none());
+    }
+
+    #[test]
+    fn test_parse_invalid_jws() {
+        let payload = encode(PAYLOAD);
+        for header in [
+            r#"{"alg":"none"}"#,
+            r#"{"kid":"policy-issuer"}"#,
+            r#"{"alg":"ES384","crit":["b64"],"b64":false}"#,
+        ] {
+            let json = alloc::format!(r#""{}.{}.AQID""#, encode(header), payload);
+            assert!(SignedObject::parse(&json, "policyData").is_none());
+        }
+
+        let json = alloc::format!(r#""{}.{}""#, encode(HEADER), payload);
+        assert!(SignedObject::parse(&json, "policyData").is_none());
+    }
+}
@@ -5,6 +5,7 @@
 use crate::{CcEvent, EventName, PolicyError, Report, REPORT_DATA_SIZE};
 use alloc::{collections::btree_map::BTreeMap, format, string::String, vec::Vec};
 
+mod envelope;
 mod servtd_collateral;
 pub use servtd_collateral::*;
 mod collaterals;
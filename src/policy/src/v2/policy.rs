@@ -2,16 +2,21 @@
 //
 // SPDX-License-Identifier: BSD-2-Clause-Patent
 
-use alloc::{collections::BTreeMap, string::String, vec::Vec};
+use alloc::{
+    collections::{BTreeMap, BTreeSet},
+    string::String,
+    vec,
+    vec::Vec,
+};
 use core::{
     cmp::Ordering,
     convert::{TryFrom, TryInto},
 };
+use crypto::signature::SignatureAlgorithm;
 use serde::{Deserialize, Serialize};
-use serde_json::{self, value::RawValue};
 
 use crate::{
-    v2::{bytes_to_hex_string, hex_string_to_bytes, policy, verify_event_hash},
+    v2::{bytes_to_hex_string, envelope::SignedObject, policy, verify_event_hash},
     CcEvent, Collaterals, EventName, PolicyError, ServtdCollateral, TdIdentity, TdTcbMapping,
 };
 
@@ -134,7 +139,7 @@ impl PartialEq for ServtdTcbStatus {
 impl Eq for ServtdTcbStatus {}
 
 /// Contains all required data to be evaluated against a policy
-#[derive(Debug, Clone, Default)]
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
 pub struct PolicyEvaluationInfo {
     /// The date of the Trusted Computing Base (TCB) in ISO-8601 format, e.g. "2023-06-19T00:00:00Z"
     pub tcb_date: Option<String>,
@@ -164,15 +169,25 @@ pub struct PolicyEvaluationInfo {
     pub root_ca_crl_num: Option<u32>,
 }
 
-pub struct VerifiedPolicy<'a> {
-    pub policy_data: policy::PolicyData<'a>,
+impl PolicyEvaluationInfo {
+    pub fn to_json(&self) -> Result<Vec<u8>, PolicyError> {
+        serde_json::to_vec(self).map_err(|_| PolicyError::InvalidParameter)
+    }
+
+    pub fn from_json(slice: &[u8]) -> Result<Self, PolicyError> {
+        serde_json::from_slice(slice).map_err(|_| PolicyError::InvalidParameter)
+    }
+}
+
+pub struct VerifiedPolicy {
+    pub policy_data: policy::PolicyData,
     pub servtd_identity: TdIdentity,
     pub servtd_identity_issuer_chain: String,
     pub servtd_tcb_mapping: TdTcbMapping,
     pub servtd_tcb_mapping_issuer_chain: String,
 }
 
-impl VerifiedPolicy<'_> {
+impl VerifiedPolicy {
     pub fn get_collaterals(&self) -> &Collaterals {
         &self.policy_data.collaterals
     }
@@ -193,43 +208,97 @@ pub fn check_policy_integrity(
     Ok(())
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+/// Issuers of the policy, given by the measured policy issuer chain. It is
+/// either the PEM certificate chain of the only issuer, or the JSON
+/// `{"threshold": m, "issuerChains": ["<PEM>", ...]}` requiring the policy to
+/// be signed by at least m of the n issuers. The leaf certificates of the
+/// issuers must have distinct keys, so that no issuer is counted twice.
+#[derive(Debug, Deserialize)]
 #[serde(rename_all = "camelCase")]
+pub struct PolicyIssuers {
+    threshold: usize,
+    issuer_chains: Vec<String>,
+}
+
+impl PolicyIssuers {
+    pub fn from_slice(issuers: &[u8]) -> Result<Self, PolicyError> {
+        let is_json = issuers.iter().find(|b| !b.is_ascii_whitespace()) == Some(&b'{');
+        let issuers = if is_json {
+            serde_json::from_slice::<PolicyIssuers>(issuers)
+                .map_err(|_| PolicyError::InvalidPolicyIssuers)?
+        } else {
+            let issuer_chain =
+                core::str::from_utf8(issuers).map_err(|_| PolicyError::InvalidPolicyIssuers)?;
+            PolicyIssuers {
+                threshold: 1,
+                issuer_chains: vec![String::from(issuer_chain)],
+            }
+        };
+
+        if issuers.threshold == 0 || issuers.threshold > issuers.issuer_chains.len() {
+            return Err(PolicyError::InvalidPolicyIssuers);
+        }
+
+        let mut keys = BTreeSet::new();
+        for issuer_chain in &issuers.issuer_chains {
+            let key = crypto::leaf_public_key(issuer_chain.as_bytes())
+                .map_err(|_| PolicyError::InvalidPolicyIssuers)?;
+            if !keys.insert(key) {
+                return Err(PolicyError::InvalidPolicyIssuers);
+            }
+        }
+
+        Ok(issuers)
+    }
+}
+
+/// Signed policyData, in the legacy or the JWS envelope
+#[derive(Debug)]
 pub struct RawPolicyData<'a> {
-    #[serde(borrow)]
-    pub policy_data: &'a RawValue,
-    pub signature: String,
+    signed: SignedObject<'a>,
 }
 
 impl<'a> RawPolicyData<'a> {
     pub fn deserialize_from_json(slice: &'a [u8]) -> Result<Self, PolicyError> {
-        serde_json::from_slice::<RawPolicyData>(slice).map_err(|_| PolicyError::InvalidPolicy)
+        let json = core::str::from_utf8(slice).map_err(|_| PolicyError::InvalidPolicy)?;
+        let signed = SignedObject::parse(json, "policyData").ok_or(PolicyError::InvalidPolicy)?;
+        Ok(Self { signed })
     }
 
     pub fn get_collaterals(&self) -> Result<Collaterals, PolicyError> {
-        let policy_data: PolicyData<'a> =
-            serde_json::from_str(self.policy_data.get()).map_err(|_| PolicyError::InvalidPolicy)?;
+        let policy_data = PolicyData::deserialize_from_json(self.signed.payload())?;
         Ok(policy_data.collaterals)
     }
 
+    /// Id of the policy, to be trusted only once the policy is verified
+    pub fn get_policy_id(&self) -> Result<String, PolicyError> {
+        let policy_data = PolicyData::deserialize_from_json(self.signed.payload())?;
+        Ok(policy_data.id)
+    }
+
+    /// Verifies the policy signed by the `policy_issuers`, see `PolicyIssuers`,
+    /// and its ServTD collaterals
     pub fn verify<'c>(
         &self,
-        policy_issuer_chain: &'c [u8],
+        policy_issuers: &'c [u8],
         servtd_identity_issuer_chain: Option<&'c [u8]>,
         servtd_tcb_mapping_issuer_chain: Option<&'c [u8]>,
-    ) -> Result<VerifiedPolicy<'a>, PolicyError> {
+    ) -> Result<VerifiedPolicy, PolicyError> {
         // Step 1: Deserialize raw policy and verify signature
-        let policy_data = self.verify_policy_data_signature(policy_issuer_chain)?;
+        let policy_data = self.verify_policy_data_signature(policy_issuers)?;
 
         // Step 2: Verify and deserialize servtd collateral
+        let allowed = policy_data.get_signature_algorithms()?;
         let servtd_collateral = &policy_data.servtd_collateral;
         let servtd_identity = servtd_collateral.servtd_identity.verify_signature(
             servtd_identity_issuer_chain
                 .unwrap_or(servtd_collateral.servtd_identity_issuer_chain.as_bytes()),
+            &allowed,
         )?;
         let servtd_tcb_mapping = servtd_collateral.servtd_tcb_mapping.verify_signature(
             servtd_tcb_mapping_issuer_chain
                 .unwrap_or(servtd_collateral.servtd_tcb_mapping_issuer_chain.as_bytes()),
+            &allowed,
         )?;
 
         let servtd_identity_issuer_chain = servtd_collateral.servtd_identity_issuer_chain.clone();
@@ -250,40 +319,49 @@ impl<'a> RawPolicyData<'a> {
         })
     }
 
-    fn verify_policy_data_signature(
-        &self,
-        issuer_chain: &[u8],
-    ) -> Result<PolicyData<'a>, PolicyError> {
-        let signature = hex_string_to_bytes(&self.signature)?;
-
-        crypto::verify_cert_chain_and_signature(
-            issuer_chain,
-            self.policy_data.get().as_bytes(),
-            &signature,
-        )
-        .map_err(|_| PolicyError::SignatureVerificationFailed)?;
-
-        serde_json::from_str::<PolicyData>(self.policy_data.get())
-            .map_err(|_| PolicyError::InvalidPolicy)
+    fn verify_policy_data_signature(&self, issuers: &[u8]) -> Result<PolicyData, PolicyError> {
+        let issuers = PolicyIssuers::from_slice(issuers)?;
+        let issuer_chains: Vec<&[u8]> = issuers
+            .issuer_chains
+            .iter()
+            .map(|chain| chain.as_bytes())
+            .collect();
+
+        // The policy restricts the algorithms of its own signature, it is not
+        // trusted before the signature is verified.
+        let policy_data = PolicyData::deserialize_from_json(self.signed.payload())?;
+        let allowed = policy_data.get_signature_algorithms()?;
+        self.signed
+            .verify_threshold(&issuer_chains, issuers.threshold, &allowed)?;
+
+        Ok(policy_data)
     }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
-pub struct PolicyData<'a> {
+pub struct PolicyData {
     id: String,
     version: String,
     policy_svn: u32,
     policy: Option<Vec<PolicyTypes>>,
     forward_policy: Option<Vec<PolicyTypes>>,
     backward_policy: Option<Vec<PolicyTypes>>,
+    /// Most verbose log level MigTD can be switched to at runtime
+    max_log_level: Option<String>,
+    /// Whether the non-secret parts of the handshakes are captured for the VMM
+    transcript_capture: Option<bool>,
+    /// Key exchange group of the RA-TLS handshakes
+    key_exchange: Option<String>,
+    /// JWS names of the algorithms allowed for the signatures of the policy,
+    /// the ServTD collaterals and their issuer chains
+    signature_algorithms: Option<Vec<String>>,
     pub collaterals: Collaterals,
-    #[serde(borrow)]
-    pub servtd_collateral: ServtdCollateral<'a>,
+    pub servtd_collateral: ServtdCollateral,
 }
 
-impl<'a> PolicyData<'a> {
-    pub fn deserialize_from_json(slice: &'a [u8]) -> Result<Self, PolicyError> {
+impl PolicyData {
+    pub fn deserialize_from_json(slice: &[u8]) -> Result<Self, PolicyError> {
         serde_json::from_slice::<PolicyData>(slice).map_err(|_| PolicyError::InvalidPolicy)
     }
 
@@ -291,6 +369,30 @@ impl<'a> PolicyData<'a> {
         !self.id.is_empty() && self.version == "2.0"
     }
 
+    pub fn get_max_log_level(&self) -> Option<&str> {
+        self.max_log_level.as_deref()
+    }
+
+    pub fn is_transcript_capture_enabled(&self) -> bool {
+        self.transcript_capture.unwrap_or(false)
+    }
+
+    pub fn get_key_exchange(&self) -> Option<&str> {
+        self.key_exchange.as_deref()
+    }
+
+    /// Returns the allowed signature algorithms, all the supported ones if
+    /// the policy does not restrict them
+    pub fn get_signature_algorithms(&self) -> Result<Vec<SignatureAlgorithm>, PolicyError> {
+        match &self.signature_algorithms {
+            Some(names) => names
+                .iter()
+                .map(|name| SignatureAlgorithm::from_name(name).ok_or(PolicyError::InvalidPolicy))
+                .collect(),
+            None => Ok(SignatureAlgorithm::ALL.to_vec()),
+        }
+    }
+
     pub fn evaluate_policy_forward(
         &self,
         value: &PolicyEvaluationInfo,
@@ -841,6 +943,125 @@ mod test {
         policy.verify(issuer_chain, None, None).unwrap();
     }
 
+    #[test]
+    fn test_verify_policy_jws() {
+        let approver1 =
+            include_bytes!("../../test/policy_v2/cert_chain/policy_approver1_chain.pem");
+        let approver2 =
+            include_bytes!("../../test/policy_v2/cert_chain/policy_approver2_chain.pem");
+
+        // Flattened JWS signed with ES384 by the first approver, with its x5c
+        let policy_data = include_bytes!("../../test/policy_v2/policy_v2_jws.json");
+        let policy = RawPolicyData::deserialize_from_json(policy_data).unwrap();
+        let verified = policy.verify(approver1, None, None).unwrap();
+        assert_eq!(verified.get_version(), "2.0");
+        assert!(matches!(
+            policy.verify(approver2, None, None),
+            Err(PolicyError::SignatureVerificationFailed)
+        ));
+
+        // Compact JWS signed with ES256 by the second approver, without x5c
+        let policy_data = include_bytes!("../../test/policy_v2/policy_v2_jws_compact.json");
+        let policy = RawPolicyData::deserialize_from_json(policy_data).unwrap();
+        let verified = policy.verify(approver2, None, None).unwrap();
+        assert_eq!(
+            policy.get_policy_id().unwrap(),
+            "E6643BDC-E50A-4315-9EF7-F217CE8AF9A6"
+        );
+        assert_eq!(verified.get_version(), "2.0");
+        assert!(matches!(
+            policy.verify(approver1, None, None),
+            Err(PolicyError::SignatureVerificationFailed)
+        ));
+    }
+
+    fn policy_issuers(threshold: usize, issuer_chains: &[&str]) -> Vec<u8> {
+        serde_json::to_vec(&serde_json::json!({
+            "threshold": threshold,
+            "issuerChains": issuer_chains,
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_verify_policy_threshold() {
+        let policy_data = include_bytes!("../../test/policy_v2/policy_v2.json");
+        let policy = RawPolicyData::deserialize_from_json(policy_data).unwrap();
+        let issuer_chain = include_str!("../../test/policy_v2/cert_chain/policy_issuer_chain.pem");
+        let approver = include_str!("../../test/policy_v2/cert_chain/policy_approver1_chain.pem");
+
+        let single = PolicyIssuers::from_slice(issuer_chain.as_bytes()).unwrap();
+        assert_eq!(single.threshold, 1);
+        assert_eq!(single.issuer_chains, [issuer_chain]);
+        assert!(PolicyIssuers::from_slice(&policy_issuers(0, &[issuer_chain])).is_err());
+        assert!(PolicyIssuers::from_slice(&policy_issuers(3, &[issuer_chain, approver])).is_err());
+        assert!(PolicyIssuers::from_slice(b"not a certificate").is_err());
+        // An issuer listed twice would be counted twice
+        assert!(matches!(
+            PolicyIssuers::from_slice(&policy_issuers(1, &[issuer_chain, issuer_chain])),
+            Err(PolicyError::InvalidPolicyIssuers)
+        ));
+
+        assert!(policy
+            .verify(&policy_issuers(1, &[approver, issuer_chain]), None, None)
+            .is_ok());
+        // The only signature is counted for one issuer
+        assert!(matches!(
+            policy.verify(&policy_issuers(2, &[issuer_chain, approver]), None, None),
+            Err(PolicyError::SignatureVerificationFailed)
+        ));
+    }
+
+    #[test]
+    fn test_verify_policy_cosigned() {
+        // JWS signed by the first approver, the second one, then the first one again
+        let policy_data = include_str!("../../test/policy_v2/policy_v2_cosigned.json");
+        let first = include_str!("../../test/policy_v2/cert_chain/policy_approver1_chain.pem");
+        let second = include_str!("../../test/policy_v2/cert_chain/policy_approver2_chain.pem");
+        let issuers = policy_issuers(2, &[first, second]);
+
+        let policy = RawPolicyData::deserialize_from_json(policy_data.as_bytes()).unwrap();
+        assert!(policy.verify(&issuers, None, None).is_ok());
+        assert!(policy
+            .verify(&policy_issuers(1, &[second]), None, None)
+            .is_ok());
+
+        // Two signatures of the first approver do not meet a 2 of 2 threshold
+        let mut jws: serde_json::Value = serde_json::from_str(policy_data).unwrap();
+        jws["signatures"].as_array_mut().unwrap().remove(1);
+        let signed_twice = serde_json::to_vec(&jws).unwrap();
+        let policy = RawPolicyData::deserialize_from_json(&signed_twice).unwrap();
+        assert!(policy
+            .verify(&policy_issuers(1, &[first]), None, None)
+            .is_ok());
+        assert!(matches!(
+            policy.verify(&issuers, None, None),
+            Err(PolicyError::SignatureVerificationFailed)
+        ));
+    }
+
+    #[test]
+    fn test_policy_signature_algorithms() {
+        let policy = include_str!("../../test/policy_v2/policy_data.json");
+        let mut policy_data = serde_json::from_str::<PolicyData>(policy).unwrap();
+        assert_eq!(
+            policy_data.get_signature_algorithms().unwrap(),
+            SignatureAlgorithm::ALL
+        );
+
+        policy_data.signature_algorithms = Some(vec!["ES384".to_string(), "PS256".to_string()]);
+        assert_eq!(
+            policy_data.get_signature_algorithms().unwrap(),
+            [
+                SignatureAlgorithm::EcdsaP384Sha384,
+                SignatureAlgorithm::RsaPssSha256
+            ]
+        );
+
+        policy_data.signature_algorithms = Some(vec!["RS256".to_string()]);
+        assert!(policy_data.get_signature_algorithms().is_err());
+    }
+
     #[test]
     fn test_global_policy() {
         let global = include_str!("../../test/policy_v2/global.json");
@@ -883,6 +1104,21 @@ mod test {
         assert!(global_policy.evaluate(&value, &relative_ref).is_ok());
     }
 
+    #[test]
+    fn test_evaluation_info_json() {
+        let value = PolicyEvaluationInfo {
+            tcb_date: Some("2025-09-01T00:00:00Z".to_string()),
+            tcb_status: Some("UpToDate".to_string()),
+            tcb_evaluation_number: Some(15),
+            fmspc: Some([0x10, 0xC0, 0x6F, 0x00, 0x00, 0x00]),
+            migtd_isvsvn: Some(1),
+            ..Default::default()
+        };
+        let json = value.to_json().unwrap();
+        assert_eq!(PolicyEvaluationInfo::from_json(&json).unwrap(), value);
+        assert!(PolicyEvaluationInfo::from_json(b"{\"fmspc\": 1}").is_err());
+    }
+
     #[test]
     fn test_policy_tcb_date() {
         // Test with a value reference
@@ -2,12 +2,13 @@
 //
 // SPDX-License-Identifier: BSD-2-Clause-Patent
 
-use alloc::{string::String, vec::Vec};
+use alloc::{boxed::Box, string::String, vec::Vec};
+use crypto::signature::SignatureAlgorithm;
 use serde::{Deserialize, Serialize};
 use serde_json::{self, value::RawValue};
 
 use crate::{
-    v2::{bytes_to_hex_string, hex_string_to_bytes},
+    v2::{bytes_to_hex_string, envelope::SignedObject},
     MigTdInfoProperty, PolicyError, Report,
 };
 
@@ -20,43 +21,45 @@ pub struct VerifiedServtdCollateral {
 
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
-pub struct ServtdCollateral<'a> {
+pub struct ServtdCollateral {
     pub major_version: u32,
     pub minor_version: u32,
     pub servtd_identity_issuer_chain: String,
-    #[serde(borrow)]
-    pub servtd_identity: RawServtdIdentity<'a>,
+    pub servtd_identity: RawServtdIdentity,
     pub servtd_tcb_mapping_issuer_chain: String,
-    pub servtd_tcb_mapping: RawServtdTcbMapping<'a>,
+    pub servtd_tcb_mapping: RawServtdTcbMapping,
 }
 
+/// Signed tdIdentity, in the legacy or the JWS envelope
 #[derive(Debug, Serialize, Deserialize)]
-#[serde(rename_all = "camelCase")]
-pub struct RawServtdIdentity<'a> {
-    #[serde(borrow)]
-    pub td_identity: &'a RawValue,
-    pub signature: String,
-}
+#[serde(transparent)]
+pub struct RawServtdIdentity(Box<RawValue>);
 
-impl<'a> RawServtdIdentity<'a> {
-    pub fn deserialize_from_json(slice: &'a [u8]) -> Result<Self, PolicyError> {
-        serde_json::from_slice::<RawServtdIdentity>(slice)
-            .map_err(|_| PolicyError::InvalidServtdIdentity)
-    }
+impl RawServtdIdentity {
+    const NAME: &'static str = "tdIdentity";
 
-    pub fn verify_signature(&self, issuer_chain: &[u8]) -> Result<TdIdentity, PolicyError> {
-        let signature = hex_string_to_bytes(&self.signature)?;
+    pub fn deserialize_from_json(slice: &[u8]) -> Result<Self, PolicyError> {
+        let raw = serde_json::from_slice::<RawServtdIdentity>(slice)
+            .map_err(|_| PolicyError::InvalidServtdIdentity)?;
+        raw.signed_object()?;
+        Ok(raw)
+    }
 
-        crypto::verify_cert_chain_and_signature(
-            issuer_chain,
-            self.td_identity.get().as_bytes(),
-            &signature,
-        )
-        .map_err(|_| PolicyError::SignatureVerificationFailed)?;
+    pub fn verify_signature(
+        &self,
+        issuer_chain: &[u8],
+        allowed: &[SignatureAlgorithm],
+    ) -> Result<TdIdentity, PolicyError> {
+        let signed = self.signed_object()?;
+        signed.verify(issuer_chain, allowed)?;
 
-        serde_json::from_str::<TdIdentity>(self.td_identity.get())
+        serde_json::from_slice::<TdIdentity>(signed.payload())
             .map_err(|_| PolicyError::InvalidServtdIdentity)
     }
+
+    fn signed_object(&self) -> Result<SignedObject<'_>, PolicyError> {
+        SignedObject::parse(self.0.get(), Self::NAME).ok_or(PolicyError::InvalidServtdIdentity)
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -103,33 +106,36 @@ pub struct Tcb {
     pub isvsvn: u16,
 }
 
+/// Signed tdTcbMapping, in the legacy or the JWS envelope
 #[derive(Debug, Serialize, Deserialize)]
-#[serde(rename_all = "camelCase")]
-pub struct RawServtdTcbMapping<'a> {
-    #[serde(borrow)]
-    pub td_tcb_mapping: &'a RawValue,
-    pub signature: String,
-}
+#[serde(transparent)]
+pub struct RawServtdTcbMapping(Box<RawValue>);
 
-impl<'a> RawServtdTcbMapping<'a> {
-    pub fn deserialize_from_json(slice: &'a [u8]) -> Result<Self, PolicyError> {
-        serde_json::from_slice::<RawServtdTcbMapping>(slice)
-            .map_err(|_| PolicyError::InvalidServtdTcbMapping)
-    }
+impl RawServtdTcbMapping {
+    const NAME: &'static str = "tdTcbMapping";
 
-    pub fn verify_signature(&self, issuer_chain: &[u8]) -> Result<TdTcbMapping, PolicyError> {
-        let signature = hex_string_to_bytes(&self.signature)?;
+    pub fn deserialize_from_json(slice: &[u8]) -> Result<Self, PolicyError> {
+        let raw = serde_json::from_slice::<RawServtdTcbMapping>(slice)
+            .map_err(|_| PolicyError::InvalidServtdTcbMapping)?;
+        raw.signed_object()?;
+        Ok(raw)
+    }
 
-        crypto::verify_cert_chain_and_signature(
-            issuer_chain,
-            self.td_tcb_mapping.get().as_bytes(),
-            &signature,
-        )
-        .map_err(|_| PolicyError::SignatureVerificationFailed)?;
+    pub fn verify_signature(
+        &self,
+        issuer_chain: &[u8],
+        allowed: &[SignatureAlgorithm],
+    ) -> Result<TdTcbMapping, PolicyError> {
+        let signed = self.signed_object()?;
+        signed.verify(issuer_chain, allowed)?;
 
-        serde_json::from_str::<TdTcbMapping>(self.td_tcb_mapping.get())
+        serde_json::from_slice::<TdTcbMapping>(signed.payload())
             .map_err(|_| PolicyError::InvalidServtdTcbMapping)
     }
+
+    fn signed_object(&self) -> Result<SignedObject<'_>, PolicyError> {
+        SignedObject::parse(self.0.get(), Self::NAME).ok_or(PolicyError::InvalidServtdTcbMapping)
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -296,13 +302,63 @@ mod test {
         let servtd_collateral = include_bytes!("../../test/policy_v2/servtd_collateral.json");
         let collateral: ServtdCollateral =
             serde_json::from_slice(servtd_collateral).expect("Failed to parse collateral");
+        let issuer_chain = collateral.servtd_tcb_mapping_issuer_chain.as_bytes();
         assert!(collateral
             .servtd_tcb_mapping
-            .verify_signature(collateral.servtd_tcb_mapping_issuer_chain.as_bytes())
+            .verify_signature(issuer_chain, SignatureAlgorithm::ALL)
             .is_ok());
         assert!(collateral
             .servtd_identity
-            .verify_signature(collateral.servtd_tcb_mapping_issuer_chain.as_bytes())
+            .verify_signature(issuer_chain, SignatureAlgorithm::ALL)
             .is_ok());
+
+        // The collaterals are signed with ECDSA P-384
+        let allowed = [
+            SignatureAlgorithm::EcdsaP256Sha256,
+            SignatureAlgorithm::Ed25519,
+        ];
+        assert!(collateral
+            .servtd_tcb_mapping
+            .verify_signature(issuer_chain, &allowed)
+            .is_err());
+        assert!(collateral
+            .servtd_identity
+            .verify_signature(issuer_chain, &allowed)
+            .is_err());
+    }
+
+    #[test]
+    fn verify_servtd_identity_jws() {
+        let issuer_chain =
+            include_bytes!("../../test/policy_v2/cert_chain/policy_approver1_chain.pem");
+        let other_chain =
+            include_bytes!("../../test/policy_v2/cert_chain/policy_approver2_chain.pem");
+
+        // Flattened JWS signed with ES384, its x5c is the issuer chain
+        let jws = include_bytes!("../../test/policy_v2/servtd_identity_jws.json");
+        let identity = RawServtdIdentity::deserialize_from_json(jws).unwrap();
+        let td_identity = identity
+            .verify_signature(issuer_chain, SignatureAlgorithm::ALL)
+            .unwrap();
+        assert!(!td_identity.tcb_levels.is_empty());
+        assert!(matches!(
+            identity.verify_signature(other_chain, SignatureAlgorithm::ALL),
+            Err(PolicyError::SignatureVerificationFailed)
+        ));
+        assert!(identity
+            .verify_signature(issuer_chain, &[SignatureAlgorithm::EcdsaP256Sha256])
+            .is_err());
+
+        // Compact JWS signed by the same key, but its x5c is another chain
+        let jws = include_bytes!("../../test/policy_v2/servtd_identity_jws_x5c_mismatch.json");
+        let identity = RawServtdIdentity::deserialize_from_json(jws).unwrap();
+        assert!(matches!(
+            identity.verify_signature(issuer_chain, SignatureAlgorithm::ALL),
+            Err(PolicyError::SignatureVerificationFailed)
+        ));
+        assert!(matches!(
+            identity.verify_signature(other_chain, SignatureAlgorithm::ALL),
+            Err(PolicyError::SignatureVerificationFailed)
+        ));
     }
 }
@@ -6,8 +6,11 @@
 
 extern crate alloc;
 
-use alloc::{collections::BTreeMap, format, string::String, string::ToString, vec::Vec};
+use alloc::{collections::BTreeMap, format, string::String, string::ToString, vec, vec::Vec};
+use base64::engine::general_purpose::{STANDARD, URL_SAFE_NO_PAD};
+use base64::Engine;
 use core::result::Result;
+pub use crypto::signature::SignatureAlgorithm;
 use crypto::x509::SubjectPublicKeyInfo;
 use der::Decode;
 pub use serde_json::Error as JsonError;
@@ -25,6 +28,18 @@ pub enum Error {
     Verify,
     NotCanonical,
     NoPublicKey,
+    AlgorithmMismatch,
+    /// The algorithm cannot be used for a signature which does not carry it
+    UnsupportedAlgorithm,
+}
+
+/// Serialization of a JWS (RFC 7515)
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum JwsFormat {
+    /// Flattened JWS JSON serialization
+    Flattened,
+    /// Compact serialization, written as a JSON string
+    Compact,
 }
 
 impl From<JsonError> for Error {
@@ -33,7 +48,12 @@ impl From<JsonError> for Error {
     }
 }
 
-pub fn json_sign(json_key: &str, data: &[u8], private_key: &[u8]) -> Result<Vec<u8>, Error> {
+pub fn json_sign(
+    json_key: &str,
+    data: &[u8],
+    private_key: &[u8],
+    algorithm: SignatureAlgorithm,
+) -> Result<Vec<u8>, Error> {
     let value: Value = serde_json::from_slice(data)?;
     let canonical_data = serde_json::to_vec(&value)?;
 
@@ -41,37 +61,115 @@ pub fn json_sign(json_key: &str, data: &[u8], private_key: &[u8]) -> Result<Vec<
         return Err(Error::NotCanonical);
     }
 
-    let private_key_der =
-        crypto::ecdsa::pem_to_der_from_slice(private_key).map_err(|_| Error::InvalidKey)?;
-    let signature = crypto::ecdsa::ecdsa_sign(&private_key_der, data).map_err(|_| Error::Sign)?;
+    let signature = sign(data, private_key, algorithm)?;
 
     json_set_signature(json_key, data, &signature)
 }
 
-pub fn json_sign_detached(data: &[u8], private_key: &[u8]) -> Result<Vec<u8>, Error> {
-    let private_key_der =
-        crypto::ecdsa::pem_to_der_from_slice(private_key).map_err(|_| Error::InvalidKey)?;
-    let signature = crypto::ecdsa::ecdsa_sign(&private_key_der, data).map_err(|_| Error::Sign)?;
+/// Signs `data` as the payload of a JWS. The `kid` and the DER certificate
+/// chain `x5c`, leaf first, are added to the protected header if provided.
+pub fn json_sign_jws(
+    data: &[u8],
+    private_key: &[u8],
+    algorithm: SignatureAlgorithm,
+    kid: Option<&str>,
+    x5c: &[Vec<u8>],
+    format: JwsFormat,
+) -> Result<Vec<u8>, Error> {
+    // The payload is signed as is, it only has to be valid JSON
+    serde_json::from_slice::<&RawValue>(data)?;
+
+    let payload = URL_SAFE_NO_PAD.encode(data);
+    let signature = sign_jws(&payload, private_key, algorithm, kid, x5c)?;
+
+    let jws = match format {
+        JwsFormat::Flattened => {
+            let mut map = Map::new();
+            map.insert("protected".to_string(), Value::String(signature.protected));
+            map.insert("payload".to_string(), Value::String(payload));
+            map.insert(
+                SIGNATURE_KEY.to_string(),
+                Value::String(signature.signature),
+            );
+            Value::Object(map)
+        }
+        JwsFormat::Compact => Value::String(format!(
+            "{}.{payload}.{}",
+            signature.protected, signature.signature
+        )),
+    };
 
-    Ok(signature.to_vec())
+    Ok(serde_json::to_vec(&jws)?)
 }
 
-pub fn json_verify(data: &[u8], public_key: &[u8], signature: &[u8]) -> Result<(), Error> {
-    let public_key_der =
-        crypto::ecdsa::pem_to_der_from_slice(public_key).map_err(|_| Error::InvalidKey)?;
-    let public_key_raw = extract_public_key_bytes(&public_key_der)?;
+/// Adds a signature to a JWS, the co-signed JWS is written in general JSON
+/// serialization. The `kid` and `x5c` are those of the new signature.
+pub fn json_cosign_jws(
+    signed_json: &[u8],
+    private_key: &[u8],
+    algorithm: SignatureAlgorithm,
+    kid: Option<&str>,
+    x5c: &[Vec<u8>],
+) -> Result<Vec<u8>, Error> {
+    let mut jws = parse_jws(signed_json)?.ok_or(Error::InvalidSignedJson)?;
+    let signature = sign_jws(&jws.payload, private_key, algorithm, kid, x5c)?;
+    jws.signatures.push(signature);
+
+    let signatures = jws
+        .signatures
+        .into_iter()
+        .map(|signature| {
+            let mut map = Map::new();
+            map.insert("protected".to_string(), Value::String(signature.protected));
+            if let Some(header) = signature.header {
+                map.insert("header".to_string(), header);
+            }
+            map.insert(
+                SIGNATURE_KEY.to_string(),
+                Value::String(signature.signature),
+            );
+            Value::Object(map)
+        })
+        .collect();
 
-    crypto::ecdsa::ecdsa_verify(&public_key_raw, data, signature).map_err(|_| Error::Verify)
+    let mut map = Map::new();
+    map.insert("payload".to_string(), Value::String(jws.payload));
+    map.insert("signatures".to_string(), Value::Array(signatures));
+
+    Ok(serde_json::to_vec(&map)?)
 }
 
+pub fn json_sign_detached(
+    data: &[u8],
+    private_key: &[u8],
+    algorithm: SignatureAlgorithm,
+) -> Result<Vec<u8>, Error> {
+    sign(data, private_key, algorithm)
+}
+
+/// Verifies the detached `signature` of `data`. The signature algorithm is
+/// given by the type of the public key if `algorithm` is `None`.
+pub fn json_verify(
+    data: &[u8],
+    public_key: &[u8],
+    signature: &[u8],
+    algorithm: Option<SignatureAlgorithm>,
+) -> Result<(), Error> {
+    verify(data, public_key, signature, algorithm)
+}
+
+/// Verifies a signed JSON object, either in the legacy envelope named
+/// `json_key` or in a JWS
 pub fn json_verify_from_signed(
     json_key: &str,
     signed_json: &[u8],
     public_key: &[u8],
+    algorithm: Option<SignatureAlgorithm>,
 ) -> Result<(), Error> {
-    let public_key_der =
-        crypto::ecdsa::pem_to_der_from_slice(public_key).map_err(|_| Error::InvalidKey)?;
-    let public_key_raw = extract_public_key_bytes(&public_key_der)?;
+    if let Some(jws) = parse_jws(signed_json)? {
+        return verify_jws(&jws, public_key, algorithm);
+    }
+
     let parsed: BTreeMap<String, &RawValue> = serde_json::from_slice(signed_json)?;
 
     // Must have exactly 2 keys
@@ -94,7 +192,206 @@ pub fn json_verify_from_signed(
         .get()
         .as_bytes();
 
-    crypto::ecdsa::ecdsa_verify(&public_key_raw, data_bytes, &signature).map_err(|_| Error::Verify)
+    verify(data_bytes, public_key, &signature, algorithm)
+}
+
+struct Jws {
+    payload: String,
+    signatures: Vec<JwsSignature>,
+}
+
+struct JwsSignature {
+    protected: String,
+    header: Option<Value>,
+    signature: String,
+}
+
+fn parse_jws(signed_json: &[u8]) -> Result<Option<Jws>, Error> {
+    let value: Value = serde_json::from_slice(signed_json)?;
+    let jws = match value {
+        Value::String(compact) => {
+            let parts: Vec<&str> = compact.split('.').collect();
+            if parts.len() != 3 {
+                return Err(Error::InvalidSignedJson);
+            }
+            Jws {
+                payload: parts[1].to_string(),
+                signatures: vec![JwsSignature {
+                    protected: parts[0].to_string(),
+                    header: None,
+                    signature: parts[2].to_string(),
+                }],
+            }
+        }
+        Value::Object(map) if map.contains_key("payload") => {
+            let signatures = match map.get("signatures") {
+                Some(Value::Array(signatures)) => signatures
+                    .iter()
+                    .map(|signature| {
+                        signature
+                            .as_object()
+                            .ok_or(Error::InvalidSignedJson)
+                            .and_then(parse_jws_signature)
+                    })
+                    .collect::<Result<Vec<_>, _>>()?,
+                Some(_) => return Err(Error::InvalidSignedJson),
+                None => vec![parse_jws_signature(&map)?],
+            };
+            if signatures.is_empty() {
+                return Err(Error::InvalidSignedJson);
+            }
+            Jws {
+                payload: string_member(&map, "payload")?,
+                signatures,
+            }
+        }
+        _ => return Ok(None),
+    };
+
+    Ok(Some(jws))
+}
+
+fn parse_jws_signature(map: &Map<String, Value>) -> Result<JwsSignature, Error> {
+    Ok(JwsSignature {
+        protected: string_member(map, "protected")?,
+        header: map.get("header").cloned(),
+        signature: string_member(map, SIGNATURE_KEY)?,
+    })
+}
+
+fn string_member(map: &Map<String, Value>, name: &str) -> Result<String, Error> {
+    map.get(name)
+        .and_then(Value::as_str)
+        .map(|s| s.to_string())
+        .ok_or(Error::InvalidSignedJson)
+}
+
+fn sign_jws(
+    payload: &str,
+    private_key: &[u8],
+    algorithm: SignatureAlgorithm,
+    kid: Option<&str>,
+    x5c: &[Vec<u8>],
+) -> Result<JwsSignature, Error> {
+    let mut header = Map::new();
+    header.insert("alg".to_string(), Value::from(algorithm.name()));
+    if let Some(kid) = kid {
+        header.insert("kid".to_string(), Value::from(kid));
+    }
+    if !x5c.is_empty() {
+        let x5c = x5c.iter().map(|cert| Value::from(STANDARD.encode(cert)));
+        header.insert("x5c".to_string(), Value::Array(x5c.collect()));
+    }
+
+    let protected = URL_SAFE_NO_PAD.encode(serde_json::to_vec(&header)?);
+    let signing_input = format!("{protected}.{payload}");
+
+    let private_key_der =
+        crypto::ecdsa::pem_to_der_from_slice(private_key).map_err(|_| Error::InvalidKey)?;
+    let signature = algorithm
+        .sign_jws(&private_key_der, signing_input.as_bytes())
+        .map_err(|_| Error::Sign)?;
+
+    Ok(JwsSignature {
+        protected,
+        header: None,
+        signature: URL_SAFE_NO_PAD.encode(signature),
+    })
+}
+
+/// Succeeds if one of the signatures of the JWS is made by `public_key`
+fn verify_jws(
+    jws: &Jws,
+    public_key: &[u8],
+    algorithm: Option<SignatureAlgorithm>,
+) -> Result<(), Error> {
+    let mut result = Err(Error::Verify);
+    for signature in &jws.signatures {
+        result = verify_jws_signature(&jws.payload, signature, public_key, algorithm);
+        if result.is_ok() {
+            break;
+        }
+    }
+
+    result
+}
+
+fn verify_jws_signature(
+    payload: &str,
+    signature: &JwsSignature,
+    public_key: &[u8],
+    algorithm: Option<SignatureAlgorithm>,
+) -> Result<(), Error> {
+    let header = URL_SAFE_NO_PAD
+        .decode(&signature.protected)
+        .map_err(|_| Error::InvalidSignedJson)?;
+    let header: Map<String, Value> = serde_json::from_slice(&header)?;
+    // MigTD does not understand any JWS extension
+    if header.contains_key("crit") {
+        return Err(Error::InvalidSignedJson);
+    }
+    let header_algorithm = header
+        .get("alg")
+        .and_then(Value::as_str)
+        .and_then(SignatureAlgorithm::from_name)
+        .ok_or(Error::InvalidSignedJson)?;
+    if algorithm.is_some_and(|algorithm| algorithm != header_algorithm) {
+        return Err(Error::AlgorithmMismatch);
+    }
+
+    let signing_input = format!("{}.{payload}", signature.protected);
+    let signature = URL_SAFE_NO_PAD
+        .decode(&signature.signature)
+        .map_err(|_| Error::InvalidSignedJson)?;
+    let public_key_der =
+        crypto::ecdsa::pem_to_der_from_slice(public_key).map_err(|_| Error::InvalidKey)?;
+    let spki = SubjectPublicKeyInfo::from_der(&public_key_der).map_err(|_| Error::InvalidKey)?;
+    let public_key_raw = spki
+        .subject_public_key
+        .as_bytes()
+        .ok_or(Error::NoPublicKey)?;
+
+    header_algorithm
+        .verify_jws(public_key_raw, signing_input.as_bytes(), &signature)
+        .map_err(|_| Error::Verify)
+}
+
+// The algorithm of a signature which is not a JWS is not carried with it, the
+// verifier derives it from the public key, see
+// `SignatureAlgorithm::from_public_key_info`. The RSA private keys are
+// rsaEncryption keys, whose signatures are verified with PS256.
+fn sign(data: &[u8], private_key: &[u8], algorithm: SignatureAlgorithm) -> Result<Vec<u8>, Error> {
+    if algorithm == SignatureAlgorithm::RsaPssSha384 {
+        return Err(Error::UnsupportedAlgorithm);
+    }
+    let private_key_der =
+        crypto::ecdsa::pem_to_der_from_slice(private_key).map_err(|_| Error::InvalidKey)?;
+    algorithm
+        .sign(&private_key_der, data)
+        .map_err(|_| Error::Sign)
+}
+
+fn verify(
+    data: &[u8],
+    public_key: &[u8],
+    signature: &[u8],
+    algorithm: Option<SignatureAlgorithm>,
+) -> Result<(), Error> {
+    let public_key_der =
+        crypto::ecdsa::pem_to_der_from_slice(public_key).map_err(|_| Error::InvalidKey)?;
+    let spki = SubjectPublicKeyInfo::from_der(&public_key_der).map_err(|_| Error::InvalidKey)?;
+    let algorithm = match algorithm {
+        Some(algorithm) => algorithm,
+        None => SignatureAlgorithm::from_public_key_info(&spki).map_err(|_| Error::InvalidKey)?,
+    };
+    let public_key_raw = spki
+        .subject_public_key
+        .as_bytes()
+        .ok_or(Error::NoPublicKey)?;
+
+    algorithm
+        .verify(public_key_raw, data, signature)
+        .map_err(|_| Error::Verify)
 }
 
 fn hex_string_to_bytes(s: &str) -> Result<Vec<u8>, Error> {
@@ -127,11 +424,3 @@ pub fn json_set_signature(
 fn bytes_to_hex_string(bytes: &[u8]) -> String {
     bytes.iter().map(|b| format!("{b:02X}")).collect()
 }
-
-fn extract_public_key_bytes(spki_der: &[u8]) -> Result<Vec<u8>, Error> {
-    let spki = SubjectPublicKeyInfo::from_der(spki_der).map_err(|_| Error::InvalidKey)?;
-    spki.subject_public_key
-        .as_bytes()
-        .map(|bytes| bytes.to_vec())
-        .ok_or(Error::NoPublicKey)
-}
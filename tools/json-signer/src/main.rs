@@ -3,9 +3,10 @@
 // SPDX-License-Identifier: BSD-2-Clause-Patent
 
 use anyhow::{Context, Result};
-use clap::Parser;
+use clap::{Parser, ValueEnum};
 use json_signer::{
-    json_set_signature, json_sign, json_sign_detached, json_verify, json_verify_from_signed,
+    json_cosign_jws, json_set_signature, json_sign, json_sign_detached, json_sign_jws, json_verify,
+    json_verify_from_signed, JwsFormat, SignatureAlgorithm,
 };
 use std::{
     fs,
@@ -29,6 +30,10 @@ struct Cli {
     #[arg(long, requires = "private_key")]
     sign: bool,
 
+    /// Add a signature to a JWS signed JSON object (requires --private-key)
+    #[arg(long, requires = "private_key")]
+    cosign: bool,
+
     /// Verify the signature of a JSON object (requires --public-key)
     #[arg(long, requires = "public_key")]
     verify: bool,
@@ -49,6 +54,24 @@ struct Cli {
     #[arg(long, value_name = "FILE")]
     public_key: Option<PathBuf>,
 
+    /// Signature algorithm: ES256, ES384, PS256, PS384 or EdDSA. Defaults to
+    /// ES384 for --sign and to the algorithm of the public key for --verify.
+    /// PS384 signatures must be JWS
+    #[arg(long, value_parser = parse_algorithm)]
+    algorithm: Option<SignatureAlgorithm>,
+
+    /// Envelope of the signed JSON object for --sign
+    #[arg(long, value_enum, default_value_t = Format::Legacy)]
+    format: Format,
+
+    /// Key identifier added to the JWS header (--sign, --cosign)
+    #[arg(long)]
+    kid: Option<String>,
+
+    /// PEM certificate chain of the signing key, leaf first, added to the JWS header (--sign, --cosign)
+    #[arg(long, value_name = "FILE")]
+    x5c: Option<PathBuf>,
+
     /// Name of the JSON object to sign (e.g., "policyData")
     #[arg(long, short)]
     name: String,
@@ -62,6 +85,16 @@ struct Cli {
     output: Option<PathBuf>,
 }
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+enum Format {
+    /// `{ "<name>": <object>, "signature": "<hex>" }`
+    Legacy,
+    /// Flattened JWS JSON serialization
+    Jws,
+    /// JWS compact serialization, as a JSON string
+    JwsCompact,
+}
+
 fn main() {
     let cli = Cli::parse();
     let input = read_file(&cli.input).unwrap_or_else(|e| {
@@ -85,10 +118,10 @@ fn main() {
                 eprintln!("Failed to read signature file: {e}");
                 exit(1);
             });
-            json_verify(&input, &public_key, &signature)
+            json_verify(&input, &public_key, &signature, cli.algorithm)
         } else {
             // Verify from signed JSON (input contains both data and signature)
-            json_verify_from_signed(&cli.name, &input, &public_key)
+            json_verify_from_signed(&cli.name, &input, &public_key, cli.algorithm)
         };
 
         match result {
@@ -108,17 +141,35 @@ fn main() {
             exit(1);
         });
 
-        let output_bytes = if cli.detach {
-            json_sign_detached(&input, &private_key).unwrap_or_else(|e| {
-                eprintln!("Failed to sign input json: {e:?}");
-                exit(1);
-            })
-        } else {
-            json_sign(&cli.name, &input, &private_key).unwrap_or_else(|e| {
-                eprintln!("Failed to sign input json: {e:?}");
-                exit(1);
-            })
+        let algorithm = cli.algorithm.unwrap_or(SignatureAlgorithm::EcdsaP384Sha384);
+        let result = match cli.format {
+            Format::Legacy if cli.detach => json_sign_detached(&input, &private_key, algorithm),
+            Format::Legacy => json_sign(&cli.name, &input, &private_key, algorithm),
+            Format::Jws | Format::JwsCompact => {
+                if cli.detach {
+                    eprintln!("--detach is only supported with the legacy format");
+                    exit(1);
+                }
+                let x5c = read_x5c(cli.x5c.as_deref());
+                let format = if cli.format == Format::Jws {
+                    JwsFormat::Flattened
+                } else {
+                    JwsFormat::Compact
+                };
+                json_sign_jws(
+                    &input,
+                    &private_key,
+                    algorithm,
+                    cli.kid.as_deref(),
+                    &x5c,
+                    format,
+                )
+            }
         };
+        let output_bytes = result.unwrap_or_else(|e| {
+            eprintln!("Failed to sign input json: {e:?}");
+            exit(1);
+        });
 
         let output = cli.output.unwrap_or_else(|| {
             eprintln!("Output file is required for sign operation");
@@ -128,6 +179,30 @@ fn main() {
             eprintln!("Failed to write output file: {e}");
             exit(1);
         }
+    } else if cli.cosign {
+        // clap guarantees private_key present
+        let private_key = read_file(&cli.private_key.unwrap()).unwrap_or_else(|e| {
+            eprintln!("Failed to read private key file: {e}");
+            exit(1);
+        });
+
+        let algorithm = cli.algorithm.unwrap_or(SignatureAlgorithm::EcdsaP384Sha384);
+        let x5c = read_x5c(cli.x5c.as_deref());
+        let output_bytes =
+            json_cosign_jws(&input, &private_key, algorithm, cli.kid.as_deref(), &x5c)
+                .unwrap_or_else(|e| {
+                    eprintln!("Failed to co-sign input json: {e:?}");
+                    exit(1);
+                });
+
+        let output = cli.output.unwrap_or_else(|| {
+            eprintln!("Output file is required for cosign operation");
+            exit(1);
+        });
+        if let Err(e) = fs::write(&output, output_bytes) {
+            eprintln!("Failed to write output file: {e}");
+            exit(1);
+        }
     } else if cli.finalize {
         // clap guarantees signature present
         let signature = read_file(&cli.signature.unwrap()).unwrap_or_else(|e| {
@@ -149,11 +224,31 @@ fn main() {
             exit(1);
         }
     } else {
-        eprintln!("One of --verify, --sign, or --finalize must be specified");
+        eprintln!("One of --verify, --sign, --cosign or --finalize must be specified");
         exit(1);
     }
 }
 
+fn parse_algorithm(name: &str) -> core::result::Result<SignatureAlgorithm, String> {
+    SignatureAlgorithm::from_name(name).ok_or_else(|| format!("unsupported algorithm {name}"))
+}
+
+fn read_x5c(path: Option<&Path>) -> Vec<Vec<u8>> {
+    match path {
+        Some(path) => read_cert_chain(path).unwrap_or_else(|e| {
+            eprintln!("Failed to read x5c certificate chain: {e}");
+            exit(1);
+        }),
+        None => Vec::new(),
+    }
+}
+
+fn read_cert_chain(path: &Path) -> Result<Vec<Vec<u8>>> {
+    let chain = crypto::pem_cert_chain_to_der(&read_file(path)?)
+        .map_err(|e| anyhow::anyhow!("Invalid PEM certificate chain: {e:?}"))?;
+    Ok(chain.into_iter().map(|cert| cert.to_vec()).collect())
+}
+
 fn read_file(path: &Path) -> Result<Vec<u8>> {
     fs::read(path).with_context(|| format!("Failed to read {}", path.display()))
 }
@@ -0,0 +1,480 @@
+// Copyright (c) 2025 Intel Corporation
+//
+// SPDX-License-Identifier: BSD-2-Clause-Patent
+
+use anyhow::{anyhow, Error, Result};
+use cc_measurement::{
+    log::CcEventLogReader, CcEventHeader, EV_EFI_PLATFORM_FIRMWARE_BLOB2, EV_PLATFORM_CONFIG_FLAGS,
+    SHA384_DIGEST_SIZE, TPML_ALG_SHA384,
+};
+use crypto::hash::digest_sha384;
+use policy::{
+    CollateralRefreshEvent, MigrationReceiptEvent, PolicyUpgradeEvent, Report, EV_EVENT_TAG,
+    TAGGED_EVENT_ID_COLLATERAL_REFRESH, TAGGED_EVENT_ID_MIGRATION_RECEIPT, TAGGED_EVENT_ID_POLICY,
+    TAGGED_EVENT_ID_POLICY_ISSUER_CHAIN, TAGGED_EVENT_ID_POLICY_UPGRADE, TAGGED_EVENT_ID_ROOT_CA,
+    TAGGED_EVENT_ID_RUNTIME, TAGGED_EVENT_ID_TEST,
+};
+use serde_json::{json, Map, Value};
+
+pub const RTMR_COUNT: usize = 4;
+
+// Size of the TD quote header preceding the TD report body
+const QUOTE_HEADER_SIZE: usize = 48;
+// Quote v5 inserts the body type and body size between header and body
+const QUOTE_V5_BODY_DESCRIPTOR_SIZE: usize = 6;
+
+// Size of the TdShimPlatformConfigInfoHeader descriptor
+const PLATFORM_CONFIG_DESCRIPTOR_SIZE: usize = 16;
+
+const EVENT_TYPE_NAMES: &[(u32, &str)] = &[
+    (0x3, "EV_NO_ACTION"),
+    (0x4, "EV_SEPARATOR"),
+    (EV_EVENT_TAG, "EV_EVENT_TAG"),
+    (EV_PLATFORM_CONFIG_FLAGS, "EV_PLATFORM_CONFIG_FLAGS"),
+    (0x8000_0001, "EV_EFI_VARIABLE_DRIVER_CONFIG"),
+    (0x8000_0002, "EV_EFI_VARIABLE_BOOT"),
+    (0x8000_0003, "EV_EFI_BOOT_SERVICES_APPLICATION"),
+    (
+        EV_EFI_PLATFORM_FIRMWARE_BLOB2,
+        "EV_EFI_PLATFORM_FIRMWARE_BLOB2",
+    ),
+    (0x8000_000B, "EV_EFI_HANDOFF_TABLES2"),
+    (0x8000_00E0, "EV_EFI_VARIABLE_AUTHORITY"),
+];
+
+pub fn bytes_to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+pub fn hex_to_bytes(hex: &str) -> Result<Vec<u8>, Error> {
+    let hex = hex.trim();
+    if hex.len() % 2 != 0 {
+        return Err(anyhow!("Odd length hex string"));
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).map_err(|_| anyhow!("Invalid hex string")))
+        .collect()
+}
+
+/// Running value of the four RTMRs while the event log is replayed.
+pub struct Rtmrs {
+    regs: [[u8; SHA384_DIGEST_SIZE * 2]; RTMR_COUNT],
+}
+
+impl Rtmrs {
+    pub fn new() -> Self {
+        Self {
+            regs: [[0u8; SHA384_DIGEST_SIZE * 2]; RTMR_COUNT],
+        }
+    }
+
+    pub fn extend(&mut self, index: usize, digest: &[u8]) -> Result<(), Error> {
+        let reg = self
+            .regs
+            .get_mut(index)
+            .ok_or_else(|| anyhow!("Invalid RTMR index {}", index))?;
+        reg[SHA384_DIGEST_SIZE..].copy_from_slice(digest);
+        let digest = digest_sha384(reg).map_err(|_| anyhow!("Calculate digest"))?;
+        reg[..SHA384_DIGEST_SIZE].copy_from_slice(&digest);
+        Ok(())
+    }
+
+    pub fn get(&self, index: usize) -> &[u8] {
+        &self.regs[index][..SHA384_DIGEST_SIZE]
+    }
+
+    pub fn to_json(&self) -> Value {
+        let mut rtmrs = Map::new();
+        for index in 0..RTMR_COUNT {
+            rtmrs.insert(
+                format!("rtmr{}", index),
+                Value::String(bytes_to_hex(self.get(index))),
+            );
+        }
+        Value::Object(rtmrs)
+    }
+}
+
+impl Default for Rtmrs {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Decodes a CCEL event log to JSON and replays it into the RTMRs.
+///
+/// Each event is listed with its type, MR index, digest, decoded payload and
+/// the value of the RTMR it was extended into right after the extension.
+pub fn decode_event_log(event_log: &[u8]) -> Result<(Value, Rtmrs), Error> {
+    let reader = CcEventLogReader::new(event_log).ok_or(anyhow!("Invalid event log header"))?;
+    let mut rtmrs = Rtmrs::new();
+    let mut events = Vec::new();
+
+    for (index, (event_header, event_data)) in reader.cc_events.enumerate() {
+        let digest = event_digest(&event_header).map_err(|e| anyhow!("Event {}: {}", index, e))?;
+        let mr_index = event_header.mr_index;
+        let event_type = event_header.event_type;
+        let event_size = event_header.event_size;
+
+        let mut event = Map::new();
+        event.insert("index".to_string(), json!(index));
+        event.insert("mrIndex".to_string(), json!(mr_index));
+        event.insert("eventType".to_string(), json!(format!("{:#x}", event_type)));
+        event.insert(
+            "eventTypeName".to_string(),
+            json!(event_type_name(event_type)),
+        );
+        event.insert("digest".to_string(), json!(bytes_to_hex(digest)));
+        event.insert("eventSize".to_string(), json!(event_size));
+        if let Some(payload) = decode_event_data(event_type, event_data) {
+            event.insert("payload".to_string(), payload);
+        }
+
+        // MR index 0 is MRTD, which is not extended at runtime
+        match mr_index {
+            1..=4 => {
+                let rtmr_index = mr_index as usize - 1;
+                rtmrs.extend(rtmr_index, digest)?;
+                event.insert("rtmr".to_string(), json!(rtmr_index));
+                event.insert(
+                    "rtmrValue".to_string(),
+                    json!(bytes_to_hex(rtmrs.get(rtmr_index))),
+                );
+            }
+            0 => {}
+            _ => return Err(anyhow!("Event {}: invalid MR index {}", index, mr_index)),
+        }
+
+        events.push(Value::Object(event));
+    }
+
+    Ok((Value::Array(events), rtmrs))
+}
+
+fn event_digest(event_header: &CcEventHeader) -> Result<&[u8], Error> {
+    let count = event_header.digest.count;
+    if count != 1 {
+        return Err(anyhow!("unexpected digest count {}", count));
+    }
+    let digest = &event_header.digest.digests[0];
+    let hash_alg = digest.hash_alg;
+    if hash_alg != TPML_ALG_SHA384 {
+        return Err(anyhow!("unexpected hash algorithm {:#x}", hash_alg));
+    }
+    Ok(&digest.digest.sha384)
+}
+
+fn event_type_name(event_type: u32) -> &'static str {
+    EVENT_TYPE_NAMES
+        .iter()
+        .find(|(value, _)| *value == event_type)
+        .map(|(_, name)| *name)
+        .unwrap_or("UNKNOWN")
+}
+
+fn decode_event_data(event_type: u32, event_data: &[u8]) -> Option<Value> {
+    match event_type {
+        EV_EVENT_TAG => Some(decode_tagged_event(event_data)),
+        EV_EFI_PLATFORM_FIRMWARE_BLOB2 => {
+            let desc_size = *event_data.first()? as usize;
+            let desc = event_data.get(1..1 + desc_size)?;
+            Some(json!({ "description": printable(desc) }))
+        }
+        EV_PLATFORM_CONFIG_FLAGS => {
+            let desc = event_data.get(..PLATFORM_CONFIG_DESCRIPTOR_SIZE)?;
+            Some(json!({ "description": printable(desc) }))
+        }
+        _ => None,
+    }
+}
+
+fn decode_tagged_event(event_data: &[u8]) -> Value {
+    let (tag_id, data) = match parse_tagged_event(event_data) {
+        Some(tagged) => tagged,
+        None => return json!({ "error": "Malformed tagged event" }),
+    };
+
+    let (name, data) = match tag_id {
+        // Policy v1 logs the policy itself, policy v2 only logs its version
+        TAGGED_EVENT_ID_POLICY => (
+            "policy",
+            serde_json::from_slice::<Value>(data).unwrap_or_else(|_| text_or_hex(data)),
+        ),
+        TAGGED_EVENT_ID_ROOT_CA => (
+            "rootCa",
+            json!({
+                "der": bytes_to_hex(data),
+                "sha384": digest_sha384(data).map(|d| bytes_to_hex(&d)).unwrap_or_default(),
+            }),
+        ),
+        TAGGED_EVENT_ID_POLICY_ISSUER_CHAIN => ("policyIssuerChain", text_or_hex(data)),
+        TAGGED_EVENT_ID_TEST => ("testFeature", text_or_hex(data)),
+        TAGGED_EVENT_ID_POLICY_UPGRADE => (
+            "policyUpgrade",
+            PolicyUpgradeEvent::from_bytes(data)
+                .map(|event| {
+                    json!({
+                        "policyDigest": bytes_to_hex(&event.policy_digest),
+                        "version": event.version,
+                    })
+                })
+                .unwrap_or_else(|| json!(bytes_to_hex(data))),
+        ),
+        TAGGED_EVENT_ID_COLLATERAL_REFRESH => (
+            "collateralRefresh",
+            CollateralRefreshEvent::from_bytes(data)
+                .map(|event| {
+                    json!({
+                        "tcbEvaluationNumber": event.tcb_evaluation_number,
+                        "collateralsDigest": bytes_to_hex(&event.collaterals_digest),
+                    })
+                })
+                .unwrap_or_else(|| json!(bytes_to_hex(data))),
+        ),
+        TAGGED_EVENT_ID_MIGRATION_RECEIPT => (
+            "migrationReceipt",
+            MigrationReceiptEvent::from_bytes(data)
+                .map(|event| {
+                    json!({
+                        "migRequestId": event.mig_request_id,
+                        "targetTdUuid": event.target_td_uuid,
+                        "migrationSource": event.migration_source != 0,
+                        "result": event.result,
+                        "tcbEvaluationNumber": event.tcb_evaluation_number,
+                        "peerPolicyDigest": bytes_to_hex(&event.peer_policy_digest),
+                    })
+                })
+                .unwrap_or_else(|| json!(bytes_to_hex(data))),
+        ),
+        _ if TAGGED_EVENT_ID_RUNTIME.contains(&tag_id) => {
+            ("unknownRuntime", json!(bytes_to_hex(data)))
+        }
+        _ => ("unknown", json!(bytes_to_hex(data))),
+    };
+
+    json!({
+        "tagId": tag_id,
+        "tag": name,
+        "data": data,
+    })
+}
+
+fn parse_tagged_event(event_data: &[u8]) -> Option<(u32, &[u8])> {
+    let tag_id = u32::from_le_bytes(event_data.get(..4)?.try_into().ok()?);
+    let data_len = u32::from_le_bytes(event_data.get(4..8)?.try_into().ok()?) as usize;
+    let data = event_data.get(8..8usize.checked_add(data_len)?)?;
+    Some((tag_id, data))
+}
+
+fn text_or_hex(data: &[u8]) -> Value {
+    match core::str::from_utf8(data) {
+        Ok(text) => json!(text),
+        Err(_) => json!(bytes_to_hex(data)),
+    }
+}
+
+fn printable(data: &[u8]) -> String {
+    data.iter()
+        .take_while(|c| **c != 0)
+        .map(|c| *c as char)
+        .collect()
+}
+
+/// Extracts RTMR0..3 from a TD quote (version 4 or 5).
+pub fn rtmrs_from_quote(quote: &[u8]) -> Result<[Vec<u8>; RTMR_COUNT], Error> {
+    let version = u16::from_le_bytes(
+        quote
+            .get(..2)
+            .ok_or(anyhow!("Quote too short"))?
+            .try_into()?,
+    );
+    let body_offset = match version {
+        4 => QUOTE_HEADER_SIZE,
+        5 => QUOTE_HEADER_SIZE + QUOTE_V5_BODY_DESCRIPTOR_SIZE,
+        _ => return Err(anyhow!("Unsupported quote version {}", version)),
+    };
+    let body = quote
+        .get(body_offset..)
+        .filter(|body| body.len() >= Report::R_MIGTD_RTMR3.end)
+        .ok_or(anyhow!("Quote too short"))?;
+
+    Ok([
+        body[Report::R_MIGTD_RTMR0].to_vec(),
+        body[Report::R_MIGTD_RTMR1].to_vec(),
+        body[Report::R_MIGTD_RTMR2].to_vec(),
+        body[Report::R_MIGTD_RTMR3].to_vec(),
+    ])
+}
+
+/// Extracts RTMR0..3 from the TD info JSON written by `migtd-hash --output-td-info`.
+pub fn rtmrs_from_td_info(td_info: &[u8]) -> Result<[Vec<u8>; RTMR_COUNT], Error> {
+    let td_info: Value = serde_json::from_slice(td_info)?;
+    let rtmr = |index: usize| -> Result<Vec<u8>, Error> {
+        let key = format!("rtmr{}", index);
+        let value = td_info
+            .get(&key)
+            .and_then(Value::as_str)
+            .ok_or_else(|| anyhow!("'{}' missing in TD info", key))?;
+        let value = hex_to_bytes(value)?;
+        if value.len() != SHA384_DIGEST_SIZE {
+            return Err(anyhow!("Invalid '{}' size in TD info", key));
+        }
+        Ok(value)
+    };
+
+    Ok([rtmr(0)?, rtmr(1)?, rtmr(2)?, rtmr(3)?])
+}
+
+/// Compares the replayed RTMRs against the expected values, skipping the
+/// RTMRs listed in `ignored`.
+///
+/// Returns the comparison in JSON and whether all the compared RTMRs match.
+pub fn compare_rtmrs(
+    rtmrs: &Rtmrs,
+    expected: &[Vec<u8>; RTMR_COUNT],
+    ignored: &[usize],
+) -> (Value, bool) {
+    let mut comparison = Map::new();
+    let mut all_match = true;
+
+    for (index, expected) in expected.iter().enumerate() {
+        let mut result = json!({
+            "expected": bytes_to_hex(expected),
+            "replayed": bytes_to_hex(rtmrs.get(index)),
+        });
+        if ignored.contains(&index) {
+            result["ignored"] = json!(true);
+        } else {
+            let matched = rtmrs.get(index) == expected.as_slice();
+            all_match &= matched;
+            result["match"] = json!(matched);
+        }
+        comparison.insert(format!("rtmr{}", index), result);
+    }
+    comparison.insert("match".to_string(), json!(all_match));
+
+    (Value::Object(comparison), all_match)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const EV_NO_ACTION: u32 = 0x3;
+
+    fn push_tagged_event(log: &mut Vec<u8>, mr_index: u32, tag_id: u32, data: &[u8]) {
+        let digest = digest_sha384(data).unwrap();
+        log.extend_from_slice(&mr_index.to_le_bytes());
+        log.extend_from_slice(&EV_EVENT_TAG.to_le_bytes());
+        log.extend_from_slice(&1u32.to_le_bytes());
+        log.extend_from_slice(&TPML_ALG_SHA384.to_le_bytes());
+        log.extend_from_slice(&digest);
+        log.extend_from_slice(&(data.len() as u32 + 8).to_le_bytes());
+        log.extend_from_slice(&tag_id.to_le_bytes());
+        log.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        log.extend_from_slice(data);
+    }
+
+    fn event_log() -> Vec<u8> {
+        // TCG_PCR_EVENT header of the specification ID event
+        let mut log = Vec::new();
+        log.extend_from_slice(&0u32.to_le_bytes());
+        log.extend_from_slice(&EV_NO_ACTION.to_le_bytes());
+        log.extend_from_slice(&[0u8; 20]);
+        log.extend_from_slice(&0u32.to_le_bytes());
+
+        push_tagged_event(&mut log, 3, TAGGED_EVENT_ID_POLICY, b"{\"id\":1}");
+        push_tagged_event(&mut log, 3, TAGGED_EVENT_ID_ROOT_CA, &[0x30, 0x82]);
+        log
+    }
+
+    #[test]
+    fn test_decode_event_log() {
+        let (events, rtmrs) = decode_event_log(&event_log()).unwrap();
+        let events = events.as_array().unwrap();
+
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0]["eventTypeName"], "EV_EVENT_TAG");
+        assert_eq!(events[0]["rtmr"], 2);
+        assert_eq!(events[0]["payload"]["tag"], "policy");
+        assert_eq!(events[0]["payload"]["data"]["id"], 1);
+        assert_eq!(events[1]["payload"]["tag"], "rootCa");
+        assert_eq!(events[1]["payload"]["data"]["der"], "3082");
+        assert_eq!(events[1]["rtmrValue"], bytes_to_hex(rtmrs.get(2)));
+
+        let mut expected = Rtmrs::new();
+        expected
+            .extend(2, &digest_sha384(b"{\"id\":1}").unwrap())
+            .unwrap();
+        expected
+            .extend(2, &digest_sha384(&[0x30, 0x82]).unwrap())
+            .unwrap();
+        assert_eq!(rtmrs.get(2), expected.get(2));
+        assert_eq!(rtmrs.get(0), &[0u8; SHA384_DIGEST_SIZE]);
+    }
+
+    #[test]
+    fn test_decode_runtime_event() {
+        let receipt = MigrationReceiptEvent {
+            mig_request_id: 7,
+            target_td_uuid: [1, 2, 3, 4],
+            migration_source: 1,
+            result: 0,
+            tcb_evaluation_number: 17,
+            peer_policy_digest: [0u8; SHA384_DIGEST_SIZE],
+        };
+        let mut log = event_log();
+        push_tagged_event(
+            &mut log,
+            4,
+            TAGGED_EVENT_ID_MIGRATION_RECEIPT,
+            &receipt.to_bytes(),
+        );
+
+        let (events, _) = decode_event_log(&log).unwrap();
+        let receipt = &events[2];
+        assert_eq!(receipt["rtmr"], 3);
+        assert_eq!(receipt["payload"]["tag"], "migrationReceipt");
+        assert_eq!(receipt["payload"]["data"]["migRequestId"], 7);
+        assert_eq!(receipt["payload"]["data"]["migrationSource"], true);
+    }
+
+    #[test]
+    fn test_compare_rtmrs() {
+        let (_, rtmrs) = decode_event_log(&event_log()).unwrap();
+        let mut expected = [
+            vec![0u8; SHA384_DIGEST_SIZE],
+            vec![0u8; SHA384_DIGEST_SIZE],
+            rtmrs.get(2).to_vec(),
+            vec![0u8; SHA384_DIGEST_SIZE],
+        ];
+
+        let (_, matched) = compare_rtmrs(&rtmrs, &expected, &[]);
+        assert!(matched);
+
+        expected[3][0] = 1;
+        let (comparison, matched) = compare_rtmrs(&rtmrs, &expected, &[]);
+        assert!(!matched);
+        assert_eq!(comparison["rtmr2"]["match"], true);
+        assert_eq!(comparison["rtmr3"]["match"], false);
+
+        let (comparison, matched) = compare_rtmrs(&rtmrs, &expected, &[3]);
+        assert!(matched);
+        assert_eq!(comparison["rtmr3"]["ignored"], true);
+    }
+
+    #[test]
+    fn test_rtmrs_from_quote() {
+        let mut quote = vec![0u8; QUOTE_HEADER_SIZE + Report::R_MIGTD_RTMR3.end];
+        quote[..2].copy_from_slice(&4u16.to_le_bytes());
+        quote[QUOTE_HEADER_SIZE + Report::R_MIGTD_RTMR1.start] = 0xaa;
+
+        let rtmrs = rtmrs_from_quote(&quote).unwrap();
+        assert_eq!(rtmrs[1][0], 0xaa);
+        assert!(rtmrs_from_quote(&quote[..quote.len() - 1]).is_err());
+
+        quote[..2].copy_from_slice(&3u16.to_le_bytes());
+        assert!(rtmrs_from_quote(&quote).is_err());
+    }
+}
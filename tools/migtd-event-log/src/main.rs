@@ -0,0 +1,106 @@
+// Copyright (c) 2025 Intel Corporation
+//
+// SPDX-License-Identifier: BSD-2-Clause-Patent
+
+use clap::Parser;
+use migtd_event_log::{
+    compare_rtmrs, decode_event_log, rtmrs_from_quote, rtmrs_from_td_info, RTMR_COUNT,
+};
+use serde_json::{json, Map, Value};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    process::exit,
+};
+
+type ExpectedRtmrs = [Vec<u8>; RTMR_COUNT];
+
+fn read_expected_rtmrs(
+    path: &Path,
+    parse: fn(&[u8]) -> anyhow::Result<ExpectedRtmrs>,
+) -> ExpectedRtmrs {
+    fs::read(path)
+        .map_err(anyhow::Error::from)
+        .and_then(|data| parse(&data))
+        .unwrap_or_else(|e| {
+            eprintln!("Failed to get RTMRs from {}: {}", path.display(), e);
+            exit(1);
+        })
+}
+
+#[derive(Clone, Parser)]
+struct Config {
+    /// Path of the CCEL event log blob
+    #[clap(short, long)]
+    pub event_log: PathBuf,
+    /// TD quote to compare the replayed RTMRs against
+    #[clap(short, long)]
+    pub quote: Option<PathBuf>,
+    /// TD info JSON generated by `migtd-hash --output-td-info` to compare the
+    /// replayed RTMRs against
+    #[clap(short, long)]
+    pub td_info: Option<PathBuf>,
+    /// RTMR excluded from the comparison, can be repeated
+    #[clap(long, value_parser = clap::value_parser!(u8).range(0..RTMR_COUNT as i64))]
+    pub ignore_rtmr: Vec<u8>,
+    /// Output file of the decoded event log in JSON, printed to stdout by default
+    #[clap(short, long)]
+    pub output_file: Option<PathBuf>,
+}
+
+fn main() {
+    let config = Config::parse();
+
+    let event_log = fs::read(&config.event_log).unwrap_or_else(|e| {
+        eprintln!("Failed to read event log: {}", e);
+        exit(1);
+    });
+
+    let (events, rtmrs) = decode_event_log(&event_log).unwrap_or_else(|e| {
+        eprintln!("Failed to decode event log: {}", e);
+        exit(1);
+    });
+
+    let mut output = Map::new();
+    output.insert("events".to_string(), events);
+    output.insert("rtmrs".to_string(), rtmrs.to_json());
+
+    // Run the same checks MigTD applies on the peer event log
+    let valid = policy::validate_event_log(&event_log);
+    output.insert(
+        "validation".to_string(),
+        match &valid {
+            Ok(()) => json!({ "valid": true }),
+            Err(e) => json!({ "valid": false, "error": format!("{:?}", e) }),
+        },
+    );
+
+    let ignored: Vec<usize> = config.ignore_rtmr.iter().map(|i| *i as usize).collect();
+    let mut all_match = true;
+    if let Some(quote) = &config.quote {
+        let expected = read_expected_rtmrs(quote, rtmrs_from_quote);
+        let (comparison, matched) = compare_rtmrs(&rtmrs, &expected, &ignored);
+        all_match &= matched;
+        output.insert("quote".to_string(), comparison);
+    }
+    if let Some(td_info) = &config.td_info {
+        let expected = read_expected_rtmrs(td_info, rtmrs_from_td_info);
+        let (comparison, matched) = compare_rtmrs(&rtmrs, &expected, &ignored);
+        all_match &= matched;
+        output.insert("tdInfo".to_string(), comparison);
+    }
+
+    let output = serde_json::to_string_pretty(&Value::Object(output)).unwrap();
+    if let Some(output_file) = config.output_file {
+        fs::write(output_file, output).unwrap_or_else(|e| {
+            eprintln!("Failed to write output file: {}", e);
+            exit(1);
+        });
+    } else {
+        println!("{}", output);
+    }
+
+    if valid.is_err() || !all_match {
+        exit(2);
+    }
+}
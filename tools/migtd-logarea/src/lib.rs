@@ -0,0 +1,853 @@
+// Copyright (c) 2025 Intel Corporation
+//
+// SPDX-License-Identifier: BSD-2-Clause-Patent
+
+//! Decoder of the LogArea written by `migtd::migration::logging`.
+//!
+//! Each vCPU owns one page starting with a `LogAreaBufferHeader`, followed by
+//! a ring of `LogEntryHeader` + message records between `startoffset` and
+//! `endoffset`. A dump is the concatenation of the pages of all the vCPUs.
+//!
+//! Version 2 of the LogArea adds a `format` byte to the entry header: an entry
+//! is either a free-form message or a message followed by key-value pairs.
+//!
+//! The crash report written by `migtd::driver::crash` embeds the latest
+//! entries in the same format, see `parse_crash_report`.
+
+use anyhow::{anyhow, Result};
+use serde_json::{json, Value};
+use std::fmt;
+
+/// Size of the LogArea buffer of one vCPU
+pub const PAGE_SIZE: usize = 0x1000;
+
+/// `migtd::migration::logging::LOGAREA_SIGNATURE`
+pub const LOGAREA_SIGNATURE: [u8; 16] = *b"MigTD LogArea 2\0";
+
+/// Signature of the LogArea version 1, whose entries are all free-form messages
+pub const LOGAREA_SIGNATURE_V1: [u8; 16] = *b"MigTD LogArea 1\0";
+
+/// The entry is a free-form UTF-8 message
+pub const LOG_ENTRY_FORMAT_TEXT: u8 = 0;
+/// The entry is a message followed by key-value pairs
+pub const LOG_ENTRY_FORMAT_KV: u8 = 1;
+
+const KV_TYPE_U64: u8 = 1;
+const KV_TYPE_I64: u8 = 2;
+const KV_TYPE_BOOL: u8 = 3;
+const KV_TYPE_STR: u8 = 4;
+const KV_TYPE_BYTES: u8 = 5;
+
+/// Size of `LogAreaBufferHeader`
+pub const LOGAREA_HEADER_SIZE: usize = 40;
+
+/// Size of `LogEntryHeader`
+pub const LOG_ENTRY_HEADER_SIZE: usize = 24;
+
+/// Request id of the entries not bound to a migration request
+pub const MIGRATION_REQUEST_ID_SENTINEL: u64 = u64::MAX;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    Error = 1,
+    Warn = 2,
+    Info = 3,
+    Debug = 4,
+    Trace = 5,
+}
+
+impl Level {
+    pub fn from_u8(value: u8) -> Option<Self> {
+        match value {
+            1 => Some(Level::Error),
+            2 => Some(Level::Warn),
+            3 => Some(Level::Info),
+            4 => Some(Level::Debug),
+            5 => Some(Level::Trace),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Level {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Level::Error => "ERROR",
+            Level::Warn => "WARN",
+            Level::Info => "INFO",
+            Level::Debug => "DEBUG",
+            Level::Trace => "TRACE",
+        };
+        f.pad(name)
+    }
+}
+
+impl std::str::FromStr for Level {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "error" | "1" => Ok(Level::Error),
+            "warn" | "2" => Ok(Level::Warn),
+            "info" | "3" => Ok(Level::Info),
+            "debug" | "4" => Ok(Level::Debug),
+            "trace" | "5" => Ok(Level::Trace),
+            _ => Err(anyhow!("invalid log level: {}", s)),
+        }
+    }
+}
+
+/// Header of the LogArea buffer of one vCPU
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LogAreaHeader {
+    pub version: u8,
+    pub vcpu_index: u32,
+    pub start_offset: usize,
+    pub end_offset: usize,
+}
+
+impl LogAreaHeader {
+    pub fn parse(page: &[u8]) -> Result<Self> {
+        if page.len() < LOGAREA_HEADER_SIZE {
+            return Err(anyhow!("buffer is too short for a LogArea header"));
+        }
+        let version = if page[..16] == LOGAREA_SIGNATURE {
+            2
+        } else if page[..16] == LOGAREA_SIGNATURE_V1 {
+            1
+        } else {
+            return Err(anyhow!("invalid LogArea signature"));
+        };
+        let vcpu_index = read_u32(page, 16);
+        let start_offset = read_u64(page, 24);
+        let end_offset = read_u64(page, 32);
+
+        let valid = LOGAREA_HEADER_SIZE as u64..=page.len() as u64;
+        if !valid.contains(&start_offset) || !valid.contains(&end_offset) {
+            return Err(anyhow!(
+                "vCPU {}: offsets out of the buffer, start {:#x} end {:#x}",
+                vcpu_index,
+                start_offset,
+                end_offset
+            ));
+        }
+
+        Ok(Self {
+            version,
+            vcpu_index,
+            start_offset: start_offset as usize,
+            end_offset: end_offset as usize,
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LogEntry {
+    pub vcpu_index: u32,
+    pub log_entry_id: u64,
+    pub mig_request_id: u64,
+    pub level: Option<Level>,
+    pub message: Vec<u8>,
+    pub fields: KvFields,
+}
+
+/// Key-value pairs of a structured entry, in the order they were logged
+pub type KvFields = Vec<(String, KvValue)>;
+
+/// Value of a key-value pair of a structured entry
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum KvValue {
+    U64(u64),
+    I64(i64),
+    Bool(bool),
+    Str(String),
+    Bytes(Vec<u8>),
+}
+
+impl KvValue {
+    pub fn to_json(&self) -> Value {
+        match self {
+            KvValue::U64(v) => json!(v),
+            KvValue::I64(v) => json!(v),
+            KvValue::Bool(v) => json!(v),
+            KvValue::Str(v) => json!(v),
+            KvValue::Bytes(v) => json!(bytes_to_hex(v)),
+        }
+    }
+}
+
+impl fmt::Display for KvValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            KvValue::U64(v) => write!(f, "{}", v),
+            KvValue::I64(v) => write!(f, "{}", v),
+            KvValue::Bool(v) => write!(f, "{}", v),
+            KvValue::Str(v) => write!(f, "{:?}", v),
+            KvValue::Bytes(v) => write!(f, "{}", bytes_to_hex(v)),
+        }
+    }
+}
+
+pub fn bytes_to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+// Reads the payload of a structured entry, see
+// `migtd::migration::logging::KvEntryEncoder` for the layout.
+struct KvReader<'a> {
+    data: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> KvReader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8]> {
+        let end = self
+            .offset
+            .checked_add(len)
+            .filter(|end| *end <= self.data.len())
+            .ok_or_else(|| anyhow!("truncated key-value entry"))?;
+        let bytes = &self.data[self.offset..end];
+        self.offset = end;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u64(&mut self) -> Result<u64> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    fn length_prefixed(&mut self) -> Result<&'a [u8]> {
+        let len = u16::from_le_bytes(self.take(2)?.try_into().unwrap());
+        self.take(len as usize)
+    }
+}
+
+/// Decodes the payload of a `LOG_ENTRY_FORMAT_KV` entry into its message and
+/// key-value pairs.
+pub fn decode_kv_entry(payload: &[u8]) -> Result<(Vec<u8>, KvFields)> {
+    let mut reader = KvReader {
+        data: payload,
+        offset: 0,
+    };
+    let message = reader.length_prefixed()?.to_vec();
+    let count = reader.u8()?;
+
+    let mut fields = Vec::with_capacity(count as usize);
+    for _ in 0..count {
+        let key_len = reader.u8()? as usize;
+        let key = String::from_utf8_lossy(reader.take(key_len)?).to_string();
+        let value = match reader.u8()? {
+            KV_TYPE_U64 => KvValue::U64(reader.u64()?),
+            KV_TYPE_I64 => KvValue::I64(reader.u64()? as i64),
+            KV_TYPE_BOOL => KvValue::Bool(reader.u8()? != 0),
+            KV_TYPE_STR => {
+                KvValue::Str(String::from_utf8_lossy(reader.length_prefixed()?).to_string())
+            }
+            KV_TYPE_BYTES => KvValue::Bytes(reader.length_prefixed()?.to_vec()),
+            tag => return Err(anyhow!("unknown value type {} of key {}", tag, key)),
+        };
+        fields.push((key, value));
+    }
+    if reader.offset != payload.len() {
+        return Err(anyhow!("trailing bytes after the key-value pairs"));
+    }
+
+    Ok((message, fields))
+}
+
+impl LogEntry {
+    pub fn message(&self) -> String {
+        String::from_utf8_lossy(&self.message)
+            .trim_end_matches(['\n', '\0'])
+            .to_string()
+    }
+
+    /// Value of the key `key` of a structured entry
+    pub fn field(&self, key: &str) -> Option<&KvValue> {
+        self.fields.iter().find(|(k, _)| k == key).map(|(_, v)| v)
+    }
+
+    pub fn to_json(&self) -> Value {
+        let mut entry = json!({
+            "id": self.log_entry_id,
+            "vcpu": self.vcpu_index,
+            "requestId": request_id_to_json(self.mig_request_id),
+            "level": self.level.map(|l| l.to_string()),
+            "message": self.message(),
+        });
+        if !self.fields.is_empty() {
+            let fields: serde_json::Map<String, Value> = self
+                .fields
+                .iter()
+                .map(|(k, v)| (k.clone(), v.to_json()))
+                .collect();
+            entry["fields"] = Value::Object(fields);
+        }
+        entry
+    }
+}
+
+impl fmt::Display for LogEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let level = self
+            .level
+            .map(|l| l.to_string())
+            .unwrap_or_else(|| "?".to_string());
+        write!(f, "[{:>8}] vcpu {:<3} ", self.log_entry_id, self.vcpu_index)?;
+        if self.mig_request_id == MIGRATION_REQUEST_ID_SENTINEL {
+            write!(f, "req {:<6} ", "-")?;
+        } else {
+            write!(f, "req {:<6} ", self.mig_request_id)?;
+        }
+        write!(f, "{:<5} {}", level, self.message())?;
+        for (key, value) in &self.fields {
+            write!(f, " {}={}", key, value)?;
+        }
+        Ok(())
+    }
+}
+
+fn request_id_to_json(request_id: u64) -> Value {
+    if request_id == MIGRATION_REQUEST_ID_SENTINEL {
+        Value::Null
+    } else {
+        json!(request_id)
+    }
+}
+
+fn read_u32(data: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap())
+}
+
+fn read_u64(data: &[u8], offset: usize) -> u64 {
+    u64::from_le_bytes(data[offset..offset + 8].try_into().unwrap())
+}
+
+// Reads the records in `page[offset..limit]` until an empty slot is found.
+fn parse_segment(
+    page: &[u8],
+    vcpu_index: u32,
+    mut offset: usize,
+    limit: usize,
+    entries: &mut Vec<LogEntry>,
+) -> Result<()> {
+    while offset + LOG_ENTRY_HEADER_SIZE <= limit {
+        let log_entry_id = read_u64(page, offset);
+        let mig_request_id = read_u64(page, offset + 8);
+        let loglevel = page[offset + 16];
+        let format = page[offset + 17];
+        let length = read_u32(page, offset + 20) as usize;
+
+        // The writer zeroes the slots it frees
+        if log_entry_id == 0 && length == 0 {
+            break;
+        }
+
+        let message_start = offset + LOG_ENTRY_HEADER_SIZE;
+        let message_end = message_start
+            .checked_add(length)
+            .filter(|end| *end <= limit)
+            .ok_or_else(|| {
+                anyhow!(
+                    "vCPU {}: entry {} at {:#x} overflows the ring, length {:#x}",
+                    vcpu_index,
+                    log_entry_id,
+                    offset,
+                    length
+                )
+            })?;
+
+        let payload = &page[message_start..message_end];
+        let (message, fields) = match format {
+            LOG_ENTRY_FORMAT_KV => decode_kv_entry(payload)
+                .map_err(|e| anyhow!("vCPU {}: entry {}: {}", vcpu_index, log_entry_id, e))?,
+            _ => (payload.to_vec(), Vec::new()),
+        };
+
+        entries.push(LogEntry {
+            vcpu_index,
+            log_entry_id,
+            mig_request_id,
+            level: Level::from_u8(loglevel),
+            message,
+            fields,
+        });
+        offset = message_end;
+    }
+    Ok(())
+}
+
+/// Parses the LogArea buffer of one vCPU, oldest entry first.
+pub fn parse_buffer(page: &[u8]) -> Result<(LogAreaHeader, Vec<LogEntry>)> {
+    let header = LogAreaHeader::parse(page)?;
+    let mut entries = Vec::new();
+
+    if header.start_offset < header.end_offset || header.start_offset == LOGAREA_HEADER_SIZE {
+        parse_segment(
+            page,
+            header.vcpu_index,
+            header.start_offset,
+            header.end_offset,
+            &mut entries,
+        )?;
+    } else {
+        // The ring has wrapped: the oldest entries run from the start offset
+        // to the zeroed tail of the page, the newest from the page header to
+        // the end offset.
+        parse_segment(
+            page,
+            header.vcpu_index,
+            header.start_offset,
+            page.len(),
+            &mut entries,
+        )?;
+        parse_segment(
+            page,
+            header.vcpu_index,
+            LOGAREA_HEADER_SIZE,
+            header.end_offset,
+            &mut entries,
+        )?;
+    }
+
+    Ok((header, entries))
+}
+
+#[derive(Debug, Default)]
+pub struct LogArea {
+    pub headers: Vec<LogAreaHeader>,
+    /// Entries of all the vCPUs ordered by `log_entry_id`
+    pub entries: Vec<LogEntry>,
+    /// Pages that could not be parsed, with the reason
+    pub errors: Vec<(usize, String)>,
+}
+
+/// Parses a dump made of the LogArea pages of one or more vCPUs and merges
+/// their entries.
+///
+/// Pages which have not been initialized by `EnableLogArea` yet or are
+/// corrupted are reported in `errors` rather than failing the whole dump.
+pub fn parse_logarea(dump: &[u8]) -> Result<LogArea> {
+    if dump.is_empty() || !dump.len().is_multiple_of(PAGE_SIZE) {
+        return Err(anyhow!(
+            "dump size {:#x} is not a multiple of the page size",
+            dump.len()
+        ));
+    }
+
+    let mut logarea = LogArea::default();
+    for (index, page) in dump.chunks(PAGE_SIZE).enumerate() {
+        match parse_buffer(page) {
+            Ok((header, entries)) => {
+                logarea.headers.push(header);
+                logarea.entries.extend(entries);
+            }
+            Err(e) => logarea.errors.push((index, e.to_string())),
+        }
+    }
+
+    // The entry id is shared by all the vCPUs
+    logarea.entries.sort_by_key(|entry| entry.log_entry_id);
+    Ok(logarea)
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct Filter {
+    pub request_id: Option<u64>,
+    /// Most verbose level to keep
+    pub max_level: Option<Level>,
+    /// Only keep the entries with a greater id
+    pub after_id: Option<u64>,
+}
+
+impl Filter {
+    pub fn matches(&self, entry: &LogEntry) -> bool {
+        if let Some(request_id) = self.request_id {
+            if entry.mig_request_id != request_id {
+                return false;
+            }
+        }
+        if let Some(max_level) = self.max_level {
+            match entry.level {
+                Some(level) if level <= max_level => {}
+                _ => return false,
+            }
+        }
+        if let Some(after_id) = self.after_id {
+            if entry.log_entry_id <= after_id {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+/// Signature of a crash report
+pub const CRASH_REPORT_SIGNATURE: [u8; 8] = *b"MIGTDCRS";
+
+/// Size of the crash report header, the message follows it
+pub const CRASH_REPORT_HEADER_SIZE: usize = 40;
+
+/// Names of the key exchange phases, indexed by `MigrationPhase`
+pub const MIGRATION_PHASES: [&str; 7] = [
+    "connect",
+    "pre_session",
+    "quote_generation",
+    "secure_session",
+    "quote_verification",
+    "policy_evaluation",
+    "set_msk",
+];
+
+// Phase of a crash outside of a key exchange
+const CRASH_NO_PHASE: u8 = 0xFF;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CrashReport {
+    pub version: u32,
+    pub error_code: u64,
+    /// Key exchange being run when MigTD crashed, if any
+    pub mig_request_id: u64,
+    pub phase: Option<u8>,
+    pub message: String,
+    /// Latest LogArea entries, oldest first. The vCPU index of the entries is
+    /// not recorded and left at 0.
+    pub entries: Vec<LogEntry>,
+}
+
+impl CrashReport {
+    pub fn phase_name(&self) -> Option<String> {
+        self.phase.map(|phase| {
+            MIGRATION_PHASES
+                .get(phase as usize)
+                .map(|name| name.to_string())
+                .unwrap_or_else(|| format!("unknown ({})", phase))
+        })
+    }
+
+    pub fn to_json(&self) -> Value {
+        json!({
+            "version": self.version,
+            "errorCode": self.error_code,
+            "requestId": request_id_to_json(self.mig_request_id),
+            "phase": self.phase_name(),
+            "message": self.message,
+            "entries": self.entries.iter().map(|e| e.to_json()).collect::<Vec<_>>(),
+        })
+    }
+}
+
+impl fmt::Display for CrashReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Crash report version {}", self.version)?;
+        writeln!(f, "Error code: {:#x}", self.error_code)?;
+        if self.mig_request_id == MIGRATION_REQUEST_ID_SENTINEL {
+            writeln!(f, "Request:    -")?;
+        } else {
+            writeln!(f, "Request:    {}", self.mig_request_id)?;
+        }
+        writeln!(
+            f,
+            "Phase:      {}",
+            self.phase_name().unwrap_or_else(|| "-".to_string())
+        )?;
+        writeln!(f, "Message:    {}", self.message)?;
+        writeln!(f, "Latest LogArea entries:")?;
+        for entry in &self.entries {
+            writeln!(f, "{}", entry)?;
+        }
+        Ok(())
+    }
+}
+
+/// Parses a crash report, e.g. the page reported through the guest crash
+/// MSRs or `TDG.VP.VMCALL<ReportFatalError>`.
+pub fn parse_crash_report(data: &[u8]) -> Result<CrashReport> {
+    if data.len() < CRASH_REPORT_HEADER_SIZE || data[..8] != CRASH_REPORT_SIGNATURE {
+        return Err(anyhow!("not a MigTD crash report"));
+    }
+    let version = read_u32(data, 8);
+    let size = read_u32(data, 12) as usize;
+    if size < CRASH_REPORT_HEADER_SIZE || size > data.len() {
+        return Err(anyhow!("invalid crash report size {:#x}", size));
+    }
+    let data = &data[..size];
+    let error_code = read_u64(data, 16);
+    let mig_request_id = read_u64(data, 24);
+    let phase = Some(data[32]).filter(|phase| *phase != CRASH_NO_PHASE);
+    let entry_count = data[33] as usize;
+    let message_size = read_u32(data, 36) as usize;
+    let message_end = CRASH_REPORT_HEADER_SIZE
+        .checked_add(message_size)
+        .filter(|end| *end <= size)
+        .ok_or_else(|| anyhow!("crash message overflows the report"))?;
+    let message = String::from_utf8_lossy(&data[CRASH_REPORT_HEADER_SIZE..message_end])
+        .trim_end_matches(['\n', '\0'])
+        .to_string();
+
+    let mut entries = Vec::new();
+    parse_segment(data, 0, message_end, size, &mut entries)?;
+    if entries.len() != entry_count {
+        return Err(anyhow!(
+            "crash report has {} entries, {} expected",
+            entries.len(),
+            entry_count
+        ));
+    }
+
+    Ok(CrashReport {
+        version,
+        error_code,
+        mig_request_id,
+        phase,
+        message,
+        entries,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    struct Ring {
+        page: Vec<u8>,
+    }
+
+    impl Ring {
+        fn new(vcpu_index: u32) -> Self {
+            let mut page = vec![0u8; PAGE_SIZE];
+            page[..16].copy_from_slice(&LOGAREA_SIGNATURE);
+            page[16..20].copy_from_slice(&vcpu_index.to_le_bytes());
+            let mut ring = Self { page };
+            ring.set_offsets(LOGAREA_HEADER_SIZE, LOGAREA_HEADER_SIZE);
+            ring
+        }
+
+        fn set_offsets(&mut self, start: usize, end: usize) {
+            self.page[24..32].copy_from_slice(&(start as u64).to_le_bytes());
+            self.page[32..40].copy_from_slice(&(end as u64).to_le_bytes());
+        }
+
+        fn write(
+            &mut self,
+            offset: usize,
+            id: u64,
+            request_id: u64,
+            level: u8,
+            msg: &[u8],
+        ) -> usize {
+            self.write_entry(offset, id, request_id, level, LOG_ENTRY_FORMAT_TEXT, msg)
+        }
+
+        fn write_entry(
+            &mut self,
+            offset: usize,
+            id: u64,
+            request_id: u64,
+            level: u8,
+            format: u8,
+            msg: &[u8],
+        ) -> usize {
+            let page = &mut self.page;
+            page[offset..offset + 8].copy_from_slice(&id.to_le_bytes());
+            page[offset + 8..offset + 16].copy_from_slice(&request_id.to_le_bytes());
+            page[offset + 16] = level;
+            page[offset + 17] = format;
+            page[offset + 20..offset + 24].copy_from_slice(&(msg.len() as u32).to_le_bytes());
+            page[offset + 24..offset + 24 + msg.len()].copy_from_slice(msg);
+            offset + LOG_ENTRY_HEADER_SIZE + msg.len()
+        }
+    }
+
+    #[test]
+    fn test_parse_linear_buffer() {
+        let mut ring = Ring::new(0);
+        let end = ring.write(LOGAREA_HEADER_SIZE, 1, 42, 3, b"first\n");
+        let end = ring.write(end, 2, 42, 1, b"second\n");
+        ring.set_offsets(LOGAREA_HEADER_SIZE, end);
+
+        let (header, entries) = parse_buffer(&ring.page).unwrap();
+        assert_eq!(header.vcpu_index, 0);
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].message(), "first");
+        assert_eq!(entries[1].level, Some(Level::Error));
+    }
+
+    #[test]
+    fn test_parse_wrapped_buffer() {
+        let mut ring = Ring::new(1);
+        let msg = [b'a'; 0x400];
+        // Oldest entry near the end of the page, the tail after it is zeroed
+        let start = PAGE_SIZE - 0x500;
+        ring.write(start, 7, 1, 3, &msg);
+        let end = ring.write(LOGAREA_HEADER_SIZE, 8, 1, 3, b"newest");
+        ring.set_offsets(start, end);
+
+        let (_, entries) = parse_buffer(&ring.page).unwrap();
+        let ids: Vec<u64> = entries.iter().map(|e| e.log_entry_id).collect();
+        assert_eq!(ids, vec![7, 8]);
+    }
+
+    #[test]
+    fn test_parse_invalid_buffer() {
+        let mut ring = Ring::new(0);
+        ring.set_offsets(LOGAREA_HEADER_SIZE, PAGE_SIZE + 1);
+        assert!(parse_buffer(&ring.page).is_err());
+
+        // Entry length running past the end offset
+        let mut ring = Ring::new(0);
+        ring.write(LOGAREA_HEADER_SIZE, 1, 1, 3, b"message");
+        ring.set_offsets(
+            LOGAREA_HEADER_SIZE,
+            LOGAREA_HEADER_SIZE + LOG_ENTRY_HEADER_SIZE + 3,
+        );
+        assert!(parse_buffer(&ring.page).is_err());
+
+        ring.page[0] = 0;
+        assert!(parse_buffer(&ring.page).is_err());
+    }
+
+    #[test]
+    fn test_parse_kv_entry() {
+        let mut payload = Vec::new();
+        payload.extend_from_slice(&[20, 0]);
+        payload.extend_from_slice(b"Key exchange failed\n");
+        payload.push(3);
+        payload.push(5);
+        payload.extend_from_slice(b"phase");
+        payload.extend_from_slice(&[KV_TYPE_STR, 7, 0]);
+        payload.extend_from_slice(b"connect");
+        payload.push(10);
+        payload.extend_from_slice(b"error_code");
+        payload.push(KV_TYPE_U64);
+        payload.extend_from_slice(&5u64.to_le_bytes());
+        payload.push(10);
+        payload.extend_from_slice(b"peer_fmspc");
+        payload.extend_from_slice(&[KV_TYPE_BYTES, 6, 0, 0x00, 0x90, 0x6e, 0xa1, 0x00, 0x00]);
+
+        let mut ring = Ring::new(0);
+        let end = ring.write_entry(LOGAREA_HEADER_SIZE, 1, 42, 1, LOG_ENTRY_FORMAT_KV, &payload);
+        ring.set_offsets(LOGAREA_HEADER_SIZE, end);
+
+        let (header, entries) = parse_buffer(&ring.page).unwrap();
+        assert_eq!(header.version, 2);
+        assert_eq!(entries[0].message(), "Key exchange failed");
+        assert_eq!(entries[0].field("error_code"), Some(&KvValue::U64(5)));
+        assert_eq!(
+            entries[0].to_json()["fields"],
+            json!({ "phase": "connect", "error_code": 5, "peer_fmspc": "00906ea10000" })
+        );
+        assert_eq!(
+            entries[0].to_string(),
+            "[       1] vcpu 0   req 42     ERROR Key exchange failed \
+             phase=\"connect\" error_code=5 peer_fmspc=00906ea10000"
+        );
+
+        // Truncated pairs and unknown value types are rejected
+        assert!(decode_kv_entry(&payload[..payload.len() - 1]).is_err());
+        let mut unknown = payload.clone();
+        // Type of the first pair
+        unknown[29] = 0xff;
+        assert!(decode_kv_entry(&unknown).is_err());
+
+        // Version 1 entries are all free-form messages
+        ring.page[..16].copy_from_slice(&LOGAREA_SIGNATURE_V1);
+        ring.write(LOGAREA_HEADER_SIZE, 1, 42, 1, b"message");
+        ring.set_offsets(
+            LOGAREA_HEADER_SIZE,
+            LOGAREA_HEADER_SIZE + LOG_ENTRY_HEADER_SIZE + 7,
+        );
+        let (header, entries) = parse_buffer(&ring.page).unwrap();
+        assert_eq!(header.version, 1);
+        assert!(entries[0].fields.is_empty());
+    }
+
+    #[test]
+    fn test_parse_crash_report() {
+        let message = b"Panic: msg: Fail to get policy from CFV";
+        let mut ring = Ring::new(0);
+        let entries_start = CRASH_REPORT_HEADER_SIZE + message.len();
+        let end = ring.write(entries_start, 9, 42, 3, b"Key exchange started\n");
+        let end = ring.write(end, 10, 42, 1, b"Policy check failed\n");
+
+        let mut report = ring.page[..end].to_vec();
+        report[..8].copy_from_slice(&CRASH_REPORT_SIGNATURE);
+        report[8..12].copy_from_slice(&1u32.to_le_bytes());
+        report[12..16].copy_from_slice(&(end as u32).to_le_bytes());
+        report[16..24].copy_from_slice(&0xffu64.to_le_bytes());
+        report[24..32].copy_from_slice(&42u64.to_le_bytes());
+        report[32] = 5;
+        report[33] = 2;
+        report[34..36].fill(0);
+        report[36..40].copy_from_slice(&(message.len() as u32).to_le_bytes());
+        report[CRASH_REPORT_HEADER_SIZE..entries_start].copy_from_slice(message);
+
+        let crash = parse_crash_report(&report).unwrap();
+        assert_eq!(crash.error_code, 0xff);
+        assert_eq!(crash.mig_request_id, 42);
+        assert_eq!(crash.phase_name().as_deref(), Some("policy_evaluation"));
+        assert_eq!(crash.message, "Panic: msg: Fail to get policy from CFV");
+        assert_eq!(crash.entries.len(), 2);
+        assert_eq!(crash.entries[1].message(), "Policy check failed");
+
+        // The entry count must match the entries found
+        report[33] = 3;
+        assert!(parse_crash_report(&report).is_err());
+        report[33] = 2;
+
+        // Only the reported size is parsed
+        report[12..16].copy_from_slice(&(PAGE_SIZE as u32).to_le_bytes());
+        assert!(parse_crash_report(&report).is_err());
+        assert!(parse_crash_report(&report[..8]).is_err());
+    }
+
+    #[test]
+    fn test_merge_and_filter() {
+        let mut vcpu0 = Ring::new(0);
+        let end = vcpu0.write(LOGAREA_HEADER_SIZE, 1, 10, 3, b"a");
+        let end = vcpu0.write(end, 4, 11, 4, b"d");
+        vcpu0.set_offsets(LOGAREA_HEADER_SIZE, end);
+
+        let mut vcpu1 = Ring::new(1);
+        let end = vcpu1.write(LOGAREA_HEADER_SIZE, 2, 10, 1, b"b");
+        let end = vcpu1.write(end, 3, MIGRATION_REQUEST_ID_SENTINEL, 3, b"c");
+        vcpu1.set_offsets(LOGAREA_HEADER_SIZE, end);
+
+        // Not yet enabled by the VMM
+        let vcpu2 = vec![0u8; PAGE_SIZE];
+
+        let dump = [vcpu0.page, vcpu1.page, vcpu2].concat();
+        let logarea = parse_logarea(&dump).unwrap();
+        assert_eq!(logarea.headers.len(), 2);
+        assert_eq!(logarea.errors.len(), 1);
+        let messages: Vec<String> = logarea.entries.iter().map(|e| e.message()).collect();
+        assert_eq!(messages, vec!["a", "b", "c", "d"]);
+
+        let filter = Filter {
+            request_id: Some(10),
+            max_level: Some(Level::Info),
+            after_id: None,
+        };
+        let ids: Vec<u64> = logarea
+            .entries
+            .iter()
+            .filter(|e| filter.matches(e))
+            .map(|e| e.log_entry_id)
+            .collect();
+        assert_eq!(ids, vec![1, 2]);
+
+        let filter = Filter {
+            after_id: Some(2),
+            ..Default::default()
+        };
+        assert_eq!(
+            logarea.entries.iter().filter(|e| filter.matches(e)).count(),
+            2
+        );
+
+        assert!(parse_logarea(&dump[..PAGE_SIZE - 1]).is_err());
+    }
+}
@@ -0,0 +1,114 @@
+// Copyright (c) 2025 Intel Corporation
+//
+// SPDX-License-Identifier: BSD-2-Clause-Patent
+
+use clap::Parser;
+use migtd_logarea::{parse_crash_report, parse_logarea, Filter, Level, LogEntry};
+use std::{fs, path::PathBuf, process::exit, thread, time::Duration};
+
+#[derive(Clone, Parser)]
+struct Config {
+    /// LogArea dump: the 4 KiB LogArea pages of the vCPUs, concatenated in
+    /// vCPU order
+    #[clap(short, long)]
+    pub input: PathBuf,
+    /// Only print the entries of this migration request
+    #[clap(short, long)]
+    pub request_id: Option<u64>,
+    /// Most verbose level to print: error, warn, info, debug or trace
+    #[clap(short, long)]
+    pub level: Option<Level>,
+    /// Print one JSON object per entry
+    #[clap(short, long)]
+    pub json: bool,
+    /// Keep polling the dump and print the new entries, e.g. the file written
+    /// by the AzCVMEmu `--logarea-file` option
+    #[clap(short, long)]
+    pub follow: bool,
+    /// Polling interval of the follow mode in milliseconds
+    #[clap(long, default_value_t = 500)]
+    pub interval: u64,
+    /// The input is a crash report, e.g. the file written by the AzCVMEmu
+    /// `--crash-file` option
+    #[clap(short, long, conflicts_with = "follow")]
+    pub crash: bool,
+}
+
+fn print_entry(entry: &LogEntry, json: bool) {
+    if json {
+        println!("{}", entry.to_json());
+    } else {
+        println!("{}", entry);
+    }
+}
+
+fn print_dump(dump: &[u8], filter: &Filter, json: bool) -> anyhow::Result<()> {
+    let logarea = parse_logarea(dump)?;
+    for (index, error) in &logarea.errors {
+        eprintln!("Skipping LogArea page {}: {}", index, error);
+    }
+    for entry in logarea.entries.iter().filter(|e| filter.matches(e)) {
+        print_entry(entry, json);
+    }
+    Ok(())
+}
+
+fn follow(config: &Config, mut filter: Filter) -> ! {
+    let mut last_id = 0;
+    loop {
+        // The dump may not exist yet or be replaced while MigTD is running
+        if let Ok(dump) = fs::read(&config.input) {
+            if let Ok(logarea) = parse_logarea(&dump) {
+                let newest = logarea.entries.last().map(|e| e.log_entry_id).unwrap_or(0);
+                // Entry ids restart from 1 when MigTD is restarted
+                if newest < last_id {
+                    eprintln!("-- LogArea has been reset --");
+                    last_id = 0;
+                }
+                filter.after_id = Some(last_id);
+                for entry in logarea.entries.iter().filter(|e| filter.matches(e)) {
+                    print_entry(entry, config.json);
+                }
+                last_id = last_id.max(newest);
+            }
+        }
+        thread::sleep(Duration::from_millis(config.interval));
+    }
+}
+
+fn main() {
+    let config = Config::parse();
+
+    let filter = Filter {
+        request_id: config.request_id,
+        max_level: config.level,
+        after_id: None,
+    };
+
+    if config.follow {
+        follow(&config, filter);
+    }
+
+    let dump = fs::read(&config.input).unwrap_or_else(|e| {
+        eprintln!("Failed to read LogArea dump: {}", e);
+        exit(1);
+    });
+
+    if config.crash {
+        let report = parse_crash_report(&dump).unwrap_or_else(|e| {
+            eprintln!("Failed to parse crash report: {}", e);
+            exit(1);
+        });
+        if config.json {
+            println!("{}", report.to_json());
+        } else {
+            print!("{}", report);
+        }
+        return;
+    }
+
+    print_dump(&dump, &filter, config.json).unwrap_or_else(|e| {
+        eprintln!("Failed to parse LogArea: {}", e);
+        exit(1);
+    });
+}
@@ -15,7 +15,7 @@ struct Args {
     #[arg(short, long)]
     policy: String,
 
-    /// Path to issuer certificate chain (PEM)
+    /// Path to issuer certificate chain (PEM) or m-of-n policy issuer configuration (JSON)
     #[arg(short, long)]
     cert_chain: String,
 }